@@ -27,11 +27,11 @@ extern crate triehash;
 extern crate substrate_client as client;
 extern crate substrate_codec as codec;
 extern crate substrate_primitives as primitives;
-extern crate substrate_rpc;
 extern crate substrate_rpc_servers as rpc;
 extern crate substrate_runtime_io as runtime_io;
 extern crate substrate_state_machine as state_machine;
 extern crate substrate_extrinsic_pool as extrinsic_pool;
+extern crate substrate_network as network;
 extern crate demo_executor;
 extern crate demo_primitives;
 extern crate demo_runtime;
@@ -79,18 +79,37 @@ impl extrinsic_pool::api::ExtrinsicPool<UncheckedExtrinsic, BlockId, Hash> for D
 	fn import_notification_stream(&self) -> extrinsic_pool::api::EventStream {
 		unreachable!()
 	}
+
+	fn pending(&self, _block: BlockId)
+		-> Result<Vec<extrinsic_pool::api::PendingExtrinsic<Hash>>, Self::Error>
+	{
+		Err("unimplemented".into())
+	}
+
+	fn remove(&self, _hashes: &[Hash], _is_valid: bool) -> Vec<Hash> {
+		Vec::new()
+	}
 }
 
-struct DummySystem;
-impl substrate_rpc::system::SystemApi for DummySystem {
-	fn system_name(&self) -> substrate_rpc::system::error::Result<String> {
-		Ok("substrate-demo".into())
+struct DummyNetwork;
+impl network::SyncProvider<Block> for DummyNetwork {
+	fn status(&self) -> network::ProtocolStatus<Block> {
+		network::ProtocolStatus {
+			sync: network::SyncStatus {
+				state: network::SyncState::Idle,
+				best_seen_block: None,
+			},
+			num_peers: 0,
+			num_active_peers: 0,
+		}
 	}
-	fn system_version(&self) -> substrate_rpc::system::error::Result<String> {
-		Ok(crate_version!().into())
+
+	fn peers(&self) -> Vec<network::NetworkPeerInfo<Block>> {
+		Vec::new()
 	}
-	fn system_chain(&self) -> substrate_rpc::system::error::Result<String> {
-		Ok("default".into())
+
+	fn node_id(&self) -> Option<String> {
+		None
 	}
 }
 
@@ -121,6 +140,7 @@ pub fn run<I, T>(args: I) -> error::Result<()> where
 		consensus: Some(ConsensusConfig {
 			code: vec![],	// TODO
 			authorities: vec![god_key.clone().into()],
+			max_instructions: None,
 		}),
 		system: None,
 		session: Some(SessionConfig {
@@ -174,9 +194,19 @@ pub fn run<I, T>(args: I) -> error::Result<()> where
 	let mut runtime = Runtime::new()?;
 	let _rpc_servers = {
 		let handler = || {
+			let state = rpc::apis::state::State::new(client.clone(), runtime.executor());
 			let chain = rpc::apis::chain::Chain::new(client.clone(), runtime.executor());
 			let author = rpc::apis::author::Author::new(client.clone(), Arc::new(DummyPool), runtime.executor());
-			rpc::rpc_handler::<Block, _, _, _, _>(client.clone(), chain, author, DummySystem)
+			let system = rpc::apis::system::System::new(
+				rpc::apis::system::SystemInfo {
+					impl_name: "substrate-demo".into(),
+					impl_version: crate_version!().into(),
+					chain_name: "default".into(),
+				},
+				Arc::new(DummyNetwork),
+				false,
+			);
+			rpc::rpc_handler::<Block, _, _, _, _>(state, chain, author, system)
 		};
 		let http_address = "127.0.0.1:9933".parse().unwrap();
 		let ws_address = "127.0.0.1:9944".parse().unwrap();
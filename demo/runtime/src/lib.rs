@@ -87,9 +87,9 @@ impl system::Trait for Concrete {
 	type BlockNumber = BlockNumber;
 	type Hash = Hash;
 	type Hashing = BlakeTwo256;
-	type Digest = generic::Digest<Vec<u8>>;
+	type Digest = generic::Digest<generic::DigestItem>;
 	type AccountId = AccountId;
-	type Header = generic::Header<BlockNumber, BlakeTwo256, Vec<u8>>;
+	type Header = generic::Header<BlockNumber, BlakeTwo256, generic::DigestItem>;
 }
 
 /// System module for this concrete runtime.
@@ -179,7 +179,7 @@ impl_outer_dispatch! {
 /// The address format for describing accounts.
 pub type Address = staking::Address<Concrete>;
 /// Block header type as expected by this runtime.
-pub type Header = generic::Header<BlockNumber, BlakeTwo256, Vec<u8>>;
+pub type Header = generic::Header<BlockNumber, BlakeTwo256, generic::DigestItem>;
 /// Block type as expected by this runtime.
 pub type Block = generic::Block<Header, UncheckedExtrinsic>;
 /// BlockId type as expected by this runtime.
@@ -206,15 +206,31 @@ impl_outer_config! {
 	}
 }
 
+impl_outer_metadata! {
+	pub fn metadata {
+		Version,
+		System with storage,
+		Consensus,
+		Timestamp with storage,
+		Session with storage,
+		Staking with storage,
+		Democracy with storage,
+		Council with storage,
+		CouncilVoting with storage,
+	}
+}
+
 pub mod api {
 	impl_stubs!(
 		version => |()| super::Version::version(),
+		metadata => |()| super::metadata(),
 		authorities => |()| super::Consensus::authorities(),
 		initialise_block => |header| super::Executive::initialise_block(&header),
 		apply_extrinsic => |extrinsic| super::Executive::apply_extrinsic(extrinsic),
 		execute_block => |block| super::Executive::execute_block(block),
 		finalise_block => |()| super::Executive::finalise_block(),
 		validator_count => |()| super::Session::validator_count(),
-		validators => |()| super::Session::validators()
+		validators => |()| super::Session::validators(),
+		offchain_worker => |_number: super::BlockNumber| ()
 	);
 }
@@ -186,6 +186,7 @@ mod tests {
 			consensus: Some(ConsensusConfig {
 				code: LocalDispatch::native_equivalent().to_vec(),
 				authorities: session_keys(),
+				max_instructions: None,
 			}),
 			system: None,
 			session: Some(SessionConfig {
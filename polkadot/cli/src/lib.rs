@@ -89,29 +89,10 @@ use names::{Generator, Name};
 
 use futures::Future;
 use tokio::runtime::Runtime;
-use service::PruningMode;
+use service::{DatabaseBackend, PruningConstraints, PruningMode};
 
 const DEFAULT_TELEMETRY_URL: &str = "wss://telemetry.polkadot.io/submit/";
 
-#[derive(Clone)]
-struct SystemConfiguration {
-	chain_name: String,
-}
-
-impl substrate_rpc::system::SystemApi for SystemConfiguration {
-	fn system_name(&self) -> substrate_rpc::system::error::Result<String> {
-		Ok("parity-polkadot".into())
-	}
-
-	fn system_version(&self) -> substrate_rpc::system::error::Result<String> {
-		Ok(crate_version!().into())
-	}
-
-	fn system_chain(&self) -> substrate_rpc::system::error::Result<String> {
-		Ok(self.chain_name.clone())
-	}
-}
-
 fn load_spec(matches: &clap::ArgMatches) -> Result<(service::ChainSpec, bool), String> {
 	let chain_spec = matches.value_of("chain")
 		.map(ChainSpec::from)
@@ -202,6 +183,22 @@ pub fn run<I, T, W>(args: I, worker: W) -> error::Result<()> where
 		return import_blocks(matches, worker.exit_only());
 	}
 
+	if let Some(matches) = matches.subcommand_matches("revert") {
+		return revert_chain(matches);
+	}
+
+	if let Some(matches) = matches.subcommand_matches("check-db") {
+		return check_db(matches);
+	}
+
+	if let Some(matches) = matches.subcommand_matches("export-state") {
+		return export_state(matches);
+	}
+
+	if let Some(matches) = matches.subcommand_matches("import-state") {
+		return import_state(matches);
+	}
+
 	let (spec, is_global) = load_spec(&matches)?;
 	let mut config = service::Configuration::default_with_spec(spec);
 
@@ -220,12 +217,12 @@ pub fn run<I, T, W>(args: I, worker: W) -> error::Result<()> where
 		.into();
 
 	config.database_path = db_path(&base_path, config.chain_spec.id()).to_string_lossy().into();
+	config.database_backend = database_backend(&matches)?;
 
 	config.pruning = match matches.value_of("pruning") {
-		Some("archive") => PruningMode::ArchiveAll,
+		Some(s) => parse_pruning(s)
+			.map_err(|_| error::ErrorKind::Input("Invalid pruning mode specified".to_owned()))?,
 		None => PruningMode::keep_blocks(256),
-		Some(s) => PruningMode::keep_blocks(s.parse()
-			.map_err(|_| error::ErrorKind::Input("Invalid pruning mode specified".to_owned()))?),
 	};
 
 	let role =
@@ -249,6 +246,14 @@ pub fn run<I, T, W>(args: I, worker: W) -> error::Result<()> where
 	if let Some(v) = matches.value_of("max-heap-pages") {
 		config.max_heap_pages = v.parse().map_err(|_| "Invalid --max-heap-pages argument")?;
 	}
+	if let Some(v) = matches.value_of("max-call-instructions") {
+		config.max_call_instructions = v.parse().map_err(|_| "Invalid --max-call-instructions argument")?;
+	}
+	config.trace_runtime = matches.is_present("trace-runtime");
+	if let Some(v) = matches.value_of("state-cache-size") {
+		let mb: usize = v.parse().map_err(|_| "Invalid --state-cache-size argument")?;
+		config.state_cache_size = mb * 1024 * 1024;
+	}
 
 	if let Some(s) = matches.value_of("execution") {
 		config.execution_strategy = match s {
@@ -289,7 +294,9 @@ pub fn run<I, T, W>(args: I, worker: W) -> error::Result<()> where
 		config.keys.push("Alice".into());
 	}
 
-	let sys_conf = SystemConfiguration {
+	let sys_info = substrate_rpc::system::SystemInfo {
+		impl_name: "parity-polkadot".into(),
+		impl_version: crate_version!().into(),
 		chain_name: config.chain_spec.name().to_owned(),
 	};
 
@@ -320,8 +327,8 @@ pub fn run<I, T, W>(args: I, worker: W) -> error::Result<()> where
 	};
 
 	match role == service::Roles::LIGHT {
-		true => run_until_exit(&mut runtime, service::new_light(config, executor)?, &matches, sys_conf, worker)?,
-		false => run_until_exit(&mut runtime, service::new_full(config, executor)?, &matches, sys_conf, worker)?,
+		true => run_until_exit(&mut runtime, service::new_light(config, executor)?, &matches, sys_info, worker)?,
+		false => run_until_exit(&mut runtime, service::new_full(config, executor)?, &matches, sys_info, worker)?,
 	}
 
 	// TODO: hard exit if this stalls?
@@ -344,6 +351,7 @@ fn export_blocks<E>(matches: &clap::ArgMatches, exit: E) -> error::Result<()>
 	let (spec, _) = load_spec(&matches)?;
 	let mut config = service::Configuration::default_with_spec(spec);
 	config.database_path = db_path(&base_path, config.chain_spec.id()).to_string_lossy().into();
+	config.database_backend = database_backend(matches)?;
 	info!("DB path: {}", config.database_path);
 	let client = service::new_client(config)?;
 	let (exit_send, exit_recv) = std::sync::mpsc::channel();
@@ -409,6 +417,7 @@ fn import_blocks<E>(matches: &clap::ArgMatches, exit: E) -> error::Result<()>
 	let base_path = base_path(matches);
 	let mut config = service::Configuration::default_with_spec(spec);
 	config.database_path = db_path(&base_path, config.chain_spec.id()).to_string_lossy().into();
+	config.database_backend = database_backend(matches)?;
 
 	if let Some(v) = matches.value_of("min-heap-pages") {
 		config.min_heap_pages = v.parse().map_err(|_| "Invalid --min-heap-pages argument")?;
@@ -416,6 +425,11 @@ fn import_blocks<E>(matches: &clap::ArgMatches, exit: E) -> error::Result<()>
 	if let Some(v) = matches.value_of("max-heap-pages") {
 		config.max_heap_pages = v.parse().map_err(|_| "Invalid --max-heap-pages argument")?;
 	}
+	config.trace_runtime = matches.is_present("trace-runtime");
+	if let Some(v) = matches.value_of("state-cache-size") {
+		let mb: usize = v.parse().map_err(|_| "Invalid --state-cache-size argument")?;
+		config.state_cache_size = mb * 1024 * 1024;
+	}
 
 	if let Some(s) = matches.value_of("execution") {
 		config.execution_strategy = match s {
@@ -466,11 +480,115 @@ fn import_blocks<E>(matches: &clap::ArgMatches, exit: E) -> error::Result<()>
 	Ok(())
 }
 
+fn revert_chain(matches: &clap::ArgMatches) -> error::Result<()> {
+	let base_path = base_path(matches);
+	let (spec, _) = load_spec(&matches)?;
+	let mut config = service::Configuration::default_with_spec(spec);
+	config.database_path = db_path(&base_path, config.chain_spec.id()).to_string_lossy().into();
+	config.database_backend = database_backend(matches)?;
+	info!("DB path: {}", config.database_path);
+	let client = service::new_client(config)?;
+
+	let blocks = match matches.value_of("blocks") {
+		Some(v) => v.parse().map_err(|_| "Invalid --blocks argument")?,
+		None => 256,
+	};
+	let reverted = client.revert(blocks)?;
+	let info = client.info()?.chain;
+	info!("Reverted {} best chain blocks. Best: #{} ({})", reverted, info.best_number, info.best_hash);
+	Ok(())
+}
+
+fn check_db(matches: &clap::ArgMatches) -> error::Result<()> {
+	let base_path = base_path(matches);
+	let (spec, _) = load_spec(&matches)?;
+	let mut config = service::Configuration::default_with_spec(spec);
+	config.database_path = db_path(&base_path, config.chain_spec.id()).to_string_lossy().into();
+	config.database_backend = database_backend(matches)?;
+	info!("DB path: {}", config.database_path);
+	let client = service::new_client(config)?;
+
+	info!("Checking database");
+	let report = client.backend().check_integrity(matches.is_present("repair"))?;
+	for error in &report.errors {
+		warn!("{}", error);
+	}
+	match report.last_consistent {
+		Some((hash, number)) => info!("Checked {} blocks. Last consistent block: #{} ({})", report.checked, number, hash),
+		None => warn!("Checked {} blocks. No consistent block found", report.checked),
+	}
+	if report.repaired {
+		info!("Truncated the chain to the last consistent block");
+	} else if !report.is_consistent() {
+		return Err(format!("Found {} inconsistencies", report.errors.len()).into());
+	}
+	Ok(())
+}
+
+fn export_state(matches: &clap::ArgMatches) -> error::Result<()> {
+	let base_path = base_path(matches);
+	let (spec, _) = load_spec(&matches)?;
+	let mut config = service::Configuration::default_with_spec(spec);
+	config.database_path = db_path(&base_path, config.chain_spec.id()).to_string_lossy().into();
+	config.database_backend = database_backend(matches)?;
+	info!("DB path: {}", config.database_path);
+	let client = service::new_client(config)?;
+
+	let finalized = client.info()?.chain.finalized_number;
+	let block = match matches.value_of("at") {
+		Some(v) => v.parse().map_err(|_| "Invalid --at argument")?,
+		None => finalized,
+	};
+	if block > finalized {
+		return Err(format!("Block #{} is not finalized", block).into());
+	}
+
+	info!("Exporting state of block #{}", block);
+	let snapshot = client.state_snapshot(&BlockId::number(block))?;
+	let mut file: Box<Write> = match matches.value_of("OUTPUT") {
+		Some(filename) => Box::new(File::create(filename)?),
+		None => Box::new(stdout()),
+	};
+	file.write_all(&snapshot.encode())?;
+	info!("Exported {} storage entries", snapshot.storage.len());
+	Ok(())
+}
+
+fn import_state(matches: &clap::ArgMatches) -> error::Result<()> {
+	let (spec, _) = load_spec(&matches)?;
+	let base_path = base_path(matches);
+	let mut config = service::Configuration::default_with_spec(spec);
+	config.database_path = db_path(&base_path, config.chain_spec.id()).to_string_lossy().into();
+	config.database_backend = database_backend(matches)?;
+	let client = service::new_client(config)?;
+
+	let mut file: Box<Read> = match matches.value_of("INPUT") {
+		Some(filename) => Box::new(File::open(filename)?),
+		None => Box::new(stdin()),
+	};
+
+	let authorities = parse_authorities(matches)?;
+	let snapshot: client::StateSnapshot<_> = Decode::decode(&mut file).ok_or("Error reading file")?;
+	let number = snapshot.header.number;
+	info!("Importing state of block #{}", number);
+	client.import_state(snapshot, &authorities)?;
+	info!("Imported state. Best: #{}", client.info()?.chain.best_number);
+	Ok(())
+}
+
+fn parse_authorities(matches: &clap::ArgMatches) -> error::Result<Vec<substrate_primitives::AuthorityId>> {
+	matches.values_of("authorities").unwrap_or_default()
+		.map(|key| ed25519::Public::from_ss58check(key)
+			.map(Into::into)
+			.map_err(|_| format!("Invalid --authorities argument: {}", key).into()))
+		.collect()
+}
+
 fn run_until_exit<C, W>(
 	runtime: &mut Runtime,
 	service: service::Service<C>,
 	matches: &clap::ArgMatches,
-	sys_conf: SystemConfiguration,
+	sys_info: substrate_rpc::system::SystemInfo,
 	worker: W,
 ) -> error::Result<()>
 	where
@@ -488,13 +606,17 @@ fn run_until_exit<C, W>(
 
 		let handler = || {
 			let client = substrate_service::Service::client(&service);
+			let state = rpc::apis::state::State::new(client.clone(), executor.clone())
+				.with_tracing(matches.is_present("trace-runtime"));
 			let chain = rpc::apis::chain::Chain::new(client.clone(), executor.clone());
 			let author = rpc::apis::author::Author::new(client.clone(), service.extrinsic_pool(), executor.clone());
+			let network = substrate_service::Service::network(&service);
+			let system = rpc::apis::system::System::new(sys_info.clone(), network, service.should_have_peers());
 			rpc::rpc_handler::<service::ComponentBlock<C>, _, _, _, _>(
-				client,
+				state,
 				chain,
 				author,
-				sys_conf.clone(),
+				system,
 			)
 		};
 		(
@@ -533,6 +655,34 @@ fn parse_address(default: &str, port_param: &str, matches: &clap::ArgMatches) ->
 	Ok(address)
 }
 
+/// Parse the pruning mode: "archive", or a number of blocks to keep and/or a memory limit
+/// in megabytes (e.g. "256", "64MB", "256,64MB").
+fn parse_pruning(s: &str) -> Result<PruningMode, ()> {
+	if s == "archive" {
+		return Ok(PruningMode::ArchiveAll);
+	}
+
+	let mut constraints = PruningConstraints::default();
+	for part in s.split(',') {
+		let part = part.trim();
+		if part.ends_with("MB") {
+			let mb: usize = part[..part.len() - 2].parse().map_err(|_| ())?;
+			constraints.max_mem = Some(mb.checked_mul(1024 * 1024).ok_or(())?);
+		} else {
+			constraints.max_blocks = Some(part.parse().map_err(|_| ())?);
+		}
+	}
+	Ok(PruningMode::Constrained(constraints))
+}
+
+fn database_backend(matches: &clap::ArgMatches) -> error::Result<DatabaseBackend> {
+	match matches.value_of("db-backend") {
+		None | Some("rocksdb") => Ok(DatabaseBackend::RocksDb),
+		Some("log") => Ok(DatabaseBackend::Log),
+		Some(_) => Err(error::ErrorKind::Input("Invalid database backend specified".to_owned()).into()),
+	}
+}
+
 fn keystore_path(base_path: &Path, chain_id: &str) -> PathBuf {
 	let mut path = base_path.to_owned();
 	path.push("chains");
@@ -620,3 +770,36 @@ fn kill_color(s: &str) -> String {
 	}
 	RE.replace_all(s, "").to_string()
 }
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn with_import_state_matches<F>(args: &[&str], f: F) where
+		F: FnOnce(Result<&clap::ArgMatches, clap::Error>),
+	{
+		let yaml = load_yaml!("./cli.yml");
+		let args = ["polkadot", "import-state"].iter().chain(args.iter());
+		match clap::App::from_yaml(yaml).get_matches_from_safe(args) {
+			Ok(matches) => f(Ok(matches.subcommand_matches("import-state").expect("import-state subcommand is given; qed"))),
+			Err(e) => f(Err(e)),
+		}
+	}
+
+	#[test]
+	fn import_state_takes_trusted_authorities() {
+		let alice = ed25519::Pair::from_seed(b"Alice                           ").public();
+		let bob = ed25519::Pair::from_seed(b"Bob                             ").public();
+		let expected: Vec<substrate_primitives::AuthorityId> = vec![alice.clone().into(), bob.clone().into()];
+
+		with_import_state_matches(&["snapshot.bin"], |matches| assert!(matches.is_err()));
+		with_import_state_matches(
+			&["snapshot.bin", "--authorities", &alice.to_ss58check(), "--authorities", &bob.to_ss58check()],
+			|matches| assert_eq!(parse_authorities(matches.unwrap()).unwrap(), expected),
+		);
+		with_import_state_matches(
+			&["--authorities", "not-a-key"],
+			|matches| assert!(parse_authorities(matches.unwrap()).is_err()),
+		);
+	}
+}
@@ -239,9 +239,25 @@ impl_outer_config! {
 	}
 }
 
+impl_outer_metadata! {
+	pub fn metadata {
+		Version,
+		System with storage,
+		Consensus,
+		Timestamp with storage,
+		Session with storage,
+		Staking with storage,
+		Democracy with storage,
+		Council with storage,
+		CouncilVoting with storage,
+		Parachains with storage,
+	}
+}
+
 pub mod api {
 	impl_stubs!(
 		version => |()| super::Version::version(),
+		metadata => |()| super::metadata(),
 		authorities => |()| super::Consensus::authorities(),
 		initialise_block => |header| super::Executive::initialise_block(&header),
 		apply_extrinsic => |extrinsic| super::Executive::apply_extrinsic(extrinsic),
@@ -249,7 +265,8 @@ pub mod api {
 		finalise_block => |()| super::Executive::finalise_block(),
 		inherent_extrinsics => |(timestamp, heads)| super::inherent_extrinsics(timestamp, heads),
 		validator_count => |()| super::Session::validator_count(),
-		validators => |()| super::Session::validators()
+		validators => |()| super::Session::validators(),
+		offchain_worker => |_number: super::BlockNumber| ()
 	);
 }
 
@@ -270,7 +287,7 @@ mod tests {
 			number: 67,
 			state_root: 3.into(),
 			extrinsics_root: 6.into(),
-			digest: { let mut d = Digest::default(); d.push(Log(vec![1])); d },
+			digest: { let mut d = Digest::default(); d.push(generic::DigestItem::Other(vec![1])); d },
 		};
 
 		assert_eq!(ser::to_string_pretty(&header), r#"{
@@ -402,4 +419,26 @@ mod tests {
 		let _register = PrivCall::Parachains(parachains::PrivCall::register_parachain(0.into(), vec![1, 2, 3], vec![]));
 		let _deregister = PrivCall::Parachains(parachains::PrivCall::deregister_parachain(0.into()));
 	}
+
+	#[test]
+	fn metadata_describes_calls_and_storage() {
+		let metadata = metadata();
+		{
+			let module = |name: &[u8]| metadata.modules.iter().find(|m| m.name == name).unwrap();
+
+			let staking = module(&b"Staking"[..]);
+			let call = staking.calls.iter().find(|c| c.name == &b"Call"[..]).unwrap();
+			assert_eq!(call.functions[0].id, 0);
+			assert_eq!(call.functions[0].name, b"transfer".to_vec());
+			assert_eq!(call.functions[0].arguments.iter().map(|a| a.name.clone()).collect::<Vec<_>>(), vec![b"dest".to_vec(), b"value".to_vec()]);
+			assert!(staking.storage.iter().any(|s| s.name == &b"CurrentEra"[..] && s.prefix == &b"sta:era"[..]));
+
+			let consensus = module(&b"Consensus"[..]);
+			assert_eq!(consensus.calls.len(), 2);
+			assert!(consensus.storage.is_empty());
+		}
+
+		let encoded = metadata.encode();
+		assert_eq!(substrate_runtime_support::metadata::RuntimeMetadata::decode(&mut &encoded[..]), Some(metadata));
+	}
 }
@@ -214,7 +214,7 @@ impl<T: Trait> Default for GenesisConfig<T> {
 #[cfg(any(feature = "std", test))]
 impl<T: Trait> runtime_primitives::BuildStorage for GenesisConfig<T>
 {
-	fn build_storage(mut self) -> ::std::result::Result<runtime_io::TestExternalities, String> {
+	fn build_storage(mut self) -> ::std::result::Result<runtime_primitives::StorageMap, String> {
 		use std::collections::HashMap;
 		use codec::Encode;
 
@@ -232,7 +232,7 @@ impl<T: Trait> runtime_primitives::BuildStorage for GenesisConfig<T>
 			map.insert(key, code.encode());
 		}
 
-		Ok(map.into())
+		Ok(map)
 	}
 }
 
@@ -281,7 +281,7 @@ mod tests {
 	type Parachains = Module<Test>;
 
 	fn new_test_ext(parachains: Vec<(Id, Vec<u8>)>) -> runtime_io::TestExternalities {
-		let mut t = system::GenesisConfig::<Test>::default().build_storage().unwrap();
+		let mut t: runtime_io::TestExternalities = system::GenesisConfig::<Test>::default().build_storage().unwrap().into();
 		t.extend(consensus::GenesisConfig::<Test>{
 			code: vec![],
 			authorities: vec![1, 2, 3],
@@ -38,14 +38,14 @@ mod error;
 
 use std::{
 	cmp::Ordering,
-	collections::HashMap,
+	collections::{HashMap, HashSet},
 	ops::Deref,
 	sync::Arc,
 };
 
 use codec::{Decode, Encode};
 use extrinsic_pool::{
-	api::{ExtrinsicPool, EventStream},
+	api::{ExtrinsicPool, EventStream, PendingExtrinsic},
 	txpool::{self, Readiness, scoring::{Change, Choice}},
 	watcher::Watcher,
 	Pool,
@@ -437,6 +437,28 @@ impl<A> ExtrinsicPool<FutureProofUncheckedExtrinsic, BlockId, Hash> for Transact
 	fn import_notification_stream(&self) -> EventStream {
 		self.inner.import_notification_stream()
 	}
+
+	fn pending(&self, block: BlockId) -> Result<Vec<PendingExtrinsic<Hash>>> {
+		let ready = Ready::create(block, &*self.api);
+		let ready: HashSet<Hash> = self.inner.pending(ready, |pending| pending.map(|tx| *tx.hash()).collect());
+
+		Ok(self.inner.pending(|_: &VerifiedTransaction| Readiness::Ready, |all| all
+			.map(|tx| PendingExtrinsic {
+				hash: *tx.hash(),
+				sender: tx.sender().map(|sender| sender.encode()),
+				index: tx.index() as u64,
+				is_ready: ready.contains(tx.hash()),
+			})
+			.collect()
+		))
+	}
+
+	fn remove(&self, hashes: &[Hash], is_valid: bool) -> Vec<Hash> {
+		self.inner.remove(hashes, is_valid)
+			.into_iter()
+			.filter_map(|tx| tx.map(|tx| *tx.hash()))
+			.collect()
+	}
 }
 
 #[cfg(test)]
@@ -730,4 +752,25 @@ mod tests {
 		assert_eq!(pending, vec![]);
 
 	}
+
+	#[test]
+	fn pending_lists_ready_and_future_extrinsics() {
+		use extrinsic_pool::api::{ExtrinsicPool, PendingExtrinsic};
+
+		let api = TestPolkadotApi::default();
+		let pool = pool(&api);
+		let ready = pool.import_unchecked_extrinsic(BlockId::number(0), uxt(Alice, 209, true)).unwrap();
+		let future = pool.import_unchecked_extrinsic(BlockId::number(0), uxt(Alice, 211, true)).unwrap();
+		let sender = Some(AccountId::from(Alice.to_raw_public()).encode());
+
+		assert_eq!(ExtrinsicPool::pending(&pool, BlockId::number(0)).unwrap(), vec![
+			PendingExtrinsic { hash: *ready.hash(), sender: sender.clone(), index: 209, is_ready: true },
+			PendingExtrinsic { hash: *future.hash(), sender: sender.clone(), index: 211, is_ready: false },
+		]);
+
+		assert_eq!(ExtrinsicPool::remove(&pool, &[*future.hash(), Hash::default()], false), vec![*future.hash()]);
+		assert_eq!(ExtrinsicPool::pending(&pool, BlockId::number(0)).unwrap(), vec![
+			PendingExtrinsic { hash: *ready.hash(), sender, index: 209, is_ready: true },
+		]);
+	}
 }
@@ -0,0 +1,509 @@
+// Copyright 2018 Parity Technologies (UK) Ltd.
+// This file is part of Substrate.
+
+// Substrate is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// Substrate is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with Substrate.  If not, see <http://www.gnu.org/licenses/>.
+
+//! Database integrity check.
+//!
+//! Walks the canonical chain from the highest indexed block down to genesis and checks that
+//! every block has a header matching its hash and number, a body matching its extrinsics root
+//! and, for blocks which are not finalized yet, a state root which is present in the state
+//! database. Blocks below the finalized one may have their state pruned. The chain of a node
+//! started from a state snapshot ends at the imported block, followed by the genesis block.
+
+use std::collections::{HashMap, HashSet};
+use kvdb::DBTransaction;
+use codec::{Decode, Encode};
+use primitives::H256;
+use runtime_primitives::generic::BlockId;
+use runtime_primitives::traits::{Block as BlockT, Header as HeaderT, Hash as HashT, As};
+use client;
+use client::blockchain::HeaderBackend;
+use utils::{children_key, db_err, meta_keys, number_to_db_key, read_children, read_id};
+use {apply_state_commit, columns, Backend};
+
+/// Outcome of a database integrity check.
+#[derive(Debug)]
+pub struct IntegrityReport<Block: BlockT> {
+	/// Number of canonical blocks checked.
+	pub checked: u64,
+	/// Descriptions of the inconsistencies found.
+	pub errors: Vec<String>,
+	/// The highest canonical block which is consistent along with all of its ancestors.
+	pub last_consistent: Option<(Block::Hash, <Block::Header as HeaderT>::Number)>,
+	/// Whether the canonical chain has been truncated back to `last_consistent`.
+	pub repaired: bool,
+}
+
+impl<Block: BlockT> IntegrityReport<Block> {
+	/// Whether no inconsistencies were found.
+	pub fn is_consistent(&self) -> bool {
+		self.errors.is_empty()
+	}
+}
+
+impl<Block: BlockT> Backend<Block> {
+	/// Check the consistency of the canonical chain. If `repair` is set and inconsistencies
+	/// are found, the canonical chain is truncated back to the last consistent block, which
+	/// becomes the best block. Blocks which are already finalized can not be truncated.
+	pub fn check_integrity(&self, repair: bool) -> Result<IntegrityReport<Block>, client::error::Error> {
+		let (best_number, finalized_hash, finalized_number) = {
+			let meta = self.blockchain.meta.read();
+			(meta.best_number.as_(), meta.finalized_hash, meta.finalized_number.as_())
+		};
+		let mut top = best_number;
+		while self.canonical_hash(top + 1)?.is_some() {
+			top += 1;
+		}
+
+		let mut errors = Vec::new();
+		self.check_meta_pointer(&mut errors, "best", meta_keys::BEST_BLOCK, top)?;
+		self.check_meta_pointer(&mut errors, "finalized", meta_keys::FINALIZED_BLOCK, finalized_number)?;
+
+		let mut checked = 0;
+		let mut last_consistent = None;
+		let mut number = top;
+		let mut child_parent: Option<Block::Hash> = None;
+		loop {
+			let mut problems = Vec::new();
+			let hash = self.canonical_hash(number)?;
+			let header = match hash {
+				Some(hash) => {
+					if let Some(parent_hash) = child_parent {
+						if parent_hash != hash {
+							problems.push(format!("Block #{} ({}) is not the parent of block #{}", number, hash, number + 1));
+						}
+					}
+					self.check_block(&mut problems, hash, number, finalized_number)?
+				},
+				None => {
+					problems.push(format!("Block #{} is missing from the block index", number));
+					None
+				},
+			};
+			checked += 1;
+			child_parent = header.as_ref().map(|header| *header.parent_hash());
+
+			match hash {
+				Some(hash) if problems.is_empty() => if last_consistent.is_none() {
+					last_consistent = Some((hash, number));
+				},
+				_ => {
+					errors.extend(problems);
+					last_consistent = None;
+				},
+			}
+
+			if number == 0 {
+				break;
+			}
+			// the parent of a block imported from a state snapshot is not stored
+			let is_starting_block = match child_parent {
+				Some(parent_hash) => self.canonical_hash(number - 1)?.is_none()
+					&& self.blockchain.header(BlockId::Hash(parent_hash))?.is_none(),
+				None => false,
+			};
+			if is_starting_block {
+				number = 0;
+				child_parent = None;
+			} else {
+				number -= 1;
+			}
+		}
+
+		let mut report = IntegrityReport {
+			checked,
+			errors,
+			last_consistent: last_consistent.map(|(hash, number)| (hash, As::sa(number))),
+			repaired: false,
+		};
+		if repair && !report.is_consistent() {
+			let (hash, number) = last_consistent
+				.ok_or_else(|| client::error::ErrorKind::Backend("No consistent block to truncate to".into()))?;
+			if number < finalized_number {
+				return Err(client::error::ErrorKind::Backend(
+					format!("Can not truncate below the finalized block #{} ({})", finalized_number, finalized_hash)).into());
+			}
+			self.truncate(hash, number, top)?;
+			report.repaired = true;
+		}
+		Ok(report)
+	}
+
+	fn canonical_hash(&self, number: u64) -> Result<Option<Block::Hash>, client::error::Error> {
+		read_id::<Block>(&*self.storage.db, columns::BLOCK_INDEX, BlockId::Number(As::sa(number)))
+	}
+
+	// Check that the block pointer stored under `key` points to the canonical block `number`.
+	fn check_meta_pointer(&self, errors: &mut Vec<String>, desc: &str, key: &[u8], number: u64) -> Result<(), client::error::Error> {
+		let stored = self.storage.db.get(columns::META, key).map_err(db_err)?;
+		let canonical = self.canonical_hash(number)?;
+		match (stored, canonical) {
+			(Some(stored), Some(canonical)) => if &stored[..] != canonical.as_ref() {
+				errors.push(format!("The {} block pointer does not point to block #{} ({})", desc, number, canonical));
+			},
+			(None, Some(_)) if number == 0 => {},
+			(None, _) => errors.push(format!("The {} block pointer is missing", desc)),
+			(Some(_), None) => errors.push(format!("The {} block pointer points to a block outside of the block index", desc)),
+		}
+		Ok(())
+	}
+
+	// Check the stored data of block `number` against its hash. Returns the header if it can be read.
+	fn check_block(
+		&self,
+		problems: &mut Vec<String>,
+		hash: Block::Hash,
+		number: u64,
+		finalized_number: u64,
+	) -> Result<Option<Block::Header>, client::error::Error> {
+		let header = match self.storage.db.get(columns::HEADER, hash.as_ref()).map_err(db_err)? {
+			Some(header) => match Block::Header::decode(&mut &header[..]) {
+				Some(header) => header,
+				None => {
+					problems.push(format!("Header of block #{} ({}) is corrupted", number, hash));
+					return Ok(None);
+				},
+			},
+			None => {
+				problems.push(format!("Header of block #{} ({}) is missing", number, hash));
+				return Ok(None);
+			},
+		};
+
+		if header.hash() != hash {
+			problems.push(format!("Header of block #{} hashes to {} instead of {}", number, header.hash(), hash));
+		}
+		if header.number().as_() != number {
+			problems.push(format!("Header of block #{} ({}) has number {}", number, hash, header.number()));
+		}
+
+		if let Some(body) = self.storage.db.get(columns::BODY, hash.as_ref()).map_err(db_err)? {
+			match Vec::<Block::Extrinsic>::decode(&mut &body[..]) {
+				Some(body) => {
+					let extrinsics_root = <<Block::Header as HeaderT>::Hashing as HashT>::ordered_trie_root(
+						body.iter().map(Encode::encode)
+					);
+					if extrinsics_root != *header.extrinsics_root() {
+						problems.push(format!("Body of block #{} ({}) does not match its extrinsics root", number, hash));
+					}
+				},
+				None => problems.push(format!("Body of block #{} ({}) is corrupted", number, hash)),
+			}
+		}
+
+		// the root of an empty trie is not stored
+		let empty_root = <<Block::Header as HeaderT>::Hashing as HashT>::trie_root(::std::iter::empty::<(&[u8], &[u8])>());
+		if number >= finalized_number && *header.state_root() != empty_root {
+			let state_root = H256::from_slice(header.state_root().as_ref());
+			match self.storage.state_db.get(&state_root, &*self.storage) {
+				Ok(Some(_)) => {},
+				Ok(None) => problems.push(format!("State of block #{} ({}) is missing", number, hash)),
+				Err(e) => problems.push(format!("State of block #{} ({}) can not be read: {:?}", number, hash, e)),
+			}
+		}
+
+		Ok(Some(header))
+	}
+
+	// Make the canonical block `number` the best block, removing all blocks above it up to the
+	// highest canonical block `top`, along with their forks and unfinalized state, in a single
+	// transaction.
+	fn truncate(&self, hash: Block::Hash, number: u64, top: u64) -> Result<(), client::error::Error> {
+		let mut transaction = DBTransaction::new();
+		let mut leaves = self.blockchain.leaves.read().clone();
+
+		let mut removed = HashSet::new();
+		for n in (number + 1)..(top + 1) {
+			if let Some(truncated_hash) = self.canonical_hash(n)? {
+				removed.insert(truncated_hash);
+			}
+			transaction.delete(columns::BLOCK_INDEX, &number_to_db_key(n));
+		}
+		let truncated = removed.len();
+
+		// walk the forks down from their leaves; the state database keeps a level for each number
+		let mut highest = top;
+		let mut parents = HashSet::new();
+		parents.insert(hash);
+		let mut parents_by_child = None;
+		for leaf in leaves.hashes() {
+			// blocks from the leaf down along with their parent, and the number of one of them
+			let mut walked = Vec::new();
+			let mut known_number = None;
+			let mut current = leaf;
+			loop {
+				let parent = match self.stored_header(current)? {
+					Some(header) => {
+						let current_number = header.number().as_();
+						known_number = Some((walked.len(), current_number));
+						if current_number <= number {
+							break;
+						}
+						Some(*header.parent_hash())
+					},
+					// walk past a missing header with the children index
+					None => {
+						if parents_by_child.is_none() {
+							parents_by_child = Some(self.parents_by_child()?);
+						}
+						parents_by_child.as_ref().and_then(|parents| parents.get(&current).cloned())
+					},
+				};
+				walked.push((current, parent));
+				match parent {
+					Some(parent) => current = parent,
+					None => break,
+				}
+			}
+
+			let (known_index, known_number) = match known_number {
+				Some(known) => known,
+				None => {
+					warn!("Can not tell the numbers of blocks without headers from {} down to {}", leaf, current);
+					continue;
+				},
+			};
+			for (index, (walked_hash, parent)) in walked.into_iter().enumerate() {
+				let walked_number = known_number + known_index as u64 - index as u64;
+				if walked_number <= number {
+					break;
+				}
+				highest = ::std::cmp::max(highest, walked_number);
+				removed.insert(walked_hash);
+				if walked_number == number + 1 {
+					parents.extend(parent);
+				}
+			}
+		}
+
+		for _ in number..highest {
+			match self.storage.state_db.revert_one() {
+				Some(commit) => apply_state_commit(&mut transaction, commit),
+				None => break,
+			}
+		}
+
+		for removed_hash in &removed {
+			leaves.remove(removed_hash);
+			transaction.delete(columns::HEADER, removed_hash.as_ref());
+			transaction.delete(columns::BODY, removed_hash.as_ref());
+			transaction.delete(columns::JUSTIFICATION, removed_hash.as_ref());
+			transaction.delete(columns::META, &children_key(removed_hash));
+		}
+		for parent_hash in parents {
+			let children: Vec<_> = read_children::<Block>(&*self.storage.db, &parent_hash)?
+				.into_iter()
+				.filter(|child| !removed.contains(child))
+				.collect();
+			if !children.is_empty() {
+				transaction.put(columns::META, &children_key(&parent_hash), &children.encode());
+				continue;
+			}
+			transaction.delete(columns::META, &children_key(&parent_hash));
+			if let Some(parent) = self.stored_header(parent_hash)? {
+				leaves.import(parent_hash, As::sa(number), *parent.parent_hash());
+			}
+		}
+		transaction.put(columns::META, meta_keys::LEAVES, &leaves.encode());
+		transaction.put(columns::META, meta_keys::BEST_BLOCK, hash.as_ref());
+
+		warn!("Truncating the canonical chain to #{} ({}), {} canonical and {} other blocks removed",
+			number, hash, truncated, removed.len() - truncated);
+		self.storage.db.write(transaction).map_err(db_err)?;
+		self.blockchain.update_meta(hash, As::sa(number), true);
+		*self.blockchain.leaves.write() = leaves;
+		self.shared_cache.lock().sync(hash, Default::default(), None);
+		Ok(())
+	}
+
+	// Read the parent of every block in the children index.
+	fn parents_by_child(&self) -> Result<HashMap<Block::Hash, Block::Hash>, client::error::Error> {
+		let mut parents = HashMap::new();
+		let prefix = &meta_keys::CHILDREN_PREFIX[..];
+		for (key, children) in self.storage.db.iter_from_prefix(columns::META, prefix) {
+			let parent = Block::Hash::decode(&mut &key[prefix.len()..]);
+			let children = Vec::<Block::Hash>::decode(&mut &children[..]);
+			match (parent, children) {
+				(Some(parent), Some(children)) => parents.extend(children.into_iter().map(|child| (child, parent))),
+				_ => return Err(client::error::ErrorKind::Backend("Error decoding children".into()).into()),
+			}
+		}
+		Ok(parents)
+	}
+
+	// Read the header of the block with given hash, if it is stored and can be decoded.
+	fn stored_header(&self, hash: Block::Hash) -> Result<Option<Block::Header>, client::error::Error> {
+		Ok(self.storage.db.get(columns::HEADER, hash.as_ref()).map_err(db_err)?
+			.and_then(|header| Block::Header::decode(&mut &header[..])))
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use std::sync::Arc;
+	use kvdb::KeyValueDB;
+	use runtime_primitives::testing::{Header, Block as RawBlock};
+	use runtime_primitives::traits::BlakeTwo256;
+	use client::backend::{Backend as BTrait, BlockImportOperation as Op};
+	use client::blockchain::Backend as BlockchainBackend;
+	use state_machine::backend::Backend as StateBackend;
+	use PruningMode;
+	use super::*;
+
+	type Block = RawBlock<u64>;
+
+	fn insert_block(backend: &Backend<Block>, number: u64, parent_hash: H256) -> H256 {
+		let mut op = backend.begin_operation(BlockId::Hash(parent_hash)).unwrap();
+		let (root, update) = op.old_state.storage_root(vec![(vec![number as u8], Some(vec![number as u8]))]);
+		op.update_storage(update).unwrap();
+		let header = Header {
+			number,
+			parent_hash,
+			state_root: root.into(),
+			digest: Default::default(),
+			extrinsics_root: BlakeTwo256::ordered_trie_root(vec![number.encode()]),
+		};
+		let hash = header.hash();
+		op.set_block_data(header, Some(vec![number]), None, true).unwrap();
+		backend.commit_operation(op).unwrap();
+		hash
+	}
+
+	fn insert_fork_block(backend: &Backend<Block>, number: u64, parent_hash: H256) -> H256 {
+		let mut op = backend.begin_operation(BlockId::Hash(parent_hash)).unwrap();
+		let header = Header {
+			number,
+			parent_hash,
+			state_root: Default::default(),
+			digest: Default::default(),
+			extrinsics_root: BlakeTwo256::ordered_trie_root(Vec::<Vec<u8>>::new()),
+		};
+		let hash = header.hash();
+		op.set_block_data(header, Some(vec![]), None, false).unwrap();
+		backend.commit_operation(op).unwrap();
+		hash
+	}
+
+	fn chain(length: u64) -> (Arc<KeyValueDB>, Backend<Block>, Vec<H256>) {
+		let db = Arc::new(::kvdb_memorydb::create(::utils::NUM_COLUMNS));
+		let backend = Backend::<Block>::from_kvdb(db.clone() as Arc<_>, PruningMode::keep_blocks(0), 10, 16 * 1024).unwrap();
+		let mut hashes = Vec::new();
+		for number in 0..length {
+			let parent_hash = hashes.last().cloned().unwrap_or_default();
+			hashes.push(insert_block(&backend, number, parent_hash));
+		}
+		(db, backend, hashes)
+	}
+
+	#[test]
+	fn consistent_chain_passes() {
+		let (_, backend, hashes) = chain(5);
+		let report = backend.check_integrity(true).unwrap();
+		assert!(report.is_consistent());
+		assert_eq!(report.checked, 5);
+		assert_eq!(report.last_consistent, Some((hashes[4], 4)));
+		assert!(!report.repaired);
+	}
+
+	#[test]
+	fn truncates_to_last_consistent_block() {
+		let (db, backend, hashes) = chain(5);
+		let mut transaction = DBTransaction::new();
+		transaction.put(columns::BODY, hashes[3].as_ref(), &vec![42u64].encode());
+		db.write(transaction).unwrap();
+
+		let report = backend.check_integrity(false).unwrap();
+		assert_eq!(report.errors.len(), 1);
+		assert_eq!(report.last_consistent, Some((hashes[2], 2)));
+		assert_eq!(backend.blockchain().info().unwrap().best_hash, hashes[4]);
+
+		let report = backend.check_integrity(true).unwrap();
+		assert!(report.repaired);
+		let info = backend.blockchain().info().unwrap();
+		assert_eq!((info.best_hash, info.best_number), (hashes[2], 2));
+		assert_eq!(backend.blockchain().hash(3).unwrap(), None);
+		assert_eq!(backend.blockchain().leaves().unwrap(), vec![hashes[2]]);
+		assert!(db.get(columns::HEADER, hashes[3].as_ref()).unwrap().is_none());
+		assert!(db.get(columns::BODY, hashes[4].as_ref()).unwrap().is_none());
+
+		let report = backend.check_integrity(false).unwrap();
+		assert!(report.is_consistent());
+		assert_eq!(report.checked, 3);
+
+		// the chain continues on top of the last consistent block
+		insert_block(&backend, 3, hashes[2]);
+		assert!(backend.check_integrity(false).unwrap().is_consistent());
+	}
+
+	#[test]
+	fn truncation_removes_forks_above_last_consistent_block() {
+		let (db, backend, hashes) = chain(4);
+		let fork = insert_fork_block(&backend, 3, hashes[2]);
+		let fork_child = insert_fork_block(&backend, 4, fork);
+		let mut transaction = DBTransaction::new();
+		transaction.delete(columns::HEADER, hashes[2].as_ref());
+		db.write(transaction).unwrap();
+
+		let report = backend.check_integrity(true).unwrap();
+		assert!(report.repaired);
+		assert_eq!(backend.blockchain().info().unwrap().best_hash, hashes[1]);
+		assert_eq!(backend.blockchain().leaves().unwrap(), vec![hashes[1]]);
+		for hash in &[hashes[3], fork, fork_child] {
+			assert!(db.get(columns::HEADER, hash.as_ref()).unwrap().is_none());
+		}
+
+		// the reverted levels of the state database can be filled again
+		let block2 = insert_block(&backend, 2, hashes[1]);
+		insert_block(&backend, 3, block2);
+		assert!(backend.check_integrity(false).unwrap().is_consistent());
+	}
+
+	#[test]
+	fn detects_broken_meta_and_refuses_to_truncate_finalized_blocks() {
+		let (db, backend, hashes) = chain(4);
+		backend.finalize_block(BlockId::Number(2)).unwrap();
+		let mut transaction = DBTransaction::new();
+		transaction.put(columns::META, meta_keys::BEST_BLOCK, hashes[1].as_ref());
+		transaction.delete(columns::HEADER, hashes[1].as_ref());
+		db.write(transaction).unwrap();
+
+		let report = backend.check_integrity(false).unwrap();
+		assert_eq!(report.errors.len(), 2);
+		assert_eq!(report.last_consistent, Some((hashes[0], 0)));
+		assert!(backend.check_integrity(true).is_err());
+	}
+
+	#[test]
+	fn truncation_walks_past_missing_fork_headers() {
+		let (db, backend, hashes) = chain(4);
+		let fork2 = insert_fork_block(&backend, 2, hashes[1]);
+		let fork3 = insert_fork_block(&backend, 3, fork2);
+		let fork4 = insert_fork_block(&backend, 4, fork3);
+		let mut transaction = DBTransaction::new();
+		transaction.delete(columns::HEADER, hashes[2].as_ref());
+		transaction.delete(columns::HEADER, fork3.as_ref());
+		db.write(transaction).unwrap();
+
+		let report = backend.check_integrity(true).unwrap();
+		assert!(report.repaired);
+		assert_eq!(backend.blockchain().info().unwrap().best_hash, hashes[1]);
+		assert_eq!(backend.blockchain().leaves().unwrap(), vec![hashes[1]]);
+		assert!(backend.blockchain().children(hashes[1]).unwrap().is_empty());
+		for hash in &[hashes[2], hashes[3], fork2, fork3, fork4] {
+			assert!(db.get(columns::HEADER, hash.as_ref()).unwrap().is_none());
+			assert!(db.get(columns::BODY, hash.as_ref()).unwrap().is_none());
+		}
+	}
+}
@@ -18,7 +18,9 @@
 
 extern crate substrate_client as client;
 extern crate kvdb_rocksdb;
+extern crate kvdb_memorydb;
 extern crate kvdb;
+extern crate linked_hash_map;
 extern crate hashdb;
 extern crate memorydb;
 extern crate parking_lot;
@@ -34,12 +36,20 @@ extern crate substrate_state_db as state_db;
 extern crate log;
 
 #[cfg(test)]
-extern crate kvdb_memorydb;
+extern crate tempdir;
+#[cfg(test)]
+extern crate substrate_keyring as keyring;
+#[cfg(test)]
+extern crate substrate_test_client as test_client;
 
 pub mod light;
 
+mod check;
+mod log_db;
+mod storage_cache;
 mod utils;
 
+use std::collections::HashMap;
 use std::sync::Arc;
 use std::path::PathBuf;
 
@@ -50,20 +60,49 @@ use parking_lot::RwLock;
 use primitives::H256;
 use runtime_primitives::generic::BlockId;
 use runtime_primitives::bft::Justification;
-use runtime_primitives::traits::{Block as BlockT, Header as HeaderT, As, Hash, HashFor, Zero};
+use runtime_primitives::traits::{Block as BlockT, Header as HeaderT, Digest, NumberFor, As, Zero, One};
 use runtime_primitives::BuildStorage;
 use state_machine::backend::Backend as StateBackend;
 use executor::RuntimeInfo;
 use state_machine::{CodeExecutor, TrieH256, DBValue, ExecutionStrategy};
-use utils::{Meta, db_err, meta_keys, number_to_db_key, open_database, read_db, read_id, read_meta};
+use utils::{Meta, children_key, db_err, meta_keys, number_to_db_key, open_database, read_children, read_db,
+	read_id, read_leaves, read_meta};
 use state_db::StateDb;
-pub use state_db::PruningMode;
+use storage_cache::{CachingState, CachingTransaction, SharedCache, StorageChanges, new_shared_cache};
+use client::leaves::LeafSet;
+pub use state_db::{Constraints as PruningConstraints, PruningMode};
+pub use check::IntegrityReport;
 
 const FINALIZATION_WINDOW: u64 = 32;
 
 /// DB-backed patricia trie state, transaction type is an overlay of changes to commit.
 pub type DbState = state_machine::TrieBackend;
 
+/// Key-value store backing the database.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DatabaseBackend {
+	/// RocksDB.
+	RocksDb,
+	/// Pure-Rust store which keeps all data in memory and appends all changes to a log file.
+	Log,
+}
+
+impl DatabaseBackend {
+	/// Identifier of the backend, as recorded in the database.
+	pub fn id(&self) -> &'static str {
+		match *self {
+			DatabaseBackend::RocksDb => "rocksdb",
+			DatabaseBackend::Log => "log",
+		}
+	}
+}
+
+impl Default for DatabaseBackend {
+	fn default() -> Self {
+		DatabaseBackend::RocksDb
+	}
+}
+
 /// Database settings.
 pub struct DatabaseSettings {
 	/// Cache size in bytes. If `None` default is used.
@@ -72,12 +111,18 @@ pub struct DatabaseSettings {
 	pub path: PathBuf,
 	/// Pruning mode.
 	pub pruning: PruningMode,
+	/// Size of the state cache in bytes. Ignored by the light storage.
+	pub state_cache_size: usize,
+	/// Key-value store backing the database.
+	pub backend: DatabaseBackend,
 }
 
-/// Create an instance of db-backed client.
+/// Create an instance of db-backed client. `call_executor` executes calls which are not
+/// a part of block execution.
 pub fn new_client<E, S, Block>(
 	settings: DatabaseSettings,
 	executor: E,
+	call_executor: E,
 	genesis_storage: S,
 	execution_strategy: ExecutionStrategy,
 ) -> Result<client::Client<Backend<Block>, client::LocalCallExecutor<Backend<Block>, E>, Block>, client::error::Error>
@@ -87,7 +132,8 @@ pub fn new_client<E, S, Block>(
 		S: BuildStorage,
 {
 	let backend = Arc::new(Backend::new(settings, FINALIZATION_WINDOW)?);
-	let executor = client::LocalCallExecutor::new(backend.clone(), executor);
+	let executor = client::LocalCallExecutor::new(backend.clone(), executor)
+		.with_call_executor(call_executor);
 	Ok(client::Client::new(backend, executor, genesis_storage, execution_strategy)?)
 }
 
@@ -99,6 +145,8 @@ mod columns {
 	pub const HEADER: Option<u32> = Some(4);
 	pub const BODY: Option<u32> = Some(5);
 	pub const JUSTIFICATION: Option<u32> = Some(6);
+	pub const OFFCHAIN: Option<u32> = Some(7);
+	pub const CHANGES_TRIE: Option<u32> = Some(8);
 }
 
 struct PendingBlock<Block: BlockT> {
@@ -106,6 +154,7 @@ struct PendingBlock<Block: BlockT> {
 	justification: Option<Justification<Block::Hash>>,
 	body: Option<Vec<Block::Extrinsic>>,
 	is_best: bool,
+	is_snapshot: bool,
 }
 
 // wrapper that implements trait required for state_db
@@ -119,18 +168,42 @@ impl<'a> state_db::MetaDb for StateMetaDb<'a> {
 	}
 }
 
+/// Database-backed node-local storage of the offchain workers.
+#[derive(Clone)]
+pub struct OffchainStorage {
+	db: Arc<KeyValueDB>,
+}
+
+impl client::backend::OffchainStorage for OffchainStorage {
+	fn get(&self, key: &[u8]) -> Result<Option<Vec<u8>>, client::error::Error> {
+		self.db.get(columns::OFFCHAIN, key).map(|value| value.map(|value| value.to_vec())).map_err(db_err)
+	}
+
+	fn set(&self, key: &[u8], value: Option<&[u8]>) -> Result<(), client::error::Error> {
+		let mut transaction = DBTransaction::new();
+		match value {
+			Some(value) => transaction.put(columns::OFFCHAIN, key, value),
+			None => transaction.delete(columns::OFFCHAIN, key),
+		}
+		self.db.write(transaction).map_err(db_err)
+	}
+}
+
 /// Block database
 pub struct BlockchainDb<Block: BlockT> {
 	db: Arc<KeyValueDB>,
 	meta: RwLock<Meta<<Block::Header as HeaderT>::Number, Block::Hash>>,
+	leaves: RwLock<LeafSet<Block::Hash, <Block::Header as HeaderT>::Number>>,
 }
 
 impl<Block: BlockT> BlockchainDb<Block> {
 	fn new(db: Arc<KeyValueDB>) -> Result<Self, client::error::Error> {
-		let meta = read_meta::<Block>(&*db, columns::HEADER)?;
+		let meta = read_meta::<Block>(&*db, columns::BLOCK_INDEX, columns::HEADER)?;
+		let leaves = read_leaves::<Block>(&*db)?;
 		Ok(BlockchainDb {
 			db,
-			meta: RwLock::new(meta)
+			meta: RwLock::new(meta),
+			leaves: RwLock::new(leaves),
 		})
 	}
 
@@ -139,11 +212,18 @@ impl<Block: BlockT> BlockchainDb<Block> {
 			let mut meta = self.meta.write();
 			if number == Zero::zero() {
 				meta.genesis_hash = hash;
+				meta.finalized_hash = hash;
 			}
 			meta.best_number = number;
 			meta.best_hash = hash;
 		}
 	}
+
+	fn update_finalized_meta(&self, hash: Block::Hash, number: <Block::Header as HeaderT>::Number) {
+		let mut meta = self.meta.write();
+		meta.finalized_hash = hash;
+		meta.finalized_number = number;
+	}
 }
 
 impl<Block: BlockT> client::blockchain::HeaderBackend<Block> for BlockchainDb<Block> {
@@ -163,12 +243,14 @@ impl<Block: BlockT> client::blockchain::HeaderBackend<Block> for BlockchainDb<Bl
 			best_hash: meta.best_hash,
 			best_number: meta.best_number,
 			genesis_hash: meta.genesis_hash,
+			finalized_hash: meta.finalized_hash,
+			finalized_number: meta.finalized_number,
 		})
 	}
 
 	fn status(&self, id: BlockId<Block>) -> Result<client::blockchain::BlockStatus, client::error::Error> {
 		let exists = match id {
-			BlockId::Hash(_) => read_id(&*self.db, columns::BLOCK_INDEX, id)?.is_some(),
+			BlockId::Hash(_) => read_db(&*self.db, columns::BLOCK_INDEX, columns::HEADER, id)?.is_some(),
 			BlockId::Number(n) => n <= self.meta.read().best_number,
 		};
 		match exists {
@@ -178,9 +260,7 @@ impl<Block: BlockT> client::blockchain::HeaderBackend<Block> for BlockchainDb<Bl
 	}
 
 	fn hash(&self, number: <Block::Header as HeaderT>::Number) -> Result<Option<Block::Hash>, client::error::Error> {
-		read_db::<Block>(&*self.db, columns::BLOCK_INDEX, columns::HEADER, BlockId::Number(number)).map(|x|
-			x.map(|raw| HashFor::<Block>::hash(&raw[..])).map(Into::into)
-		)
+		read_id::<Block>(&*self.db, columns::BLOCK_INDEX, BlockId::Number(number))
 	}
 }
 
@@ -204,17 +284,26 @@ impl<Block: BlockT> client::blockchain::Backend<Block> for BlockchainDb<Block> {
 			None => Ok(None),
 		}
 	}
+
+	fn leaves(&self) -> Result<Vec<Block::Hash>, client::error::Error> {
+		Ok(self.leaves.read().hashes())
+	}
+
+	fn children(&self, parent_hash: Block::Hash) -> Result<Vec<Block::Hash>, client::error::Error> {
+		read_children::<Block>(&*self.db, &parent_hash)
+	}
 }
 
 /// Database transaction
 pub struct BlockImportOperation<Block: BlockT> {
-	old_state: DbState,
+	old_state: CachingState<Block>,
 	updates: MemoryDB,
+	storage_changes: Option<StorageChanges>,
 	pending_block: Option<PendingBlock<Block>>,
 }
 
 impl<Block: BlockT> client::backend::BlockImportOperation<Block> for BlockImportOperation<Block> {
-	type State = DbState;
+	type State = CachingState<Block>;
 
 	fn state(&self) -> Result<Option<&Self::State>, client::error::Error> {
 		Ok(Some(&self.old_state))
@@ -227,19 +316,41 @@ impl<Block: BlockT> client::backend::BlockImportOperation<Block> for BlockImport
 			body,
 			justification,
 			is_best,
+			is_snapshot: false,
 		});
 		Ok(())
 	}
 
-	fn update_storage(&mut self, update: MemoryDB) -> Result<(), client::error::Error> {
-		self.updates = update;
+	fn set_snapshot_block_data(&mut self, header: Block::Header, justification: Justification<Block::Hash>) -> Result<(), client::error::Error> {
+		assert!(self.pending_block.is_none(), "Only one block per operation is allowed");
+		self.pending_block = Some(PendingBlock {
+			header,
+			body: None,
+			justification: Some(justification),
+			is_best: true,
+			is_snapshot: true,
+		});
+		Ok(())
+	}
+
+	fn update_storage(&mut self, update: CachingTransaction) -> Result<(), client::error::Error> {
+		self.updates = update.trie;
+		self.storage_changes = Some(update.storage);
 		Ok(())
 	}
 
 	fn reset_storage<I: Iterator<Item=(Vec<u8>, Vec<u8>)>>(&mut self, iter: I) -> Result<(), client::error::Error> {
 		// TODO: wipe out existing trie.
 		let (_, update) = self.old_state.storage_root(iter.into_iter().map(|(k, v)| (k, Some(v))));
-		self.updates = update;
+		self.updates.consolidate(update.trie);
+		// the whole state is replaced; the cache is cleared on commit
+		self.storage_changes = None;
+		Ok(())
+	}
+
+	fn reset_child_storage<I: Iterator<Item=(Vec<u8>, Vec<u8>)>>(&mut self, storage_key: &[u8], iter: I) -> Result<(), client::error::Error> {
+		let (_, _, update) = self.old_state.child_storage_root(storage_key, iter.map(|(k, v)| (k, Some(v))));
+		self.updates.consolidate(update.trie);
 		Ok(())
 	}
 }
@@ -265,6 +376,34 @@ impl<Block: BlockT> state_db::HashDb for StorageDb<Block> {
 	}
 }
 
+// changes trie nodes, kept apart from the state so that pruning never removes them
+struct ChangesTrieDb {
+	db: Arc<KeyValueDB>,
+}
+
+impl state_machine::Storage for ChangesTrieDb {
+	fn get(&self, key: &TrieH256) -> Result<Option<DBValue>, String> {
+		self.db.get(columns::CHANGES_TRIE, &key[..])
+			.map_err(|e| format!("Database backend error: {:?}", e))
+	}
+}
+
+// changes trie nodes of a block being imported: they are part of its state changes, unless
+// they cancel out with a deleted state node of the same hash
+struct ImportedChangesTrie<Block: BlockT> {
+	updates: MemoryDB,
+	state: Arc<StorageDb<Block>>,
+}
+
+impl<Block: BlockT> state_machine::Storage for ImportedChangesTrie<Block> {
+	fn get(&self, key: &TrieH256) -> Result<Option<DBValue>, String> {
+		match hashdb::HashDB::get(&self.updates, key) {
+			Some(value) => Ok(Some(value)),
+			None => state_machine::Storage::get(&*self.state, key),
+		}
+	}
+}
+
 
 /// Disk backend. Keeps data in a key-value store. In archive mode, trie nodes are kept from all blocks.
 /// Otherwise, trie nodes are kept only from the most recent block.
@@ -272,6 +411,7 @@ pub struct Backend<Block: BlockT> {
 	storage: Arc<StorageDb<Block>>,
 	blockchain: BlockchainDb<Block>,
 	finalization_window: u64,
+	shared_cache: SharedCache<Block>,
 }
 
 impl<Block: BlockT> Backend<Block> {
@@ -279,7 +419,7 @@ impl<Block: BlockT> Backend<Block> {
 	pub fn new(config: DatabaseSettings, finalization_window: u64) -> Result<Self, client::error::Error> {
 		let db = open_database(&config, "full")?;
 
-		Backend::from_kvdb(db as Arc<_>, config.pruning, finalization_window)
+		Backend::from_kvdb(db as Arc<_>, config.pruning, finalization_window, config.state_cache_size)
 	}
 
 	#[cfg(test)]
@@ -288,13 +428,29 @@ impl<Block: BlockT> Backend<Block> {
 
 		let db = Arc::new(::kvdb_memorydb::create(NUM_COLUMNS));
 
-		Backend::from_kvdb(db as Arc<_>, PruningMode::keep_blocks(0), 0).expect("failed to create test-db")
+		Backend::from_kvdb(db as Arc<_>, PruningMode::keep_blocks(0), 0, 16 * 1024).expect("failed to create test-db")
 	}
 
-	fn from_kvdb(db: Arc<KeyValueDB>, pruning: PruningMode, finalization_window: u64) -> Result<Self, client::error::Error> {
+	fn from_kvdb(
+		db: Arc<KeyValueDB>,
+		pruning: PruningMode,
+		finalization_window: u64,
+		state_cache_size: usize,
+	) -> Result<Self, client::error::Error> {
 		let blockchain = BlockchainDb::new(db.clone())?;
-		let map_e = |e: state_db::Error<kvdb::Error>| ::client::error::Error::from(format!("State database error: {:?}", e));
-		let state_db: StateDb<Block::Hash, H256> = StateDb::new(pruning, &StateMetaDb(&*db)).map_err(map_e)?;
+		let state_db: StateDb<Block::Hash, H256> = StateDb::new(pruning, &StateMetaDb(&*db)).map_err(state_db_err)?;
+
+		// databases created before the last finalized block was recorded have finalized the
+		// blocks canonicalized by the state db
+		if db.get(columns::META, meta_keys::FINALIZED_BLOCK).map_err(db_err)?.is_none() {
+			if let Some((hash, number)) = state_db.last_finalized() {
+				let mut transaction = DBTransaction::new();
+				transaction.put(columns::META, meta_keys::FINALIZED_BLOCK, hash.as_ref());
+				db.write(transaction).map_err(db_err)?;
+				blockchain.update_finalized_meta(hash, As::sa(number));
+			}
+		}
+
 		let storage_db = StorageDb {
 			db,
 			state_db,
@@ -304,8 +460,101 @@ impl<Block: BlockT> Backend<Block> {
 			storage: Arc::new(storage_db),
 			blockchain,
 			finalization_window,
+			shared_cache: new_shared_cache(state_cache_size),
 		})
 	}
+
+	// Move the nodes of the changes trie with given root out of the state changes of a block and
+	// into their own column, where state pruning doesn't reach them.
+	fn move_changes_trie(
+		&self,
+		transaction: &mut DBTransaction,
+		updates: &mut MemoryDB,
+		root: [u8; 32],
+	) -> Result<(), client::error::Error> {
+		let imported = ImportedChangesTrie { updates: updates.clone(), state: self.storage.clone() };
+		let pairs = DbState::with_storage(Arc::new(imported), root.into()).pairs();
+		let (rebuilt_root, mut nodes) = DbState::with_memorydb(MemoryDB::default(), Default::default()).build_trie(pairs);
+		if rebuilt_root != root {
+			return Err(client::error::ErrorKind::Backend(format!("Missing nodes of changes trie {}", H256::from(root))).into());
+		}
+
+		for (key, (value, rc)) in nodes.drain() {
+			if rc > 0 {
+				transaction.put(columns::CHANGES_TRIE, &key[..], &value);
+			}
+			for _ in 0..rc {
+				hashdb::HashDB::remove(updates, &key);
+			}
+		}
+		Ok(())
+	}
+
+	// Finalize all canonical blocks up to and including `number` which are not finalized yet,
+	// writing the changes into `transaction`. Canonical blocks set by the same transaction are
+	// not readable from the database yet and are passed in `pending`. Returns the new finalized block.
+	fn note_finalized(
+		&self,
+		transaction: &mut DBTransaction,
+		number: u64,
+		pending: &[(Block::Hash, u64)],
+	) -> Result<Option<(Block::Hash, u64)>, client::error::Error> {
+		let finalized_number: u64 = self.blockchain.meta.read().finalized_number.as_();
+		// the state database may have canonicalized blocks before they are finalized
+		let canonical_number = self.storage.state_db.last_finalized().map_or(0, |(_, n)| n);
+		let mut finalized = None;
+		for n in (finalized_number + 1)..(number + 1) {
+			let hash = self.canonical_hash(n, pending)?;
+			if n > canonical_number {
+				trace!("Finalizing block #{} ({:?})", n, hash);
+				let commit = self.storage.state_db.finalize_block(&hash).map_err(state_db_err)?;
+				apply_state_commit(transaction, commit);
+			}
+			finalized = Some((hash, n));
+		}
+
+		if let Some((hash, _)) = finalized {
+			transaction.put(columns::META, meta_keys::FINALIZED_BLOCK, hash.as_ref());
+		}
+		Ok(finalized)
+	}
+
+	// Canonicalize the canonical blocks up to and including `best_number` in the state database
+	// while it exceeds its memory limit, writing the changes into `transaction`. The blocks are
+	// not finalized, but the state of their forks is discarded.
+	fn canonicalize_early(
+		&self,
+		transaction: &mut DBTransaction,
+		best_number: u64,
+		pending: &[(Block::Hash, u64)],
+	) -> Result<(), client::error::Error> {
+		while self.storage.state_db.max_mem_exceeded() {
+			let n = match self.storage.state_db.last_finalized() {
+				Some((_, canonical_number)) if canonical_number < best_number => canonical_number + 1,
+				_ => break,
+			};
+			let hash = self.canonical_hash(n, pending)?;
+			debug!("Canonicalizing block #{} ({:?}) early, state database memory limit exceeded", n, hash);
+			let commit = self.storage.state_db.finalize_block(&hash).map_err(state_db_err)?;
+			apply_state_commit(transaction, commit);
+		}
+		Ok(())
+	}
+
+	// Hash of the canonical block with given number, which may be set by the pending transaction.
+	fn canonical_hash(&self, number: u64, pending: &[(Block::Hash, u64)]) -> Result<Block::Hash, client::error::Error> {
+		use client::blockchain::HeaderBackend;
+
+		match pending.iter().find(|&&(_, pending_number)| pending_number == number) {
+			Some(&(hash, _)) => Ok(hash),
+			None => self.blockchain.hash(As::sa(number))?
+				.ok_or_else(|| client::error::ErrorKind::UnknownBlock(format!("#{}", number)).into()),
+		}
+	}
+}
+
+fn state_db_err(e: state_db::Error<kvdb::Error>) -> client::error::Error {
+	format!("State database error: {:?}", e).into()
 }
 
 fn apply_state_commit(transaction: &mut DBTransaction, commit: state_db::CommitSet<H256>) {
@@ -326,7 +575,8 @@ fn apply_state_commit(transaction: &mut DBTransaction, commit: state_db::CommitS
 impl<Block: BlockT> client::backend::Backend<Block> for Backend<Block> {
 	type BlockImportOperation = BlockImportOperation<Block>;
 	type Blockchain = BlockchainDb<Block>;
-	type State = DbState;
+	type State = CachingState<Block>;
+	type OffchainStorage = OffchainStorage;
 
 	fn begin_operation(&self, block: BlockId<Block>) -> Result<Self::BlockImportOperation, client::error::Error> {
 		let state = self.state_at(block)?;
@@ -334,6 +584,7 @@ impl<Block: BlockT> client::backend::Backend<Block> for Backend<Block> {
 			pending_block: None,
 			old_state: state,
 			updates: MemoryDB::default(),
+			storage_changes: None,
 		})
 	}
 
@@ -343,18 +594,63 @@ impl<Block: BlockT> client::backend::Backend<Block> for Backend<Block> {
 		if let Some(pending_block) = operation.pending_block {
 			let hash = pending_block.header.hash();
 			let number = pending_block.header.number().clone();
-			let key = number_to_db_key(number.clone());
-			transaction.put(columns::HEADER, &key, &pending_block.header.encode());
+			let parent_hash = *pending_block.header.parent_hash();
+			transaction.put(columns::HEADER, hash.as_ref(), &pending_block.header.encode());
 			if let Some(body) = pending_block.body {
-				transaction.put(columns::BODY, &key, &body.encode());
+				transaction.put(columns::BODY, hash.as_ref(), &body.encode());
 			}
 			if let Some(justification) = pending_block.justification {
-				transaction.put(columns::JUSTIFICATION, &key, &justification.encode());
+				transaction.put(columns::JUSTIFICATION, hash.as_ref(), &justification.encode());
 			}
-			transaction.put(columns::BLOCK_INDEX, hash.as_ref(), &key);
+
+			// a block imported from a state snapshot, without its ancestry, starts a new chain
+			// and is finalized right away.
+			let is_starting_block = pending_block.is_snapshot;
+			if number != Zero::zero() && !is_starting_block && self.blockchain.header(BlockId::Hash(parent_hash))?.is_none() {
+				return Err(client::error::ErrorKind::UnknownBlock(format!("parent {:?} of block {:?}", parent_hash, hash)).into());
+			}
+
+			let mut leaves = if is_starting_block {
+				LeafSet::new()
+			} else {
+				self.blockchain.leaves.read().clone()
+			};
+			leaves.import(hash, number, parent_hash);
+			transaction.put(columns::META, meta_keys::LEAVES, &leaves.encode());
+			if number != Zero::zero() && !is_starting_block {
+				let mut children = read_children::<Block>(&*self.storage.db, &parent_hash)?;
+				children.push(hash);
+				transaction.put(columns::META, &children_key(&parent_hash), &children.encode());
+			}
+
+			// blocks which become canonical with this commit
+			let mut enacted: Vec<(Block::Hash, u64)> = Vec::new();
 			if pending_block.is_best {
-				transaction.put(columns::META, meta_keys::BEST_BLOCK, &key);
+				let best_hash = self.blockchain.meta.read().best_hash;
+				if number != Zero::zero() && !is_starting_block && parent_hash != best_hash {
+					// re-canonicalize the new best block's ancestry
+					let route = client::blockchain::tree_route(
+						&self.blockchain,
+						BlockId::Hash(best_hash),
+						BlockId::Hash(parent_hash),
+					)?;
+					for retracted in route.retracted() {
+						transaction.delete(columns::BLOCK_INDEX, &number_to_db_key(retracted.number));
+					}
+					for entry in route.enacted() {
+						transaction.put(columns::BLOCK_INDEX, &number_to_db_key(entry.number), entry.hash.as_ref());
+						enacted.push((entry.hash, entry.number.as_()));
+					}
+				}
+				transaction.put(columns::BLOCK_INDEX, &number_to_db_key(number), hash.as_ref());
+				transaction.put(columns::META, meta_keys::BEST_BLOCK, hash.as_ref());
 			}
+			if !is_starting_block {
+				if let Some(root) = pending_block.header.digest().changes_trie_root() {
+					self.move_changes_trie(&mut transaction, &mut operation.updates, root)?;
+				}
+			}
+
 			let mut changeset: state_db::ChangeSet<H256> = state_db::ChangeSet::default();
 			for (key, (val, rc)) in operation.updates.drain() {
 				if rc > 0 {
@@ -368,22 +664,51 @@ impl<Block: BlockT> client::backend::Backend<Block> for Backend<Block> {
 			apply_state_commit(&mut transaction, commit);
 
 			//finalize an older block
-			if number_u64 > self.finalization_window {
-				let finalizing_hash = if self.finalization_window == 0 {
-					Some(hash)
+			let finalized = if is_starting_block {
+				let commit = self.storage.state_db.finalize_block(&hash).map_err(state_db_err)?;
+				apply_state_commit(&mut transaction, commit);
+				transaction.put(columns::META, meta_keys::FINALIZED_BLOCK, hash.as_ref());
+				Some((hash, number_u64))
+			} else if pending_block.is_best {
+				enacted.push((hash, number_u64));
+				let finalized = if number_u64 > self.finalization_window {
+					self.note_finalized(&mut transaction, number_u64 - self.finalization_window, &enacted)?
 				} else {
-					self.blockchain.hash(As::sa((number_u64 - self.finalization_window) as u64))?
+					None
 				};
-				if let Some(finalizing_hash) = finalizing_hash {
-					trace!("Finalizing block #{} ({:?})", number_u64 - self.finalization_window, finalizing_hash);
-					let commit = self.storage.state_db.finalize_block(&finalizing_hash);
-					apply_state_commit(&mut transaction, commit);
-				}
-			}
+				self.canonicalize_early(&mut transaction, number_u64, &enacted)?;
+				finalized
+			} else {
+				None
+			};
 
 			debug!("DB Commit {:?} ({})", hash, number);
 			self.storage.db.write(transaction).map_err(db_err)?;
 			self.blockchain.update_meta(hash, number, pending_block.is_best);
+			*self.blockchain.leaves.write() = leaves;
+			if pending_block.is_best {
+				self.shared_cache.lock().sync(hash, parent_hash, operation.storage_changes);
+			}
+			if let Some((finalized_hash, finalized_number)) = finalized {
+				self.blockchain.update_finalized_meta(finalized_hash, As::sa(finalized_number));
+			}
+		}
+		Ok(())
+	}
+
+	fn finalize_block(&self, block: BlockId<Block>) -> Result<(), client::error::Error> {
+		use client::blockchain::HeaderBackend;
+
+		let header = self.blockchain.header(block)?
+			.ok_or_else(|| client::error::ErrorKind::UnknownBlock(format!("{:?}", block)))?;
+		if self.blockchain.hash(*header.number())? != Some(header.hash()) {
+			return Err(client::error::ErrorKind::NotInCanonicalChain(format!("{:?}", block)).into());
+		}
+		let mut transaction = DBTransaction::new();
+		if let Some((hash, number)) = self.note_finalized(&mut transaction, header.number().as_(), &[])? {
+			debug!("DB Finalize {:?} ({})", hash, number);
+			self.storage.db.write(transaction).map_err(db_err)?;
+			self.blockchain.update_finalized_meta(hash, As::sa(number));
 		}
 		Ok(())
 	}
@@ -397,16 +722,109 @@ impl<Block: BlockT> client::backend::Backend<Block> for Backend<Block> {
 
 		// special case for genesis initialization
 		match block {
-			BlockId::Hash(h) if h == Default::default() =>
-				return Ok(DbState::with_storage_for_genesis(self.storage.clone())),
+			BlockId::Hash(h) if h == Default::default() => {
+				let state = DbState::with_storage_for_genesis(self.storage.clone());
+				return Ok(CachingState::new(state, self.shared_cache.clone(), None));
+			},
 			_ => {}
 		}
 
 		self.blockchain.header(block).and_then(|maybe_hdr| maybe_hdr.map(|hdr| {
 			let root: TrieH256  = TrieH256::from_slice(hdr.state_root().as_ref());
-			DbState::with_storage(self.storage.clone(), root)
+			let state = DbState::with_storage(self.storage.clone(), root);
+			CachingState::new(state, self.shared_cache.clone(), Some(hdr.hash()))
 		}).ok_or_else(|| client::error::ErrorKind::UnknownBlock(format!("{:?}", block)).into()))
 	}
+
+	fn revert(&self, n: NumberFor<Block>) -> Result<NumberFor<Block>, client::error::Error> {
+		use client::blockchain::HeaderBackend;
+
+		let n: u64 = n.as_();
+		let mut reverted = 0;
+		while reverted < n {
+			let (best_hash, best_number, finalized_number) = {
+				let meta = self.blockchain.meta.read();
+				(meta.best_hash, meta.best_number, meta.finalized_number)
+			};
+			// the state database reverts a level at a time, i.e. all blocks with the highest
+			// number, which are all leaves. A fork may be higher than the best block.
+			let mut leaves = self.blockchain.leaves.read().clone();
+			let (number, hashes) = match leaves.highest() {
+				Some(highest) => highest,
+				None => break,
+			};
+			if number <= finalized_number {
+				break;
+			}
+			let commit = match self.storage.state_db.revert_one() {
+				Some(commit) => commit,
+				None => break,
+			};
+			let mut transaction = DBTransaction::new();
+			apply_state_commit(&mut transaction, commit);
+
+			let parent_number = number - One::one();
+			let mut children = HashMap::new();
+			for hash in hashes {
+				let header = match self.blockchain.header(BlockId::Hash(hash))? {
+					Some(header) => header,
+					None => continue,
+				};
+				trace!("Reverting {} (#{})", hash, number);
+				leaves.remove(&hash);
+				transaction.delete(columns::HEADER, hash.as_ref());
+				transaction.delete(columns::BODY, hash.as_ref());
+				transaction.delete(columns::JUSTIFICATION, hash.as_ref());
+				let parent_hash = *header.parent_hash();
+				if !children.contains_key(&parent_hash) {
+					children.insert(parent_hash, read_children::<Block>(&*self.storage.db, &parent_hash)?);
+				}
+				if let Some(siblings) = children.get_mut(&parent_hash) {
+					siblings.retain(|child| *child != hash);
+				}
+			}
+			for (parent_hash, siblings) in children {
+				if siblings.is_empty() {
+					transaction.delete(columns::META, &children_key(&parent_hash));
+					if let Some(parent) = self.blockchain.header(BlockId::Hash(parent_hash))? {
+						leaves.import(parent_hash, parent_number, *parent.parent_hash());
+					}
+				} else {
+					transaction.put(columns::META, &children_key(&parent_hash), &siblings.encode());
+				}
+			}
+			transaction.put(columns::META, meta_keys::LEAVES, &leaves.encode());
+
+			if number != best_number {
+				debug!("DB Revert fork blocks at #{}", number);
+				self.storage.db.write(transaction).map_err(db_err)?;
+				*self.blockchain.leaves.write() = leaves;
+				continue;
+			}
+
+			let best_parent = *self.blockchain.header(BlockId::Hash(best_hash))?
+				.ok_or_else(|| client::error::ErrorKind::UnknownBlock(format!("{}", best_hash)))?
+				.parent_hash();
+			transaction.delete(columns::BLOCK_INDEX, &number_to_db_key(best_number));
+			transaction.put(columns::META, meta_keys::BEST_BLOCK, best_parent.as_ref());
+
+			debug!("DB Revert to {:?} ({})", best_parent, parent_number);
+			self.storage.db.write(transaction).map_err(db_err)?;
+			self.blockchain.update_meta(best_parent, parent_number, true);
+			*self.blockchain.leaves.write() = leaves;
+			self.shared_cache.lock().sync(best_parent, best_hash, None);
+			reverted += 1;
+		}
+		Ok(As::sa(reverted))
+	}
+
+	fn offchain_storage(&self) -> Option<Self::OffchainStorage> {
+		Some(OffchainStorage { db: self.storage.db.clone() })
+	}
+
+	fn changes_trie_storage(&self) -> Option<state_machine::TrieBackend> {
+		Some(DbState::with_storage(Arc::new(ChangesTrieDb { db: self.storage.db.clone() }), Default::default()))
+	}
 }
 
 impl<Block: BlockT> client::backend::LocalBackend<Block> for Backend<Block> 
@@ -462,6 +880,251 @@ mod tests {
 		}
 	}
 
+	#[test]
+	fn finalize_block_updates_finalized_head() {
+		let db = Arc::new(::kvdb_memorydb::create(::utils::NUM_COLUMNS));
+		let backend = Backend::<Block>::from_kvdb(db as Arc<_>, PruningMode::keep_blocks(0), 3, 16 * 1024).unwrap();
+		for i in 0..6 {
+			let mut op = backend.begin_operation(if i == 0 {
+				BlockId::Hash(Default::default())
+			} else {
+				BlockId::Number(i - 1)
+			}).unwrap();
+			let header = Header {
+				number: i,
+				parent_hash: if i == 0 {
+					Default::default()
+				} else {
+					backend.blockchain.hash(i - 1).unwrap().unwrap()
+				},
+				state_root: Default::default(),
+				digest: Default::default(),
+				extrinsics_root: Default::default(),
+			};
+			op.set_block_data(header, Some(vec![]), None, true).unwrap();
+			backend.commit_operation(op).unwrap();
+		}
+
+		// blocks older than the finalization window are finalized on import
+		let info = backend.blockchain().info().unwrap();
+		assert_eq!(info.finalized_number, 2);
+		assert_eq!(info.finalized_hash, backend.blockchain().hash(2).unwrap().unwrap());
+
+		backend.finalize_block(BlockId::Number(4)).unwrap();
+		let info = backend.blockchain().info().unwrap();
+		assert_eq!(info.finalized_number, 4);
+		assert_eq!(info.finalized_hash, backend.blockchain().hash(4).unwrap().unwrap());
+
+		// finalizing an ancestor of the finalized block is a no-op
+		backend.finalize_block(BlockId::Number(3)).unwrap();
+		assert_eq!(backend.blockchain().info().unwrap().finalized_number, 4);
+		assert!(backend.finalize_block(BlockId::Number(10)).is_err());
+	}
+
+	#[test]
+	fn finalized_block_is_restored_from_state_db() {
+		let db = Arc::new(::kvdb_memorydb::create(::utils::NUM_COLUMNS));
+		let import = |backend: &Backend<Block>, i: u64| {
+			let mut op = backend.begin_operation(if i == 0 {
+				BlockId::Hash(Default::default())
+			} else {
+				BlockId::Number(i - 1)
+			}).unwrap();
+			let header = Header {
+				number: i,
+				parent_hash: if i == 0 {
+					Default::default()
+				} else {
+					backend.blockchain.hash(i - 1).unwrap().unwrap()
+				},
+				state_root: Default::default(),
+				digest: Default::default(),
+				extrinsics_root: Default::default(),
+			};
+			op.set_block_data(header, Some(vec![]), None, true).unwrap();
+			backend.commit_operation(op).unwrap();
+		};
+
+		{
+			let backend = Backend::<Block>::from_kvdb(db.clone() as Arc<_>, PruningMode::keep_blocks(0), 3, 16 * 1024).unwrap();
+			for i in 0..6 {
+				import(&backend, i);
+			}
+		}
+
+		// databases written before the finalized block was recorded
+		let mut transaction = DBTransaction::new();
+		transaction.delete(columns::META, meta_keys::FINALIZED_BLOCK);
+		db.write(transaction).unwrap();
+
+		let backend = Backend::<Block>::from_kvdb(db.clone() as Arc<_>, PruningMode::keep_blocks(0), 3, 16 * 1024).unwrap();
+		let info = backend.blockchain().info().unwrap();
+		assert_eq!(info.finalized_number, 2);
+		assert_eq!(info.finalized_hash, backend.blockchain().hash(2).unwrap().unwrap());
+
+		// blocks finalized by the state db are not finalized again
+		import(&backend, 6);
+		assert_eq!(backend.blockchain().info().unwrap().finalized_number, 3);
+	}
+
+	fn insert_header(backend: &Backend<Block>, number: u64, parent_hash: H256, extrinsics_root: H256, is_best: bool) -> H256 {
+		let mut op = backend.begin_operation(BlockId::Hash(parent_hash)).unwrap();
+		let header = Header {
+			number,
+			parent_hash,
+			state_root: Default::default(),
+			digest: Default::default(),
+			extrinsics_root,
+		};
+		let hash = header.hash();
+		op.set_block_data(header, Some(vec![]), None, is_best).unwrap();
+		backend.commit_operation(op).unwrap();
+		hash
+	}
+
+	#[test]
+	fn reorg_recanonicalizes_blocks() {
+		let db = Arc::new(::kvdb_memorydb::create(::utils::NUM_COLUMNS));
+		let backend = Backend::<Block>::from_kvdb(db as Arc<_>, PruningMode::keep_blocks(0), 10, 16 * 1024).unwrap();
+
+		let genesis = insert_header(&backend, 0, Default::default(), Default::default(), true);
+		let a1 = insert_header(&backend, 1, genesis, Default::default(), true);
+		let a2 = insert_header(&backend, 2, a1, Default::default(), true);
+		let b1 = insert_header(&backend, 1, genesis, 1.into(), false);
+		assert_eq!(backend.blockchain().hash(1).unwrap(), Some(a1));
+		assert_eq!(backend.blockchain().status(BlockId::Hash(b1)).unwrap(), client::blockchain::BlockStatus::InChain);
+
+		let b2 = insert_header(&backend, 2, b1, 1.into(), false);
+		let b3 = insert_header(&backend, 3, b2, 1.into(), true);
+		let info = backend.blockchain().info().unwrap();
+		assert_eq!(info.best_hash, b3);
+		assert_eq!(info.best_number, 3);
+		assert_eq!(backend.blockchain().hash(1).unwrap(), Some(b1));
+		assert_eq!(backend.blockchain().hash(2).unwrap(), Some(b2));
+		assert_eq!(backend.blockchain().hash(3).unwrap(), Some(b3));
+
+		// retracted blocks are still available by hash
+		assert_eq!(backend.blockchain().header(BlockId::Hash(a2)).unwrap().unwrap().parent_hash, a1);
+		assert_eq!(backend.blockchain().header(BlockId::Number(2)).unwrap().unwrap().parent_hash, b1);
+		assert!(backend.finalize_block(BlockId::Hash(a1)).is_err());
+	}
+
+	#[test]
+	fn tracks_leaves_and_children() {
+		use client::blockchain::Backend as BlockchainBackend;
+
+		let db = Arc::new(::kvdb_memorydb::create(::utils::NUM_COLUMNS));
+		let backend = Backend::<Block>::from_kvdb(db.clone() as Arc<_>, PruningMode::keep_blocks(0), 10, 16 * 1024).unwrap();
+
+		let genesis = insert_header(&backend, 0, Default::default(), Default::default(), true);
+		assert_eq!(backend.blockchain().leaves().unwrap(), vec![genesis]);
+
+		let a1 = insert_header(&backend, 1, genesis, Default::default(), true);
+		let a2 = insert_header(&backend, 2, a1, Default::default(), true);
+		let b1 = insert_header(&backend, 1, genesis, 1.into(), false);
+		let c2 = insert_header(&backend, 2, a1, 2.into(), false);
+		assert_eq!(backend.blockchain().leaves().unwrap(), vec![a2, c2, b1]);
+		assert_eq!(backend.blockchain().children(genesis).unwrap(), vec![a1, b1]);
+		assert_eq!(backend.blockchain().children(a1).unwrap(), vec![a2, c2]);
+		assert!(backend.blockchain().children(a2).unwrap().is_empty());
+
+		let a3 = insert_header(&backend, 3, a2, Default::default(), true);
+		assert_eq!(backend.blockchain().leaves().unwrap(), vec![a3, c2, b1]);
+
+		// leaves and children are persisted
+		let backend = Backend::<Block>::from_kvdb(db as Arc<_>, PruningMode::keep_blocks(0), 10, 16 * 1024).unwrap();
+		assert_eq!(backend.blockchain().leaves().unwrap(), vec![a3, c2, b1]);
+		assert_eq!(backend.blockchain().children(a1).unwrap(), vec![a2, c2]);
+	}
+
+	#[test]
+	fn revert_removes_unfinalized_blocks() {
+		use client::blockchain::Backend as BlockchainBackend;
+
+		let db = Arc::new(::kvdb_memorydb::create(::utils::NUM_COLUMNS));
+		let backend = Backend::<Block>::from_kvdb(db as Arc<_>, PruningMode::keep_blocks(0), 10, 16 * 1024).unwrap();
+
+		let genesis = insert_header(&backend, 0, Default::default(), Default::default(), true);
+		let a1 = insert_header(&backend, 1, genesis, Default::default(), true);
+		let a2 = insert_header(&backend, 2, a1, Default::default(), true);
+		let a3 = insert_header(&backend, 3, a2, Default::default(), true);
+		let b3 = insert_header(&backend, 3, a2, 1.into(), false);
+		backend.finalize_block(BlockId::Hash(a1)).unwrap();
+
+		assert_eq!(backend.revert(1).unwrap(), 1);
+		let info = backend.blockchain().info().unwrap();
+		assert_eq!((info.best_hash, info.best_number), (a2, 2));
+		assert_eq!(backend.blockchain().hash(3).unwrap(), None);
+		assert!(backend.blockchain().header(BlockId::Hash(a3)).unwrap().is_none());
+		assert!(backend.blockchain().header(BlockId::Hash(b3)).unwrap().is_none());
+		assert_eq!(backend.blockchain().leaves().unwrap(), vec![a2]);
+		assert!(backend.blockchain().children(a2).unwrap().is_empty());
+
+		// finalized blocks are not reverted
+		assert_eq!(backend.revert(5).unwrap(), 1);
+		let info = backend.blockchain().info().unwrap();
+		assert_eq!((info.best_hash, info.best_number), (a1, 1));
+		assert_eq!(backend.blockchain().leaves().unwrap(), vec![a1]);
+
+		// reverted blocks can be imported again
+		assert_eq!(insert_header(&backend, 2, a1, Default::default(), true), a2);
+		assert_eq!(backend.blockchain().hash(2).unwrap(), Some(a2));
+		assert_eq!(backend.blockchain().children(a1).unwrap(), vec![a2]);
+	}
+
+	#[test]
+	fn exceeding_state_memory_does_not_finalize_blocks() {
+		use client::blockchain::HeaderBackend;
+
+		let db = Arc::new(::kvdb_memorydb::create(::utils::NUM_COLUMNS));
+		let pruning = PruningMode::Constrained(PruningConstraints { max_blocks: None, max_mem: Some(0) });
+		let backend = Backend::<Block>::from_kvdb(db as Arc<_>, pruning, 10, 16 * 1024).unwrap();
+
+		let genesis = insert_header(&backend, 0, Default::default(), Default::default(), true);
+		let a1 = insert_header(&backend, 1, genesis, Default::default(), true);
+		let a2 = insert_header(&backend, 2, a1, Default::default(), true);
+
+		// the state database canonicalizes the blocks to stay within its limit
+		assert_eq!(backend.storage.state_db.last_finalized(), Some((a2, 2)));
+		let info = backend.blockchain().info().unwrap();
+		assert_eq!((info.finalized_hash, info.finalized_number), (genesis, 0));
+
+		backend.finalize_block(BlockId::Hash(a1)).unwrap();
+		let info = backend.blockchain().info().unwrap();
+		assert_eq!((info.finalized_hash, info.finalized_number), (a1, 1));
+	}
+
+	#[test]
+	fn revert_removes_forks_above_best_block() {
+		use client::blockchain::Backend as BlockchainBackend;
+
+		let db = Arc::new(::kvdb_memorydb::create(::utils::NUM_COLUMNS));
+		let backend = Backend::<Block>::from_kvdb(db as Arc<_>, PruningMode::keep_blocks(0), 10, 16 * 1024).unwrap();
+
+		let genesis = insert_header(&backend, 0, Default::default(), Default::default(), true);
+		let a1 = insert_header(&backend, 1, genesis, Default::default(), true);
+		let a2 = insert_header(&backend, 2, a1, Default::default(), true);
+		let b1 = insert_header(&backend, 1, genesis, 1.into(), false);
+		let b2 = insert_header(&backend, 2, b1, 1.into(), false);
+		let b3 = insert_header(&backend, 3, b2, 1.into(), false);
+
+		assert_eq!(backend.revert(1).unwrap(), 1);
+		let info = backend.blockchain().info().unwrap();
+		assert_eq!((info.best_hash, info.best_number), (a1, 1));
+		for hash in &[a2, b2, b3] {
+			assert!(backend.blockchain().header(BlockId::Hash(*hash)).unwrap().is_none());
+		}
+		let mut leaves = backend.blockchain().leaves().unwrap();
+		leaves.sort();
+		let mut expected = vec![a1, b1];
+		expected.sort();
+		assert_eq!(leaves, expected);
+
+		// the state database reverted the same levels, so the blocks can be imported again
+		assert_eq!(insert_header(&backend, 2, b1, 1.into(), false), b2);
+		assert_eq!(insert_header(&backend, 3, b2, 1.into(), false), b3);
+	}
+
 	#[test]
 	fn set_state_data() {
 		let db = Backend::<Block>::new_test();
@@ -508,7 +1171,7 @@ mod tests {
 			let mut op = db.begin_operation(BlockId::Number(0)).unwrap();
 			let mut header = Header {
 				number: 1,
-				parent_hash: Default::default(),
+				parent_hash: db.blockchain.hash(0).unwrap().unwrap(),
 				state_root: Default::default(),
 				digest: Default::default(),
 				extrinsics_root: Default::default(),
@@ -540,6 +1203,249 @@ mod tests {
 		}
 	}
 
+	#[test]
+	fn state_cache_follows_best_block() {
+		let kvdb = Arc::new(::kvdb_memorydb::create(::utils::NUM_COLUMNS));
+		let db = Backend::<Block>::from_kvdb(kvdb as Arc<_>, PruningMode::ArchiveAll, 10, 16 * 1024).unwrap();
+		let mut op = db.begin_operation(BlockId::Hash(Default::default())).unwrap();
+		let storage = vec![(vec![1], vec![1])];
+		let mut header = Header {
+			number: 0,
+			parent_hash: Default::default(),
+			state_root: Default::default(),
+			digest: Default::default(),
+			extrinsics_root: Default::default(),
+		};
+		header.state_root = op.old_state.storage_root(storage.iter().cloned().map(|(x, y)| (x, Some(y)))).0.into();
+		let genesis = header.hash();
+		op.reset_storage(storage.into_iter()).unwrap();
+		op.set_block_data(header, Some(vec![]), None, true).unwrap();
+		db.commit_operation(op).unwrap();
+		assert_eq!(db.shared_cache.lock().hash(), Some(genesis));
+
+		let genesis_state = db.state_at(BlockId::Hash(genesis)).unwrap();
+		assert_eq!(genesis_state.storage(&[1]).unwrap(), Some(vec![1]));
+
+		let import = |number, extrinsics_root: H256, value, is_best| {
+			let mut op = db.begin_operation(BlockId::Hash(genesis)).unwrap();
+			let (root, update) = op.old_state.storage_root(vec![(vec![1], Some(vec![value]))]);
+			op.update_storage(update).unwrap();
+			let header = Header {
+				number,
+				parent_hash: genesis,
+				state_root: root.into(),
+				digest: Default::default(),
+				extrinsics_root,
+			};
+			let hash = header.hash();
+			op.set_block_data(header, Some(vec![]), None, is_best).unwrap();
+			db.commit_operation(op).unwrap();
+			hash
+		};
+
+		// a non-best block leaves the cache at the best block
+		let fork = import(1, 1.into(), 3, false);
+		assert_eq!(db.shared_cache.lock().hash(), Some(genesis));
+		assert_eq!(db.state_at(BlockId::Hash(fork)).unwrap().storage(&[1]).unwrap(), Some(vec![3]));
+
+		let best = import(1, Default::default(), 2, true);
+		assert_eq!(db.shared_cache.lock().hash(), Some(best));
+		assert_eq!(db.state_at(BlockId::Hash(best)).unwrap().storage(&[1]).unwrap(), Some(vec![2]));
+		// states of other blocks are not affected
+		assert_eq!(genesis_state.storage(&[1]).unwrap(), Some(vec![1]));
+		assert_eq!(db.state_at(BlockId::Hash(fork)).unwrap().storage(&[1]).unwrap(), Some(vec![3]));
+	}
+
+	#[test]
+	fn child_storage_is_persisted() {
+		use state_machine::{Externalities, Ext, OverlayedChanges};
+
+		let backend = Backend::<Block>::new_test();
+		let storage_key = b":child_storage:test".to_vec();
+
+		let genesis = {
+			let mut op = backend.begin_operation(BlockId::Hash(Default::default())).unwrap();
+			let storage = vec![(vec![1], vec![1])];
+			let header = Header {
+				number: 0,
+				parent_hash: Default::default(),
+				state_root: op.old_state.storage_root(storage.iter().cloned().map(|(x, y)| (x, Some(y)))).0.into(),
+				digest: Default::default(),
+				extrinsics_root: Default::default(),
+			};
+			let hash = header.hash();
+			op.reset_storage(storage.into_iter()).unwrap();
+			op.set_block_data(header, Some(vec![]), None, true).unwrap();
+			backend.commit_operation(op).unwrap();
+			hash
+		};
+
+		let mut op = backend.begin_operation(BlockId::Hash(genesis)).unwrap();
+		let mut overlay = OverlayedChanges::default();
+		let (root, update) = {
+			let mut ext = Ext::new(&mut overlay, &op.old_state);
+			ext.set_child_storage(storage_key.clone(), b"key".to_vec(), b"value".to_vec());
+			let root = ext.storage_root();
+			(root, ext.transaction())
+		};
+		op.update_storage(update).unwrap();
+		let header = Header {
+			number: 1,
+			parent_hash: genesis,
+			state_root: root.into(),
+			digest: Default::default(),
+			extrinsics_root: Default::default(),
+		};
+		let hash = header.hash();
+		op.set_block_data(header, Some(vec![]), None, true).unwrap();
+		backend.commit_operation(op).unwrap();
+
+		let state = backend.state_at(BlockId::Hash(hash)).unwrap();
+		assert_eq!(state.child_storage(&storage_key, b"key").unwrap(), Some(b"value".to_vec()));
+		assert_eq!(state.storage(&[1]).unwrap(), Some(vec![1]));
+		assert!(state.storage(&storage_key).unwrap().is_some());
+		let genesis_state = backend.state_at(BlockId::Hash(genesis)).unwrap();
+		assert_eq!(genesis_state.child_storage(&storage_key, b"key").unwrap(), None);
+	}
+
+	fn changes_trie_client(backend: Arc<Backend<test_client::runtime::Block>>) -> test_client::client::Client<
+		Backend<test_client::runtime::Block>,
+		test_client::client::LocalCallExecutor<Backend<test_client::runtime::Block>, executor::NativeExecutor<test_client::LocalExecutor>>,
+		test_client::runtime::Block,
+	> {
+		test_client::new_with_backend(backend, true)
+	}
+
+	fn transfer(from: ::keyring::Keyring, to: ::keyring::Keyring, nonce: u64) -> test_client::runtime::Extrinsic {
+		let transfer = test_client::runtime::Transfer {
+			from: from.to_raw_public().into(),
+			to: to.to_raw_public().into(),
+			amount: 42,
+			nonce,
+		};
+		let signature = from.sign(&transfer.encode()).into();
+		test_client::runtime::Extrinsic { transfer, signature }
+	}
+
+	fn balance_key(who: ::keyring::Keyring) -> ::primitives::storage::StorageKey {
+		use codec::KeyedVec;
+		::primitives::storage::StorageKey(::primitives::hashing::twox_128(&who.to_raw_public().to_keyed_vec(b"balance:")).to_vec())
+	}
+
+	#[test]
+	fn key_changes_are_queried_over_imported_blocks() {
+		use keyring::Keyring;
+		use runtime_primitives::traits::Digest;
+		use test_client::TestClient;
+		use test_client::client::BlockOrigin;
+
+		let db = Arc::new(::kvdb_memorydb::create(::utils::NUM_COLUMNS));
+		let backend = Arc::new(Backend::from_kvdb(db as Arc<_>, PruningMode::keep_blocks(0), 10, 16 * 1024).unwrap());
+		let client = changes_trie_client(backend);
+
+		let mut builder = client.new_block().unwrap();
+		builder.push(transfer(Keyring::Alice, Keyring::Ferdie, 0)).unwrap();
+		let block1 = builder.bake().unwrap();
+		assert!(block1.header.digest.changes_trie_root().is_some());
+		client.justify_and_import(BlockOrigin::Own, block1.clone()).unwrap();
+
+		let block2 = client.new_block().unwrap().bake().unwrap();
+		client.justify_and_import(BlockOrigin::Own, block2.clone()).unwrap();
+
+		let keys = vec![balance_key(Keyring::Ferdie), balance_key(Keyring::Bob)];
+		assert_eq!(
+			client.key_changes(block1.hash(), block2.hash(), &keys).unwrap(),
+			vec![(block1.hash(), vec![balance_key(Keyring::Ferdie)])],
+		);
+	}
+
+	#[test]
+	fn changes_tries_outlive_pruned_state() {
+		use keyring::Keyring;
+		use test_client::TestClient;
+		use test_client::client::BlockOrigin;
+
+		let db = Arc::new(::kvdb_memorydb::create(::utils::NUM_COLUMNS));
+		let backend = Arc::new(Backend::from_kvdb(db as Arc<_>, PruningMode::keep_blocks(0), 10, 16 * 1024).unwrap());
+		let client = changes_trie_client(backend.clone());
+
+		let mut blocks = Vec::new();
+		for nonce in 0..2 {
+			let mut builder = client.new_block().unwrap();
+			builder.push(transfer(Keyring::Alice, Keyring::Ferdie, nonce)).unwrap();
+			let block = builder.bake().unwrap();
+			client.justify_and_import(BlockOrigin::Own, block.clone()).unwrap();
+			blocks.push(block.hash());
+		}
+		backend.finalize_block(BlockId::Hash(blocks[1])).unwrap();
+
+		let key = balance_key(Keyring::Ferdie);
+		let pruned_state = backend.state_at(BlockId::Hash(blocks[0])).unwrap();
+		assert!(pruned_state.storage(&key.0).is_err());
+		assert_eq!(
+			client.key_changes(blocks[0], blocks[1], &[key.clone()]).unwrap(),
+			vec![(blocks[0], vec![key.clone()]), (blocks[1], vec![key])],
+		);
+	}
+
+	#[test]
+	fn starting_block_is_imported_without_ancestry() {
+		use client::blockchain::Backend as BlockchainBackend;
+
+		let db = Arc::new(::kvdb_memorydb::create(::utils::NUM_COLUMNS));
+		let backend = Backend::<Block>::from_kvdb(db as Arc<_>, PruningMode::keep_blocks(0), 3, 16 * 1024).unwrap();
+		let genesis = insert_header(&backend, 0, Default::default(), Default::default(), true);
+
+		// only blocks of state snapshots can be imported without their parents
+		let mut op = backend.begin_operation(BlockId::Hash(genesis)).unwrap();
+		let orphan = Header {
+			number: 10,
+			parent_hash: 5.into(),
+			state_root: Default::default(),
+			digest: Default::default(),
+			extrinsics_root: Default::default(),
+		};
+		op.set_block_data(orphan, Some(vec![]), None, true).unwrap();
+		assert!(backend.commit_operation(op).is_err());
+
+		let storage_key = b":child_storage:test".to_vec();
+		let child_storage = vec![(b"key".to_vec(), b"value".to_vec())];
+		let mut op = backend.begin_operation(BlockId::Hash(Default::default())).unwrap();
+		let (child_root, _, _) = op.old_state.child_storage_root(
+			&storage_key,
+			child_storage.iter().cloned().map(|(x, y)| (x, Some(y))),
+		);
+		let storage = vec![(vec![1], vec![1]), (storage_key.clone(), child_root.to_vec())];
+		let header = Header {
+			number: 10,
+			parent_hash: 5.into(),
+			state_root: op.old_state.storage_root(storage.iter().cloned().map(|(x, y)| (x, Some(y)))).0.into(),
+			digest: Default::default(),
+			extrinsics_root: Default::default(),
+		};
+		let hash = header.hash();
+		op.reset_storage(storage.into_iter()).unwrap();
+		op.reset_child_storage(&storage_key, child_storage.into_iter()).unwrap();
+		op.set_snapshot_block_data(header, Justification { round_number: 0, hash, signatures: Vec::new() }).unwrap();
+		backend.commit_operation(op).unwrap();
+
+		let info = backend.blockchain().info().unwrap();
+		assert_eq!(info.genesis_hash, genesis);
+		assert_eq!((info.best_hash, info.best_number), (hash, 10));
+		assert_eq!((info.finalized_hash, info.finalized_number), (hash, 10));
+		assert_eq!(backend.blockchain().leaves().unwrap(), vec![hash]);
+
+		let state = backend.state_at(BlockId::Number(10)).unwrap();
+		assert_eq!(state.storage(&[1]).unwrap(), Some(vec![1]));
+		assert_eq!(state.child_storage(&storage_key, b"key").unwrap(), Some(b"value".to_vec()));
+
+		// the chain continues on top of the starting block
+		let next = insert_header(&backend, 11, hash, Default::default(), true);
+		assert_eq!(backend.blockchain().hash(11).unwrap(), Some(next));
+		assert_eq!(backend.blockchain().leaves().unwrap(), vec![next]);
+		assert_eq!(backend.blockchain().children(hash).unwrap(), vec![next]);
+	}
+
 	#[test]
 	fn delete_only_when_negative_rc() {
 		let key;
@@ -645,4 +1551,16 @@ mod tests {
 			assert!(backend.storage.db.get(::columns::STATE, &key.0[..]).unwrap().is_none());
 		}
 	}
+
+	#[test]
+	fn offchain_storage_works() {
+		use client::backend::OffchainStorage as OffchainStorageT;
+
+		let storage = Backend::<Block>::new_test().offchain_storage().unwrap();
+		assert_eq!(storage.get(b"key").unwrap(), None);
+		storage.set(b"key", Some(&b"value"[..])).unwrap();
+		assert_eq!(storage.get(b"key").unwrap(), Some(b"value".to_vec()));
+		storage.set(b"key", None).unwrap();
+		assert_eq!(storage.get(b"key").unwrap(), None);
+	}
 }
@@ -22,26 +22,31 @@ use parking_lot::RwLock;
 use kvdb::{KeyValueDB, DBTransaction};
 
 use client::blockchain::{BlockStatus, HeaderBackend as BlockchainHeaderBackend,
-	Info as BlockchainInfo};
+	Info as BlockchainInfo, tree_route};
+use client::cht;
 use client::error::{ErrorKind as ClientErrorKind, Result as ClientResult};
+use client::leaves::LeafSet;
 use client::light::blockchain::Storage as LightBlockchainStorage;
 use codec::{Decode, Encode};
 use primitives::AuthorityId;
 use runtime_primitives::generic::BlockId;
-use runtime_primitives::traits::{Block as BlockT, Header as HeaderT, Hash, HashFor, Zero};
-use utils::{meta_keys, Meta, db_err, number_to_db_key, open_database, read_db, read_id, read_meta};
+use runtime_primitives::traits::{As, Block as BlockT, Header as HeaderT, NumberFor, Zero};
+use utils::{meta_keys, Meta, children_key, db_err, number_to_db_key, open_database, read_children, read_db,
+	read_id, read_leaves, read_meta};
 use DatabaseSettings;
 
 pub(crate) mod columns {
 	pub const META: Option<u32> = ::utils::COLUMN_META;
 	pub const BLOCK_INDEX: Option<u32> = Some(1);
 	pub const HEADER: Option<u32> = Some(2);
+	pub const CHT: Option<u32> = Some(3);
 }
 
 /// Light blockchain storage. Stores most recent headers + CHTs for older headers.
 pub struct LightStorage<Block: BlockT> {
 	db: Arc<KeyValueDB>,
 	meta: RwLock<Meta<<<Block as BlockT>::Header as HeaderT>::Number, Block::Hash>>,
+	leaves: RwLock<LeafSet<Block::Hash, <<Block as BlockT>::Header as HeaderT>::Number>>,
 }
 
 #[derive(Clone, PartialEq, Debug)]
@@ -73,11 +78,13 @@ impl<Block> LightStorage<Block>
 	}
 
 	fn from_kvdb(db: Arc<KeyValueDB>) -> ClientResult<Self> {
-		let meta = RwLock::new(read_meta::<Block>(&*db, columns::HEADER)?);
+		let meta = RwLock::new(read_meta::<Block>(&*db, columns::BLOCK_INDEX, columns::HEADER)?);
+		let leaves = RwLock::new(read_leaves::<Block>(&*db)?);
 
 		Ok(LightStorage {
 			db,
 			meta,
+			leaves,
 		})
 	}
 
@@ -86,12 +93,34 @@ impl<Block> LightStorage<Block>
 			let mut meta = self.meta.write();
 			if number == <<Block as BlockT>::Header as HeaderT>::Number::zero() {
 				meta.genesis_hash = hash;
+				meta.finalized_hash = hash;
 			}
 
 			meta.best_number = number;
 			meta.best_hash = hash;
 		}
 	}
+
+	/// Build the CHT covering given range of finalized blocks and prune the headers it replaces.
+	fn build_cht(&self, transaction: &mut DBTransaction, cht_num: u64) -> ClientResult<()> {
+		let cht_start = cht::start_number(cht_num);
+		let hashes = (cht_start..cht::end_number(cht_num) + 1)
+			.map(|num| self.hash(As::sa(num)))
+			.collect::<ClientResult<Vec<_>>>()?;
+		let cht_root = cht::compute_root::<Block::Header, _>(cht_num, hashes.iter().cloned().map(Ok))?;
+		transaction.put(columns::CHT, &number_to_db_key(cht_num), cht_root.as_ref());
+
+		for (num, hash) in (cht_start..).zip(hashes) {
+			if let Some(hash) = hash {
+				transaction.delete(columns::HEADER, hash.as_ref());
+				transaction.delete(columns::META, &children_key(&hash));
+			}
+			transaction.delete(columns::BLOCK_INDEX, &number_to_db_key(num));
+		}
+
+		debug!(target: "db", "Replaced headers of blocks {}..{} with CHT", cht_start, cht::end_number(cht_num));
+		Ok(())
+	}
 }
 
 impl<Block> BlockchainHeaderBackend<Block> for LightStorage<Block>
@@ -114,12 +143,14 @@ impl<Block> BlockchainHeaderBackend<Block> for LightStorage<Block>
 			best_hash: meta.best_hash,
 			best_number: meta.best_number,
 			genesis_hash: meta.genesis_hash,
+			finalized_hash: meta.finalized_hash,
+			finalized_number: meta.finalized_number,
 		})
 	}
 
 	fn status(&self, id: BlockId<Block>) -> ClientResult<BlockStatus> {
 		let exists = match id {
-			BlockId::Hash(_) => read_id(&*self.db, columns::BLOCK_INDEX, id)?.is_some(),
+			BlockId::Hash(_) => read_db(&*self.db, columns::BLOCK_INDEX, columns::HEADER, id)?.is_some(),
 			BlockId::Number(n) => n <= self.meta.read().best_number,
 		};
 		match exists {
@@ -129,9 +160,7 @@ impl<Block> BlockchainHeaderBackend<Block> for LightStorage<Block>
 	}
 
 	fn hash(&self, number: <<Block as BlockT>::Header as HeaderT>::Number) -> ClientResult<Option<Block::Hash>> {
-		read_db::<Block>(&*self.db, columns::BLOCK_INDEX, columns::HEADER, BlockId::Number(number)).map(|x|
-			x.map(|raw| HashFor::<Block>::hash(&raw[..])).map(Into::into)
-		)
+		read_id::<Block>(&*self.db, columns::BLOCK_INDEX, BlockId::Number(number))
 	}
 }
 
@@ -144,21 +173,90 @@ impl<Block> LightBlockchainStorage<Block> for LightStorage<Block>
 
 		let hash = header.hash();
 		let number = *header.number();
-		let key = number_to_db_key(number);
+		let parent_hash = *header.parent_hash();
+
+		transaction.put(columns::HEADER, hash.as_ref(), &header.encode());
 
-		transaction.put(columns::HEADER, &key, &header.encode());
-		transaction.put(columns::BLOCK_INDEX, hash.as_ref(), &key);
+		let mut leaves = self.leaves.read().clone();
+		leaves.import(hash, number, parent_hash);
+		transaction.put(columns::META, meta_keys::LEAVES, &leaves.encode());
+		if number != Zero::zero() {
+			let mut children = read_children::<Block>(&*self.db, &parent_hash)?;
+			children.push(hash);
+			transaction.put(columns::META, &children_key(&parent_hash), &children.encode());
+		}
 
 		if is_new_best {
-			transaction.put(columns::META, meta_keys::BEST_BLOCK, &key);
+			let best_hash = self.meta.read().best_hash;
+			if number != Zero::zero() && parent_hash != best_hash {
+				// re-canonicalize the new best header's ancestry
+				let route = tree_route(self, BlockId::Hash(best_hash), BlockId::Hash(parent_hash))?;
+				for retracted in route.retracted() {
+					transaction.delete(columns::BLOCK_INDEX, &number_to_db_key(retracted.number));
+				}
+				for enacted in route.enacted() {
+					transaction.put(columns::BLOCK_INDEX, &number_to_db_key(enacted.number), enacted.hash.as_ref());
+				}
+			}
+			transaction.put(columns::BLOCK_INDEX, &number_to_db_key(number), hash.as_ref());
+			transaction.put(columns::META, meta_keys::BEST_BLOCK, hash.as_ref());
 		}
 
 		debug!("Light DB Commit {:?} ({})", hash, number);
 		self.db.write(transaction).map_err(db_err)?;
 		self.update_meta(hash, number, is_new_best);
+		*self.leaves.write() = leaves;
+
+		Ok(())
+	}
+
+	fn finalize_header(&self, id: BlockId<Block>) -> ClientResult<()> {
+		let header = self.header(id)?
+			.ok_or_else(|| ClientErrorKind::UnknownBlock(format!("{:?}", id)))?;
+		let hash = header.hash();
+		let number = *header.number();
+		if self.hash(number)? != Some(hash) {
+			return Err(ClientErrorKind::NotInCanonicalChain(format!("{:?}", id)).into());
+		}
+		let finalized_number = self.meta.read().finalized_number;
+		if number <= finalized_number {
+			return Ok(());
+		}
 
+		let mut transaction = DBTransaction::new();
+		transaction.put(columns::META, meta_keys::FINALIZED_BLOCK, hash.as_ref());
+
+		// replace headers of ancient finalized blocks with CHTs
+		let first_cht = cht::max_cht_number(finalized_number).map_or(0, |cht_num| cht_num + 1);
+		if let Some(last_cht) = cht::max_cht_number(number) {
+			for cht_num in first_cht..last_cht + 1 {
+				self.build_cht(&mut transaction, cht_num)?;
+			}
+		}
+
+		self.db.write(transaction).map_err(db_err)?;
+
+		let mut meta = self.meta.write();
+		meta.finalized_hash = hash;
+		meta.finalized_number = number;
 		Ok(())
 	}
+
+	fn leaves(&self) -> ClientResult<Vec<Block::Hash>> {
+		Ok(self.leaves.read().hashes())
+	}
+
+	fn children(&self, parent_hash: Block::Hash) -> ClientResult<Vec<Block::Hash>> {
+		read_children::<Block>(&*self.db, &parent_hash)
+	}
+
+	fn cht_root(&self, block: NumberFor<Block>) -> ClientResult<Block::Hash> {
+		let no_cht_for_block = || ClientErrorKind::Backend(format!("CHT for block {} does not exist", block));
+		let cht_num = cht::block_to_cht_number(block).ok_or_else(no_cht_for_block)?;
+		self.db.get(columns::CHT, &number_to_db_key(cht_num)).map_err(db_err)?
+			.and_then(|root| Decode::decode(&mut &root[..]))
+			.ok_or_else(|| no_cht_for_block().into())
+	}
 }
 
 #[cfg(test)]
@@ -191,6 +289,22 @@ pub(crate) mod tests {
 		assert_eq!(header_by_hash, header_by_number);
 	}
 
+	#[test]
+	fn finalize_header_updates_finalized_head() {
+		let db = LightStorage::new_test();
+		let genesis_hash = insert_block(&db, &Default::default(), 0);
+		assert_eq!(db.info().unwrap().finalized_hash, genesis_hash);
+
+		let hash1 = insert_block(&db, &genesis_hash, 1);
+		let hash2 = insert_block(&db, &hash1, 2);
+		db.finalize_header(BlockId::Hash(hash2)).unwrap();
+		assert_eq!(db.info().unwrap().finalized_hash, hash2);
+		assert_eq!(db.info().unwrap().finalized_number, 2);
+
+		db.finalize_header(BlockId::Number(1)).unwrap();
+		assert_eq!(db.info().unwrap().finalized_number, 2);
+	}
+
 	#[test]
 	fn does_not_return_unknown_header() {
 		let db = LightStorage::<Block>::new_test();
@@ -243,4 +357,122 @@ pub(crate) mod tests {
 		assert_eq!(db.db.iter(columns::HEADER).count(), 2);
 		assert_eq!(db.db.iter(columns::BLOCK_INDEX).count(), 2);
 	}
+
+	#[test]
+	fn finalized_headers_are_replaced_with_cht() {
+		let db = LightStorage::new_test();
+		let mut hashes = vec![insert_block(&db, &Default::default(), 0)];
+		for number in 1..cht::SIZE * 3 + 1 {
+			let parent = hashes[number as usize - 1];
+			hashes.push(insert_block(&db, &parent, number as u32));
+		}
+
+		// headers are kept until the next CHT range is finalized
+		db.finalize_header(BlockId::Number(cht::SIZE * 2 - 1)).unwrap();
+		assert!(db.cht_root(1).is_err());
+		assert_eq!(db.db.iter(columns::HEADER).count(), cht::SIZE as usize * 3 + 1);
+
+		db.finalize_header(BlockId::Number(cht::SIZE * 3)).unwrap();
+		let expected_root = |cht_num| cht::compute_root::<Header, _>(cht_num,
+			hashes[cht::start_number(cht_num) as usize..].iter().map(|hash| Ok(Some(*hash)))).unwrap();
+		assert_eq!(db.cht_root(1).unwrap(), expected_root(0));
+		assert_eq!(db.cht_root(cht::SIZE + 1).unwrap(), expected_root(1));
+		assert!(db.cht_root(cht::SIZE * 2 + 1).is_err());
+		assert!(db.cht_root(0).is_err());
+
+		// headers of genesis and the most recent CHT range are kept
+		assert_eq!(db.db.iter(columns::HEADER).count(), cht::SIZE as usize + 1);
+		assert!(db.header(BlockId::Number(0)).unwrap().is_some());
+		assert!(db.header(BlockId::Hash(hashes[1])).unwrap().is_none());
+		assert!(db.header(BlockId::Number(cht::SIZE * 2)).unwrap().is_none());
+		assert!(db.header(BlockId::Number(cht::SIZE * 2 + 1)).unwrap().is_some());
+		assert_eq!(db.hash(cht::SIZE).unwrap(), None);
+	}
+
+	#[test]
+	fn tracks_leaves_and_children() {
+		let db = LightStorage::new_test();
+		let genesis_hash = insert_block(&db, &Default::default(), 0);
+		assert_eq!(db.leaves().unwrap(), vec![genesis_hash]);
+
+		let hash1 = insert_block(&db, &genesis_hash, 1);
+		let hash2 = insert_block(&db, &hash1, 2);
+		let fork_header = Header {
+			number: 1,
+			parent_hash: genesis_hash,
+			state_root: Default::default(),
+			digest: Default::default(),
+			extrinsics_root: 1.into(),
+		};
+		let fork_hash1 = fork_header.hash();
+		db.import_header(false, fork_header).unwrap();
+
+		assert_eq!(db.leaves().unwrap(), vec![hash2, fork_hash1]);
+		assert_eq!(db.children(genesis_hash).unwrap(), vec![hash1, fork_hash1]);
+		assert_eq!(db.children(hash1).unwrap(), vec![hash2]);
+		assert!(db.children(hash2).unwrap().is_empty());
+
+		// leaves are persisted
+		let reopened = LightStorage::<Block>::from_kvdb(db.db.clone()).unwrap();
+		assert_eq!(reopened.leaves().unwrap(), vec![hash2, fork_hash1]);
+	}
+
+	#[test]
+	fn pruned_headers_are_fetched_from_remote_node() {
+		use client::{BlockOrigin, CallResult, Client};
+		use client::light::blockchain::Blockchain;
+		use client::light::fetcher::{Fetcher, RemoteCallRequest, RemoteHeaderRequest};
+		use test_client::{self, TestClient};
+		use test_client::runtime::{Block, Hash, Header};
+
+		struct RemoteNode(Client<test_client::Backend, test_client::Executor, Block>);
+
+		impl Fetcher<Block> for RemoteNode {
+			type RemoteCallResult = ClientResult<CallResult>;
+			type RemoteHeaderResult = ClientResult<Header>;
+
+			fn remote_call(&self, _request: RemoteCallRequest<Hash>) -> Self::RemoteCallResult {
+				Err(ClientErrorKind::RemoteFetchFailed.into())
+			}
+
+			fn remote_header(&self, request: RemoteHeaderRequest<Header>) -> Self::RemoteHeaderResult {
+				let (header, proof) = self.0.header_proof(&BlockId::Number(request.block))?;
+				cht::check_proof(request.cht_root, request.block, &header, proof)?;
+				Ok(header)
+			}
+		}
+
+		// the remote full node serves proofs of the first CHT once the next range is finalized
+		let remote = test_client::new();
+		for _ in 0..cht::SIZE * 2 {
+			remote.justify_and_import(BlockOrigin::Own, remote.new_block().unwrap().bake().unwrap()).unwrap();
+		}
+		remote.finalize_block(BlockId::Number(cht::SIZE * 2)).unwrap();
+
+		// and the light node replaces the headers of the first CHT range with its root
+		let storage = LightStorage::new_test();
+		for number in 0..cht::SIZE * 2 + 1 {
+			storage.import_header(true, remote.header(&BlockId::Number(number)).unwrap().unwrap()).unwrap();
+		}
+		storage.finalize_header(BlockId::Number(cht::SIZE * 2)).unwrap();
+		assert!(storage.header(BlockId::Number(1)).unwrap().is_none());
+
+		let remote = Arc::new(RemoteNode(remote));
+		let blockchain = Blockchain::new(storage);
+		blockchain.set_fetcher(Arc::downgrade(&remote));
+
+		let expected = remote.0.header(&BlockId::Number(1)).unwrap().unwrap();
+		assert_eq!(blockchain.header(BlockId::Number(1)).unwrap(), Some(expected.clone()));
+		assert_eq!(blockchain.header(BlockId::Number(cht::SIZE * 2)).unwrap(),
+			remote.0.header(&BlockId::Number(cht::SIZE * 2)).unwrap());
+		assert!(blockchain.header(BlockId::Number(cht::SIZE * 2 + 1)).unwrap().is_none());
+
+		// hashes are only looked up locally
+		assert!(blockchain.header(BlockId::Hash(expected.hash())).unwrap().is_none());
+		assert_eq!(blockchain.hash(1).unwrap(), None);
+
+		// and nothing is fetched once the remote node is gone
+		drop(remote);
+		assert!(blockchain.header(BlockId::Number(1)).is_err());
+	}
 }
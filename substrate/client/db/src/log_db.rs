@@ -0,0 +1,435 @@
+// Copyright 2018 Parity Technologies (UK) Ltd.
+// This file is part of Substrate.
+
+// Substrate is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// Substrate is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with Substrate.  If not, see <http://www.gnu.org/licenses/>.
+
+//! Pure-Rust, file-backed key-value database.
+//!
+//! All data is kept in memory. Every transaction is appended to a log file, which is replayed
+//! when the database is opened and then compacted into a single record of the current contents.
+//! The log is compacted the same way whenever it has grown to several times its compacted size.
+//! Each record is prefixed with its length and checksum, so a transaction which has only been
+//! partially written when the node stopped is detected and discarded, together with the rest
+//! of the log.
+//! Suited for tests and small deployments, where the whole database fits in memory.
+
+use std::cmp;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Read, Write};
+use std::iter;
+use std::path::{Path, PathBuf};
+use codec::{Decode, Encode};
+use kvdb::{self, DBOp, DBTransaction, DBValue, KeyValueDB};
+use kvdb_memorydb::{self, InMemory};
+use parking_lot::Mutex;
+use primitives::twox_128;
+
+/// Name of the log file in the database directory.
+pub const LOG_FILE: &str = "log.db";
+const COMPACTED_LOG_FILE: &str = "log.db.compacted";
+
+/// A single change: column, key and the new value, or `None` if the key is deleted.
+type LogOp = (Option<u32>, Vec<u8>, Option<Vec<u8>>);
+
+/// Length of the checksum following the length of each record.
+const CHECKSUM_LEN: usize = 16;
+
+/// The log is compacted once it is longer than this many times its length after the last
+/// compaction...
+const COMPACTION_RATIO: u64 = 4;
+/// ...and longer than this.
+const MIN_COMPACTION_LEN: u64 = 64 * 1024 * 1024;
+
+/// Log-structured database. See module documentation.
+pub struct LogDb {
+	db: InMemory,
+	log: Mutex<Log>,
+}
+
+/// The open log file.
+struct Log {
+	file: File,
+	/// Directory of the database.
+	path: PathBuf,
+	num_columns: u32,
+	/// Length of the log, up to the end of the last completely written record.
+	len: u64,
+	/// Length of the log above which it is compacted.
+	compaction_len: u64,
+	/// Error of a buffered write, which is reported by the next `write` or `flush`.
+	error: Option<io::Error>,
+}
+
+impl Log {
+	/// Append the record of `transaction` to the log. If it can't be written completely, the
+	/// log is truncated back to the end of the last complete record.
+	fn append(&mut self, transaction: &DBTransaction) -> io::Result<()> {
+		let record = encode_record(transaction)?;
+		if let Err(e) = self.file.write_all(&record) {
+			self.file.set_len(self.len)?;
+			return Err(e);
+		}
+		self.len += record.len() as u64;
+		Ok(())
+	}
+
+	/// Compact the log into a single record of the contents of `db` if it has grown too long.
+	/// A failed compaction is not fatal: the current log is still complete and is kept.
+	fn compact_if_needed(&mut self, db: &InMemory) {
+		if self.len <= self.compaction_len {
+			return;
+		}
+		match compact(db, &self.path, self.num_columns) {
+			Ok((file, len)) => {
+				debug!(target: "db", "Compacted the database log from {} to {} bytes", self.len, len);
+				self.file = file;
+				self.len = len;
+			},
+			Err(e) => warn!(target: "db", "Error compacting the database log: {}", e),
+		}
+		// also after an error, so that it is not retried on every write
+		self.compaction_len = compaction_len(self.len);
+	}
+
+	/// Take the error of the last failed buffered write, if any.
+	fn take_error(&mut self) -> io::Result<()> {
+		match self.error.take() {
+			Some(e) => Err(e),
+			None => Ok(()),
+		}
+	}
+}
+
+impl LogDb {
+	/// Open the database in the given directory, creating it if it does not exist.
+	pub fn open(path: &Path, num_columns: u32) -> kvdb::Result<LogDb> {
+		fs::create_dir_all(path)?;
+		let log_path = path.join(LOG_FILE);
+		let db = kvdb_memorydb::create(num_columns);
+		if log_path.exists() {
+			let mut log = Vec::new();
+			File::open(&log_path)?.read_to_end(&mut log)?;
+			let records = replay(&db, &log);
+			debug!(target: "db", "Replayed {} records of {}", records, log_path.display());
+		}
+
+		let (file, len) = compact(&db, path, num_columns)?;
+		Ok(LogDb {
+			db,
+			log: Mutex::new(Log {
+				file,
+				path: path.to_owned(),
+				num_columns,
+				len,
+				compaction_len: compaction_len(len),
+				error: None,
+			}),
+		})
+	}
+}
+
+fn compaction_len(compacted_len: u64) -> u64 {
+	cmp::max(compacted_len.saturating_mul(COMPACTION_RATIO), MIN_COMPACTION_LEN)
+}
+
+// Replace the log in directory `path` with a single record of the contents of `db`. Returns the
+// new log, opened for appending, and its length. The current log is left intact on error.
+fn compact(db: &InMemory, path: &Path, num_columns: u32) -> io::Result<(File, u64)> {
+	let mut contents = DBTransaction::new();
+	for col in iter::once(None).chain((0..num_columns).map(Some)) {
+		for (key, value) in db.iter(col) {
+			contents.put(col, &key, &value);
+		}
+	}
+	let compacted_path = path.join(COMPACTED_LOG_FILE);
+	let record = encode_record(&contents)?;
+	{
+		let mut compacted = File::create(&compacted_path)?;
+		compacted.write_all(&record)?;
+		compacted.sync_all()?;
+	}
+	// opened before renaming, so that the current log is kept if this fails
+	let file = OpenOptions::new().append(true).open(&compacted_path)?;
+	fs::rename(&compacted_path, path.join(LOG_FILE))?;
+	// the current log is gone, so the new one must be used from now on
+	if let Err(e) = sync_dir(path) {
+		warn!(target: "db", "Error syncing the database directory: {}", e);
+	}
+	Ok((file, record.len() as u64))
+}
+
+// Make the renaming of a file in the directory durable.
+#[cfg(unix)]
+fn sync_dir(path: &Path) -> io::Result<()> {
+	File::open(path)?.sync_all()
+}
+
+#[cfg(not(unix))]
+fn sync_dir(_path: &Path) -> io::Result<()> {
+	Ok(())
+}
+
+// Apply the records of the log to `db`, stopping at the first incomplete or corrupted one.
+// Returns the number of applied records.
+fn replay(db: &InMemory, mut log: &[u8]) -> usize {
+	let mut records = 0;
+	while !log.is_empty() {
+		let len = match u32::decode(&mut log) {
+			Some(len) => len as usize,
+			None => break,
+		};
+		if log.len() < CHECKSUM_LEN + len {
+			break;
+		}
+		let (checksum, rest) = log.split_at(CHECKSUM_LEN);
+		let (mut record, rest) = rest.split_at(len);
+		if checksum != &twox_128(record)[..] {
+			break;
+		}
+		log = rest;
+		match Vec::<LogOp>::decode(&mut record) {
+			Some(ops) => {
+				let mut transaction = DBTransaction::new();
+				for (col, key, value) in ops {
+					match value {
+						Some(value) => transaction.put(col, &key, &value),
+						None => transaction.delete(col, &key),
+					}
+				}
+				db.write_buffered(transaction);
+				records += 1;
+			},
+			None => break,
+		}
+	}
+	if !log.is_empty() {
+		warn!(target: "db", "Discarding {} bytes of the log after an incomplete or corrupted record", log.len());
+	}
+	records
+}
+
+fn encode_record(transaction: &DBTransaction) -> io::Result<Vec<u8>> {
+	let ops: Vec<LogOp> = transaction.ops.iter().map(|op| match *op {
+		DBOp::Insert { col, ref key, ref value } => (col, key.to_vec(), Some(value.to_vec())),
+		DBOp::Delete { col, ref key } => (col, key.to_vec(), None),
+	}).collect();
+	let record = ops.encode();
+	if record.len() > u32::max_value() as usize {
+		return Err(io::Error::new(io::ErrorKind::InvalidInput, "Transaction is too large for the database log"));
+	}
+	let mut encoded = (record.len() as u32).encode();
+	encoded.extend_from_slice(&twox_128(&record));
+	encoded.extend(record);
+	Ok(encoded)
+}
+
+impl KeyValueDB for LogDb {
+	fn get(&self, col: Option<u32>, key: &[u8]) -> kvdb::Result<Option<DBValue>> {
+		self.db.get(col, key)
+	}
+
+	fn get_by_prefix(&self, col: Option<u32>, prefix: &[u8]) -> Option<Box<[u8]>> {
+		self.db.get_by_prefix(col, prefix)
+	}
+
+	fn write_buffered(&self, transaction: DBTransaction) {
+		let mut log = self.log.lock();
+		match log.append(&transaction) {
+			Ok(()) => {
+				self.db.write_buffered(transaction);
+				log.compact_if_needed(&self.db);
+			},
+			Err(e) => {
+				warn!(target: "db", "Error writing to the database log: {}", e);
+				if log.error.is_none() {
+					log.error = Some(e);
+				}
+			},
+		}
+	}
+
+	fn write(&self, transaction: DBTransaction) -> kvdb::Result<()> {
+		let mut log = self.log.lock();
+		log.take_error()?;
+		log.append(&transaction)?;
+		log.file.sync_data()?;
+		self.db.write_buffered(transaction);
+		log.compact_if_needed(&self.db);
+		Ok(())
+	}
+
+	fn flush(&self) -> kvdb::Result<()> {
+		let mut log = self.log.lock();
+		log.take_error()?;
+		log.file.sync_data()?;
+		Ok(())
+	}
+
+	fn iter<'a>(&'a self, col: Option<u32>) -> Box<Iterator<Item=(Box<[u8]>, Box<[u8]>)> + 'a> {
+		self.db.iter(col)
+	}
+
+	fn iter_from_prefix<'a>(&'a self, col: Option<u32>, prefix: &'a [u8])
+		-> Box<Iterator<Item=(Box<[u8]>, Box<[u8]>)> + 'a>
+	{
+		self.db.iter_from_prefix(col, prefix)
+	}
+
+	fn restore(&self, _new_db: &str) -> kvdb::Result<()> {
+		Err("Restoring is not supported by the log database".into())
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use tempdir::TempDir;
+	use state_db::PruningMode;
+	use utils::{COLUMN_META, meta_keys, open_database};
+	use {DatabaseBackend, DatabaseSettings};
+	use super::*;
+
+	const COL: Option<u32> = Some(0);
+
+	fn put(db: &LogDb, key: &[u8], value: &[u8]) {
+		let mut transaction = DBTransaction::new();
+		transaction.put(COL, key, value);
+		db.write(transaction).unwrap();
+	}
+
+	#[test]
+	fn data_survives_reopening() {
+		let dir = TempDir::new("log-db").unwrap();
+		{
+			let db = LogDb::open(dir.path(), 1).unwrap();
+			put(&db, b"a", b"1");
+			put(&db, b"b", b"2");
+			let mut transaction = DBTransaction::new();
+			transaction.delete(COL, b"a");
+			transaction.put(COL, b"c", b"3");
+			db.write(transaction).unwrap();
+		}
+
+		let db = LogDb::open(dir.path(), 1).unwrap();
+		assert_eq!(db.get(COL, b"a").unwrap(), None);
+		assert_eq!(db.get(COL, b"b").unwrap().map(|v| v.to_vec()), Some(b"2".to_vec()));
+		assert_eq!(db.get(COL, b"c").unwrap().map(|v| v.to_vec()), Some(b"3".to_vec()));
+	}
+
+	#[test]
+	fn incomplete_record_is_discarded() {
+		let dir = TempDir::new("log-db").unwrap();
+		{
+			let db = LogDb::open(dir.path(), 1).unwrap();
+			put(&db, b"a", b"1");
+			put(&db, b"b", b"2");
+		}
+		let log_path = dir.path().join(LOG_FILE);
+		let len = fs::metadata(&log_path).unwrap().len();
+		OpenOptions::new().write(true).open(&log_path).unwrap().set_len(len - 1).unwrap();
+
+		let db = LogDb::open(dir.path(), 1).unwrap();
+		assert_eq!(db.get(COL, b"a").unwrap().map(|v| v.to_vec()), Some(b"1".to_vec()));
+		assert_eq!(db.get(COL, b"b").unwrap(), None);
+
+		// the log is compacted on opening, so new records are appended after the valid ones
+		put(&db, b"c", b"3");
+		drop(db);
+		let db = LogDb::open(dir.path(), 1).unwrap();
+		assert_eq!(db.get(COL, b"a").unwrap().map(|v| v.to_vec()), Some(b"1".to_vec()));
+		assert_eq!(db.get(COL, b"c").unwrap().map(|v| v.to_vec()), Some(b"3".to_vec()));
+	}
+
+	#[test]
+	fn corrupted_record_is_discarded() {
+		let dir = TempDir::new("log-db").unwrap();
+		{
+			let db = LogDb::open(dir.path(), 1).unwrap();
+			put(&db, b"a", b"1");
+			put(&db, b"b", b"2");
+		}
+		let log_path = dir.path().join(LOG_FILE);
+		let mut log = Vec::new();
+		File::open(&log_path).unwrap().read_to_end(&mut log).unwrap();
+		let last = log.len() - 1;
+		log[last] ^= 0xff;
+		File::create(&log_path).unwrap().write_all(&log).unwrap();
+
+		let db = LogDb::open(dir.path(), 1).unwrap();
+		assert_eq!(db.get(COL, b"a").unwrap().map(|v| v.to_vec()), Some(b"1".to_vec()));
+		assert_eq!(db.get(COL, b"b").unwrap(), None);
+	}
+
+	#[test]
+	fn database_of_another_backend_is_not_opened() {
+		let dir = TempDir::new("log-db").unwrap();
+		let settings = |backend| DatabaseSettings {
+			cache_size: None,
+			path: dir.path().to_owned(),
+			pruning: PruningMode::ArchiveAll,
+			state_cache_size: 0,
+			backend,
+		};
+
+		open_database(&settings(DatabaseBackend::Log), "full").unwrap();
+		assert!(open_database(&settings(DatabaseBackend::RocksDb), "full").is_err());
+		assert!(open_database(&settings(DatabaseBackend::Log), "light").is_err());
+		open_database(&settings(DatabaseBackend::Log), "full").unwrap();
+	}
+
+	#[test]
+	fn database_of_another_version_is_not_opened() {
+		let dir = TempDir::new("log-db").unwrap();
+		let settings = DatabaseSettings {
+			cache_size: None,
+			path: dir.path().to_owned(),
+			pruning: PruningMode::ArchiveAll,
+			state_cache_size: 0,
+			backend: DatabaseBackend::Log,
+		};
+
+		let db = open_database(&settings, "full").unwrap();
+		let mut transaction = DBTransaction::new();
+		transaction.delete(COLUMN_META, meta_keys::VERSION);
+		db.write(transaction).unwrap();
+		drop(db);
+		assert!(open_database(&settings, "full").is_err());
+	}
+
+	#[test]
+	fn log_is_compacted_when_it_grows() {
+		let dir = TempDir::new("log-db").unwrap();
+		let log_path = dir.path().join(LOG_FILE);
+		{
+			let db = LogDb::open(dir.path(), 1).unwrap();
+			let compacted_len = fs::metadata(&log_path).unwrap().len();
+			db.log.lock().compaction_len = compacted_len + 300;
+			put(&db, b"a", &[1; 200]);
+			assert!(fs::metadata(&log_path).unwrap().len() > compacted_len + 200);
+			put(&db, b"a", &[2; 200]);
+
+			// only the last value is left
+			let len = fs::metadata(&log_path).unwrap().len();
+			assert!(len < compacted_len + 300, "log of {} bytes has not been compacted", len);
+			assert_eq!(db.log.lock().len, len);
+			assert!(!dir.path().join(COMPACTED_LOG_FILE).exists());
+
+			// and new records are appended to the compacted log
+			put(&db, b"b", b"2");
+		}
+
+		let db = LogDb::open(dir.path(), 1).unwrap();
+		assert_eq!(db.get(COL, b"a").unwrap().map(|v| v.to_vec()), Some(vec![2; 200]));
+		assert_eq!(db.get(COL, b"b").unwrap().map(|v| v.to_vec()), Some(b"2".to_vec()));
+	}
+}
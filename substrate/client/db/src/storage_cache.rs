@@ -0,0 +1,286 @@
+// Copyright 2018 Parity Technologies (UK) Ltd.
+// This file is part of Substrate.
+
+// Substrate is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// Substrate is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with Substrate.  If not, see <http://www.gnu.org/licenses/>.
+
+//! Global cache of storage values.
+//!
+//! The cache holds the storage values of a single block, normally the best block. States of
+//! that block (e.g. the ones used to import its children) read through the cache; states of
+//! all other blocks go straight to the trie. When a new best block is committed on top of the
+//! cached one, its storage changes are applied to the cache, which then moves on to the new
+//! block. Any other change of the best block clears the cache.
+//!
+//! Only top-level storage is cached; child storage is always read from the trie.
+
+use std::sync::Arc;
+use linked_hash_map::LinkedHashMap;
+use memorydb::MemoryDB;
+use parking_lot::Mutex;
+use runtime_primitives::traits::Block as BlockT;
+use state_machine::{Backend as StateBackend, Consolidate, TryIntoTrieBackend, TrieBackend};
+
+/// Storage changes, as passed to `storage_root`.
+pub type StorageChanges = Vec<(Vec<u8>, Option<Vec<u8>>)>;
+
+/// Shared cache of storage values.
+pub type SharedCache<Block> = Arc<Mutex<Cache<Block>>>;
+
+/// Create a new shared cache instance with given size limit in bytes.
+pub fn new_shared_cache<Block: BlockT>(limit: usize) -> SharedCache<Block> {
+	Arc::new(Mutex::new(Cache {
+		lru: LinkedHashMap::new(),
+		used: 0,
+		limit,
+		hash: None,
+	}))
+}
+
+/// LRU cache of the storage values of a single block.
+pub struct Cache<Block: BlockT> {
+	lru: LinkedHashMap<Vec<u8>, Option<Vec<u8>>>,
+	used: usize,
+	limit: usize,
+	/// Hash of the block the cached values belong to.
+	hash: Option<Block::Hash>,
+}
+
+fn entry_size(key: &[u8], value: &Option<Vec<u8>>) -> usize {
+	key.len() + value.as_ref().map_or(0, |v| v.len())
+}
+
+impl<Block: BlockT> Cache<Block> {
+	/// Hash of the block the cached values belong to.
+	pub fn hash(&self) -> Option<Block::Hash> {
+		self.hash
+	}
+
+	/// Get the cached value of the given key at the given block. `None` if the key is not cached.
+	fn get(&mut self, at: &Block::Hash, key: &[u8]) -> Option<Option<Vec<u8>>> {
+		if self.hash.as_ref() != Some(at) {
+			return None;
+		}
+		self.lru.get_refresh(key).map(|value| value.clone())
+	}
+
+	/// Cache the value of the given key at the given block. Ignored if the cache has moved on.
+	fn insert(&mut self, at: &Block::Hash, key: Vec<u8>, value: Option<Vec<u8>>) {
+		if self.hash.as_ref() != Some(at) {
+			return;
+		}
+		self.set(key, value);
+	}
+
+	fn set(&mut self, key: Vec<u8>, value: Option<Vec<u8>>) {
+		self.used += entry_size(&key, &value);
+		if let Some(old) = self.lru.insert(key.clone(), value) {
+			self.used -= entry_size(&key, &old);
+		}
+		while self.used > self.limit {
+			match self.lru.pop_front() {
+				Some((key, value)) => self.used -= entry_size(&key, &value),
+				None => break,
+			}
+		}
+	}
+
+	fn clear(&mut self) {
+		self.lru.clear();
+		self.used = 0;
+	}
+
+	/// Note a new best block. If it's a child of the cached block and its storage changes are
+	/// known, these are applied and the cache moves on to the new block. Otherwise the cache is
+	/// cleared.
+	pub fn sync(&mut self, hash: Block::Hash, parent_hash: Block::Hash, changes: Option<StorageChanges>) {
+		match changes {
+			Some(changes) if self.hash == Some(parent_hash) => {
+				for (key, value) in changes {
+					// only update what's already cached; new keys are cached on first read
+					if self.lru.contains_key(&key) {
+						self.set(key, value);
+					}
+				}
+			},
+			_ => self.clear(),
+		}
+		self.hash = Some(hash);
+	}
+}
+
+/// Transaction of the caching state: the trie update along with the top-level storage changes
+/// it was computed from.
+#[derive(Default)]
+pub struct CachingTransaction {
+	/// Trie nodes update.
+	pub trie: MemoryDB,
+	/// Top-level storage changes.
+	pub storage: StorageChanges,
+}
+
+impl Consolidate for CachingTransaction {
+	fn consolidate(&mut self, other: Self) {
+		self.trie.consolidate(other.trie);
+		self.storage.extend(other.storage);
+	}
+}
+
+/// State backend that reads through the shared cache when it's at the cached block.
+#[derive(Clone)]
+pub struct CachingState<Block: BlockT> {
+	state: TrieBackend,
+	cache: SharedCache<Block>,
+	/// Hash of the block this state is at. `None` for the genesis state under construction.
+	hash: Option<Block::Hash>,
+}
+
+impl<Block: BlockT> CachingState<Block> {
+	/// Create a new instance wrapping the state of the given block.
+	pub fn new(state: TrieBackend, cache: SharedCache<Block>, hash: Option<Block::Hash>) -> Self {
+		CachingState {
+			state,
+			cache,
+			hash,
+		}
+	}
+}
+
+impl<Block: BlockT> StateBackend for CachingState<Block> {
+	type Error = <TrieBackend as StateBackend>::Error;
+	type Transaction = CachingTransaction;
+
+	fn storage(&self, key: &[u8]) -> Result<Option<Vec<u8>>, Self::Error> {
+		let hash = match self.hash {
+			Some(ref hash) => hash,
+			None => return self.state.storage(key),
+		};
+		if let Some(value) = self.cache.lock().get(hash, key) {
+			return Ok(value);
+		}
+		let value = self.state.storage(key)?;
+		self.cache.lock().insert(hash, key.to_vec(), value.clone());
+		Ok(value)
+	}
+
+	fn for_keys_with_prefix<F: FnMut(&[u8])>(&self, prefix: &[u8], f: F) {
+		self.state.for_keys_with_prefix(prefix, f)
+	}
+
+	fn keys_with_prefix(&self, prefix: &[u8], start_key: Option<&[u8]>, count: usize) -> Vec<Vec<u8>> {
+		self.state.keys_with_prefix(prefix, start_key, count)
+	}
+
+	fn storage_root<I>(&self, delta: I) -> ([u8; 32], Self::Transaction)
+		where I: IntoIterator<Item=(Vec<u8>, Option<Vec<u8>>)>
+	{
+		let storage: StorageChanges = delta.into_iter().collect();
+		let (root, trie) = self.state.storage_root(storage.iter().cloned());
+		(root, CachingTransaction { trie, storage })
+	}
+
+	fn child_storage(&self, storage_key: &[u8], key: &[u8]) -> Result<Option<Vec<u8>>, Self::Error> {
+		self.state.child_storage(storage_key, key)
+	}
+
+	fn for_keys_in_child_storage<F: FnMut(&[u8])>(&self, storage_key: &[u8], f: F) -> Result<(), Self::Error> {
+		self.state.for_keys_in_child_storage(storage_key, f)
+	}
+
+	fn child_storage_root<I>(&self, storage_key: &[u8], delta: I) -> ([u8; 32], bool, Self::Transaction)
+		where I: IntoIterator<Item=(Vec<u8>, Option<Vec<u8>>)>
+	{
+		let (root, is_empty, trie) = self.state.child_storage_root(storage_key, delta);
+		(root, is_empty, CachingTransaction { trie, storage: Vec::new() })
+	}
+
+	fn killed_child_storage_root<I>(&self, storage_key: &[u8], delta: I) -> ([u8; 32], bool, Self::Transaction)
+		where I: IntoIterator<Item=(Vec<u8>, Option<Vec<u8>>)>
+	{
+		let (root, is_empty, trie) = self.state.killed_child_storage_root(storage_key, delta);
+		(root, is_empty, CachingTransaction { trie, storage: Vec::new() })
+	}
+
+	fn build_trie<I>(&self, pairs: I) -> ([u8; 32], Self::Transaction)
+		where I: IntoIterator<Item=(Vec<u8>, Vec<u8>)>
+	{
+		let (root, trie) = self.state.build_trie(pairs);
+		(root, CachingTransaction { trie, storage: Vec::new() })
+	}
+
+	fn pairs(&self) -> Vec<(Vec<u8>, Vec<u8>)> {
+		self.state.pairs()
+	}
+}
+
+impl<Block: BlockT> TryIntoTrieBackend for CachingState<Block> {
+	fn try_into_trie_backend(self) -> Option<TrieBackend> {
+		Some(self.state)
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use primitives::H256;
+	use runtime_primitives::testing::Block as RawBlock;
+
+	type Block = RawBlock<u64>;
+
+	#[test]
+	fn cache_is_bound_to_block() {
+		let cache = new_shared_cache::<Block>(1024);
+		let (h0, h1, h2) = (H256::from(1), H256::from(2), H256::from(3));
+		let mut cache = cache.lock();
+		cache.sync(h0, Default::default(), None);
+
+		cache.insert(&h0, vec![1], Some(vec![1]));
+		cache.insert(&h0, vec![2], Some(vec![2]));
+		cache.insert(&h1, vec![3], Some(vec![3]));
+		assert_eq!(cache.get(&h0, &[1]), Some(Some(vec![1])));
+		assert_eq!(cache.get(&h0, &[3]), None);
+		assert_eq!(cache.get(&h1, &[1]), None);
+
+		// child of the cached block: changes are applied
+		cache.sync(h1, h0, Some(vec![(vec![1], None), (vec![4], Some(vec![4]))]));
+		assert_eq!(cache.get(&h1, &[1]), Some(None));
+		assert_eq!(cache.get(&h1, &[2]), Some(Some(vec![2])));
+		assert_eq!(cache.get(&h1, &[4]), None);
+		assert_eq!(cache.get(&h0, &[2]), None);
+
+		// not a child of the cached block: cache is cleared
+		cache.sync(h2, h0, Some(vec![]));
+		assert_eq!(cache.get(&h2, &[2]), None);
+		assert_eq!(cache.hash(), Some(h2));
+	}
+
+	#[test]
+	fn cache_is_size_bounded() {
+		let cache = new_shared_cache::<Block>(10);
+		let h0 = H256::from(1);
+		let mut cache = cache.lock();
+		cache.sync(h0, Default::default(), None);
+
+		cache.insert(&h0, vec![1], Some(vec![0; 4]));
+		cache.insert(&h0, vec![2], Some(vec![0; 4]));
+		assert_eq!(cache.used, 10);
+		assert!(cache.get(&h0, &[1]).is_some());
+
+		// evicts the least recently used entry
+		cache.insert(&h0, vec![3], Some(vec![0; 4]));
+		assert_eq!(cache.used, 10);
+		assert!(cache.get(&h0, &[1]).is_some());
+		assert!(cache.get(&h0, &[2]).is_none());
+		assert!(cache.get(&h0, &[3]).is_some());
+	}
+}
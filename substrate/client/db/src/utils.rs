@@ -23,15 +23,22 @@ use kvdb::{self, KeyValueDB, DBTransaction};
 use kvdb_rocksdb::{Database, DatabaseConfig};
 
 use client;
-use codec::Decode;
+use client::leaves::LeafSet;
+use codec::{Decode, Encode};
 use hashdb::DBValue;
 use runtime_primitives::generic::BlockId;
-use runtime_primitives::traits::{As, Block as BlockT, Header as HeaderT, Hash, HashFor, Zero};
-use DatabaseSettings;
+use runtime_primitives::traits::{As, Block as BlockT, Header as HeaderT, Zero};
+use log_db::{LogDb, LOG_FILE};
+use {DatabaseBackend, DatabaseSettings};
 
 /// Number of columns in the db. Must be the same for both full && light dbs.
 /// Otherwise RocksDb will fail to open database && check its type.
-pub const NUM_COLUMNS: u32 = 7;
+pub const NUM_COLUMNS: u32 = 9;
+/// File present in the directory of every RocksDB database.
+const ROCKSDB_FILE: &str = "CURRENT";
+/// Version of the database layout. Must be increased on every change of the layout, as
+/// databases of other versions are refused.
+pub const DB_VERSION: u32 = 1;
 /// Meta column. Thes set of keys in the column is shared by full && light storages.
 pub const COLUMN_META: Option<u32> = Some(0);
 
@@ -39,8 +46,18 @@ pub const COLUMN_META: Option<u32> = Some(0);
 pub mod meta_keys {
 	/// Type of storage (full or light).
 	pub const TYPE: &[u8; 4] = b"type";
+	/// Key-value store backing the database.
+	pub const BACKEND: &[u8; 7] = b"backend";
+	/// Version of the database layout.
+	pub const VERSION: &[u8; 7] = b"version";
 	/// Best block key.
 	pub const BEST_BLOCK: &[u8; 4] = b"best";
+	/// Last finalized block key.
+	pub const FINALIZED_BLOCK: &[u8; 5] = b"final";
+	/// Leaves of the block tree key.
+	pub const LEAVES: &[u8; 6] = b"leaves";
+	/// Prefix of the keys of block children lists, followed by the parent block hash.
+	pub const CHILDREN_PREFIX: &[u8; 8] = b"children";
 }
 
 /// Database metadata.
@@ -49,14 +66,18 @@ pub struct Meta<N, H> {
 	pub best_hash: H,
 	/// Number of the best known block.
 	pub best_number: N,
+	/// Hash of the last finalized block.
+	pub finalized_hash: H,
+	/// Number of the last finalized block.
+	pub finalized_number: N,
 	/// Hash of the genesis block.
 	pub genesis_hash: H,
 }
 
-/// Type of block key in the database (LE block number).
+/// Type of the key of the canonical block index in the database (BE block number).
 pub type BlockKey = [u8; 4];
 
-/// Convert block number into key (LE representation).
+/// Convert block number into key (BE representation).
 pub fn number_to_db_key<N>(n: N) -> BlockKey where N: As<u64> {
 	let n: u64 = n.as_();
 	assert!(n & 0xffffffff00000000 == 0);
@@ -81,85 +102,161 @@ pub fn db_err(err: kvdb::Error) -> client::error::Error {
 
 /// Open RocksDB database.
 pub fn open_database(config: &DatabaseSettings, db_type: &str) -> client::error::Result<Arc<KeyValueDB>> {
-	let mut db_config = DatabaseConfig::with_columns(Some(NUM_COLUMNS));
-	db_config.memory_budget = config.cache_size;
-	db_config.wal = true;
-	let path = config.path.to_str().ok_or_else(|| client::error::ErrorKind::Backend("Invalid database path".into()))?;
-	let db = Database::open(&db_config, &path).map_err(db_err)?;
+	// the files of the other backend would otherwise be ignored and a new database created
+	let foreign_file = match config.backend {
+		DatabaseBackend::RocksDb => LOG_FILE,
+		DatabaseBackend::Log => ROCKSDB_FILE,
+	};
+	if config.path.join(foreign_file).exists() {
+		return Err(client::error::ErrorKind::Backend(
+			format!("Database was created by a different backend. Expected: {}", config.backend.id())).into());
+	}
 
-	// check database type
+	let db: Arc<KeyValueDB> = match config.backend {
+		DatabaseBackend::RocksDb => {
+			let mut db_config = DatabaseConfig::with_columns(Some(NUM_COLUMNS));
+			db_config.memory_budget = config.cache_size;
+			db_config.wal = true;
+			let path = config.path.to_str().ok_or_else(|| client::error::ErrorKind::Backend("Invalid database path".into()))?;
+			Arc::new(Database::open(&db_config, &path).map_err(db_err)?)
+		},
+		DatabaseBackend::Log => Arc::new(LogDb::open(&config.path, NUM_COLUMNS).map_err(db_err)?),
+	};
+
+	// check database type and backend
+	let stored_backend = db.get(COLUMN_META, meta_keys::BACKEND).map_err(db_err)?;
 	match db.get(COLUMN_META, meta_keys::TYPE).map_err(db_err)? {
 		Some(stored_type) => {
 			if db_type.as_bytes() != &*stored_type {
 				return Err(client::error::ErrorKind::Backend(
 					format!("Unexpected database type. Expected: {}", db_type)).into());
 			}
+			// databases created before the backend was recorded are RocksDB ones
+			let stored_backend = stored_backend.map(|b| b.to_vec())
+				.unwrap_or_else(|| DatabaseBackend::RocksDb.id().as_bytes().to_vec());
+			if config.backend.id().as_bytes() != &stored_backend[..] {
+				return Err(client::error::ErrorKind::Backend(
+					format!("Unexpected database backend. Expected: {}", config.backend.id())).into());
+			}
+			// databases created before the version was recorded have the old layout, in which
+			// blocks were keyed by number
+			let stored_version = match db.get(COLUMN_META, meta_keys::VERSION).map_err(db_err)? {
+				Some(version) => u32::decode(&mut &version[..])
+					.ok_or_else(|| client::error::ErrorKind::Backend("Error decoding database version".into()))?,
+				None => 0,
+			};
+			if stored_version != DB_VERSION {
+				return Err(client::error::ErrorKind::Backend(
+					format!("Unsupported database version {}. Expected: {}. The database must be purged and resynced", stored_version, DB_VERSION)).into());
+			}
 		},
 		None => {
 			let mut transaction = DBTransaction::new();
 			transaction.put(COLUMN_META, meta_keys::TYPE, db_type.as_bytes());
+			transaction.put(COLUMN_META, meta_keys::BACKEND, config.backend.id().as_bytes());
+			transaction.put(COLUMN_META, meta_keys::VERSION, &DB_VERSION.encode());
 			db.write(transaction).map_err(db_err)?;
 		},
 	}
 
-	Ok(Arc::new(db))
+	Ok(db)
 }
 
-/// Convert block id to block key, reading number from db if required.
-pub fn read_id<Block>(db: &KeyValueDB, col_index: Option<u32>, id: BlockId<Block>) -> Result<Option<BlockKey>, client::error::Error>
+/// Convert block id to block hash, reading the hash of the canonical block with given number
+/// from db if required.
+pub fn read_id<Block>(db: &KeyValueDB, col_index: Option<u32>, id: BlockId<Block>) -> Result<Option<Block::Hash>, client::error::Error>
 	where
 		Block: BlockT,
 {
 	match id {
-		BlockId::Hash(h) => db.get(col_index, h.as_ref())
-			.map(|v| v.map(|v| {
-				let mut key: [u8; 4] = [0; 4];
-				key.copy_from_slice(&v);
-				key
-			})).map_err(db_err),
-		BlockId::Number(n) => Ok(Some(number_to_db_key(n))),
+		BlockId::Hash(h) => Ok(Some(h)),
+		BlockId::Number(n) => match db.get(col_index, &number_to_db_key(n)).map_err(db_err)? {
+			Some(hash) => match Decode::decode(&mut &hash[..]) {
+				Some(hash) => Ok(Some(hash)),
+				None => Err(client::error::ErrorKind::Backend("Error decoding block hash".into()).into()),
+			},
+			None => Ok(None),
+		},
 	}
 }
 
-/// Read database column entry for the given block.
+/// Read database column entry for the given block. Block data is keyed by block hash.
 pub fn read_db<Block>(db: &KeyValueDB, col_index: Option<u32>, col: Option<u32>, id: BlockId<Block>) -> client::error::Result<Option<DBValue>>
 	where
 		Block: BlockT,
 {
-	read_id(db, col_index, id).and_then(|key| match key {
-		Some(key) => db.get(col, &key).map_err(db_err),
+	read_id(db, col_index, id).and_then(|hash| match hash {
+		Some(hash) => db.get(col, hash.as_ref()).map_err(db_err),
 		None => Ok(None),
 	})
 }
 
 /// Read meta from the database.
-pub fn read_meta<Block>(db: &KeyValueDB, col_header: Option<u32>) -> Result<Meta<<<Block as BlockT>::Header as HeaderT>::Number, Block::Hash>, client::error::Error>
+pub fn read_meta<Block>(db: &KeyValueDB, col_index: Option<u32>, col_header: Option<u32>) -> Result<Meta<<<Block as BlockT>::Header as HeaderT>::Number, Block::Hash>, client::error::Error>
 	where
 		Block: BlockT,
 {
 	let genesis_number = <<Block as BlockT>::Header as HeaderT>::Number::zero();
-	let (best_hash, best_number) = if let Some(Some(header)) = db.get(COLUMN_META, meta_keys::BEST_BLOCK).and_then(|id|
-		match id {
-			Some(id) => db.get(col_header, &id).map(|h| h.map(|b| Block::Header::decode(&mut &b[..]))),
-			None => Ok(None),
-		}).map_err(db_err)?
-	{
-		let hash = header.hash();
-		debug!("DB Opened blockchain db, best {:?} ({})", hash, header.number());
-		(hash, *header.number())
-	} else {
-		(Default::default(), genesis_number)
+	let load_meta_block = |desc: &str, key: &[u8]| -> Result<_, client::error::Error> {
+		if let Some(Some(header)) = db.get(COLUMN_META, key).and_then(|id|
+			match id {
+				Some(id) => db.get(col_header, &id).map(|h| h.map(|b| Block::Header::decode(&mut &b[..]))),
+				None => Ok(None),
+			}).map_err(db_err)?
+		{
+			let hash = header.hash();
+			debug!("DB Opened blockchain db, {} {:?} ({})", desc, hash, header.number());
+			Ok(Some((hash, *header.number())))
+		} else {
+			Ok(None)
+		}
 	};
 
-	let genesis_hash = db.get(col_header, &number_to_db_key(genesis_number))
-		.map_err(db_err)?
-		.map(|raw| HashFor::<Block>::hash(&raw[..]))
-		.unwrap_or_default()
-		.into();
+	let genesis_hash: Block::Hash = read_id::<Block>(db, col_index, BlockId::Number(genesis_number))?
+		.unwrap_or_default();
+
+	let (best_hash, best_number) = load_meta_block("best", meta_keys::BEST_BLOCK)?
+		.unwrap_or_else(|| (Default::default(), genesis_number));
+	// the genesis block is final
+	let (finalized_hash, finalized_number) = load_meta_block("final", meta_keys::FINALIZED_BLOCK)?
+		.unwrap_or_else(|| (genesis_hash, genesis_number));
 
 	Ok(Meta {
 		best_hash,
 		best_number,
+		finalized_hash,
+		finalized_number,
 		genesis_hash,
 	})
 }
+
+/// Read the leaves of the block tree from the database.
+pub fn read_leaves<Block>(db: &KeyValueDB) -> client::error::Result<LeafSet<Block::Hash, <<Block as BlockT>::Header as HeaderT>::Number>>
+	where
+		Block: BlockT,
+{
+	match db.get(COLUMN_META, meta_keys::LEAVES).map_err(db_err)? {
+		Some(leaves) => LeafSet::decode(&mut &leaves[..])
+			.ok_or_else(|| client::error::ErrorKind::Backend("Error decoding leaves".into()).into()),
+		None => Ok(LeafSet::new()),
+	}
+}
+
+/// Key of the children list of the given block in the meta column.
+pub fn children_key<H: AsRef<[u8]>>(parent_hash: &H) -> Vec<u8> {
+	let mut key = meta_keys::CHILDREN_PREFIX.to_vec();
+	key.extend_from_slice(parent_hash.as_ref());
+	key
+}
+
+/// Read hashes of the known children of the given block from the database.
+pub fn read_children<Block>(db: &KeyValueDB, parent_hash: &Block::Hash) -> client::error::Result<Vec<Block::Hash>>
+	where
+		Block: BlockT,
+{
+	match db.get(COLUMN_META, &children_key(parent_hash)).map_err(db_err)? {
+		Some(children) => Decode::decode(&mut &children[..])
+			.ok_or_else(|| client::error::ErrorKind::Backend("Error decoding children".into()).into()),
+		None => Ok(Vec::new()),
+	}
+}
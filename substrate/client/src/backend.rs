@@ -19,7 +19,7 @@
 use state_machine::backend::Backend as StateBackend;
 use error;
 use runtime_primitives::bft::Justification;
-use runtime_primitives::traits::Block as BlockT;
+use runtime_primitives::traits::{Block as BlockT, NumberFor};
 use runtime_primitives::generic::BlockId;
 
 /// Block insertion operation. Keeps hold if the inserted block state and data.
@@ -38,10 +38,22 @@ pub trait BlockImportOperation<Block: BlockT> {
 		is_new_best: bool
 	) -> error::Result<()>;
 
+	/// Append the block of a state snapshot to the transaction. Unlike with `set_block_data`,
+	/// the parent of the block need not be known: the block starts a new chain as its best and
+	/// finalized block.
+	fn set_snapshot_block_data(
+		&mut self,
+		header: Block::Header,
+		justification: Justification<Block::Hash>,
+	) -> error::Result<()>;
+
 	/// Inject storage data into the database.
 	fn update_storage(&mut self, update: <Self::State as StateBackend>::Transaction) -> error::Result<()>;
 	/// Inject storage data into the database replacing any existing data.
 	fn reset_storage<I: Iterator<Item=(Vec<u8>, Vec<u8>)>>(&mut self, iter: I) -> error::Result<()>;
+	/// Inject the data of the child trie stored under `storage_key` into the database. Must be
+	/// called after `reset_storage`, which sets the root of the child trie.
+	fn reset_child_storage<I: Iterator<Item=(Vec<u8>, Vec<u8>)>>(&mut self, storage_key: &[u8], iter: I) -> error::Result<()>;
 }
 
 /// Client backend. Manages the data layer.
@@ -59,16 +71,42 @@ pub trait Backend<Block: BlockT>: Send + Sync {
 	type Blockchain: ::blockchain::Backend<Block>;
 	/// Associated state backend type.
 	type State: StateBackend;
+	/// Associated node-local storage type of the offchain workers.
+	type OffchainStorage: OffchainStorage + 'static;
 
 	/// Begin a new block insertion transaction with given parent block id.
 	/// When constructing the genesis, this is called with all-zero hash.
 	fn begin_operation(&self, block: BlockId<Block>) -> error::Result<Self::BlockImportOperation>;
 	/// Commit block insertion.
 	fn commit_operation(&self, transaction: Self::BlockImportOperation) -> error::Result<()>;
+	/// Mark a block and all of its ancestors as finalized. Finalizing a block that is
+	/// already finalized is a no-op.
+	fn finalize_block(&self, block: BlockId<Block>) -> error::Result<()>;
 	/// Returns reference to blockchain backend.
 	fn blockchain(&self) -> &Self::Blockchain;
 	/// Returns state backend with post-state of given block.
 	fn state_at(&self, block: BlockId<Block>) -> error::Result<Self::State>;
+	/// Attempts to revert the chain by `n` blocks. Each step removes the best block along with
+	/// all other blocks of the same number. Finalized blocks are never reverted. Returns the
+	/// number of best chain blocks that were reverted: blocks of forks higher than the new best
+	/// block are removed too, but not counted.
+	fn revert(&self, n: NumberFor<Block>) -> error::Result<NumberFor<Block>>;
+	/// Returns the node-local storage of the offchain workers. `None` if the backend does not
+	/// support offchain workers.
+	fn offchain_storage(&self) -> Option<Self::OffchainStorage>;
+	/// Returns a trie backend over the node storage holding the changes tries of the imported
+	/// blocks. Changes trie nodes are stored apart from the state and are not pruned along with
+	/// it. `None` if the backend does not keep them.
+	fn changes_trie_storage(&self) -> Option<state_machine::TrieBackend>;
+}
+
+/// Node-local key/value store of the offchain workers. Its contents are not a part of the chain
+/// state and are never shared with other nodes.
+pub trait OffchainStorage: Send + Sync {
+	/// Read the entry with given key.
+	fn get(&self, key: &[u8]) -> error::Result<Option<Vec<u8>>>;
+	/// Set or clear the entry with given key.
+	fn set(&self, key: &[u8], value: Option<&[u8]>) -> error::Result<()>;
 }
 
 /// Mark for all Backend implementations, that are making use of state data, stored locally.
@@ -20,7 +20,7 @@ use runtime_primitives::traits::{Block as BlockT, Header as HeaderT};
 use runtime_primitives::generic::BlockId;
 use runtime_primitives::bft::Justification;
 
-use error::Result;
+use error::{ErrorKind, Result};
 
 /// Blockchain database header backend. Does not perform any validation.
 pub trait HeaderBackend<Block: BlockT>: Send + Sync {
@@ -40,6 +40,10 @@ pub trait Backend<Block: BlockT>: HeaderBackend<Block> {
 	fn body(&self, id: BlockId<Block>) -> Result<Option<Vec<<Block as BlockT>::Extrinsic>>>;
 	/// Get block justification. Returns `None` if justification does not exist.
 	fn justification(&self, id: BlockId<Block>) -> Result<Option<Justification<Block::Hash>>>;
+	/// Get hashes of all blocks that have no children, highest first.
+	fn leaves(&self) -> Result<Vec<Block::Hash>>;
+	/// Get hashes of all known children of the given block.
+	fn children(&self, parent_hash: Block::Hash) -> Result<Vec<Block::Hash>>;
 }
 
 /// Block import outcome
@@ -63,6 +67,10 @@ pub struct Info<Block: BlockT> {
 	pub best_number: <<Block as BlockT>::Header as HeaderT>::Number,
 	/// Genesis block hash.
 	pub genesis_hash: <<Block as BlockT>::Header as HeaderT>::Hash,
+	/// Last finalized block hash.
+	pub finalized_hash: <<Block as BlockT>::Header as HeaderT>::Hash,
+	/// Last finalized block number.
+	pub finalized_number: <<Block as BlockT>::Header as HeaderT>::Number,
 }
 
 /// Block status.
@@ -73,3 +81,107 @@ pub enum BlockStatus {
 	/// Not in the queue or the blockchain.
 	Unknown,
 }
+
+/// A tree-route from one block to another in the chain.
+///
+/// All blocks prior to the pivot in the route are the reverse-order unique ancestry of the
+/// first block, the block at the pivot index is the common ancestor, and all blocks after
+/// the pivot are the ancestry of the second block, in order.
+///
+/// The ancestry sets include the given blocks, so the tree-route is never empty.
+///
+/// ```ignore
+/// Tree route from R1 to E2. Retracted is [R1, R2, R3], Common is C, enacted [E1, E2]
+///   <- R3 <- R2 <- R1
+///  /
+/// C
+///  \-> E1 -> E2
+/// ```
+#[derive(Debug)]
+pub struct TreeRoute<Block: BlockT> {
+	route: Vec<RouteEntry<Block>>,
+	pivot: usize,
+}
+
+impl<Block: BlockT> TreeRoute<Block> {
+	/// Get the blocks retracted when moving from the first block to the second one, starting
+	/// with the first block.
+	pub fn retracted(&self) -> &[RouteEntry<Block>] {
+		&self.route[..self.pivot]
+	}
+
+	/// Get the common ancestor block. This might be one of the two blocks of the route.
+	pub fn common_block(&self) -> &RouteEntry<Block> {
+		self.route.get(self.pivot).expect("tree-routes are computed between blocks; \
+			which are included in the route; \
+			thus it is never empty; qed")
+	}
+
+	/// Get the blocks enacted when moving from the first block to the second one, ending
+	/// with the second block.
+	pub fn enacted(&self) -> &[RouteEntry<Block>] {
+		&self.route[self.pivot + 1..]
+	}
+}
+
+/// Block of a tree route.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RouteEntry<Block: BlockT> {
+	/// Number of the block.
+	pub number: <<Block as BlockT>::Header as HeaderT>::Number,
+	/// Hash of the block.
+	pub hash: Block::Hash,
+}
+
+/// Compute a tree-route between two blocks. See `TreeRoute` docs for more details.
+pub fn tree_route<Block: BlockT, Backend: HeaderBackend<Block>>(
+	backend: &Backend,
+	from: BlockId<Block>,
+	to: BlockId<Block>,
+) -> Result<TreeRoute<Block>> {
+	let load_header = |id: BlockId<Block>| -> Result<Block::Header> {
+		match backend.header(id)? {
+			Some(header) => Ok(header),
+			None => Err(ErrorKind::UnknownBlock(format!("{:?}", id)).into()),
+		}
+	};
+	let entry = |header: &Block::Header| RouteEntry {
+		number: *header.number(),
+		hash: header.hash(),
+	};
+
+	let mut from = load_header(from)?;
+	let mut to = load_header(to)?;
+
+	let mut from_branch = Vec::new();
+	let mut to_branch = Vec::new();
+
+	while to.number() > from.number() {
+		to_branch.push(entry(&to));
+		to = load_header(BlockId::Hash(*to.parent_hash()))?;
+	}
+
+	while from.number() > to.number() {
+		from_branch.push(entry(&from));
+		from = load_header(BlockId::Hash(*from.parent_hash()))?;
+	}
+
+	// numbers are equal now; walk backwards until the blocks are the same
+	while to.hash() != from.hash() {
+		to_branch.push(entry(&to));
+		to = load_header(BlockId::Hash(*to.parent_hash()))?;
+
+		from_branch.push(entry(&from));
+		from = load_header(BlockId::Hash(*from.parent_hash()))?;
+	}
+
+	// add the pivot block and append the reversed to-branch
+	let pivot = from_branch.len();
+	from_branch.push(entry(&to));
+	from_branch.extend(to_branch.into_iter().rev());
+
+	Ok(TreeRoute {
+		route: from_branch,
+		pivot,
+	})
+}
@@ -17,8 +17,8 @@
 use std::sync::Arc;
 use runtime_primitives::generic::BlockId;
 use runtime_primitives::traits::Block as BlockT;
-use state_machine::{self, OverlayedChanges, Ext, 
-	CodeExecutor, ExecutionManager, native_when_possible};
+use state_machine::{self, OverlayedChanges, Ext, OffchainExt,
+	CodeExecutor, ExecutionManager, ExecutionStrategy, native_when_possible};
 use runtime_io::Externalities;
 use executor::{RuntimeVersion, RuntimeInfo};
 
@@ -77,6 +77,17 @@ pub trait CallExecutor<B: BlockT> {
 		call_data: &[u8]
 	) -> Result<(Vec<u8>, Vec<Vec<u8>>), error::Error>;
 
+	/// Execute a call as an offchain worker on top of state in a block of given hash. The runtime
+	/// can use the node-local capabilities given by `offchain`.
+	///
+	/// No changes are made to the state.
+	fn call_offchain(&self,
+		id: &BlockId<B>,
+		offchain: &mut OffchainExt,
+		method: &str,
+		call_data: &[u8],
+	) -> Result<Vec<u8>, error::Error>;
+
 	/// Get runtime version if supported.
 	fn native_runtime_version(&self) -> Option<RuntimeVersion>;
 }
@@ -86,12 +97,20 @@ pub trait CallExecutor<B: BlockT> {
 pub struct LocalCallExecutor<B, E> {
 	backend: Arc<B>,
 	executor: E,
+	call_executor: Option<E>,
 }
 
 impl<B, E> LocalCallExecutor<B, E> {
 	/// Creates new instance of local call executor.
 	pub fn new(backend: Arc<B>, executor: E) -> Self {
-		LocalCallExecutor { backend, executor }
+		LocalCallExecutor { backend, executor, call_executor: None }
+	}
+
+	/// Use given executor for calls which are not a part of block execution (e.g. made over RPC),
+	/// so that they can be executed with different limits.
+	pub fn with_call_executor(mut self, call_executor: E) -> Self {
+		self.call_executor = Some(call_executor);
+		self
 	}
 }
 
@@ -100,6 +119,7 @@ impl<B, E> Clone for LocalCallExecutor<B, E> where E: Clone {
 		LocalCallExecutor {
 			backend: self.backend.clone(),
 			executor: self.executor.clone(),
+			call_executor: self.call_executor.clone(),
 		}
 	}
 }
@@ -118,9 +138,10 @@ impl<B, E, Block> CallExecutor<Block> for LocalCallExecutor<B, E>
 		call_data: &[u8],
 	) -> error::Result<CallResult> {
 		let mut changes = OverlayedChanges::default();
-		let (return_data, _) = self.call_at_state(
+		let (return_data, _) = state_machine::execute_using_consensus_failure_handler(
 			&self.backend.state_at(*id)?,
 			&mut changes,
+			self.call_executor.as_ref().unwrap_or(&self.executor),
 			method,
 			call_data,
 			native_when_possible(),
@@ -176,6 +197,22 @@ impl<B, E, Block> CallExecutor<Block> for LocalCallExecutor<B, E>
 		.map_err(Into::into)
 	}
 
+	fn call_offchain(&self,
+		id: &BlockId<Block>,
+		offchain: &mut OffchainExt,
+		method: &str,
+		call_data: &[u8],
+	) -> error::Result<Vec<u8>> {
+		state_machine::execute_offchain(
+			&self.backend.state_at(*id)?,
+			self.call_executor.as_ref().unwrap_or(&self.executor),
+			offchain,
+			method,
+			call_data,
+			ExecutionStrategy::NativeWhenPossible,
+		).map_err(Into::into)
+	}
+
 	fn native_runtime_version(&self) -> Option<RuntimeVersion> {
 		<E as RuntimeInfo>::NATIVE_VERSION
 	}
@@ -0,0 +1,204 @@
+// Copyright 2018 Parity Technologies (UK) Ltd.
+// This file is part of Substrate.
+
+// Substrate is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// Substrate is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with Substrate.  If not, see <http://www.gnu.org/licenses/>.
+
+//! Canonical hash trie definitions and helper functions.
+//!
+//! Each CHT is a trie mapping numbers of `SIZE` consecutive blocks to their canonical hashes.
+//! Once the blocks of a CHT are finalized, light clients keep only its root and prune the headers
+//! themselves. A pruned header can then be requested from a full node, which proves it against
+//! the root.
+
+use std::collections::HashMap;
+
+use codec::Encode;
+use runtime_primitives::traits::{As, Hash as HashT, Header as HeaderT};
+use state_machine::{self, TrieBackend, TryIntoTrieBackend, backend::InMemory};
+
+use error::{ErrorKind as ClientErrorKind, Result as ClientResult};
+use light::backend::check_read_proof_with_root;
+
+/// The number of blocks covered by each CHT.
+pub const SIZE: u64 = 2048;
+
+/// Get the number of the CHT covering given block. The genesis block is not covered by any CHT.
+pub fn block_to_cht_number<N: As<u64>>(block_num: N) -> Option<u64> {
+	match block_num.as_() {
+		0 => None,
+		block_num => Some((block_num - 1) / SIZE),
+	}
+}
+
+/// Get the number of the first block covered by given CHT.
+pub fn start_number(cht_num: u64) -> u64 {
+	cht_num * SIZE + 1
+}
+
+/// Get the number of the last block covered by given CHT.
+pub fn end_number(cht_num: u64) -> u64 {
+	(cht_num + 1) * SIZE
+}
+
+/// Get the number of the last CHT which can be built once given block is finalized.
+///
+/// A CHT is only built when all blocks of the next CHT are finalized too, so that the headers
+/// of at least `SIZE` most recent finalized blocks are always kept.
+pub fn max_cht_number<N: As<u64>>(finalized_num: N) -> Option<u64> {
+	(finalized_num.as_() / SIZE).checked_sub(2)
+}
+
+/// Compute the root of given CHT. `hashes` yields canonical hashes of its blocks, starting with
+/// the first one.
+pub fn compute_root<Header, I>(cht_num: u64, hashes: I) -> ClientResult<Header::Hash>
+	where
+		Header: HeaderT,
+		I: IntoIterator<Item=ClientResult<Option<Header::Hash>>>,
+{
+	Ok(Header::Hashing::trie_root(build_pairs::<Header, I>(cht_num, hashes)?))
+}
+
+/// Build the trie of given CHT, from which proofs of its blocks can then be generated with
+/// `build_proof_from_trie`. `hashes` yields canonical hashes of the CHT blocks, starting with
+/// the first one.
+pub fn build_trie<Header, I>(cht_num: u64, hashes: I) -> ClientResult<TrieBackend>
+	where
+		Header: HeaderT,
+		I: IntoIterator<Item=ClientResult<Option<Header::Hash>>>,
+{
+	let storage: HashMap<_, _> = build_pairs::<Header, I>(cht_num, hashes)?.into_iter().collect();
+	InMemory::from(storage).try_into_trie_backend()
+		.ok_or_else(|| ClientErrorKind::Backend(format!("Failed to build trie of CHT#{}", cht_num)).into())
+}
+
+/// Build a proof of the canonical hash of given block from the trie of its CHT.
+pub fn build_proof_from_trie(trie: TrieBackend, block_num: u64) -> ClientResult<Vec<Vec<u8>>> {
+	state_machine::prove_read(trie, &[encode_cht_key(block_num)])
+		.map(|(_, proof)| proof)
+		.map_err(Into::into)
+}
+
+/// Build a proof of the canonical hash of given block against the root of its CHT. `hashes`
+/// yields canonical hashes of the CHT blocks, starting with the first one.
+pub fn build_proof<Header, I>(cht_num: u64, block_num: u64, hashes: I) -> ClientResult<Vec<Vec<u8>>>
+	where
+		Header: HeaderT,
+		I: IntoIterator<Item=ClientResult<Option<Header::Hash>>>,
+{
+	build_proof_from_trie(build_trie::<Header, I>(cht_num, hashes)?, block_num)
+}
+
+/// Check a proof that the remote header is the canonical header of the given block, built
+/// against the root of its CHT.
+pub fn check_proof<Header: HeaderT>(
+	local_root: Header::Hash,
+	local_number: Header::Number,
+	remote_header: &Header,
+	remote_proof: Vec<Vec<u8>>,
+) -> ClientResult<()> {
+	if *remote_header.number() != local_number {
+		return Err(ClientErrorKind::InvalidHeaderProof.into());
+	}
+
+	let remote_hash = remote_header.hash();
+	match check_read_proof_with_root(local_root, &encode_cht_key(local_number.as_()), remote_proof)? {
+		Some(ref hash) if &hash[..] == remote_hash.as_ref() => Ok(()),
+		_ => Err(ClientErrorKind::InvalidHeaderProof.into()),
+	}
+}
+
+fn build_pairs<Header, I>(cht_num: u64, hashes: I) -> ClientResult<Vec<(Vec<u8>, Vec<u8>)>>
+	where
+		Header: HeaderT,
+		I: IntoIterator<Item=ClientResult<Option<Header::Hash>>>,
+{
+	let mut hashes = hashes.into_iter();
+	let mut pairs = Vec::new();
+	for block_num in start_number(cht_num)..end_number(cht_num) + 1 {
+		match hashes.next() {
+			Some(Ok(Some(hash))) => pairs.push((encode_cht_key(block_num), hash.as_ref().to_vec())),
+			Some(Err(error)) => return Err(error),
+			_ => return Err(ClientErrorKind::MissingHashRequiredForCHT(cht_num, block_num).into()),
+		}
+	}
+	Ok(pairs)
+}
+
+fn encode_cht_key(block_num: u64) -> Vec<u8> {
+	block_num.encode()
+}
+
+#[cfg(test)]
+mod tests {
+	use runtime_primitives::testing::{H256, Header};
+	use super::*;
+
+	fn header(number: u64) -> Header {
+		Header {
+			number,
+			parent_hash: Default::default(),
+			state_root: Default::default(),
+			extrinsics_root: Default::default(),
+			digest: Default::default(),
+		}
+	}
+
+	fn hashes(cht_num: u64) -> Vec<ClientResult<Option<H256>>> {
+		(start_number(cht_num)..end_number(cht_num) + 1).map(|num| Ok(Some(header(num).hash()))).collect()
+	}
+
+	#[test]
+	fn block_to_cht_number_works() {
+		assert_eq!(block_to_cht_number(0u64), None);
+		assert_eq!(block_to_cht_number(1u64), Some(0));
+		assert_eq!(block_to_cht_number(SIZE), Some(0));
+		assert_eq!(block_to_cht_number(SIZE + 1), Some(1));
+		assert_eq!(start_number(1), SIZE + 1);
+		assert_eq!(end_number(1), SIZE * 2);
+	}
+
+	#[test]
+	fn max_cht_number_works() {
+		assert_eq!(max_cht_number(0u64), None);
+		assert_eq!(max_cht_number(SIZE * 2 - 1), None);
+		assert_eq!(max_cht_number(SIZE * 2), Some(0));
+		assert_eq!(max_cht_number(SIZE * 3 - 1), Some(0));
+		assert_eq!(max_cht_number(SIZE * 3), Some(1));
+	}
+
+	#[test]
+	fn compute_root_fails_on_missing_hash() {
+		let mut hashes = hashes(0);
+		hashes[10] = Ok(None);
+		assert!(compute_root::<Header, _>(0, hashes).is_err());
+		assert!(compute_root::<Header, _>(0, vec![Ok(Some(H256::default()))]).is_err());
+	}
+
+	#[test]
+	fn header_proof_is_generated_and_checked() {
+		let root = compute_root::<Header, _>(1, hashes(1)).unwrap();
+		let proof = build_proof::<Header, _>(1, SIZE + 10, hashes(1)).unwrap();
+		check_proof(root, SIZE + 10, &header(SIZE + 10), proof.clone()).unwrap();
+
+		// header of another block
+		assert!(check_proof(root, SIZE + 10, &header(SIZE + 11), proof.clone()).is_err());
+		// header which is not canonical
+		let mut fork_header = header(SIZE + 10);
+		fork_header.extrinsics_root = H256::from(1);
+		assert!(check_proof(root, SIZE + 10, &fork_header, proof.clone()).is_err());
+		// another root
+		let other_root = compute_root::<Header, _>(0, hashes(0)).unwrap();
+		assert!(check_proof(other_root, SIZE + 10, &header(SIZE + 10), proof).is_err());
+	}
+}
@@ -16,40 +16,54 @@
 
 //! Substrate Client
 
+use std::collections::VecDeque;
 use std::sync::Arc;
 use futures::sync::mpsc;
 use parking_lot::{Mutex, RwLock};
 use primitives::AuthorityId;
 use runtime_primitives::{bft::Justification, generic::{BlockId, SignedBlock, Block as RuntimeBlock}};
-use runtime_primitives::traits::{Block as BlockT, Header as HeaderT, Zero, One, As};
+use runtime_primitives::traits::{Block as BlockT, Header as HeaderT, Hash as HashT, Digest as DigestT, Zero, As};
 use runtime_primitives::BuildStorage;
 use primitives::storage::{StorageKey, StorageData};
-use codec::Decode;
-use state_machine::{Ext, OverlayedChanges, Backend as StateBackend, CodeExecutor, ExecutionStrategy, ExecutionManager};
+use codec::{Decode, Encode, Input, Output};
+use state_machine::{self, Ext, OverlayedChanges, Backend as StateBackend, CodeExecutor, ExecutionStrategy, ExecutionManager,
+	OffchainExt};
 
 use backend::{self, BlockImportOperation};
 use blockchain::{self, Info as ChainInfo, Backend as ChainBackend, HeaderBackend as ChainHeaderBackend};
 use call_executor::{CallExecutor, LocalCallExecutor};
 use executor::{RuntimeVersion, RuntimeInfo};
-use {error, in_mem, block_builder, runtime_io, bft, genesis};
+use {cht, error, in_mem, block_builder, runtime_io, bft, genesis};
 
 /// Type that implements `futures::Stream` of block import events.
 pub type BlockchainEventStream<Block> = mpsc::UnboundedReceiver<BlockImportNotification<Block>>;
 
+/// Type that implements `futures::Stream` of finality notifications.
+pub type FinalityNotifications<Block> = mpsc::UnboundedReceiver<FinalityNotification<Block>>;
+
 /// Substrate Client
 pub struct Client<B, E, Block> where Block: BlockT {
 	backend: Arc<B>,
 	executor: E,
 	import_notification_sinks: Mutex<Vec<mpsc::UnboundedSender<BlockImportNotification<Block>>>>,
+	finality_notification_sinks: Mutex<Vec<mpsc::UnboundedSender<FinalityNotification<Block>>>>,
 	import_lock: Mutex<()>,
 	importing_block: RwLock<Option<Block::Hash>>, // holds the block hash currently being imported. TODO: replace this with block queue
 	execution_strategy: ExecutionStrategy,
+	cht_cache: Mutex<VecDeque<(u64, state_machine::TrieBackend)>>,
 }
 
+/// The number of most recently used CHT tries kept by the client to serve header proofs.
+const CHT_CACHE_SIZE: usize = 4;
+
 /// A source of blockchain evenets.
 pub trait BlockchainEvents<Block: BlockT> {
 	/// Get block import event stream.
 	fn import_notification_stream(&self) -> mpsc::UnboundedReceiver<BlockImportNotification<Block>>;
+
+	/// Get a stream of finality notifications. Not guaranteed to be fired for every
+	/// finalized block.
+	fn finality_notification_stream(&self) -> FinalityNotifications<Block>;
 }
 
 /// Chain head information.
@@ -126,6 +140,20 @@ pub struct BlockImportNotification<Block: BlockT> {
 	pub header: Block::Header,
 	/// Is this the new best block.
 	pub is_new_best: bool,
+	/// Blocks removed from the canonical chain by this import, starting with the previous best block.
+	pub retracted: Vec<Block::Hash>,
+	/// Blocks added to the canonical chain by this import, ending with the imported block.
+	/// Empty if the imported block is not the new best block.
+	pub enacted: Vec<Block::Hash>,
+}
+
+/// Summary of a finalized block.
+#[derive(Clone, Debug)]
+pub struct FinalityNotification<Block: BlockT> {
+	/// Finalized block header hash.
+	pub hash: Block::Hash,
+	/// Finalized block header.
+	pub header: Block::Header,
 }
 
 /// A header paired with a justification which has already been checked.
@@ -142,6 +170,40 @@ impl<Block: BlockT> JustifiedHeader<Block> {
 	}
 }
 
+/// The full state of a finalized block along with its header and justification. Allows a node to
+/// start from that block without the preceding block history.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct StateSnapshot<Block: BlockT> {
+	/// Header of the block.
+	pub header: <Block as BlockT>::Header,
+	/// Justification of the block.
+	pub justification: Justification<Block::Hash>,
+	/// Top-level storage pairs, including the roots of the child tries.
+	pub storage: Vec<(Vec<u8>, Vec<u8>)>,
+	/// Storage pairs of the child tries, along with their storage keys.
+	pub children: Vec<(Vec<u8>, Vec<(Vec<u8>, Vec<u8>)>)>,
+}
+
+impl<Block: BlockT> Encode for StateSnapshot<Block> {
+	fn encode_to<T: Output>(&self, dest: &mut T) {
+		dest.push(&self.header);
+		dest.push(&self.justification);
+		dest.push(&self.storage);
+		dest.push(&self.children);
+	}
+}
+
+impl<Block: BlockT> Decode for StateSnapshot<Block> {
+	fn decode<I: Input>(value: &mut I) -> Option<Self> {
+		Some(StateSnapshot {
+			header: Decode::decode(value)?,
+			justification: Decode::decode(value)?,
+			storage: Decode::decode(value)?,
+			children: Decode::decode(value)?,
+		})
+	}
+}
+
 /// Create an instance of in-memory client.
 pub fn new_in_mem<E, Block, S>(
 	executor: E,
@@ -182,9 +244,11 @@ impl<B, E, Block> Client<B, E, Block> where
 			backend,
 			executor,
 			import_notification_sinks: Mutex::new(Vec::new()),
+			finality_notification_sinks: Mutex::new(Vec::new()),
 			import_lock: Mutex::new(()),
 			importing_block: RwLock::new(None),
 			execution_strategy,
+			cht_cache: Mutex::new(VecDeque::new()),
 		})
 	}
 
@@ -206,6 +270,95 @@ impl<B, E, Block> Client<B, E, Block> where
 			.to_vec()))
 	}
 
+	/// Return up to `count` storage keys with given `prefix` in state of the given block, ordered
+	/// lexicographically and starting after `start_key` if one is given.
+	pub fn storage_keys(
+		&self,
+		id: &BlockId<Block>,
+		prefix: &StorageKey,
+		start_key: Option<&StorageKey>,
+		count: usize,
+	) -> error::Result<Vec<StorageKey>> {
+		let keys = self.state_at(id)?.keys_with_prefix(&prefix.0, start_key.map(|key| &key.0[..]), count);
+		Ok(keys.into_iter().map(StorageKey).collect())
+	}
+
+	/// Return up to `count` storage entries with given `prefix` in state of the given block, ordered
+	/// lexicographically by key and starting after `start_key` if one is given.
+	pub fn storage_pairs(
+		&self,
+		id: &BlockId<Block>,
+		prefix: &StorageKey,
+		start_key: Option<&StorageKey>,
+		count: usize,
+	) -> error::Result<Vec<(StorageKey, StorageData)>> {
+		let state = self.state_at(id)?;
+		let mut pairs = Vec::new();
+		for key in state.keys_with_prefix(&prefix.0, start_key.map(|key| &key.0[..]), count) {
+			if let Some(value) = state.storage(&key).map_err(|e| error::Error::from_state(Box::new(e)))? {
+				pairs.push((StorageKey(key), StorageData(value)));
+			}
+		}
+		Ok(pairs)
+	}
+
+	/// Return the blocks from `first` to `last` (inclusive, `first` being an ancestor of `last`) in
+	/// which any of the given keys has changed, in ascending order, along with the changed keys.
+	///
+	/// Only the changes tries of the blocks are read, so this works on pruned state too. Fails if
+	/// any of the blocks does not commit to a changes trie, or the backend does not keep them.
+	pub fn key_changes(
+		&self,
+		first: Block::Hash,
+		last: Block::Hash,
+		keys: &[StorageKey],
+	) -> error::Result<Vec<(Block::Hash, Vec<StorageKey>)>> {
+		let first_number = self.block_number_from_id(&BlockId::Hash(first))?
+			.ok_or_else(|| error::ErrorKind::UnknownBlock(format!("{}", BlockId::<Block>::Hash(first))))?;
+
+		let mut changes = Vec::new();
+		let mut hash = last;
+		loop {
+			let header = self.header(&BlockId::Hash(hash))?
+				.ok_or_else(|| error::ErrorKind::UnknownBlock(format!("{}", BlockId::<Block>::Hash(hash))))?;
+			if *header.number() < first_number {
+				return Err(error::ErrorKind::InvalidBlockRange(
+					format!("{} is not an ancestor of {}", BlockId::<Block>::Hash(first), BlockId::<Block>::Hash(last))
+				).into());
+			}
+
+			let changed_keys = self.changed_keys(hash, &header, keys)?;
+			if !changed_keys.is_empty() {
+				changes.push((hash, changed_keys));
+			}
+
+			if hash == first {
+				break;
+			}
+			hash = *header.parent_hash();
+		}
+
+		changes.reverse();
+		Ok(changes)
+	}
+
+	/// Return the given keys that have changed in the block with given hash and header.
+	fn changed_keys(&self, hash: Block::Hash, header: &Block::Header, keys: &[StorageKey]) -> error::Result<Vec<StorageKey>> {
+		let unavailable = || error::ErrorKind::ChangesTrieUnavailable(format!("{}", BlockId::<Block>::Hash(hash)));
+		let root = header.digest().changes_trie_root().ok_or_else(unavailable)?;
+		let storage = self.backend.changes_trie_storage().ok_or_else(unavailable)?;
+
+		let mut changed_keys = Vec::new();
+		for key in keys {
+			let changes = state_machine::changes_trie::key_changes(&storage, root, &key.0)
+				.map_err(|e| error::Error::from_state(Box::new(e)))?;
+			if changes.is_some() {
+				changed_keys.push(key.clone());
+			}
+		}
+		Ok(changed_keys)
+	}
+
 	/// Get the code at a given block.
 	pub fn code_at(&self, id: &BlockId<Block>) -> error::Result<Vec<u8>> {
 		self.storage(id, &StorageKey(b":code".to_vec())).map(|data| data.0)
@@ -237,6 +390,77 @@ impl<B, E, Block> Client<B, E, Block> where
 		self.state_at(id).and_then(|state| self.executor.prove_at_state(state, &mut Default::default(), method, call_data))
 	}
 
+	/// Run the offchain worker of the runtime on top of the state of given block. The runtime can
+	/// use the node-local capabilities given by `offchain`.
+	///
+	/// No changes are made to the state.
+	pub fn offchain_worker(&self, id: &BlockId<Block>, offchain: &mut OffchainExt) -> error::Result<()> {
+		let header = self.header(id)?.ok_or_else(|| error::ErrorKind::UnknownBlock(format!("{:?}", id)))?;
+		self.executor.call_offchain(id, offchain, "offchain_worker", &header.number().encode())?;
+		Ok(())
+	}
+
+	/// Execute given block again on top of the state of its parent, collecting the host
+	/// function calls made by the Wasm runtime.
+	pub fn trace_block(&self, id: &BlockId<Block>) -> error::Result<Vec<executor::trace::ExtrinsicTrace>> {
+		let unknown_block = || error::ErrorKind::UnknownBlock(format!("{:?}", id));
+		let header = self.header(id)?.ok_or_else(unknown_block)?;
+		let body = self.body(id)?.ok_or_else(unknown_block)?;
+		let state = self.state_at(&BlockId::Hash(*header.parent_hash()))?;
+		let block = <Block as BlockT>::new(header, body).encode();
+		let (result, traces) = executor::trace::collect(|| self.executor.call_at_state(
+			&state,
+			&mut Default::default(),
+			"execute_block",
+			&block,
+			state_machine::always_wasm(),
+		));
+		result?;
+		Ok(traces)
+	}
+
+	/// Read storage values of given keys at a given block AND return read proof.
+	///
+	/// Returned proof contains all trie nodes touched while reading the keys.
+	pub fn read_proof(&self, id: &BlockId<Block>, keys: &[Vec<u8>]) -> error::Result<Vec<Vec<u8>>> {
+		self.state_at(id)
+			.and_then(|state| state_machine::prove_read(state, keys).map_err(Into::into))
+			.map(|(_, proof)| proof)
+	}
+
+	/// Read the header of given canonical block AND return a proof of it against the root of
+	/// the CHT covering the block.
+	pub fn header_proof(&self, id: &BlockId<Block>) -> error::Result<(Block::Header, Vec<Vec<u8>>)> {
+		let header = self.header(id)?.ok_or_else(|| error::ErrorKind::UnknownBlock(format!("{:?}", id)))?;
+		let block_num = header.number().as_();
+		let cht_num = cht::block_to_cht_number(block_num)
+			.ok_or_else(|| error::ErrorKind::Backend("Genesis header is not covered by any CHT".into()))?;
+		let proof = cht::build_proof_from_trie(self.cht_trie(cht_num)?, block_num)?;
+		Ok((header, proof))
+	}
+
+	/// Get the trie of given CHT, building it only if it isn't cached yet. Fails unless all
+	/// blocks of the CHT are finalized, since the canonical hashes could change otherwise.
+	fn cht_trie(&self, cht_num: u64) -> error::Result<state_machine::TrieBackend> {
+		if cht::end_number(cht_num) > self.backend.blockchain().info()?.finalized_number.as_() {
+			return Err(error::ErrorKind::UnfinalizedCHT(cht_num).into());
+		}
+
+		let mut cache = self.cht_cache.lock();
+		if let Some(position) = cache.iter().position(|&(num, _)| num == cht_num) {
+			let entry = cache.remove(position).expect("position is within the cache; qed");
+			let trie = entry.1.clone();
+			cache.push_front(entry);
+			return Ok(trie);
+		}
+
+		let hashes = (cht::start_number(cht_num)..).map(|num| self.block_hash(As::sa(num)));
+		let trie = cht::build_trie::<Block::Header, _>(cht_num, hashes)?;
+		cache.push_front((cht_num, trie.clone()));
+		cache.truncate(CHT_CACHE_SIZE);
+		Ok(trie)
+	}
+
 	/// Set up the native execution environment to call into a native runtime code.
 	pub fn using_environment<F: FnOnce() -> T, T>(
 		&self, f: F
@@ -358,7 +582,28 @@ impl<B, E, Block> Client<B, E, Block> where
 			None => None,
 		};
 
-		let is_new_best = header.number() == &(self.backend.blockchain().info()?.best_number + One::one());
+		let chain_info = self.backend.blockchain().info()?;
+		let is_new_best = header.number() > &chain_info.best_number;
+		let (retracted, enacted) = if is_new_best && parent_hash != chain_info.best_hash {
+			let route = blockchain::tree_route(
+				self.backend.blockchain(),
+				BlockId::Hash(chain_info.best_hash),
+				BlockId::Hash(parent_hash),
+			)?;
+			if route.common_block().number < chain_info.finalized_number {
+				warn!("Refusing to import {} (#{}): its fork does not include the finalized block {}",
+					hash, header.number(), chain_info.finalized_hash);
+				return Ok(ImportResult::KnownBad);
+			}
+			trace!("Reorganizing chain: {} blocks retracted, {} enacted", route.retracted().len(), route.enacted().len() + 1);
+			let retracted = route.retracted().iter().map(|entry| entry.hash).collect();
+			let enacted = route.enacted().iter().map(|entry| entry.hash).chain(Some(hash)).collect();
+			(retracted, enacted)
+		} else if is_new_best {
+			(Vec::new(), vec![hash])
+		} else {
+			(Vec::new(), Vec::new())
+		};
 		trace!("Imported {}, (#{}), best={}, origin={:?}", hash, header.number(), is_new_best, origin);
 		let unchecked: bft::UncheckedJustification<_> = justification.uncheck().into();
 		transaction.set_block_data(header.clone(), body, Some(unchecked.into()), is_new_best)?;
@@ -366,12 +611,15 @@ impl<B, E, Block> Client<B, E, Block> where
 			transaction.update_storage(storage_update)?;
 		}
 		self.backend.commit_operation(transaction)?;
+		self.notify_finalized(chain_info.finalized_hash)?;
 		if origin == BlockOrigin::NetworkBroadcast || origin == BlockOrigin::Own || origin == BlockOrigin::ConsensusBroadcast {
 			let notification = BlockImportNotification::<Block> {
 				hash: hash,
 				origin: origin,
 				header: header,
 				is_new_best: is_new_best,
+				retracted,
+				enacted,
 			};
 			self.import_notification_sinks.lock()
 				.retain(|sink| sink.unbounded_send(notification.clone()).is_ok());
@@ -379,6 +627,117 @@ impl<B, E, Block> Client<B, E, Block> where
 		Ok(ImportResult::Queued)
 	}
 
+	/// Mark a block and all of its ancestors as finalized.
+	///
+	/// Finality notifications are sent out if the finalized head has changed.
+	pub fn finalize_block(&self, id: BlockId<Block>) -> error::Result<()> {
+		let _import_lock = self.import_lock.lock();
+		let last_finalized = self.backend.blockchain().info()?.finalized_hash;
+		self.backend.finalize_block(id)?;
+		self.notify_finalized(last_finalized)
+	}
+
+	// Sends out a finality notification if the finalized head has moved away from `last_finalized`.
+	fn notify_finalized(&self, last_finalized: Block::Hash) -> error::Result<()> {
+		let finalized_hash = self.backend.blockchain().info()?.finalized_hash;
+		if finalized_hash == last_finalized {
+			return Ok(());
+		}
+
+		let header = self.header(&BlockId::Hash(finalized_hash))?
+			.expect("finalized block is always in the blockchain; qed");
+		trace!("Finalized {} (#{})", finalized_hash, header.number());
+		let notification = FinalityNotification::<Block> {
+			hash: finalized_hash,
+			header,
+		};
+		self.finality_notification_sinks.lock()
+			.retain(|sink| sink.unbounded_send(notification.clone()).is_ok());
+		Ok(())
+	}
+
+	/// Attempts to revert the chain by `n` blocks. Finalized blocks are never reverted. Returns
+	/// the number of best chain blocks that were reverted. Blocks of forks higher than the new
+	/// best block are removed as well.
+	pub fn revert(&self, n: <<Block as BlockT>::Header as HeaderT>::Number) -> error::Result<<<Block as BlockT>::Header as HeaderT>::Number> {
+		let _import_lock = self.import_lock.lock();
+		self.backend.revert(n)
+	}
+
+	/// Take a snapshot of the state at the given block. The block must have a justification.
+	pub fn state_snapshot(&self, id: &BlockId<Block>) -> error::Result<StateSnapshot<Block>> {
+		let header = self.header(id)?
+			.ok_or_else(|| error::ErrorKind::UnknownBlock(format!("{:?}", id)))?;
+		let justification = self.justification(id)?
+			.ok_or_else(|| error::ErrorKind::InvalidStateSnapshot(format!("block {:?} has no justification", id)))?;
+		let state = self.state_at(id)?;
+		let storage = state.pairs();
+		let mut children = Vec::new();
+		for &(ref storage_key, _) in storage.iter().filter(|&&(ref key, _)| state_machine::is_child_storage_key(key)) {
+			let mut keys = Vec::new();
+			state.for_keys_in_child_storage(storage_key, |key| keys.push(key.to_vec()))
+				.map_err(|e| error::Error::from_state(Box::new(e)))?;
+			let mut pairs = Vec::with_capacity(keys.len());
+			for key in keys {
+				let value = state.child_storage(storage_key, &key)
+					.map_err(|e| error::Error::from_state(Box::new(e)))?
+					.ok_or_else(|| error::ErrorKind::NoValueForKey(key.clone()))?;
+				pairs.push((key, value));
+			}
+			children.push((storage_key.clone(), pairs));
+		}
+
+		Ok(StateSnapshot {
+			header,
+			justification,
+			storage,
+			children,
+		})
+	}
+
+	/// Import a state snapshot as the new best and finalized block. Only possible while the chain
+	/// consists of the genesis block alone.
+	///
+	/// The ancestry of the block, which determines the authorities of the block, is not known.
+	/// So its justification is checked against the given `authorities`, which must be trusted.
+	pub fn import_state(&self, snapshot: StateSnapshot<Block>, authorities: &[AuthorityId]) -> error::Result<()> {
+		let _import_lock = self.import_lock.lock();
+		let StateSnapshot { header, justification, storage, children } = snapshot;
+		let hash = header.hash();
+		if self.backend.blockchain().info()?.best_number != Zero::zero() {
+			return Err(error::ErrorKind::InvalidStateSnapshot("the chain already has blocks beyond genesis".into()).into());
+		}
+		if header.number().is_zero() {
+			return Err(error::ErrorKind::InvalidStateSnapshot("the genesis state can not be imported".into()).into());
+		}
+		if justification.hash != hash {
+			return Err(error::ErrorKind::InvalidStateSnapshot(format!("justification does not match block {}", hash)).into());
+		}
+		let unchecked: bft::UncheckedJustification<_> = justification.uncheck().into();
+		let justification = ::bft::check_justification::<Block>(authorities, *header.parent_hash(), unchecked)
+			.map_err(|_| error::ErrorKind::BadJustification(format!("{}", hash)))?;
+		let state_root = <<Block::Header as HeaderT>::Hashing as HashT>::trie_root(storage.iter().cloned());
+		if state_root != *header.state_root() {
+			return Err(error::ErrorKind::InvalidStateSnapshot(format!("storage does not match the state root of block {}", hash)).into());
+		}
+		for &(ref storage_key, ref pairs) in &children {
+			let child_root = <<Block::Header as HeaderT>::Hashing as HashT>::trie_root(pairs.iter().cloned());
+			let stored_root = storage.iter().find(|&&(ref key, _)| key == storage_key).map(|&(_, ref root)| &root[..]);
+			if stored_root != Some(child_root.as_ref()) {
+				return Err(error::ErrorKind::InvalidStateSnapshot(format!("child storage {:?} does not match its root", storage_key)).into());
+			}
+		}
+
+		info!("Importing state of block {} (#{})", hash, header.number());
+		let mut op = self.backend.begin_operation(BlockId::Hash(Default::default()))?;
+		op.reset_storage(storage.into_iter())?;
+		for (storage_key, pairs) in children {
+			op.reset_child_storage(&storage_key, pairs.into_iter())?;
+		}
+		op.set_snapshot_block_data(header, justification)?;
+		self.backend.commit_operation(op)
+	}
+
 	/// Get blockchain info.
 	pub fn info(&self) -> error::Result<ClientInfo<Block>> {
 		let info = self.backend.blockchain().info().map_err(|e| error::Error::from_blockchain(Box::new(e)))?;
@@ -507,6 +866,13 @@ impl<B, E, Block> BlockchainEvents<Block> for Client<B, E, Block>
 		self.import_notification_sinks.lock().push(sink);
 		stream
 	}
+
+	/// Get finality notification stream.
+	fn finality_notification_stream(&self) -> FinalityNotifications<Block> {
+		let (sink, stream) = mpsc::unbounded();
+		self.finality_notification_sinks.lock().push(sink);
+		stream
+	}
 }
 
 impl<B, E, Block> ChainHead<Block> for Client<B, E, Block>
@@ -561,6 +927,20 @@ mod tests {
 		assert_eq!(client.info().unwrap().chain.best_number, 1);
 	}
 
+	#[test]
+	fn header_proof_of_unfinalized_cht_fails() {
+		let client = test_client::new();
+
+		let builder = client.new_block().unwrap();
+		client.justify_and_import(BlockOrigin::Own, builder.bake().unwrap()).unwrap();
+
+		match client.header_proof(&BlockId::Number(1)).unwrap_err().kind() {
+			&error::ErrorKind::UnfinalizedCHT(0) => (),
+			kind => panic!("Unexpected error: {:?}", kind),
+		}
+		assert!(client.header_proof(&BlockId::Number(0)).is_err());
+	}
+
 	fn sign_tx(tx: Transfer) -> Extrinsic {
 		let signature = Keyring::from_raw_public(tx.from.0.clone()).unwrap().sign(&tx.encode()).into();
 		Extrinsic { transfer: tx, signature }
@@ -586,4 +966,211 @@ mod tests {
 		assert_eq!(client.using_environment(|| test_runtime::system::balance_of(Keyring::Alice.to_raw_public().into())).unwrap(), 958);
 		assert_eq!(client.using_environment(|| test_runtime::system::balance_of(Keyring::Ferdie.to_raw_public().into())).unwrap(), 42);
 	}
+
+	#[test]
+	fn block_execution_is_traced() {
+		let client = test_client::new();
+
+		let mut builder = client.new_block().unwrap();
+		builder.push(sign_tx(Transfer {
+			from: Keyring::Alice.to_raw_public().into(),
+			to: Keyring::Ferdie.to_raw_public().into(),
+			amount: 42,
+			nonce: 0,
+		})).unwrap();
+		client.justify_and_import(BlockOrigin::Own, builder.bake().unwrap()).unwrap();
+
+		let traces = client.trace_block(&BlockId::Number(1)).unwrap();
+		let functions: Vec<_> = traces.iter()
+			.flat_map(|trace| trace.calls.iter())
+			.map(|call| call.function.as_str())
+			.collect();
+		assert!(functions.contains(&"ext_enumerated_trie_root"));
+		assert!(functions.contains(&"ext_ed25519_verify"));
+		assert!(functions.contains(&"storage_root"));
+
+		assert!(client.trace_block(&BlockId::Number(2)).is_err());
+	}
+
+	#[test]
+	fn offchain_worker_is_run_on_block_state() {
+		use std::collections::HashMap;
+
+		#[derive(Default)]
+		struct TestOffchain(HashMap<Vec<u8>, Vec<u8>>);
+
+		impl OffchainExt for TestOffchain {
+			fn submit_extrinsic(&mut self, _extrinsic: Vec<u8>) {}
+
+			fn local_storage(&self, key: &[u8]) -> Option<Vec<u8>> {
+				self.0.get(key).cloned()
+			}
+
+			fn set_local_storage(&mut self, key: &[u8], value: Option<&[u8]>) {
+				match value {
+					Some(value) => self.0.insert(key.to_vec(), value.to_vec()),
+					None => self.0.remove(key),
+				};
+			}
+		}
+
+		let client = test_client::new();
+		client.justify_and_import(BlockOrigin::Own, client.new_block().unwrap().bake().unwrap()).unwrap();
+
+		let mut offchain = TestOffchain::default();
+		client.offchain_worker(&BlockId::Number(1), &mut offchain).unwrap();
+		assert_eq!(offchain.local_storage(test_runtime::system::LAST_OFFCHAIN_BLOCK), Some(1u64.encode()));
+		assert!(client.offchain_worker(&BlockId::Number(2), &mut offchain).is_err());
+	}
+
+	#[test]
+	fn reorg_notifies_retracted_and_enacted_blocks() {
+		use futures::Stream;
+
+		let client = test_client::new();
+		let notifications = client.import_notification_stream();
+
+		// A1 <- A2 on top of genesis
+		let a1 = client.new_block().unwrap().bake().unwrap();
+		client.justify_and_import(BlockOrigin::Own, a1.clone()).unwrap();
+		let a2 = client.new_block().unwrap().bake().unwrap();
+		client.justify_and_import(BlockOrigin::Own, a2.clone()).unwrap();
+
+		// B1 <- B2 <- B3 on top of genesis; B1 differs from A1 by its extrinsics
+		let mut builder = client.new_block_at(&BlockId::Number(0)).unwrap();
+		builder.push(sign_tx(Transfer {
+			from: Keyring::Alice.to_raw_public().into(),
+			to: Keyring::Ferdie.to_raw_public().into(),
+			amount: 42,
+			nonce: 0,
+		})).unwrap();
+		let b1 = builder.bake().unwrap();
+		client.justify_and_import(BlockOrigin::Own, b1.clone()).unwrap();
+		let b2 = client.new_block_at(&BlockId::Hash(b1.hash())).unwrap().bake().unwrap();
+		client.justify_and_import(BlockOrigin::Own, b2.clone()).unwrap();
+		assert_eq!(client.info().unwrap().chain.best_hash, a2.hash());
+
+		let b3 = client.new_block_at(&BlockId::Hash(b2.hash())).unwrap().bake().unwrap();
+		client.justify_and_import(BlockOrigin::Own, b3.clone()).unwrap();
+		assert_eq!(client.info().unwrap().chain.best_hash, b3.hash());
+		assert_eq!(client.block_hash(1).unwrap(), Some(b1.hash()));
+		assert_eq!(client.block_hash(2).unwrap(), Some(b2.hash()));
+
+		drop(client);
+		let notifications: Vec<_> = notifications.wait().map(|n| n.unwrap()).collect();
+		assert_eq!(notifications.len(), 5);
+		assert!(!notifications[2].is_new_best);
+		assert!(notifications[2].enacted.is_empty());
+		assert_eq!(notifications[1].enacted, vec![a2.hash()]);
+		assert_eq!(notifications[4].retracted, vec![a2.hash(), a1.hash()]);
+		assert_eq!(notifications[4].enacted, vec![b1.hash(), b2.hash(), b3.hash()]);
+	}
+
+	#[test]
+	fn finalize_block_sends_notifications() {
+		use futures::Stream;
+
+		let client = test_client::new();
+		let finality = client.finality_notification_stream();
+		for _ in 0..3 {
+			let builder = client.new_block().unwrap();
+			client.justify_and_import(BlockOrigin::Own, builder.bake().unwrap()).unwrap();
+		}
+		let genesis_hash = client.info().unwrap().chain.genesis_hash;
+		assert_eq!(client.info().unwrap().chain.finalized_hash, genesis_hash);
+
+		let hash = client.block_hash(2).unwrap().unwrap();
+		client.finalize_block(BlockId::Number(2)).unwrap();
+		assert_eq!(client.info().unwrap().chain.finalized_hash, hash);
+		assert_eq!(client.info().unwrap().chain.finalized_number, 2);
+
+		// finalized head does not move backwards
+		client.finalize_block(BlockId::Number(1)).unwrap();
+		assert_eq!(client.info().unwrap().chain.finalized_hash, hash);
+
+		drop(client);
+		let notified: Vec<_> = finality.wait().map(|n| n.unwrap().hash).collect();
+		assert_eq!(notified, vec![hash]);
+	}
+
+	#[test]
+	fn key_changes_requires_changes_tries() {
+		let client = test_client::new();
+		let keys = vec![StorageKey(b"key".to_vec())];
+
+		let a1 = client.new_block().unwrap().bake().unwrap();
+		client.justify_and_import(BlockOrigin::Own, a1.clone()).unwrap();
+		let genesis_hash = client.info().unwrap().chain.genesis_hash;
+
+		// the test runtime does not enable changes tries and the in-memory backend keeps none
+		match client.key_changes(genesis_hash, a1.hash(), &keys).unwrap_err().kind() {
+			&error::ErrorKind::ChangesTrieUnavailable(_) => (),
+			kind => panic!("Unexpected error: {:?}", kind),
+		}
+		match client.key_changes(a1.hash(), genesis_hash, &keys).unwrap_err().kind() {
+			&error::ErrorKind::InvalidBlockRange(_) => (),
+			kind => panic!("Unexpected error: {:?}", kind),
+		}
+	}
+
+	#[test]
+	fn revert_works() {
+		let client = test_client::new();
+		let a1 = client.new_block().unwrap().bake().unwrap();
+		client.justify_and_import(BlockOrigin::Own, a1.clone()).unwrap();
+		let a2 = client.new_block().unwrap().bake().unwrap();
+		client.justify_and_import(BlockOrigin::Own, a2.clone()).unwrap();
+		client.finalize_block(BlockId::Hash(a1.hash())).unwrap();
+
+		assert_eq!(client.revert(10).unwrap(), 1);
+		assert_eq!(client.info().unwrap().chain.best_hash, a1.hash());
+		assert_eq!(client.block_status(&BlockId::Hash(a2.hash())).unwrap(), BlockStatus::Unknown);
+		assert!(client.state_at(&BlockId::Hash(a2.hash())).is_err());
+
+		client.justify_and_import(BlockOrigin::Own, a2.clone()).unwrap();
+		assert_eq!(client.info().unwrap().chain.best_hash, a2.hash());
+	}
+
+	#[test]
+	fn state_snapshot_import_works() {
+		use codec::KeyedVec;
+
+		let client = test_client::new();
+		let mut builder = client.new_block().unwrap();
+		builder.push(sign_tx(Transfer {
+			from: Keyring::Alice.to_raw_public().into(),
+			to: Keyring::Ferdie.to_raw_public().into(),
+			amount: 42,
+			nonce: 0,
+		})).unwrap();
+		let a1 = builder.bake().unwrap();
+		client.justify_and_import(BlockOrigin::Own, a1.clone()).unwrap();
+
+		let snapshot = client.state_snapshot(&BlockId::Number(1)).unwrap();
+		assert_eq!(StateSnapshot::decode(&mut &snapshot.encode()[..]), Some(snapshot.clone()));
+
+		let mut corrupted = snapshot.clone();
+		corrupted.storage.pop();
+		let imported = test_client::new();
+		let authorities = client.authorities_at(&BlockId::Number(0)).unwrap();
+		assert!(imported.import_state(corrupted, &authorities).is_err());
+		assert!(imported.import_state(snapshot.clone(), &[Keyring::Dave.to_raw_public().into()]).is_err());
+		imported.import_state(snapshot.clone(), &authorities).unwrap();
+		let info = imported.info().unwrap().chain;
+		assert_eq!((info.best_hash, info.best_number), (a1.hash(), 1));
+		assert_eq!((info.finalized_hash, info.finalized_number), (a1.hash(), 1));
+		let ferdie_balance = StorageKey(
+			runtime_io::twox_128(&Keyring::Ferdie.to_raw_public().to_keyed_vec(b"balance:")).to_vec()
+		);
+		assert_eq!(
+			imported.storage(&BlockId::Number(1), &ferdie_balance).unwrap(),
+			client.storage(&BlockId::Number(1), &ferdie_balance).unwrap()
+		);
+		assert!(imported.import_state(snapshot, &authorities).is_err());
+
+		// the chain continues on top of the imported state
+		let a2 = imported.new_block().unwrap().bake().unwrap();
+		imported.justify_and_import(BlockOrigin::Own, a2.clone()).unwrap();
+		assert_eq!(imported.info().unwrap().chain.best_hash, a2.hash());
+	}
 }
@@ -34,6 +34,12 @@ error_chain! {
 			display("UnknownBlock: {}", &*h),
 		}
 
+		/// Block is known, but not part of the canonical chain.
+		NotInCanonicalChain(h: String) {
+			description("block not in the canonical chain"),
+			display("Block {} is not in the canonical chain", &*h),
+		}
+
 		/// Execution error.
 		Execution(e: Box<state_machine::Error>) {
 			description("execution error"),
@@ -100,6 +106,12 @@ error_chain! {
 			display("Remote node has responded with invalid execution proof"),
 		}
 
+		/// Invalid remote header proof.
+		InvalidHeaderProof {
+			description("invalid header proof"),
+			display("Remote node has responded with invalid header proof"),
+		}
+
 		/// Remote fetch has been cancelled.
 		RemoteFetchCancelled {
 			description("remote fetch cancelled"),
@@ -111,6 +123,36 @@ error_chain! {
 			description("remote fetch failed"),
 			display("Remote data fetch has been failed"),
 		}
+
+		/// Invalid block range.
+		InvalidBlockRange(e: String) {
+			description("invalid block range"),
+			display("Invalid block range: {}", e),
+		}
+
+		/// The block does not commit to a changes trie, or the backend does not keep them.
+		ChangesTrieUnavailable(block: String) {
+			description("changes trie is not available"),
+			display("Changes trie of block {} is not available", block),
+		}
+
+		/// Hash that is required for building CHT is missing.
+		MissingHashRequiredForCHT(cht_num: u64, block_number: u64) {
+			description("missed hash required for building CHT"),
+			display("Failed to get hash of block#{} for building CHT#{}", block_number, cht_num),
+		}
+
+		/// Blocks of the CHT are not finalized yet.
+		UnfinalizedCHT(cht_num: u64) {
+			description("CHT is not finalized"),
+			display("Blocks of CHT#{} are not finalized yet", cht_num),
+		}
+
+		/// Invalid state snapshot.
+		InvalidStateSnapshot(e: String) {
+			description("invalid state snapshot"),
+			display("Invalid state snapshot: {}", e),
+		}
 	}
 }
 
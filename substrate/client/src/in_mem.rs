@@ -21,16 +21,19 @@ use std::sync::Arc;
 use parking_lot::RwLock;
 use error;
 use backend;
+use cht;
 use light;
 use runtime_primitives::generic::BlockId;
-use runtime_primitives::traits::{Block as BlockT, Header as HeaderT, Zero};
+use runtime_primitives::traits::{As, Block as BlockT, Header as HeaderT, NumberFor, Zero, One};
 use runtime_primitives::bft::Justification;
 use blockchain::{self, BlockStatus};
+use leaves::LeafSet;
 use state_machine::backend::{Backend as StateBackend, InMemory};
 
 struct PendingBlock<B: BlockT> {
 	block: StoredBlock<B>,
 	is_best: bool,
+	is_snapshot: bool,
 }
 
 #[derive(PartialEq, Eq, Clone)]
@@ -84,7 +87,11 @@ struct BlockchainStorage<Block: BlockT> {
 	hashes: HashMap<<<Block as BlockT>::Header as HeaderT>::Number, Block::Hash>,
 	best_hash: Block::Hash,
 	best_number: <<Block as BlockT>::Header as HeaderT>::Number,
+	finalized_hash: Block::Hash,
+	finalized_number: <<Block as BlockT>::Header as HeaderT>::Number,
 	genesis_hash: Block::Hash,
+	leaves: LeafSet<Block::Hash, <<Block as BlockT>::Header as HeaderT>::Number>,
+	children: HashMap<Block::Hash, Vec<Block::Hash>>,
 }
 
 /// In-memory blockchain. Supports concurrent reads.
@@ -110,14 +117,18 @@ impl<Block: BlockT> Blockchain<Block> {
 				hashes: HashMap::new(),
 				best_hash: Default::default(),
 				best_number: Zero::zero(),
+				finalized_hash: Default::default(),
+				finalized_number: Zero::zero(),
 				genesis_hash: Default::default(),
+				leaves: LeafSet::new(),
+				children: HashMap::new(),
 			}));
 		Blockchain {
 			storage: storage,
 		}
 	}
 
-	/// Insert a block header and associated data.
+	/// Insert a block header and associated data. The parent of the block must be known.
 	pub fn insert(
 		&self,
 		hash: Block::Hash,
@@ -125,18 +136,131 @@ impl<Block: BlockT> Blockchain<Block> {
 		justification: Option<Justification<Block::Hash>>,
 		body: Option<Vec<<Block as BlockT>::Extrinsic>>,
 		is_new_best: bool
-	) {
+	) -> error::Result<()> {
+		self.insert_block(hash, header, justification, body, is_new_best, false)
+	}
+
+	/// Insert the header and justification of a block imported from a state snapshot, without
+	/// its ancestry. The block starts a new chain as its best and finalized block.
+	pub fn insert_snapshot(
+		&self,
+		hash: Block::Hash,
+		header: <Block as BlockT>::Header,
+		justification: Justification<Block::Hash>,
+	) -> error::Result<()> {
+		self.insert_block(hash, header, Some(justification), None, true, true)
+	}
+
+	fn insert_block(
+		&self,
+		hash: Block::Hash,
+		header: <Block as BlockT>::Header,
+		justification: Option<Justification<Block::Hash>>,
+		body: Option<Vec<<Block as BlockT>::Extrinsic>>,
+		is_new_best: bool,
+		is_starting_block: bool,
+	) -> error::Result<()> {
 		let number = header.number().clone();
+		let parent_hash = header.parent_hash().clone();
+		let best_hash = self.storage.read().best_hash;
+		if number != Zero::zero() && !is_starting_block && !self.storage.read().blocks.contains_key(&parent_hash) {
+			return Err(error::ErrorKind::UnknownBlock(format!("parent {} of block {}", parent_hash, hash)).into());
+		}
+		let route = if is_new_best && number != Zero::zero() && !is_starting_block && parent_hash != best_hash {
+			Some(blockchain::tree_route(self, BlockId::Hash(best_hash), BlockId::Hash(parent_hash))?)
+		} else {
+			None
+		};
+
 		let mut storage = self.storage.write();
 		storage.blocks.insert(hash.clone(), StoredBlock::new(header, body, justification));
-		storage.hashes.insert(number, hash.clone());
+		if is_starting_block {
+			storage.leaves = LeafSet::new();
+		}
+		storage.leaves.import(hash.clone(), number.clone(), parent_hash.clone());
+		if number != Zero::zero() && !is_starting_block {
+			storage.children.entry(parent_hash).or_insert_with(Vec::new).push(hash.clone());
+		}
 		if is_new_best {
+			if let Some(route) = route {
+				for retracted in route.retracted() {
+					storage.hashes.remove(&retracted.number);
+				}
+				for enacted in route.enacted() {
+					storage.hashes.insert(enacted.number, enacted.hash);
+				}
+			}
+			storage.hashes.insert(number, hash.clone());
 			storage.best_hash = hash.clone();
 			storage.best_number = number.clone();
 		}
 		if number == Zero::zero() {
-			storage.genesis_hash = hash;
+			storage.genesis_hash = hash.clone();
+			storage.finalized_hash = hash;
+		} else if is_starting_block {
+			storage.finalized_hash = hash;
+			storage.finalized_number = number;
 		}
+		Ok(())
+	}
+
+	/// Mark a block and all of its ancestors as finalized.
+	pub fn finalize_header(&self, id: BlockId<Block>) -> error::Result<()> {
+		let hash = self.id(id).ok_or_else(|| error::ErrorKind::UnknownBlock(format!("{}", id)))?;
+		let mut storage = self.storage.write();
+		let number = match storage.blocks.get(&hash) {
+			Some(block) => block.header().number().clone(),
+			None => return Err(error::ErrorKind::UnknownBlock(format!("{}", id)).into()),
+		};
+		if storage.hashes.get(&number) != Some(&hash) {
+			return Err(error::ErrorKind::NotInCanonicalChain(format!("{}", id)).into());
+		}
+		if number > storage.finalized_number {
+			storage.finalized_hash = hash;
+			storage.finalized_number = number;
+		}
+		Ok(())
+	}
+
+	/// Remove the best block along with all other blocks of the same number, unless it is
+	/// finalized. The parent of the best block becomes the new best block. Returns the hashes
+	/// of the removed blocks.
+	pub fn revert_one(&self) -> Vec<Block::Hash> {
+		let mut storage = self.storage.write();
+		let storage = &mut *storage;
+		if storage.best_number <= storage.finalized_number {
+			return Vec::new();
+		}
+
+		let number = storage.best_number;
+		let reverted: Vec<_> = storage.blocks.iter()
+			.filter(|&(_, block)| *block.header().number() == number)
+			.map(|(hash, _)| *hash)
+			.collect();
+		let best_parent = *storage.blocks[&storage.best_hash].header().parent_hash();
+		for hash in &reverted {
+			let parent_hash = *storage.blocks.remove(hash)
+				.expect("reverted blocks are collected from stored blocks; qed")
+				.header().parent_hash();
+			storage.leaves.remove(hash);
+			let has_children = match storage.children.get_mut(&parent_hash) {
+				Some(children) => {
+					children.retain(|child| child != hash);
+					!children.is_empty()
+				},
+				None => false,
+			};
+			if !has_children {
+				storage.children.remove(&parent_hash);
+				if let Some(grandparent_hash) = storage.blocks.get(&parent_hash).map(|parent| *parent.header().parent_hash()) {
+					storage.leaves.import(parent_hash, number - One::one(), grandparent_hash);
+				}
+			}
+		}
+		storage.hashes.remove(&number);
+		storage.best_hash = best_parent;
+		storage.best_number = number - One::one();
+		reverted
 	}
 
 	/// Compare this blockchain with another in-mem blockchain
@@ -151,6 +275,7 @@ impl<Block: BlockT> Blockchain<Block> {
 			this.hashes == other.hashes
 			&& this.best_hash == other.best_hash
 			&& this.best_number == other.best_number
+			&& this.finalized_hash == other.finalized_hash
 			&& this.genesis_hash == other.genesis_hash
 	}
 }
@@ -168,6 +293,8 @@ impl<Block: BlockT> blockchain::HeaderBackend<Block> for Blockchain<Block> {
 			best_hash: storage.best_hash,
 			best_number: storage.best_number,
 			genesis_hash: storage.genesis_hash,
+			finalized_hash: storage.finalized_hash,
+			finalized_number: storage.finalized_number,
 		})
 	}
 
@@ -197,13 +324,40 @@ impl<Block: BlockT> blockchain::Backend<Block> for Blockchain<Block> {
 			b.justification().map(|x| x.clone()))
 		))
 	}
+
+	fn leaves(&self) -> error::Result<Vec<Block::Hash>> {
+		Ok(self.storage.read().leaves.hashes())
+	}
+
+	fn children(&self, parent_hash: Block::Hash) -> error::Result<Vec<Block::Hash>> {
+		Ok(self.storage.read().children.get(&parent_hash).cloned().unwrap_or_default())
+	}
 }
 
 impl<Block: BlockT> light::blockchain::Storage<Block> for Blockchain<Block> {
 	fn import_header(&self, is_new_best: bool, header: Block::Header) -> error::Result<()> {
 		let hash = header.hash();
-		self.insert(hash, header, None, None, is_new_best);
-		Ok(())
+		self.insert(hash, header, None, None, is_new_best)
+	}
+
+	fn finalize_header(&self, id: BlockId<Block>) -> error::Result<()> {
+		Blockchain::finalize_header(self, id)
+	}
+
+	fn leaves(&self) -> error::Result<Vec<Block::Hash>> {
+		blockchain::Backend::leaves(self)
+	}
+
+	fn children(&self, parent_hash: Block::Hash) -> error::Result<Vec<Block::Hash>> {
+		blockchain::Backend::children(self, parent_hash)
+	}
+
+	fn cht_root(&self, block: NumberFor<Block>) -> error::Result<Block::Hash> {
+		// headers are never pruned => compute the root on demand
+		let cht_num = cht::block_to_cht_number(block)
+			.ok_or_else(|| error::ErrorKind::Backend(format!("No CHT covers block {}", block)))?;
+		let hashes = (cht::start_number(cht_num)..).map(|num| blockchain::HeaderBackend::hash(self, As::sa(num)));
+		cht::compute_root::<Block::Header, _>(cht_num, hashes)
 	}
 }
 
@@ -232,6 +386,21 @@ impl<Block: BlockT> backend::BlockImportOperation<Block> for BlockImportOperatio
 		self.pending_block = Some(PendingBlock {
 			block: StoredBlock::new(header, body, justification),
 			is_best: is_new_best,
+			is_snapshot: false,
+		});
+		Ok(())
+	}
+
+	fn set_snapshot_block_data(
+		&mut self,
+		header: <Block as BlockT>::Header,
+		justification: Justification<Block::Hash>,
+	) -> error::Result<()> {
+		assert!(self.pending_block.is_none(), "Only one block per operation is allowed");
+		self.pending_block = Some(PendingBlock {
+			block: StoredBlock::new(header, None, Some(justification)),
+			is_best: true,
+			is_snapshot: true,
 		});
 		Ok(())
 	}
@@ -245,6 +414,34 @@ impl<Block: BlockT> backend::BlockImportOperation<Block> for BlockImportOperatio
 		self.new_state = Some(InMemory::from(iter.collect::<HashMap<_, _>>()));
 		Ok(())
 	}
+
+	fn reset_child_storage<I: Iterator<Item=(Vec<u8>, Vec<u8>)>>(&mut self, storage_key: &[u8], iter: I) -> error::Result<()> {
+		let changes = iter.map(|(k, v)| (Some(storage_key.to_vec()), k, Some(v))).collect();
+		let state = self.new_state.take().unwrap_or_default();
+		self.new_state = Some(state.update(changes));
+		Ok(())
+	}
+}
+
+/// In-memory node-local storage of the offchain workers.
+#[derive(Clone, Default)]
+pub struct OffchainStorage {
+	storage: Arc<RwLock<HashMap<Vec<u8>, Vec<u8>>>>,
+}
+
+impl backend::OffchainStorage for OffchainStorage {
+	fn get(&self, key: &[u8]) -> error::Result<Option<Vec<u8>>> {
+		Ok(self.storage.read().get(key).cloned())
+	}
+
+	fn set(&self, key: &[u8], value: Option<&[u8]>) -> error::Result<()> {
+		let mut storage = self.storage.write();
+		match value {
+			Some(value) => storage.insert(key.to_vec(), value.to_vec()),
+			None => storage.remove(key),
+		};
+		Ok(())
+	}
 }
 
 /// In-memory backend. Keeps all states and blocks in memory. Useful for testing.
@@ -253,6 +450,7 @@ pub struct Backend<Block> where
 {
 	states: RwLock<HashMap<Block::Hash, InMemory>>,
 	blockchain: Blockchain<Block>,
+	offchain_storage: OffchainStorage,
 }
 
 impl<Block> Backend<Block> where
@@ -263,6 +461,7 @@ impl<Block> Backend<Block> where
 		Backend {
 			states: RwLock::new(HashMap::new()),
 			blockchain: Blockchain::new(),
+			offchain_storage: OffchainStorage::default(),
 		}
 	}
 }
@@ -273,6 +472,7 @@ impl<Block> backend::Backend<Block> for Backend<Block> where
 	type BlockImportOperation = BlockImportOperation<Block>;
 	type Blockchain = Blockchain<Block>;
 	type State = InMemory;
+	type OffchainStorage = OffchainStorage;
 
 	fn begin_operation(&self, block: BlockId<Block>) -> error::Result<Self::BlockImportOperation> {
 		let state = match block {
@@ -293,12 +493,21 @@ impl<Block> backend::Backend<Block> for Backend<Block> where
 			let (header, body, justification) = pending_block.block.into_inner();
 			let hash = header.hash();
 
+			if pending_block.is_snapshot {
+				let justification = justification.expect("snapshot blocks are always justified; qed");
+				self.blockchain.insert_snapshot(hash, header, justification)?;
+			} else {
+				self.blockchain.insert(hash, header, justification, body, pending_block.is_best)?;
+			}
 			self.states.write().insert(hash, operation.new_state.unwrap_or_else(|| old_state.clone()));
-			self.blockchain.insert(hash, header, justification, body, pending_block.is_best);
 		}
 		Ok(())
 	}
 
+	fn finalize_block(&self, block: BlockId<Block>) -> error::Result<()> {
+		self.blockchain.finalize_header(block)
+	}
+
 	fn blockchain(&self) -> &Self::Blockchain {
 		&self.blockchain
 	}
@@ -309,6 +518,30 @@ impl<Block> backend::Backend<Block> for Backend<Block> where
 			None => Err(error::ErrorKind::UnknownBlock(format!("{}", block)).into()),
 		}
 	}
+
+	fn revert(&self, n: NumberFor<Block>) -> error::Result<NumberFor<Block>> {
+		let mut reverted = Zero::zero();
+		while reverted < n {
+			let hashes = self.blockchain.revert_one();
+			if hashes.is_empty() {
+				break;
+			}
+			let mut states = self.states.write();
+			for hash in hashes {
+				states.remove(&hash);
+			}
+			reverted = reverted + One::one();
+		}
+		Ok(reverted)
+	}
+
+	fn offchain_storage(&self) -> Option<Self::OffchainStorage> {
+		Some(self.offchain_storage.clone())
+	}
+
+	fn changes_trie_storage(&self) -> Option<state_machine::TrieBackend> {
+		None
+	}
 }
 
 impl<Block: BlockT> backend::LocalBackend<Block> for Backend<Block> {}
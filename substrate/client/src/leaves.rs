@@ -0,0 +1,172 @@
+// Copyright 2018 Parity Technologies (UK) Ltd.
+// This file is part of Substrate.
+
+// Substrate is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// Substrate is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with Substrate.  If not, see <http://www.gnu.org/licenses/>.
+
+//! Set of the leaves of the block tree, i.e. blocks without children.
+
+use std::cmp::Reverse;
+use std::collections::BTreeMap;
+use codec::{Decode, Encode, Input, Output};
+
+/// Set of the leaves of the block tree, ordered by block number, highest first.
+/// Leaves with the same number are kept in the order they were imported.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LeafSet<H, N> {
+	storage: BTreeMap<Reverse<N>, Vec<H>>,
+}
+
+impl<H, N> LeafSet<H, N> where
+	H: Clone + PartialEq,
+	N: Clone + Ord,
+{
+	/// Create a new, empty leaf set.
+	pub fn new() -> Self {
+		LeafSet {
+			storage: BTreeMap::new(),
+		}
+	}
+
+	/// Note a newly imported block. Its parent is no longer a leaf.
+	pub fn import(&mut self, hash: H, number: N, parent_hash: H) {
+		let mut emptied = None;
+		for (number, hashes) in self.storage.iter_mut() {
+			if let Some(position) = hashes.iter().position(|h| *h == parent_hash) {
+				hashes.remove(position);
+				if hashes.is_empty() {
+					emptied = Some(number.clone());
+				}
+				break;
+			}
+		}
+		if let Some(number) = emptied {
+			self.storage.remove(&number);
+		}
+
+		self.storage.entry(Reverse(number)).or_insert_with(Vec::new).push(hash);
+	}
+
+	/// Remove a leaf, e.g. a block which is no longer part of the chain. Returns whether it was
+	/// a leaf.
+	pub fn remove(&mut self, hash: &H) -> bool {
+		let mut removed = None;
+		for (number, hashes) in self.storage.iter_mut() {
+			if let Some(position) = hashes.iter().position(|h| h == hash) {
+				hashes.remove(position);
+				removed = Some((number.clone(), hashes.is_empty()));
+				break;
+			}
+		}
+		match removed {
+			Some((number, emptied)) => {
+				if emptied {
+					self.storage.remove(&number);
+				}
+				true
+			},
+			None => false,
+		}
+	}
+
+	/// Whether the block with given hash is a leaf.
+	pub fn contains(&self, hash: &H) -> bool {
+		self.storage.values().any(|hashes| hashes.contains(hash))
+	}
+
+	/// Hashes of all leaves, highest first.
+	pub fn hashes(&self) -> Vec<H> {
+		self.storage.values().flat_map(|hashes| hashes.iter().cloned()).collect()
+	}
+
+	/// Number of the highest leaves and their hashes, or `None` if the set is empty.
+	pub fn highest(&self) -> Option<(N, Vec<H>)> {
+		self.storage.iter().next().map(|(number, hashes)| (number.0.clone(), hashes.clone()))
+	}
+}
+
+impl<H: Encode + Clone, N: Encode + Clone> Encode for LeafSet<H, N> {
+	fn encode_to<T: Output>(&self, dest: &mut T) {
+		let leaves: Vec<(N, H)> = self.storage.iter()
+			.flat_map(|(number, hashes)| hashes.iter().map(move |hash| (number.0.clone(), hash.clone())))
+			.collect();
+		dest.push(&leaves);
+	}
+}
+
+impl<H: Decode, N: Decode + Ord> Decode for LeafSet<H, N> {
+	fn decode<I: Input>(value: &mut I) -> Option<Self> {
+		let leaves: Vec<(N, H)> = Decode::decode(value)?;
+		let mut storage = BTreeMap::new();
+		for (number, hash) in leaves {
+			storage.entry(Reverse(number)).or_insert_with(Vec::new).push(hash);
+		}
+		Some(LeafSet { storage })
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn import_works() {
+		let mut set = LeafSet::new();
+		set.import(0u32, 0u32, 0u32);
+
+		set.import(1_1, 1, 0);
+		set.import(2_1, 2, 1_1);
+		set.import(3_1, 3, 2_1);
+
+		assert!(set.contains(&3_1));
+		assert!(!set.contains(&2_1));
+		assert!(!set.contains(&1_1));
+		assert!(!set.contains(&0));
+
+		set.import(2_2, 2, 1_1);
+
+		assert!(set.contains(&3_1));
+		assert!(set.contains(&2_2));
+		assert_eq!(set.hashes(), vec![3_1, 2_2]);
+	}
+
+	#[test]
+	fn encoding_roundtrips() {
+		let mut set = LeafSet::new();
+		set.import(0u32, 0u32, 0u32);
+		set.import(1_1, 1, 0);
+		set.import(1_2, 1, 0);
+		set.import(2_1, 2, 1_1);
+
+		let encoded = set.encode();
+		assert_eq!(LeafSet::decode(&mut &encoded[..]), Some(set));
+	}
+
+	#[test]
+	fn remove_works() {
+		let mut set = LeafSet::new();
+		set.import(0u32, 0u32, 0u32);
+		set.import(1_1, 1, 0);
+		set.import(1_2, 1, 0);
+		set.import(2_1, 2, 1_1);
+		assert_eq!(set.highest(), Some((2, vec![2_1])));
+
+		assert!(set.remove(&2_1));
+		assert!(!set.remove(&1_1));
+		assert_eq!(set.hashes(), vec![1_2]);
+		assert_eq!(set.highest(), Some((1, vec![1_2])));
+		assert!(set.remove(&1_2));
+		assert!(set.hashes().is_empty());
+		assert_eq!(set.highest(), None);
+	}
+}
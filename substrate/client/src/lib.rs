@@ -44,19 +44,22 @@ extern crate triehash;
 pub mod error;
 pub mod blockchain;
 pub mod backend;
+pub mod cht;
 pub mod in_mem;
 pub mod genesis;
 pub mod block_builder;
 pub mod light;
+pub mod leaves;
 mod call_executor;
 mod client;
 
 pub use client::{
 	new_in_mem,
 	BlockStatus, BlockOrigin, BlockchainEventStream, BlockchainEvents,
+	FinalityNotification, FinalityNotifications,
 	Client, ClientInfo, ChainHead,
-	ImportResult, JustifiedHeader,
+	ImportResult, JustifiedHeader, StateSnapshot,
 };
 pub use blockchain::Info as ChainInfo;
 pub use call_executor::{CallResult, CallExecutor, LocalCallExecutor};
-pub use state_machine::ExecutionStrategy;
+pub use state_machine::{ExecutionStrategy, OffchainExt};
@@ -20,15 +20,16 @@
 use std::sync::{Arc, Weak};
 
 use runtime_primitives::{bft::Justification, generic::BlockId};
-use runtime_primitives::traits::Block as BlockT;
+use runtime_primitives::traits::{Block as BlockT, Header as HeaderT, NumberFor};
 use state_machine::{Backend as StateBackend, TrieBackend as StateTrieBackend,
-	TryIntoTrieBackend as TryIntoStateTrieBackend};
+	TryIntoTrieBackend as TryIntoStateTrieBackend, TrieH256, read_proof_check};
 
 use backend::{Backend as ClientBackend, BlockImportOperation, RemoteBackend};
 use blockchain::HeaderBackend as BlockchainHeaderBackend;
 use error::{Error as ClientError, ErrorKind as ClientErrorKind, Result as ClientResult};
 use light::blockchain::{Blockchain, Storage as BlockchainStorage};
-use light::fetcher::Fetcher;
+use light::fetcher::{Fetcher, RemoteReadRequest};
+use in_mem;
 
 /// Light client backend.
 pub struct Backend<S, F> {
@@ -64,6 +65,7 @@ impl<S, F, Block> ClientBackend<Block> for Backend<S, F> where Block: BlockT, S:
 	type BlockImportOperation = ImportOperation<Block, F>;
 	type Blockchain = Blockchain<S, F>;
 	type State = OnDemandState<Block, F>;
+	type OffchainStorage = in_mem::OffchainStorage;
 
 	fn begin_operation(&self, _block: BlockId<Block>) -> ClientResult<Self::BlockImportOperation> {
 		Ok(ImportOperation {
@@ -78,6 +80,10 @@ impl<S, F, Block> ClientBackend<Block> for Backend<S, F> where Block: BlockT, S:
 		self.blockchain.storage().import_header(operation.is_new_best, header)
 	}
 
+	fn finalize_block(&self, block: BlockId<Block>) -> ClientResult<()> {
+		self.blockchain.storage().finalize_header(block)
+	}
+
 	fn blockchain(&self) -> &Blockchain<S, F> {
 		&self.blockchain
 	}
@@ -93,6 +99,18 @@ impl<S, F, Block> ClientBackend<Block> for Backend<S, F> where Block: BlockT, S:
 			fetcher: self.blockchain.fetcher(),
 		})
 	}
+
+	fn revert(&self, _n: NumberFor<Block>) -> ClientResult<NumberFor<Block>> {
+		Err(ClientErrorKind::NotAvailableOnLightClient.into())
+	}
+
+	fn offchain_storage(&self) -> Option<Self::OffchainStorage> {
+		None
+	}
+
+	fn changes_trie_storage(&self) -> Option<StateTrieBackend> {
+		None
+	}
 }
 
 impl<S, F, Block> RemoteBackend<Block> for Backend<S, F> where Block: BlockT, S: BlockchainStorage<Block>, F: Fetcher<Block> {}
@@ -117,6 +135,14 @@ impl<F, Block> BlockImportOperation<Block> for ImportOperation<Block, F> where B
 		Ok(())
 	}
 
+	fn set_snapshot_block_data(
+		&mut self,
+		_header: Block::Header,
+		_justification: Justification<Block::Hash>,
+	) -> ClientResult<()> {
+		Err(ClientErrorKind::NotAvailableOnLightClient.into())
+	}
+
 	fn update_storage(&mut self, _update: <Self::State as StateBackend>::Transaction) -> ClientResult<()> {
 		// we're not storing anything locally => ignore changes
 		Ok(())
@@ -126,6 +152,11 @@ impl<F, Block> BlockImportOperation<Block> for ImportOperation<Block, F> where B
 		// we're not storing anything locally => ignore changes
 		Ok(())
 	}
+
+	fn reset_child_storage<I: Iterator<Item=(Vec<u8>, Vec<u8>)>>(&mut self, _storage_key: &[u8], _iter: I) -> ClientResult<()> {
+		// we're not storing anything locally => ignore changes
+		Ok(())
+	}
 }
 
 impl<Block: BlockT, F> Clone for OnDemandState<Block, F> {
@@ -149,11 +180,39 @@ impl<Block, F> StateBackend for OnDemandState<Block, F> where Block: BlockT, F:
 		// whole state is not available on light node
 	}
 
+	fn keys_with_prefix(&self, _prefix: &[u8], _start_key: Option<&[u8]>, _count: usize) -> Vec<Vec<u8>> {
+		// whole state is not available on light node
+		Vec::new()
+	}
+
 	fn storage_root<I>(&self, _delta: I) -> ([u8; 32], Self::Transaction)
 		where I: IntoIterator<Item=(Vec<u8>, Option<Vec<u8>>)> {
 		([0; 32], ())
 	}
 
+	fn child_storage(&self, _storage_key: &[u8], _key: &[u8]) -> ClientResult<Option<Vec<u8>>> {
+		Err(ClientErrorKind::NotAvailableOnLightClient.into()) // TODO: fetch from remote node
+	}
+
+	fn for_keys_in_child_storage<A: FnMut(&[u8])>(&self, _storage_key: &[u8], _action: A) -> ClientResult<()> {
+		Err(ClientErrorKind::NotAvailableOnLightClient.into())
+	}
+
+	fn child_storage_root<I>(&self, _storage_key: &[u8], _delta: I) -> ([u8; 32], bool, Self::Transaction)
+		where I: IntoIterator<Item=(Vec<u8>, Option<Vec<u8>>)> {
+		([0; 32], false, ())
+	}
+
+	fn killed_child_storage_root<I>(&self, _storage_key: &[u8], _delta: I) -> ([u8; 32], bool, Self::Transaction)
+		where I: IntoIterator<Item=(Vec<u8>, Option<Vec<u8>>)> {
+		([0; 32], false, ())
+	}
+
+	fn build_trie<I>(&self, _pairs: I) -> ([u8; 32], Self::Transaction)
+		where I: IntoIterator<Item=(Vec<u8>, Vec<u8>)> {
+		([0; 32], ())
+	}
+
 	fn pairs(&self) -> Vec<(Vec<u8>, Vec<u8>)> {
 		// whole state is not available on light node
 		Vec::new()
@@ -166,22 +225,77 @@ impl<Block, F> TryIntoStateTrieBackend for OnDemandState<Block, F> where Block:
 	}
 }
 
+/// Check remote storage read proof using given blockchain.
+pub fn check_read_proof<Block, B>(
+	blockchain: &B,
+	request: &RemoteReadRequest<Block::Hash>,
+	remote_proof: Vec<Vec<u8>>
+) -> ClientResult<Option<Vec<u8>>>
+	where
+		Block: BlockT,
+		B: BlockchainHeaderBackend<Block>,
+{
+	let local_header = blockchain.header(BlockId::Hash(request.block))?;
+	let local_header = local_header.ok_or_else(|| ClientErrorKind::UnknownBlock(format!("{}", request.block)))?;
+	check_read_proof_with_root(*local_header.state_root(), &request.key, remote_proof)
+}
+
+/// Check remote storage read proof using given state root.
+///
+/// Unlike `check_read_proof` this does not require the block header to be known locally.
+pub fn check_read_proof_with_root<Hash: AsRef<[u8]>>(
+	local_state_root: Hash,
+	key: &[u8],
+	remote_proof: Vec<Vec<u8>>,
+) -> ClientResult<Option<Vec<u8>>> {
+	read_proof_check(TrieH256::from_slice(local_state_root.as_ref()).into(), remote_proof, key)
+		.map_err(Into::into)
+}
+
 #[cfg(test)]
 pub mod tests {
-	use futures::future::{ok, FutureResult};
+	use futures::future::{err, ok, FutureResult};
 	use parking_lot::Mutex;
 	use call_executor::CallResult;
-	use error::Error as ClientError;
-	use test_client::runtime::{Hash, Block};
-	use light::fetcher::{Fetcher, RemoteCallRequest};
+	use error::{Error as ClientError, ErrorKind as ClientErrorKind};
+	use test_client::runtime::{Hash, Block, Header};
+	use light::fetcher::{Fetcher, RemoteCallRequest, RemoteHeaderRequest};
 
 	pub type OkCallFetcher = Mutex<CallResult>;
 
 	impl Fetcher<Block> for OkCallFetcher {
 		type RemoteCallResult = FutureResult<CallResult, ClientError>;
+		type RemoteHeaderResult = FutureResult<Header, ClientError>;
 
 		fn remote_call(&self, _request: RemoteCallRequest<Hash>) -> Self::RemoteCallResult {
 			ok((*self.lock()).clone())
 		}
+
+		fn remote_header(&self, _request: RemoteHeaderRequest<Header>) -> Self::RemoteHeaderResult {
+			err(ClientErrorKind::RemoteFetchFailed.into())
+		}
+	}
+
+	#[test]
+	fn storage_read_proof_is_generated_and_checked() {
+		use runtime_primitives::generic::BlockId;
+		use state_machine::Backend;
+		use test_client;
+		use super::check_read_proof_with_root;
+
+		// prepare remote client
+		let remote_client = test_client::new();
+		let remote_block_id = BlockId::Number(0);
+		let remote_block_storage_root = remote_client.state_at(&remote_block_id)
+			.unwrap().storage_root(::std::iter::empty()).0;
+		let authorities_len = remote_client.storage(&remote_block_id, &::primitives::storage::StorageKey(b":auth:len".to_vec()))
+			.unwrap().0;
+
+		// 'fetch' read proof from remote node
+		let remote_read_proof = remote_client.read_proof(&remote_block_id, &[b":auth:len".to_vec()]).unwrap();
+
+		// check remote read proof locally
+		assert_eq!(check_read_proof_with_root(remote_block_storage_root, b":auth:len", remote_read_proof).unwrap(),
+			Some(authorities_len));
 	}
 }
@@ -18,20 +18,33 @@
 //! blocks. CHT roots are stored for headers of ancient blocks.
 
 use std::sync::Weak;
+use futures::{Future, IntoFuture};
 use parking_lot::Mutex;
 
 use runtime_primitives::{bft::Justification, generic::BlockId};
-use runtime_primitives::traits::{Block as BlockT, Header as HeaderT};
+use runtime_primitives::traits::{Block as BlockT, Header as HeaderT, NumberFor, Zero};
 
 use blockchain::{Backend as BlockchainBackend, BlockStatus,
 	HeaderBackend as BlockchainHeaderBackend, Info as BlockchainInfo};
-use error::Result as ClientResult;
-use light::fetcher::Fetcher;
+use error::{ErrorKind as ClientErrorKind, Result as ClientResult};
+use light::fetcher::{Fetcher, RemoteHeaderRequest};
 
 /// Light client blockchain storage.
 pub trait Storage<Block: BlockT>: BlockchainHeaderBackend<Block> {
 	/// Store new header.
 	fn import_header(&self, is_new_best: bool, header: Block::Header) -> ClientResult<()>;
+
+	/// Mark a header and all of its ancestors as finalized.
+	fn finalize_header(&self, block: BlockId<Block>) -> ClientResult<()>;
+
+	/// Get hashes of all headers that have no children, highest first.
+	fn leaves(&self) -> ClientResult<Vec<Block::Hash>>;
+
+	/// Get hashes of all known children of the given header.
+	fn children(&self, parent_hash: Block::Hash) -> ClientResult<Vec<Block::Hash>>;
+
+	/// Get the root of the CHT covering given block.
+	fn cht_root(&self, block: NumberFor<Block>) -> ClientResult<Block::Hash>;
 }
 
 /// Light client blockchain.
@@ -65,9 +78,35 @@ impl<S, F> Blockchain<S, F> {
 	}
 }
 
+impl<S, F, Block> Blockchain<S, F> where Block: BlockT, S: Storage<Block>, F: Fetcher<Block> {
+	/// Request the pruned header of the ancient canonical block from the remote node. The
+	/// returned future resolves to the header once its proof is checked against the CHT root.
+	pub fn remote_header(&self, number: NumberFor<Block>) -> ClientResult<F::RemoteHeaderResult> {
+		if number.is_zero() || self.storage.status(BlockId::Number(number))? != BlockStatus::InChain {
+			return Err(ClientErrorKind::UnknownBlock(format!("{:?}", BlockId::<Block>::Number(number))).into());
+		}
+
+		Ok(self.fetcher().upgrade().ok_or(ClientErrorKind::NotAvailableOnLightClient)?
+			.remote_header(RemoteHeaderRequest {
+				cht_root: self.storage.cht_root(number)?,
+				block: number,
+			}))
+	}
+}
+
 impl<S, F, Block> BlockchainHeaderBackend<Block> for Blockchain<S, F> where Block: BlockT, S: Storage<Block>, F: Fetcher<Block> {
 	fn header(&self, id: BlockId<Block>) -> ClientResult<Option<Block::Header>> {
-		self.storage.header(id)
+		match self.storage.header(id)? {
+			Some(header) => Ok(Some(header)),
+			None => match id {
+				// headers of ancient canonical blocks are pruned => fetch from remote node. This
+				// blocks until the response is received, so the network thread must not get here:
+				// light nodes don't serve block requests
+				BlockId::Number(number) if self.storage.status(id)? == BlockStatus::InChain =>
+					self.remote_header(number)?.into_future().wait().map(Some),
+				_ => Ok(None),
+			},
+		}
 	}
 
 	fn info(&self) -> ClientResult<BlockchainInfo<Block>> {
@@ -79,6 +118,8 @@ impl<S, F, Block> BlockchainHeaderBackend<Block> for Blockchain<S, F> where Bloc
 	}
 
 	fn hash(&self, number: <<Block as BlockT>::Header as HeaderT>::Number) -> ClientResult<Option<Block::Hash>> {
+		// sync looks up hashes on the network thread, so they are never fetched from remote node;
+		// use `header` to get the hash of an ancient block
 		self.storage.hash(number)
 	}
 }
@@ -92,4 +133,12 @@ impl<S, F, Block> BlockchainBackend<Block> for Blockchain<S, F> where Block: Blo
 	fn justification(&self, _id: BlockId<Block>) -> ClientResult<Option<Justification<Block::Hash>>> {
 		Ok(None)
 	}
+
+	fn leaves(&self) -> ClientResult<Vec<Block::Hash>> {
+		self.storage.leaves()
+	}
+
+	fn children(&self, parent_hash: Block::Hash) -> ClientResult<Vec<Block::Hash>> {
+		self.storage.children(parent_hash)
+	}
 }
@@ -22,7 +22,7 @@ use futures::{IntoFuture, Future};
 
 use runtime_primitives::generic::BlockId;
 use runtime_primitives::traits::{Block as BlockT, Header as HeaderT};
-use state_machine::{Backend as StateBackend, CodeExecutor, OverlayedChanges,
+use state_machine::{Backend as StateBackend, CodeExecutor, OverlayedChanges, OffchainExt,
 	execution_proof_check, TrieH256, ExecutionManager};
 
 use blockchain::Backend as ChainBackend;
@@ -91,6 +91,10 @@ impl<B, F, Block> CallExecutor<Block> for RemoteCallExecutor<B, F>
 		Err(ClientErrorKind::NotAvailableOnLightClient.into())
 	}
 
+	fn call_offchain(&self, _id: &BlockId<Block>, _offchain: &mut OffchainExt, _method: &str, _call_data: &[u8]) -> ClientResult<Vec<u8>> {
+		Err(ClientErrorKind::NotAvailableOnLightClient.into())
+	}
+
 	fn native_runtime_version(&self) -> Option<RuntimeVersion> {
 		None
 	}
@@ -111,11 +115,13 @@ pub fn check_execution_proof<Block, B, E>(
 	let local_header = blockchain.header(BlockId::Hash(request.block))?;
 	let local_header = local_header.ok_or_else(|| ClientErrorKind::UnknownBlock(format!("{}", request.block)))?;
 	let local_state_root = *local_header.state_root();
-	do_check_execution_proof(local_state_root.into(), executor, request, remote_proof)
+	check_execution_proof_with_root(local_state_root, executor, request, remote_proof)
 }
 
 /// Check remote execution proof using given state root.
-fn do_check_execution_proof<Hash, E>(
+///
+/// Unlike `check_execution_proof` this does not require the block header to be known locally.
+pub fn check_execution_proof_with_root<Hash, E>(
 	local_state_root: Hash,
 	executor: &E,
 	request: &RemoteCallRequest<Hash>,
@@ -156,7 +162,7 @@ mod tests {
 		
 		// check remote execution proof locally
 		let local_executor = test_client::LocalExecutor::with_heap_pages(8, 8);
-		do_check_execution_proof(remote_block_storage_root.into(), &local_executor, &RemoteCallRequest {
+		check_execution_proof_with_root(remote_block_storage_root.into(), &local_executor, &RemoteCallRequest {
 			block: test_client::runtime::Hash::default(),
 			method: "authorities".into(),
 			call_data: vec![],
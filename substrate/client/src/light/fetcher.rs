@@ -19,12 +19,14 @@
 use std::sync::Arc;
 use futures::IntoFuture;
 
-use runtime_primitives::traits::{Block as BlockT};
+use runtime_primitives::traits::{Block as BlockT, Header as HeaderT};
 use state_machine::CodeExecutor;
 
 use call_executor::CallResult;
-use error::{Error as ClientError, Result as ClientResult};
+use cht;
+use error::{Error as ClientError, ErrorKind as ClientErrorKind, Result as ClientResult};
 use light::blockchain::{Blockchain, Storage as BlockchainStorage};
+use light::backend::check_read_proof;
 use light::call_executor::check_execution_proof;
 
 /// Remote call request.
@@ -38,20 +40,52 @@ pub struct RemoteCallRequest<Hash: ::std::fmt::Display> {
 	pub call_data: Vec<u8>,
 }
 
+/// Remote storage read request.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct RemoteReadRequest<Hash: ::std::fmt::Display> {
+	/// Read at state of given block.
+	pub block: Hash,
+	/// Storage key to read.
+	pub key: Vec<u8>,
+}
+
+/// Remote canonical header request.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct RemoteHeaderRequest<Header: HeaderT> {
+	/// The root of the CHT covering the block.
+	pub cht_root: Header::Hash,
+	/// Number of the block to request the header of.
+	pub block: Header::Number,
+}
+
 /// Light client data fetcher. Implementations of this trait must check if remote data
 /// is correct (see FetchedDataChecker) and return already checked data.
 pub trait Fetcher<Block: BlockT>: Send + Sync {
 	/// Remote call result future.
 	type RemoteCallResult: IntoFuture<Item=CallResult, Error=ClientError>;
 
+	/// Remote header result future.
+	type RemoteHeaderResult: IntoFuture<Item=Block::Header, Error=ClientError>;
+
 	/// Fetch remote call result.
 	fn remote_call(&self, request: RemoteCallRequest<Block::Hash>) -> Self::RemoteCallResult;
+	/// Fetch remote canonical header.
+	fn remote_header(&self, request: RemoteHeaderRequest<Block::Header>) -> Self::RemoteHeaderResult;
 }
 
 /// Light client remote data checker.
 pub trait FetchChecker<Block: BlockT>: Send + Sync {
 	/// Check remote method execution proof.
 	fn check_execution_proof(&self, request: &RemoteCallRequest<Block::Hash>, remote_proof: Vec<Vec<u8>>) -> ClientResult<CallResult>;
+	/// Check remote storage read proof.
+	fn check_read_proof(&self, request: &RemoteReadRequest<Block::Hash>, remote_proof: Vec<Vec<u8>>) -> ClientResult<Option<Vec<u8>>>;
+	/// Check remote header proof.
+	fn check_header_proof(
+		&self,
+		request: &RemoteHeaderRequest<Block::Header>,
+		remote_header: Option<Block::Header>,
+		remote_proof: Vec<Vec<u8>>
+	) -> ClientResult<Block::Header>;
 }
 
 /// Remote data checker.
@@ -85,4 +119,19 @@ impl<S, E, F, Block> FetchChecker<Block> for LightDataChecker<S, E, F>
 	fn check_execution_proof(&self, request: &RemoteCallRequest<Block::Hash>, remote_proof: Vec<Vec<u8>>) -> ClientResult<CallResult> {
 		check_execution_proof(&*self.blockchain, &self.executor, request, remote_proof)
 	}
+
+	fn check_read_proof(&self, request: &RemoteReadRequest<Block::Hash>, remote_proof: Vec<Vec<u8>>) -> ClientResult<Option<Vec<u8>>> {
+		check_read_proof(&*self.blockchain, request, remote_proof)
+	}
+
+	fn check_header_proof(
+		&self,
+		request: &RemoteHeaderRequest<Block::Header>,
+		remote_header: Option<Block::Header>,
+		remote_proof: Vec<Vec<u8>>
+	) -> ClientResult<Block::Header> {
+		let remote_header = remote_header.ok_or_else(|| ClientError::from(ClientErrorKind::InvalidHeaderProof))?;
+		cht::check_proof(request.cht_root, request.block, &remote_header, remote_proof)?;
+		Ok(remote_header)
+	}
 }
@@ -76,6 +76,18 @@ error_chain! {
 			display("Invalid memory reference"),
 		}
 
+		/// The call has executed more instructions than allowed.
+		ExecutionLimitExceeded(limit: u64) {
+			description("execution limit exceeded"),
+			display("Execution exceeded the limit of {} instructions", limit),
+		}
+
+		/// Code could not be instrumented for metering.
+		Instrumentation {
+			description("instrumentation failure"),
+			display("Failed to instrument the code for metering"),
+		}
+
 		/// Retry, please.
 		PleaseRetry {
 			description("retry needed"),
@@ -37,7 +37,11 @@ extern crate substrate_runtime_version as runtime_version;
 extern crate ed25519;
 
 extern crate serde;
+#[macro_use]
+extern crate serde_derive;
 extern crate wasmi;
+extern crate parity_wasm;
+extern crate pwasm_utils;
 extern crate byteorder;
 extern crate rustc_hex;
 extern crate triehash;
@@ -52,6 +56,7 @@ extern crate lazy_static;
 extern crate error_chain;
 
 #[cfg(test)]
+#[macro_use]
 extern crate assert_matches;
 
 #[cfg(test)]
@@ -65,6 +70,7 @@ mod native_executor;
 mod sandbox;
 
 pub mod error;
+pub mod trace;
 pub use wasm_executor::WasmExecutor;
 pub use native_executor::{with_native_environment, NativeExecutor, NativeExecutionDispatch};
 pub use state_machine::Externalities;
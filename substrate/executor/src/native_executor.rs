@@ -16,22 +16,21 @@
 
 use error::{Error, ErrorKind, Result};
 use state_machine::{CodeExecutor, Externalities};
-use wasm_executor::WasmExecutor;
-use wasmi::Module as WasmModule;
+use wasm_executor::{WasmExecutor, code_hash};
 use runtime_version::RuntimeVersion;
 use std::collections::HashMap;
 use codec::Decode;
-use twox_hash::XxHash;
-use std::hash::Hasher;
 use parking_lot::{Mutex, MutexGuard};
+use trace::{self, TracingExt};
 use RuntimeInfo;
 
 // For the internal Runtime Cache:
-// Is it compatible enough to run this natively or do we need to fall back on the WasmModule
+// Is it compatible enough to run this natively or do we need to fall back on the Wasm code.
+// Instances of the Wasm code are cached by the `WasmExecutor`.
 enum Compatibility {
-	InvalidVersion(WasmModule),
+	InvalidVersion,
 	IsCompatible(RuntimeVersion),
-	NotCompatible(RuntimeVersion, WasmModule)
+	NotCompatible(RuntimeVersion)
 }
 
 type CacheType = HashMap<u64, Compatibility>;
@@ -40,16 +39,6 @@ lazy_static! {
 	static ref RUNTIMES_CACHE: Mutex<CacheType> = Mutex::new(HashMap::new());
 }
 
-// helper function to generate low-over-head caching_keys
-// it is asserted that part of the audit process that any potential on-chain code change
-// will have done is to ensure that the two-x hash is different to that of any other
-// :code value from the same chain
-fn gen_cache_key(code: &[u8]) -> u64 {
-	let mut h = XxHash::with_seed(0);
-	h.write(code);
-	h.finish()
-}
-
 /// fetch a runtime version from the cache or if there is no cached version yet, create
 /// the runtime version entry for `code`, determines whether `Compatibility::IsCompatible`
 /// can be used by by comparing returned RuntimeVersion to `ref_version`
@@ -59,20 +48,19 @@ fn fetch_cached_runtime_version<'a, E: Externalities>(
 	code: &[u8],
 	ref_version: RuntimeVersion
 ) -> &'a Compatibility {
-	cache.entry(gen_cache_key(code))
+	cache.entry(code_hash(code))
 		.or_insert_with(|| {
-			let module = WasmModule::from_buffer(code).expect("all modules compiled with rustc are valid wasm code; qed");
-			let version = WasmExecutor::new(8, 8).call_in_wasm_module(ext, &module, "version", &[]).ok()
+			let version = WasmExecutor::new(8, 8).call(ext, code, "version", &[], false).0.ok()
 				.and_then(|v| RuntimeVersion::decode(&mut v.as_slice()));
 
 			if let Some(v) = version {
 				if ref_version.can_call_with(&v) {
 					Compatibility::IsCompatible(v)
 				} else {
-					Compatibility::NotCompatible(v, module)
+					Compatibility::NotCompatible(v)
 				}
 			} else {
-				Compatibility::InvalidVersion(module)
+				Compatibility::InvalidVersion
 			}
 	})
 }
@@ -118,6 +106,8 @@ pub struct NativeExecutor<D: NativeExecutionDispatch> {
 	_dummy: ::std::marker::PhantomData<D>,
 	/// The fallback executor in case native isn't available.
 	fallback: WasmExecutor,
+	/// Whether to log the host function calls made by each call.
+	trace: bool,
 }
 
 impl<D: NativeExecutionDispatch> NativeExecutor<D> {
@@ -125,12 +115,45 @@ impl<D: NativeExecutionDispatch> NativeExecutor<D> {
 	pub fn with_heap_pages(min_heap_pages: usize, max_heap_pages: usize) -> Self {
 		// FIXME: set this entry at compile time
 		RUNTIMES_CACHE.lock().insert(
-			gen_cache_key(D::native_equivalent()),
+			code_hash(D::native_equivalent()),
 			Compatibility::IsCompatible(D::VERSION));
 
 		NativeExecutor {
 			_dummy: Default::default(),
 			fallback: WasmExecutor::new(min_heap_pages, max_heap_pages),
+			trace: false,
+		}
+	}
+
+	/// Set a local limit of the number of instructions a single call of the wasm fallback may
+	/// execute, in addition to the limit of the chain. Native calls are not metered.
+	pub fn with_max_instructions(mut self, max_instructions: u64) -> Self {
+		self.fallback = self.fallback.with_max_instructions(max_instructions);
+		self
+	}
+
+	/// Set whether to log the host function calls made by each call, whether native or not.
+	pub fn with_tracing(mut self, trace: bool) -> Self {
+		self.trace = trace;
+		self
+	}
+
+	/// Call a given method in the runtime, recording the storage accesses of native code if
+	/// host function calls are being collected.
+	fn call_traceable<E: Externalities>(
+		&self,
+		ext: &mut E,
+		code: &[u8],
+		method: &str,
+		data: &[u8],
+		use_native: bool,
+	) -> (Result<Vec<u8>>, bool) {
+		let mut c = RUNTIMES_CACHE.lock();
+		match (use_native, fetch_cached_runtime_version(&mut c, ext, code, D::VERSION)) {
+			(_, Compatibility::NotCompatible(_)) | (_, Compatibility::InvalidVersion) | (false, _) =>
+				(self.fallback.call(ext, code, method, data, false).0, false),
+			_ if trace::is_collecting() => (D::dispatch(&mut TracingExt::new(ext), method, data), true),
+			_ => (D::dispatch(ext, method, data), true),
 		}
 	}
 }
@@ -140,6 +163,7 @@ impl<D: NativeExecutionDispatch> Clone for NativeExecutor<D> {
 		NativeExecutor {
 			_dummy: Default::default(),
 			fallback: self.fallback.clone(),
+			trace: self.trace,
 		}
 	}
 }
@@ -154,8 +178,8 @@ impl<D: NativeExecutionDispatch> RuntimeInfo for NativeExecutor<D> {
 	) -> Option<RuntimeVersion> {
 		let mut c = RUNTIMES_CACHE.lock();
 		match fetch_cached_runtime_version(&mut c, ext, code, D::VERSION) {
-			Compatibility::IsCompatible(v) | Compatibility::NotCompatible(v, _) => Some(v.clone()),
-			Compatibility::InvalidVersion(_m) => None
+			Compatibility::IsCompatible(v) | Compatibility::NotCompatible(v) => Some(v.clone()),
+			Compatibility::InvalidVersion => None
 		}
 	}
 }
@@ -171,14 +195,13 @@ impl<D: NativeExecutionDispatch> CodeExecutor for NativeExecutor<D> {
 		data: &[u8],
 		use_native: bool,
 	) -> (Result<Vec<u8>>, bool) {
-		let mut c = RUNTIMES_CACHE.lock();
-		match (use_native, fetch_cached_runtime_version(&mut c, ext, code, D::VERSION)) {
-			(_, Compatibility::NotCompatible(_, m)) | (_, Compatibility::InvalidVersion(m)) =>
-				(self.fallback.call_in_wasm_module(ext, m, method, data), false),
-			(false, _) =>
-				(self.fallback.call(ext, code, method, data, false).0, false),
-			_ => (D::dispatch(ext, method, data), true),
+		if !self.trace {
+			return self.call_traceable(ext, code, method, data, use_native)
 		}
+
+		let (result, traces) = trace::collect(|| self.call_traceable(ext, code, method, data, use_native));
+		trace::log_traces(method, &traces);
+		result
 	}
 }
 
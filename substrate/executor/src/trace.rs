@@ -0,0 +1,357 @@
+// Copyright 2018 Parity Technologies (UK) Ltd.
+// This file is part of Substrate.
+
+// Substrate is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// Substrate is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with Substrate.  If not, see <http://www.gnu.org/licenses/>.
+
+//! Tracing of the host function calls made by the runtime.
+//!
+//! While `collect` runs, the executors record every storage access of the runtime, along with
+//! the hashing and signature verification done by Wasm code, grouped by the extrinsic being
+//! executed. Hashing done by native code is not a host call and is not recorded.
+
+use std::cell::RefCell;
+use std::mem;
+use std::time::Instant;
+use codec::Decode;
+use primitives::Bytes;
+use primitives::hexdisplay::HexDisplay;
+use state_machine::{Externalities, OffchainExt, EXTRINSIC_INDEX};
+
+/// A host function call made by the runtime.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct HostCall {
+	/// Name of the function.
+	pub function: String,
+	/// Storage key of the child trie accessed by the call, if any.
+	pub child_storage_key: Option<Bytes>,
+	/// Storage key accessed by the call, if any. Within the child trie for child storage calls.
+	pub key: Option<Bytes>,
+	/// Size of the data read, written or processed by the call, in bytes.
+	pub size: u64,
+	/// Time spent in the call, in nanoseconds.
+	pub duration: u64,
+}
+
+/// Host function calls made while executing an extrinsic, or outside of any extrinsic.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ExtrinsicTrace {
+	/// Index of the extrinsic in the block. `None` before and after the extrinsics.
+	pub extrinsic: Option<u32>,
+	/// Calls made, in order.
+	pub calls: Vec<HostCall>,
+}
+
+#[derive(Default)]
+struct Collector {
+	/// Index of the extrinsic which is currently being executed.
+	extrinsic: Option<u32>,
+	traces: Vec<ExtrinsicTrace>,
+}
+
+thread_local! {
+	static COLLECTOR: RefCell<Option<Collector>> = RefCell::new(None);
+}
+
+/// Run `f`, collecting the host function calls made by all the runtime calls it executes on
+/// this thread. Calls collected by a nested `collect` are also returned by the outer one.
+pub fn collect<R, F: FnOnce() -> R>(f: F) -> (R, Vec<ExtrinsicTrace>) {
+	let outer = COLLECTOR.with(|c| mem::replace(&mut *c.borrow_mut(), Some(Collector::default())));
+	let result = f();
+	let traces = COLLECTOR.with(|c| {
+		let mut collector = c.borrow_mut();
+		let traces = mem::replace(&mut *collector, outer).map(|c| c.traces).unwrap_or_default();
+		if let Some(ref mut outer) = *collector {
+			outer.traces.extend(traces.iter().cloned());
+		}
+		traces
+	});
+	(result, traces)
+}
+
+/// Whether host function calls are currently being collected on this thread.
+pub fn is_collecting() -> bool {
+	COLLECTOR.with(|c| c.borrow().is_some())
+}
+
+/// Start timing a host function call. `None` unless calls are being collected, so that nothing
+/// is measured otherwise.
+pub(crate) fn start() -> Option<Instant> {
+	if is_collecting() { Some(Instant::now()) } else { None }
+}
+
+/// Record a host function call of `function` timed by `start`, processing `size` bytes.
+pub(crate) fn record_host_call(function: &str, size: usize, started: Option<Instant>) {
+	if let Some(started) = started {
+		record(function, None, size, started)
+	}
+}
+
+/// Record a call of `function` which has started at `started`, if calls are being collected.
+fn record(function: &str, key: Option<&[u8]>, size: usize, started: Instant) {
+	record_call(function, None, key, size, started)
+}
+
+/// Record a call of `function` accessing the child trie with given storage key, which has
+/// started at `started`, if calls are being collected.
+fn record_child(function: &str, storage_key: &[u8], key: Option<&[u8]>, size: usize, started: Instant) {
+	record_call(function, Some(storage_key), key, size, started)
+}
+
+fn record_call(function: &str, child_storage_key: Option<&[u8]>, key: Option<&[u8]>, size: usize, started: Instant) {
+	let elapsed = started.elapsed();
+	COLLECTOR.with(|c| if let Some(ref mut collector) = *c.borrow_mut() {
+		let call = HostCall {
+			function: function.into(),
+			child_storage_key: child_storage_key.map(|key| Bytes(key.to_vec())),
+			key: key.map(|key| Bytes(key.to_vec())),
+			size: size as u64,
+			duration: elapsed.as_secs() * 1_000_000_000 + elapsed.subsec_nanos() as u64,
+		};
+		let extrinsic = collector.extrinsic;
+		if collector.traces.last().map_or(true, |trace| trace.extrinsic != extrinsic) {
+			collector.traces.push(ExtrinsicTrace { extrinsic, calls: Vec::new() });
+		}
+		collector.traces.last_mut()
+			.expect("a trace of the current extrinsic has just been pushed if the last one was not; qed")
+			.calls.push(call);
+	})
+}
+
+fn set_extrinsic(extrinsic: Option<u32>) {
+	COLLECTOR.with(|c| if let Some(ref mut collector) = *c.borrow_mut() {
+		collector.extrinsic = extrinsic;
+	})
+}
+
+fn decode_extrinsic_index(value: Option<&[u8]>) -> Option<u32> {
+	value.and_then(|mut value| u32::decode(&mut value))
+}
+
+/// Log the host function calls made by a runtime call of `method`, one extrinsic at a time.
+pub fn log_traces(method: &str, traces: &[ExtrinsicTrace]) {
+	for trace in traces {
+		let duration: u64 = trace.calls.iter().map(|call| call.duration).sum();
+		match trace.extrinsic {
+			Some(index) => info!(target: "runtime-trace", "{}: extrinsic #{}, {} host calls in {} ns",
+				method, index, trace.calls.len(), duration),
+			None => info!(target: "runtime-trace", "{}: {} host calls outside of extrinsics in {} ns",
+				method, trace.calls.len(), duration),
+		}
+		for call in &trace.calls {
+			match (&call.child_storage_key, &call.key) {
+				(&Some(ref storage_key), &Some(ref key)) => info!(target: "runtime-trace", "  {}({}, {}): {} bytes in {} ns",
+					call.function, HexDisplay::from(&storage_key.0), HexDisplay::from(&key.0), call.size, call.duration),
+				(&Some(ref key), &None) | (&None, &Some(ref key)) => info!(target: "runtime-trace", "  {}({}): {} bytes in {} ns",
+					call.function, HexDisplay::from(&key.0), call.size, call.duration),
+				(&None, &None) => info!(target: "runtime-trace", "  {}: {} bytes in {} ns",
+					call.function, call.size, call.duration),
+			}
+		}
+	}
+}
+
+/// Externalities recording the storage accesses made through them.
+pub(crate) struct TracingExt<'a, E: 'a> {
+	ext: &'a mut E,
+}
+
+impl<'a, E: Externalities> TracingExt<'a, E> {
+	/// Wrap given externalities. The extrinsic being executed is initially read from them.
+	pub fn new(ext: &'a mut E) -> Self {
+		set_extrinsic(ext.extrinsic_index());
+		TracingExt { ext }
+	}
+}
+
+impl<'a, E: Externalities> Externalities for TracingExt<'a, E> {
+	fn storage(&self, key: &[u8]) -> Option<Vec<u8>> {
+		let started = Instant::now();
+		let value = self.ext.storage(key);
+		record("storage", Some(key), value.as_ref().map_or(0, Vec::len), started);
+		value
+	}
+
+	fn exists_storage(&self, key: &[u8]) -> bool {
+		let started = Instant::now();
+		let exists = self.ext.exists_storage(key);
+		record("exists_storage", Some(key), 0, started);
+		exists
+	}
+
+	fn clear_prefix(&mut self, prefix: &[u8]) {
+		let started = Instant::now();
+		self.ext.clear_prefix(prefix);
+		record("clear_prefix", Some(prefix), 0, started);
+	}
+
+	fn place_storage(&mut self, key: Vec<u8>, value: Option<Vec<u8>>) {
+		if &key[..] == EXTRINSIC_INDEX {
+			set_extrinsic(decode_extrinsic_index(value.as_ref().map(|value| &value[..])));
+		}
+		let started = Instant::now();
+		let size = value.as_ref().map_or(0, Vec::len);
+		let function = if value.is_some() { "set_storage" } else { "clear_storage" };
+		self.ext.place_storage(key.clone(), value);
+		record(function, Some(&key), size, started);
+	}
+
+	fn child_storage(&self, storage_key: &[u8], key: &[u8]) -> Option<Vec<u8>> {
+		let started = Instant::now();
+		let value = self.ext.child_storage(storage_key, key);
+		record_child("child_storage", storage_key, Some(key), value.as_ref().map_or(0, Vec::len), started);
+		value
+	}
+
+	fn place_child_storage(&mut self, storage_key: Vec<u8>, key: Vec<u8>, value: Option<Vec<u8>>) {
+		let started = Instant::now();
+		let size = value.as_ref().map_or(0, Vec::len);
+		let function = if value.is_some() { "set_child_storage" } else { "clear_child_storage" };
+		self.ext.place_child_storage(storage_key.clone(), key.clone(), value);
+		record_child(function, &storage_key, Some(&key), size, started);
+	}
+
+	fn kill_child_storage(&mut self, storage_key: &[u8]) {
+		let started = Instant::now();
+		self.ext.kill_child_storage(storage_key);
+		record_child("kill_child_storage", storage_key, None, 0, started);
+	}
+
+	fn start_transaction(&mut self) {
+		self.ext.start_transaction()
+	}
+
+	fn commit_transaction(&mut self) -> bool {
+		self.ext.commit_transaction()
+	}
+
+	fn rollback_transaction(&mut self) -> bool {
+		self.ext.rollback_transaction()
+	}
+
+	fn chain_id(&self) -> u64 {
+		self.ext.chain_id()
+	}
+
+	fn storage_root(&mut self) -> [u8; 32] {
+		let started = Instant::now();
+		let root = self.ext.storage_root();
+		record("storage_root", None, 0, started);
+		root
+	}
+
+	fn child_storage_root(&mut self, storage_key: &[u8]) -> Option<[u8; 32]> {
+		let started = Instant::now();
+		let root = self.ext.child_storage_root(storage_key);
+		record_child("child_storage_root", storage_key, None, 0, started);
+		root
+	}
+
+	fn storage_changes_root(&mut self) -> Option<[u8; 32]> {
+		let started = Instant::now();
+		let root = self.ext.storage_changes_root();
+		record("storage_changes_root", None, 0, started);
+		root
+	}
+
+	fn extrinsic_index(&self) -> Option<u32> {
+		self.ext.extrinsic_index()
+	}
+
+	fn offchain(&mut self) -> Option<&mut OffchainExt> {
+		self.ext.offchain()
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use state_machine::{Ext, OverlayedChanges, TestExternalities};
+	use state_machine::backend::InMemory;
+	use std::collections::HashMap;
+	use codec::Encode;
+	use super::*;
+
+	#[test]
+	fn storage_accesses_are_collected_by_extrinsic() {
+		let mut ext = TestExternalities::default();
+		let (_, traces) = collect(|| {
+			let mut ext = TracingExt::new(&mut ext);
+			ext.set_storage(b"a".to_vec(), b"123".to_vec());
+			ext.set_storage(EXTRINSIC_INDEX.to_vec(), 1u32.encode());
+			assert_eq!(ext.storage(b"a"), Some(b"123".to_vec()));
+			ext.clear_storage(EXTRINSIC_INDEX);
+			ext.clear_storage(b"a");
+		});
+
+		let calls = |trace: &ExtrinsicTrace| trace.calls.iter()
+			.map(|call| (call.function.clone(), call.size))
+			.collect::<Vec<_>>();
+		assert_eq!(traces.len(), 3);
+		assert_eq!(traces[0].extrinsic, None);
+		assert_eq!(calls(&traces[0]), vec![("set_storage".to_owned(), 3)]);
+		assert_eq!(traces[1].extrinsic, Some(1));
+		assert_eq!(calls(&traces[1]), vec![("set_storage".to_owned(), 4), ("storage".to_owned(), 3)]);
+		assert_eq!(traces[2].extrinsic, None);
+		assert_eq!(calls(&traces[2]), vec![("clear_storage".to_owned(), 0), ("clear_storage".to_owned(), 0)]);
+		assert!(!is_collecting());
+	}
+
+	#[test]
+	fn child_storage_accesses_record_both_keys() {
+		let storage_key = b":child_storage:test".to_vec();
+		let mut ext = TestExternalities::default();
+		let (_, traces) = collect(|| {
+			let mut ext = TracingExt::new(&mut ext);
+			ext.set_child_storage(storage_key.clone(), b"a".to_vec(), b"123".to_vec());
+			assert_eq!(ext.child_storage(&storage_key, b"a"), Some(b"123".to_vec()));
+		});
+
+		assert_eq!(traces.len(), 1);
+		for call in &traces[0].calls {
+			assert_eq!(call.child_storage_key, Some(Bytes(storage_key.clone())));
+			assert_eq!(call.key, Some(Bytes(b"a".to_vec())));
+		}
+		assert_eq!(traces[0].calls.len(), 2);
+	}
+
+	#[test]
+	fn extrinsic_index_is_not_read_from_the_backend() {
+		let storage: HashMap<_, _> = vec![(EXTRINSIC_INDEX.to_vec(), 1u32.encode())].into_iter().collect();
+		let backend = InMemory::from(storage);
+		let mut overlay = OverlayedChanges::default();
+		let mut ext = Ext::new(&mut overlay, &backend);
+		let (_, traces) = collect(|| {
+			let ext = TracingExt::new(&mut ext);
+			ext.storage(b"a");
+		});
+
+		assert_eq!(traces.len(), 1);
+		assert_eq!(traces[0].extrinsic, None);
+		assert_eq!(traces[0].calls.len(), 1);
+	}
+
+	#[test]
+	fn host_calls_are_only_timed_while_collecting() {
+		assert!(start().is_none());
+		let (started, traces) = collect(|| {
+			let started = start();
+			record_host_call("ext_twox_128", 3, started);
+			started
+		});
+		assert!(started.is_some());
+		assert_eq!(traces.len(), 1);
+		assert_eq!(traces[0].calls[0].function, "ext_twox_128");
+	}
+}
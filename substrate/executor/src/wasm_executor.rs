@@ -16,41 +16,53 @@
 
 //! Rust implementation of Substrate contracts.
 
+use std::cell::RefCell;
 use std::cmp::Ordering;
+use std::hash::Hasher;
 use parking_lot::Mutex;
 use std::collections::HashMap;
+use twox_hash::XxHash;
 use wasmi::{
-	Module, ModuleInstance,  MemoryInstance, MemoryRef, TableRef, ImportsBuilder
+	Module, ModuleInstance,  MemoryInstance, MemoryRef, ModuleRef, TableRef, ImportsBuilder
 };
 use wasmi::RuntimeValue::{I32, I64};
 use wasmi::memory_units::{Pages, Bytes};
+use parity_wasm::elements;
+use log::LogLevel;
+use pwasm_utils::rules;
 use state_machine::{Externalities, CodeExecutor};
 use error::{Error, ErrorKind, Result};
 use wasm_utils::UserError;
 use primitives::{blake2_256, twox_128, twox_256};
 use primitives::hexdisplay::HexDisplay;
+use primitives::storage;
 use primitives::sandbox as sandbox_primitives;
 use triehash::ordered_trie_root;
 use sandbox;
+use trace::{self, TracingExt};
 
 struct Heap {
 	end: u32,
 }
 
 impl Heap {
-	/// Construct new `Heap` struct with a given number of pages.
+	/// Construct new `Heap` struct with a given number of pages, starting at `base`.
+	/// The memory is grown if it ends before the last page of the heap.
 	///
 	/// Returns `Err` if the heap couldn't allocate required
 	/// number of pages.
 	///
 	/// This could mean that wasm binary specifies memory
 	/// limit and we are trying to allocate beyond that limit.
-	fn new(memory: &MemoryRef, pages: usize) -> Result<Self> {
-		let prev_page_count = memory
-			.grow(Pages(pages))
-			.map_err(|_| Error::from(ErrorKind::Runtime))?;
+	fn new(memory: &MemoryRef, base: Pages, pages: usize) -> Result<Self> {
+		let current_page_count = memory.current_size();
+		if current_page_count.0 < base.0 + pages {
+			memory
+				.grow(Pages(base.0 + pages - current_page_count.0))
+				.map_err(|_| Error::from(ErrorKind::Runtime))?;
+		}
 		Ok(Heap {
-			end: Bytes::from(prev_page_count).0 as u32,
+			end: Bytes::from(base).0 as u32,
 		})
 	}
 
@@ -80,19 +92,28 @@ struct FunctionExecutor<'e, E: Externalities + 'e> {
 	table: Option<TableRef>,
 	ext: &'e mut E,
 	hash_lookup: HashMap<Vec<u8>, Vec<u8>>,
+	max_instructions: u64,
+	instructions: u64,
 }
 
 impl<'e, E: Externalities> FunctionExecutor<'e, E> {
-	fn new(m: MemoryRef, heap_pages: usize, t: Option<TableRef>, e: &'e mut E) -> Result<Self> {
+	fn new(m: MemoryRef, heap_base: Pages, heap_pages: usize, max_instructions: u64, t: Option<TableRef>, e: &'e mut E) -> Result<Self> {
 		Ok(FunctionExecutor {
 			sandbox_store: sandbox::Store::new(),
-			heap: Heap::new(&m, heap_pages)?,
+			heap: Heap::new(&m, heap_base, heap_pages)?,
 			memory: m,
 			table: t,
 			ext: e,
 			hash_lookup: HashMap::new(),
+			max_instructions,
+			instructions: 0,
 		})
 	}
+
+	/// Whether the code has executed more instructions than allowed, which is why it has trapped.
+	fn limit_exceeded(&self) -> bool {
+		self.instructions > self.max_instructions
+	}
 }
 
 impl<'e, E: Externalities> sandbox::SandboxCapabilities for FunctionExecutor<'e, E> {
@@ -159,6 +180,21 @@ impl_function_executor!(this: FunctionExecutor<'e, E>,
 		println!("{}", number);
 		Ok(())
 	},
+	ext_log(level: u32, target_data: *const u8, target_len: u32, message_data: *const u8, message_len: u32) => {
+		let level = match level {
+			1 => LogLevel::Error,
+			2 => LogLevel::Warn,
+			3 => LogLevel::Info,
+			4 => LogLevel::Debug,
+			5 => LogLevel::Trace,
+			_ => return Err(UserError("Invalid log level in ext_log").into()),
+		};
+		let target = this.memory.get(target_data, target_len as usize).map_err(|_| UserError("Invalid attempt to get target in ext_log"))?;
+		let message = this.memory.get(message_data, message_len as usize).map_err(|_| UserError("Invalid attempt to get message in ext_log"))?;
+		let target = String::from_utf8(target).map_err(|_| UserError("Invalid UTF-8 target in ext_log"))?;
+		log!(target: &target, level, "{}", String::from_utf8_lossy(&message));
+		Ok(())
+	},
 	ext_memcmp(s1: *const u8, s2: *const u8, n: usize) -> i32 => {
 		let sl1 = this.memory.get(s1, n as usize).map_err(|_| UserError("Invalid attempt to read from memory in first arg of ext_memcmp"))?;
 		let sl2 = this.memory.get(s2, n as usize).map_err(|_| UserError("Invalid attempt to read from memory in second arg of ext_memcmp"))?;
@@ -285,12 +321,82 @@ impl_function_executor!(this: FunctionExecutor<'e, E>,
 			Ok(u32::max_value())
 		}
 	},
+	// return 0 and place u32::max_value() into written_out if no value exists for the key.
+	ext_get_allocated_child_storage(storage_key_data: *const u8, storage_key_len: u32, key_data: *const u8, key_len: u32, written_out: *mut u32) -> *mut u8 => {
+		let storage_key = this.memory.get(storage_key_data, storage_key_len as usize).map_err(|_| UserError("Invalid attempt to determine storage_key in ext_get_allocated_child_storage"))?;
+		let key = this.memory.get(key_data, key_len as usize).map_err(|_| UserError("Invalid attempt to determine key in ext_get_allocated_child_storage"))?;
+		let maybe_value = this.ext.child_storage(&storage_key, &key);
+
+		if let Some(value) = maybe_value {
+			let offset = this.heap.allocate(value.len() as u32) as u32;
+			this.memory.set(offset, &value).map_err(|_| UserError("Invalid attempt to set memory in ext_get_allocated_child_storage"))?;
+			this.memory.write_primitive(written_out, value.len() as u32)
+				.map_err(|_| UserError("Invalid attempt to write written_out in ext_get_allocated_child_storage"))?;
+			Ok(offset)
+		} else {
+			this.memory.write_primitive(written_out, u32::max_value())
+				.map_err(|_| UserError("Invalid attempt to write failed written_out in ext_get_allocated_child_storage"))?;
+			Ok(0)
+		}
+	},
+	ext_set_child_storage(storage_key_data: *const u8, storage_key_len: u32, key_data: *const u8, key_len: u32, value_data: *const u8, value_len: u32) => {
+		let storage_key = this.memory.get(storage_key_data, storage_key_len as usize).map_err(|_| UserError("Invalid attempt to determine storage_key in ext_set_child_storage"))?;
+		let key = this.memory.get(key_data, key_len as usize).map_err(|_| UserError("Invalid attempt to determine key in ext_set_child_storage"))?;
+		let value = this.memory.get(value_data, value_len as usize).map_err(|_| UserError("Invalid attempt to determine value in ext_set_child_storage"))?;
+		this.ext.set_child_storage(storage_key, key, value);
+		Ok(())
+	},
+	ext_clear_child_storage(storage_key_data: *const u8, storage_key_len: u32, key_data: *const u8, key_len: u32) => {
+		let storage_key = this.memory.get(storage_key_data, storage_key_len as usize).map_err(|_| UserError("Invalid attempt to determine storage_key in ext_clear_child_storage"))?;
+		let key = this.memory.get(key_data, key_len as usize).map_err(|_| UserError("Invalid attempt to determine key in ext_clear_child_storage"))?;
+		this.ext.clear_child_storage(&storage_key, &key);
+		Ok(())
+	},
+	ext_kill_child_storage(storage_key_data: *const u8, storage_key_len: u32) => {
+		let storage_key = this.memory.get(storage_key_data, storage_key_len as usize).map_err(|_| UserError("Invalid attempt to determine storage_key in ext_kill_child_storage"))?;
+		this.ext.kill_child_storage(&storage_key);
+		Ok(())
+	},
+	// return 0 if storage_key is not a child storage key.
+	ext_child_storage_root(storage_key_data: *const u8, storage_key_len: u32, result: *mut u8) -> u32 => {
+		let storage_key = this.memory.get(storage_key_data, storage_key_len as usize).map_err(|_| UserError("Invalid attempt to determine storage_key in ext_child_storage_root"))?;
+		match this.ext.child_storage_root(&storage_key) {
+			Some(r) => {
+				this.memory.set(result, &r[..]).map_err(|_| UserError("Invalid attempt to set memory in ext_child_storage_root"))?;
+				Ok(1)
+			},
+			None => Ok(0),
+		}
+	},
+	// return 0 if changes tries are not enabled.
+	ext_storage_changes_root(result: *mut u8) -> u32 => {
+		match this.ext.storage_changes_root() {
+			Some(r) => {
+				this.memory.set(result, &r[..]).map_err(|_| UserError("Invalid attempt to set memory in ext_storage_changes_root"))?;
+				Ok(1)
+			},
+			None => Ok(0),
+		}
+	},
+	ext_start_transaction() => {
+		this.ext.start_transaction();
+		Ok(())
+	},
+	// return 0 if there is no open transaction.
+	ext_commit_transaction() -> u32 => {
+		Ok(if this.ext.commit_transaction() { 1 } else { 0 })
+	},
+	// return 0 if there is no open transaction.
+	ext_rollback_transaction() -> u32 => {
+		Ok(if this.ext.rollback_transaction() { 1 } else { 0 })
+	},
 	ext_storage_root(result: *mut u8) => {
 		let r = this.ext.storage_root();
 		this.memory.set(result, &r[..]).map_err(|_| UserError("Invalid attempt to set memory in ext_storage_root"))?;
 		Ok(())
 	},
 	ext_enumerated_trie_root(values_data: *const u8, lens_data: *const u32, lens_len: u32, result: *mut u8) => {
+		let started = trace::start();
 		let values = (0..lens_len)
 			.map(|i| this.memory.read_primitive(lens_data + i * 4))
 			.collect::<::std::result::Result<Vec<u32>, UserError>>()?
@@ -301,14 +407,56 @@ impl_function_executor!(this: FunctionExecutor<'e, E>,
 					.map_err(|_| UserError("Invalid attempt to get memory in ext_enumerated_trie_root"))
 			)
 			.collect::<::std::result::Result<Vec<_>, UserError>>()?;
+		let size: usize = values.iter().map(Vec::len).sum();
 		let r = ordered_trie_root(values.into_iter());
 		this.memory.set(result, &r[..]).map_err(|_| UserError("Invalid attempt to set memory in ext_enumerated_trie_root"))?;
+		trace::record_host_call("ext_enumerated_trie_root", size, started);
 		Ok(())
 	},
 	ext_chain_id() -> u64 => {
 		Ok(this.ext.chain_id())
 	},
+	ext_submit_extrinsic(data: *const u8, len: u32) => {
+		let extrinsic = this.memory.get(data, len as usize).map_err(|_| UserError("Invalid attempt to get extrinsic in ext_submit_extrinsic"))?;
+		let offchain = this.ext.offchain().ok_or_else(|| UserError("ext_submit_extrinsic is only available to offchain workers"))?;
+		offchain.submit_extrinsic(extrinsic);
+		Ok(())
+	},
+	ext_get_allocated_local_storage(key_data: *const u8, key_len: u32, written_out: *mut u32) -> *mut u8 => {
+		let key = this.memory.get(key_data, key_len as usize).map_err(|_| UserError("Invalid attempt to determine key in ext_get_allocated_local_storage"))?;
+		let maybe_value = this.ext.offchain()
+			.ok_or_else(|| UserError("ext_get_allocated_local_storage is only available to offchain workers"))?
+			.local_storage(&key);
+
+		if let Some(value) = maybe_value {
+			let offset = this.heap.allocate(value.len() as u32) as u32;
+			this.memory.set(offset, &value).map_err(|_| UserError("Invalid attempt to set memory in ext_get_allocated_local_storage"))?;
+			this.memory.write_primitive(written_out, value.len() as u32)
+				.map_err(|_| UserError("Invalid attempt to write written_out in ext_get_allocated_local_storage"))?;
+			Ok(offset)
+		} else {
+			this.memory.write_primitive(written_out, u32::max_value())
+				.map_err(|_| UserError("Invalid attempt to write failed written_out in ext_get_allocated_local_storage"))?;
+			Ok(0)
+		}
+	},
+	ext_set_local_storage(key_data: *const u8, key_len: u32, value_data: *const u8, value_len: u32) => {
+		let key = this.memory.get(key_data, key_len as usize).map_err(|_| UserError("Invalid attempt to determine key in ext_set_local_storage"))?;
+		let value = this.memory.get(value_data, value_len as usize).map_err(|_| UserError("Invalid attempt to determine value in ext_set_local_storage"))?;
+		this.ext.offchain()
+			.ok_or_else(|| UserError("ext_set_local_storage is only available to offchain workers"))?
+			.set_local_storage(&key, Some(&value[..]));
+		Ok(())
+	},
+	ext_clear_local_storage(key_data: *const u8, key_len: u32) => {
+		let key = this.memory.get(key_data, key_len as usize).map_err(|_| UserError("Invalid attempt to determine key in ext_clear_local_storage"))?;
+		this.ext.offchain()
+			.ok_or_else(|| UserError("ext_clear_local_storage is only available to offchain workers"))?
+			.set_local_storage(&key, None);
+		Ok(())
+	},
 	ext_twox_128(data: *const u8, len: u32, out: *mut u8) => {
+		let started = trace::start();
 		let result = if len == 0 {
 			let hashed = twox_128(&[0u8; 0]);
 			debug_trace!(target: "xxhash", "XXhash: '' -> {}", HexDisplay::from(&hashed));
@@ -330,34 +478,42 @@ impl_function_executor!(this: FunctionExecutor<'e, E>,
 		};
 
 		this.memory.set(out, &result).map_err(|_| UserError("Invalid attempt to set result in ext_twox_128"))?;
+		trace::record_host_call("ext_twox_128", len as usize, started);
 		Ok(())
 	},
 	ext_twox_256(data: *const u8, len: u32, out: *mut u8) => {
+		let started = trace::start();
 		let result = if len == 0 {
 			twox_256(&[0u8; 0])
 		} else {
 			twox_256(&this.memory.get(data, len as usize).map_err(|_| UserError("Invalid attempt to get data in ext_twox_256"))?)
 		};
 		this.memory.set(out, &result).map_err(|_| UserError("Invalid attempt to set result in ext_twox_256"))?;
+		trace::record_host_call("ext_twox_256", len as usize, started);
 		Ok(())
 	},
 	ext_blake2_256(data: *const u8, len: u32, out: *mut u8) => {
+		let started = trace::start();
 		let result = if len == 0 {
 			blake2_256(&[0u8; 0])
 		} else {
 			blake2_256(&this.memory.get(data, len as usize).map_err(|_| UserError("Invalid attempt to get data in ext_blake2_256"))?)
 		};
 		this.memory.set(out, &result).map_err(|_| UserError("Invalid attempt to set result in ext_blake2_256"))?;
+		trace::record_host_call("ext_blake2_256", len as usize, started);
 		Ok(())
 	},
 	ext_ed25519_verify(msg_data: *const u8, msg_len: u32, sig_data: *const u8, pubkey_data: *const u8) -> u32 => {
+		let started = trace::start();
 		let mut sig = [0u8; 64];
 		this.memory.get_into(sig_data, &mut sig[..]).map_err(|_| UserError("Invalid attempt to get signature in ext_ed25519_verify"))?;
 		let mut pubkey = [0u8; 32];
 		this.memory.get_into(pubkey_data, &mut pubkey[..]).map_err(|_| UserError("Invalid attempt to get pubkey in ext_ed25519_verify"))?;
 		let msg = this.memory.get(msg_data, msg_len as usize).map_err(|_| UserError("Invalid attempt to get message in ext_ed25519_verify"))?;
 
-		Ok(if ::ed25519::verify(&sig, &msg, &pubkey) {
+		let verified = ::ed25519::verify(&sig, &msg, &pubkey);
+		trace::record_host_call("ext_ed25519_verify", msg_len as usize, started);
+		Ok(if verified {
 			0
 		} else {
 			5
@@ -478,6 +634,14 @@ impl_function_executor!(this: FunctionExecutor<'e, E>,
 		this.sandbox_store.memory_teardown(memory_idx)?;
 		Ok(())
 	},
+	// Called by the metering code injected into the module in `prepare_module`.
+	gas(instructions: u32) => {
+		this.instructions = this.instructions.saturating_add(instructions as u64);
+		if this.limit_exceeded() {
+			return Err(UserError("Instruction limit exceeded").into());
+		}
+		Ok(())
+	},
 	=> <'e, E: Externalities + 'e>
 );
 
@@ -490,6 +654,11 @@ pub struct WasmExecutor {
 	pub min_heap_pages: usize,
 	/// The max number of pages to allocate for the heap.
 	pub max_heap_pages: usize,
+	/// Local limit of the number of instructions a single call may execute, applied on top of
+	/// the limit of the chain. Only for calls which are not a part of block execution.
+	pub max_instructions: Option<u64>,
+	/// Whether to log the host function calls made by each call.
+	pub trace: bool,
 
 	try_heap_pages: Mutex<(usize, usize)>,
 }
@@ -499,6 +668,8 @@ impl Clone for WasmExecutor {
 		WasmExecutor {
 			min_heap_pages: self.min_heap_pages,
 			max_heap_pages: self.max_heap_pages,
+			max_instructions: self.max_instructions,
+			trace: self.trace,
 			try_heap_pages: Mutex::new((self.min_heap_pages, 0)),
 		}
 	}
@@ -507,6 +678,107 @@ impl Clone for WasmExecutor {
 // Number of executions to continue with the old heap_pages before reducing to the next lowest POT.
 const DECAY_TIMEOUT: usize = 16;
 
+/// Default max number of instructions a single call may execute, if the chain doesn't set one.
+const DEFAULT_MAX_INSTRUCTIONS: u64 = 1_000_000_000;
+
+/// Maximum number of module instances kept in the cache of each thread.
+const MAX_CACHED_INSTANCES: usize = 8;
+
+thread_local! {
+	/// Instances of recently called modules with their code hashes, most recently used last.
+	static INSTANCE_CACHE: RefCell<Vec<(u64, Instance)>> = RefCell::new(Vec::new());
+}
+
+/// Hash of the code, used as a low-overhead cache key.
+///
+/// It is asserted that part of the audit process that any potential on-chain code change
+/// will have done is to ensure that the two-x hash is different to that of any other
+/// :code value from the same chain.
+pub(crate) fn code_hash(code: &[u8]) -> u64 {
+	let mut h = XxHash::with_seed(0);
+	h.write(code);
+	h.finish()
+}
+
+/// An instantiated module along with the initial state its memory is reset to after each call.
+///
+/// Only instances of modules whose state is fully restored by `reset` are cached: see
+/// `is_cacheable`. Instances whose call has failed are discarded, as a trap may leave their
+/// globals modified.
+struct Instance {
+	module: ModuleRef,
+	memory: MemoryRef,
+	table: Option<TableRef>,
+	/// Size of the memory after instantiation, without the heap. The heap starts here.
+	heap_base: Pages,
+	/// Size of the memory after instantiation, including the heap.
+	memory_size: Pages,
+	/// Contents of the memory below `heap_base` after instantiation.
+	initial_memory: Vec<u8>,
+}
+
+impl Instance {
+	/// Restore the memory to its state after instantiation. Returns `false` if that is not
+	/// possible because the memory has grown, in which case the instance must be discarded.
+	fn reset(&self) -> Result<bool> {
+		if self.memory.current_size() != self.memory_size {
+			return Ok(false)
+		}
+		let heap_base = Bytes::from(self.heap_base).0;
+		let memory_end = Bytes::from(self.memory_size).0;
+		self.memory.set(0, &self.initial_memory)
+			.and_then(|_| self.memory.clear(heap_base, 0, memory_end - heap_base))
+			.map_err(|_| Error::from(ErrorKind::Runtime))?;
+		Ok(true)
+	}
+}
+
+/// Whether the state of instances of the module is fully restored by `Instance::reset`, so
+/// that they can be reused for later calls.
+///
+/// The host can't access the mutable globals of an instance to restore them. The only one
+/// allowed is the stack pointer of code compiled by rustc, which is the first global, is not
+/// exported and is restored by every call which returns. Any other mutable global could carry
+/// state from one call to the next.
+fn is_cacheable(module: &elements::Module) -> bool {
+	let imports_mutable_global = module.import_section().map_or(false, |section| section.entries().iter()
+		.any(|entry| match *entry.external() {
+			elements::External::Global(ref global_type) => global_type.is_mutable(),
+			_ => false,
+		}));
+	let imported_globals = module.import_count(elements::ImportCountType::Global);
+	let mutable_globals: Vec<usize> = module.global_section().map(|section| section.entries().iter()
+		.enumerate()
+		.filter(|&(_, entry)| entry.global_type().is_mutable())
+		.map(|(index, _)| imported_globals + index)
+		.collect()
+	).unwrap_or_default();
+	let exports_mutable_global = module.export_section().map_or(false, |section| section.entries().iter()
+		.any(|entry| match *entry.internal() {
+			elements::Internal::Global(index) => mutable_globals.contains(&(index as usize)),
+			_ => false,
+		}));
+
+	!imports_mutable_global && !exports_mutable_global && mutable_globals.iter().all(|&index| index == 0)
+}
+
+fn take_cached_instance(code_hash: u64) -> Option<Instance> {
+	INSTANCE_CACHE.with(|cache| {
+		let mut cache = cache.borrow_mut();
+		cache.iter().position(|&(hash, _)| hash == code_hash).map(|index| cache.remove(index).1)
+	})
+}
+
+fn cache_instance(code_hash: u64, instance: Instance) {
+	INSTANCE_CACHE.with(|cache| {
+		let mut cache = cache.borrow_mut();
+		cache.push((code_hash, instance));
+		if cache.len() > MAX_CACHED_INSTANCES {
+			cache.remove(0);
+		}
+	})
+}
+
 impl WasmExecutor {
 
 	/// Create a new instance.
@@ -514,11 +786,58 @@ impl WasmExecutor {
 		WasmExecutor {
 			min_heap_pages,
 			max_heap_pages,
+			max_instructions: None,
+			trace: false,
 			try_heap_pages: Mutex::new((min_heap_pages, 0)),
 		}
 	}
 
+	/// Set a local limit of the number of instructions a single call may execute, in addition
+	/// to the limit set by the chain.
+	///
+	/// Nodes executing a block must use the same limit to agree on its validity, so this is
+	/// only for executors of calls which are not a part of block execution, e.g. made over RPC.
+	pub fn with_max_instructions(mut self, max_instructions: u64) -> Self {
+		self.max_instructions = Some(max_instructions);
+		self
+	}
+
+	/// The max number of instructions a single call may execute: the limit stored by the chain
+	/// under `:max_instructions`, or the default one, capped by the local limit.
+	fn max_instructions<E: Externalities>(&self, ext: &E) -> u64 {
+		use codec::Decode;
+
+		let chain_limit = ext.storage(storage::MAX_INSTRUCTIONS)
+			.and_then(|limit| u64::decode(&mut &limit[..]))
+			.unwrap_or(DEFAULT_MAX_INSTRUCTIONS);
+		self.max_instructions.map_or(chain_limit, |limit| limit.min(chain_limit))
+	}
+
+	/// Set whether to log the host function calls made by each call.
+	pub fn with_tracing(mut self, trace: bool) -> Self {
+		self.trace = trace;
+		self
+	}
+
+	/// Load the module from given code, injecting the metering of executed instructions.
+	pub fn prepare_module(code: &[u8]) -> Result<Module> {
+		Self::prepare(code).map(|(module, _)| module)
+	}
+
+	/// Load the module from given code like `prepare_module`, also returning whether its
+	/// instances can be cached.
+	fn prepare(code: &[u8]) -> Result<(Module, bool)> {
+		let module = elements::deserialize_buffer(code)
+			.map_err(|_| Error::from(ErrorKind::Instrumentation))?;
+		let cacheable = is_cacheable(&module);
+		let module = pwasm_utils::inject_gas_counter(module, &rules::Set::new(1, Default::default()))
+			.map_err(|_| Error::from(ErrorKind::Instrumentation))?;
+		Ok((Module::from_parity_wasm_module(module)?, cacheable))
+	}
+
 	/// Call a given method in the given wasm-module runtime.
+	///
+	/// Executed instructions are only limited if the module was loaded with `prepare_module`.
 	pub fn call_in_wasm_module<E: Externalities>(
 		&self,
 		ext: &mut E,
@@ -526,6 +845,52 @@ impl WasmExecutor {
 		method: &str,
 		data: &[u8],
 	) -> Result<Vec<u8>> {
+		let instance = self.instantiate(ext, module)?;
+		self.call_in_instance(ext, &instance, method, data)
+	}
+
+	/// Call a given method in the runtime with given code, reusing an instance of the module
+	/// cached by an earlier call if there is one.
+	fn call_in_cached_instance<E: Externalities>(
+		&self,
+		ext: &mut E,
+		code: &[u8],
+		method: &str,
+		data: &[u8],
+	) -> Result<Vec<u8>> {
+		let code_hash = code_hash(code);
+		let (instance, cacheable) = match take_cached_instance(code_hash) {
+			Some(instance) => (instance, true),
+			None => {
+				let (module, cacheable) = Self::prepare(code)?;
+				(self.instantiate(ext, &module)?, cacheable)
+			},
+		};
+
+		let result = self.call_in_instance(ext, &instance, method, data);
+		if result.is_ok() && cacheable && instance.reset()? {
+			cache_instance(code_hash, instance);
+		}
+		result
+	}
+
+	/// Call a given method in the runtime with given code, recording the storage accesses if
+	/// host function calls are being collected.
+	fn call_traceable<E: Externalities>(
+		&self,
+		ext: &mut E,
+		code: &[u8],
+		method: &str,
+		data: &[u8],
+	) -> Result<Vec<u8>> {
+		if trace::is_collecting() {
+			self.call_in_cached_instance(&mut TracingExt::new(ext), code, method, data)
+		} else {
+			self.call_in_cached_instance(ext, code, method, data)
+		}
+	}
+
+	fn instantiate<E: Externalities>(&self, ext: &mut E, module: &Module) -> Result<Instance> {
 		// start module instantiation. Don't run 'start' function yet.
 		let intermediate_instance = ModuleInstance::new(
 			module,
@@ -549,11 +914,43 @@ impl WasmExecutor {
 			.export_by_name("__indirect_function_table")
 			.and_then(|e| e.as_table().cloned());
 
-		let mut try_heap_pages = self.try_heap_pages.lock();
-		let mut fec = FunctionExecutor::new(memory.clone(), try_heap_pages.0, table, ext)?;
+		let heap_base = memory.current_size();
+		let max_instructions = self.max_instructions(ext);
+		let module = {
+			let mut fec = FunctionExecutor::new(memory.clone(), heap_base, self.try_heap_pages.lock().0, max_instructions, table.clone(), ext)?;
 
-		// finish instantiation by running 'start' function (if any).
-		let instance = intermediate_instance.run_start(&mut fec)?;
+			// finish instantiation by running 'start' function (if any).
+			match intermediate_instance.run_start(&mut fec) {
+				Ok(module) => module,
+				Err(_) if fec.limit_exceeded() => return Err(ErrorKind::ExecutionLimitExceeded(max_instructions).into()),
+				Err(e) => return Err(e.into()),
+			}
+		};
+		let initial_memory = memory.get(0, Bytes::from(heap_base).0)
+			.map_err(|_| Error::from(ErrorKind::Runtime))?;
+		let memory_size = memory.current_size();
+
+		Ok(Instance {
+			module,
+			memory,
+			table,
+			heap_base,
+			memory_size,
+			initial_memory,
+		})
+	}
+
+	fn call_in_instance<E: Externalities>(
+		&self,
+		ext: &mut E,
+		instance: &Instance,
+		method: &str,
+		data: &[u8],
+	) -> Result<Vec<u8>> {
+		let memory = &instance.memory;
+		let max_instructions = self.max_instructions(ext);
+		let mut try_heap_pages = self.try_heap_pages.lock();
+		let mut fec = FunctionExecutor::new(memory.clone(), instance.heap_base, try_heap_pages.0, max_instructions, instance.table.clone(), ext)?;
 
 		let size = data.len() as u32;
 		let offset = fec.heap.allocate(size);
@@ -564,7 +961,7 @@ impl WasmExecutor {
 			return Err(ErrorKind::PleaseRetry.into())
 		}
 
-		let result = instance.invoke_export(
+		let result = instance.module.invoke_export(
 			method,
 			&[
 				I32(offset as i32),
@@ -575,6 +972,10 @@ impl WasmExecutor {
 
 		let returned = match result {
 			Ok(x) => x,
+			Err(_) if fec.limit_exceeded() => {
+				trace!(target: "wasm-executor", "Exceeded the limit of {} instructions", max_instructions);
+				return Err(ErrorKind::ExecutionLimitExceeded(max_instructions).into())
+			}
 			Err(_) if try_heap_pages.0 < self.max_heap_pages => {
 				let old = try_heap_pages.0;
 				*try_heap_pages = ((old * 2).min(self.max_heap_pages), DECAY_TIMEOUT);
@@ -596,14 +997,16 @@ impl WasmExecutor {
 			try_heap_pages.1 -= 1;
 		}
 
-		if let Some(I64(r)) = returned {
+		let output = if let Some(I64(r)) = returned {
 			let offset = r as u32;
 			let length = (r >> 32) as u32 as usize;
 			memory.get(offset, length)
-				.map_err(|_| ErrorKind::Runtime.into())
+				.map_err(|_| Error::from(ErrorKind::Runtime))?
 		} else {
-			Err(ErrorKind::InvalidReturn.into())
-		}
+			return Err(ErrorKind::InvalidReturn.into())
+		};
+
+		Ok(output)
 	}
 }
 
@@ -618,9 +1021,13 @@ impl CodeExecutor for WasmExecutor {
 		data: &[u8],
 		_use_native: bool
 	) -> (Result<Vec<u8>>, bool) {
-		(Module::from_buffer(code).map_err(Into::into).and_then(|module| 
-			self.call_in_wasm_module(ext, &module, method, data)
-		), false)
+		if !self.trace {
+			return (self.call_traceable(ext, code, method, data), false)
+		}
+
+		let (result, traces) = trace::collect(|| self.call_traceable(ext, code, method, data));
+		trace::log_traces(method, &traces);
+		(result, false)
 	}
 }
 
@@ -629,7 +1036,8 @@ mod tests {
 	use super::*;
 	use rustc_hex::FromHex;
 	use codec::Encode;
-	use state_machine::TestExternalities;
+	use state_machine::{Ext, OffchainExt, OverlayedChanges, TestExternalities};
+	use state_machine::backend::InMemory;
 
 	// TODO: move into own crate.
 	macro_rules! map {
@@ -647,6 +1055,50 @@ mod tests {
 		assert_eq!(output, vec![0u8; 0]);
 	}
 
+	#[test]
+	fn instances_are_cached_and_reset() {
+		let mut ext = TestExternalities::default();
+		let test_code = include_bytes!("../wasm/target/wasm32-unknown-unknown/release/runtime_test.compact.wasm");
+		let cached_instances = || INSTANCE_CACHE.with(|cache| cache.borrow().len());
+
+		let output = WasmExecutor::new(8, 8).call(&mut ext, &test_code[..], "test_blake2_256", b"Hello world!", true).0.unwrap();
+		assert_eq!(output, blake2_256(&b"Hello world!"[..]).encode());
+		assert_eq!(cached_instances(), 1);
+
+		let output = WasmExecutor::new(8, 8).call(&mut ext, &test_code[..], "test_blake2_256", &[], true).0.unwrap();
+		assert_eq!(output, blake2_256(&b""[..]).encode());
+		assert_eq!(cached_instances(), 1);
+
+		// an instance which has failed is discarded
+		assert!(WasmExecutor::new(8, 8).call(&mut ext, &test_code[..], "test_panic", &[], true).0.is_err());
+		assert_eq!(cached_instances(), 0);
+
+		let output = WasmExecutor::new(8, 8).call(&mut ext, &test_code[..], "test_blake2_256", b"Hello world!", true).0.unwrap();
+		assert_eq!(output, blake2_256(&b"Hello world!"[..]).encode());
+		assert_eq!(cached_instances(), 1);
+	}
+
+	#[test]
+	fn instruction_limit_should_work() {
+		let mut ext = TestExternalities::default();
+		let test_code = include_bytes!("../wasm/target/wasm32-unknown-unknown/release/runtime_test.compact.wasm");
+		let data = vec![0u8; 1024];
+
+		let output = WasmExecutor::new(8, 8).with_max_instructions(1000).call(&mut ext, &test_code[..], "test_blake2_256", &data, true).0;
+		assert_matches!(output, Err(Error(ErrorKind::ExecutionLimitExceeded(1000), _)));
+
+		let output = WasmExecutor::new(8, 8).call(&mut ext, &test_code[..], "test_blake2_256", &data, true).0.unwrap();
+		assert_eq!(output, blake2_256(&data).encode());
+
+		// the limit set by the chain applies to every executor
+		ext.insert(storage::MAX_INSTRUCTIONS.to_vec(), 1000u64.encode());
+		let output = WasmExecutor::new(8, 8).call(&mut ext, &test_code[..], "test_blake2_256", &data, true).0;
+		assert_matches!(output, Err(Error(ErrorKind::ExecutionLimitExceeded(1000), _)));
+
+		let output = WasmExecutor::new(8, 8).with_max_instructions(500).call(&mut ext, &test_code[..], "test_blake2_256", &data, true).0;
+		assert_matches!(output, Err(Error(ErrorKind::ExecutionLimitExceeded(500), _)));
+	}
+
 	#[test]
 	fn panicking_should_work() {
 		let mut ext = TestExternalities::default();
@@ -677,6 +1129,29 @@ mod tests {
 		assert_eq!(expected, ext);
 	}
 
+	#[test]
+	fn host_calls_should_be_traced() {
+		let mut ext = TestExternalities::default();
+		ext.set_storage(b"foo".to_vec(), b"bar".to_vec());
+		let test_code = include_bytes!("../wasm/target/wasm32-unknown-unknown/release/runtime_test.compact.wasm");
+
+		let (output, traces) = trace::collect(||
+			WasmExecutor::new(8, 8).call(&mut ext, &test_code[..], "test_data_in", b"Hello world", true).0
+		);
+		assert_eq!(output.unwrap(), b"all ok!".to_vec());
+
+		assert_eq!(traces.len(), 1);
+		assert_eq!(traces[0].extrinsic, None);
+		let calls: Vec<_> = traces[0].calls.iter()
+			.map(|call| (call.function.as_str(), call.key.as_ref().map(|key| key.0.as_slice()), call.size))
+			.collect();
+		assert_eq!(calls, vec![
+			("set_storage", Some(&b"input"[..]), 11),
+			("storage", Some(&b"foo"[..]), 3),
+			("set_storage", Some(&b"baz"[..]), 3),
+		]);
+	}
+
 	#[test]
 	fn clear_prefix_should_work() {
 		let mut ext = TestExternalities::default();
@@ -778,5 +1253,116 @@ mod tests {
 		);
 	}
 
+	#[derive(Default)]
+	struct TestOffchain {
+		extrinsics: Vec<Vec<u8>>,
+		local_storage: HashMap<Vec<u8>, Vec<u8>>,
+	}
+
+	impl OffchainExt for TestOffchain {
+		fn submit_extrinsic(&mut self, extrinsic: Vec<u8>) {
+			self.extrinsics.push(extrinsic);
+		}
+
+		fn local_storage(&self, key: &[u8]) -> Option<Vec<u8>> {
+			self.local_storage.get(key).cloned()
+		}
+
+		fn set_local_storage(&mut self, key: &[u8], value: Option<&[u8]>) {
+			match value {
+				Some(value) => self.local_storage.insert(key.to_vec(), value.to_vec()),
+				None => self.local_storage.remove(key),
+			};
+		}
+	}
+
+	#[test]
+	fn submit_extrinsic_should_work() {
+		let test_code = include_bytes!("../wasm/target/wasm32-unknown-unknown/release/runtime_test.compact.wasm");
+		let backend = InMemory::default();
+		let mut overlay = OverlayedChanges::default();
+		let mut offchain = TestOffchain::default();
+		{
+			let mut ext = Ext::new(&mut overlay, &backend).with_offchain(&mut offchain);
+			WasmExecutor::new(8, 8).call(&mut ext, &test_code[..], "test_submit_extrinsic", b"xt", true).0.unwrap();
+		}
+		assert_eq!(offchain.extrinsics, vec![b"xt".to_vec()]);
+
+		// only offchain workers can submit extrinsics
+		let mut ext = TestExternalities::default();
+		assert!(WasmExecutor::new(8, 8).call(&mut ext, &test_code[..], "test_submit_extrinsic", b"xt", true).0.is_err());
+	}
 
+	#[test]
+	fn local_storage_should_work() {
+		let test_code = include_bytes!("../wasm/target/wasm32-unknown-unknown/release/runtime_test.compact.wasm");
+		let backend = InMemory::default();
+		let mut overlay = OverlayedChanges::default();
+		let mut offchain = TestOffchain::default();
+		offchain.local_storage = map![
+			b"foo".to_vec() => b"old".to_vec(),
+			b"bar".to_vec() => b"cleared".to_vec()
+		];
+		{
+			let mut ext = Ext::new(&mut overlay, &backend).with_offchain(&mut offchain);
+			assert_eq!(
+				WasmExecutor::new(8, 8).call(&mut ext, &test_code[..], "test_local_storage", b"new", true).0.unwrap(),
+				b"old".to_vec()
+			);
+		}
+		let expected: HashMap<_, _> = map![
+			b"foo".to_vec() => b"new".to_vec()
+		];
+		assert_eq!(offchain.local_storage, expected);
+
+		// only offchain workers can use the node-local storage
+		let mut ext = TestExternalities::default();
+		assert!(WasmExecutor::new(8, 8).call(&mut ext, &test_code[..], "test_local_storage", b"new", true).0.is_err());
+	}
+
+	lazy_static! {
+		static ref LOGGED: Mutex<Vec<(LogLevel, String)>> = Mutex::new(Vec::new());
+	}
+
+	struct TestLogger;
+
+	impl ::log::Log for TestLogger {
+		fn enabled(&self, metadata: &::log::LogMetadata) -> bool {
+			metadata.target() == "runtime_test"
+		}
+
+		fn log(&self, record: &::log::LogRecord) {
+			if self.enabled(record.metadata()) {
+				LOGGED.lock().push((record.level(), format!("{}", record.args())));
+			}
+		}
+	}
+
+	#[test]
+	fn log_should_work() {
+		let _ = ::log::set_logger(|max| {
+			max.set(::log::LogLevelFilter::Trace);
+			Box::new(TestLogger)
+		});
+		let test_code = include_bytes!("../wasm/target/wasm32-unknown-unknown/release/runtime_test.compact.wasm");
+		let mut ext = TestExternalities::default();
+
+		for level in 1..6 {
+			WasmExecutor::new(8, 8).call(&mut ext, &test_code[..], "test_log", &[level], true).0.unwrap();
+		}
+		let message = || "message".to_string();
+		assert_eq!(*LOGGED.lock(), vec![
+			(LogLevel::Error, message()),
+			(LogLevel::Warn, message()),
+			(LogLevel::Info, message()),
+			(LogLevel::Debug, message()),
+			(LogLevel::Trace, message()),
+		]);
+
+		// invalid levels trap
+		for level in &[0, 6] {
+			assert!(WasmExecutor::new(8, 8).call(&mut ext, &test_code[..], "test_log", &[*level], true).0.is_err());
+		}
+		assert_eq!(LOGGED.lock().len(), 5);
+	}
 }
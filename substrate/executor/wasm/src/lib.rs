@@ -12,9 +12,14 @@ extern crate substrate_runtime_sandbox as sandbox;
 
 use runtime_io::{
 	set_storage, storage, clear_prefix, print, blake2_256,
-	twox_128, twox_256, ed25519_verify, enumerated_trie_root
+	twox_128, twox_256, ed25519_verify, enumerated_trie_root,
+	submit_extrinsic, local_storage, set_local_storage, clear_local_storage, log, LogLevel
 };
 
+extern "C" {
+	fn ext_log(level: u32, target_data: *const u8, target_len: u32, message_data: *const u8, message_len: u32);
+}
+
 impl_stubs!(
 	test_data_in NO_DECODE => |input| {
 		print("set_storage");
@@ -57,6 +62,35 @@ impl_stubs!(
 	test_enumerated_trie_root NO_DECODE => |_| {
 		enumerated_trie_root(&[&b"zero"[..], &b"one"[..], &b"two"[..]]).to_vec()
 	},
+	test_submit_extrinsic NO_DECODE => |input: &[u8]| {
+		submit_extrinsic(input);
+		Vec::new()
+	},
+	test_local_storage NO_DECODE => |input: &[u8]| {
+		let value = local_storage(b"foo").unwrap_or_else(Vec::new);
+		set_local_storage(b"foo", input);
+		clear_local_storage(b"bar");
+		value
+	},
+	test_log NO_DECODE => |input: &[u8]| {
+		let level = match input[0] {
+			1 => LogLevel::Error,
+			2 => LogLevel::Warn,
+			3 => LogLevel::Info,
+			4 => LogLevel::Debug,
+			5 => LogLevel::Trace,
+			level => {
+				// `log` only takes valid levels, so go around it to pass an invalid one.
+				let (target, message) = (b"runtime_test", b"message");
+				unsafe {
+					ext_log(level as u32, target.as_ptr(), target.len() as u32, message.as_ptr(), message.len() as u32);
+				}
+				return Vec::new();
+			}
+		};
+		log(level, "runtime_test", "message");
+		Vec::new()
+	},
 	test_sandbox NO_DECODE => |code: &[u8]| {
 		let ok = execute_sandboxed(code, &[]).is_ok();
 		[ok as u8].to_vec()
@@ -38,6 +38,19 @@ impl Error for txpool::Error {
 /// Modification notification event stream type;
 pub type EventStream = mpsc::UnboundedReceiver<()>;
 
+/// Extrinsic residing in the pool.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PendingExtrinsic<Hash> {
+	/// Hash of the extrinsic.
+	pub hash: Hash,
+	/// Encoded sender of the extrinsic. `None` if the sender is not known yet.
+	pub sender: Option<Vec<u8>>,
+	/// Index (nonce) of the extrinsic.
+	pub index: u64,
+	/// Whether the extrinsic can be included in the next block.
+	pub is_ready: bool,
+}
+
 /// Extrinsic pool.
 pub trait ExtrinsicPool<Ex, BlockId, Hash>: Send + Sync + 'static {
 	/// Error type
@@ -54,4 +67,13 @@ pub trait ExtrinsicPool<Ex, BlockId, Hash>: Send + Sync + 'static {
 
 	/// Return an event stream of transactions imported to the pool.
 	fn import_notification_stream(&self) -> EventStream;
+
+	/// Returns all extrinsics in the pool, both ready to be included on top of `block`
+	/// and those waiting in the future queue.
+	fn pending(&self, block: BlockId) -> Result<Vec<PendingExtrinsic<Hash>>, Self::Error>;
+
+	/// Remove extrinsics with given hashes from the pool.
+	///
+	/// Returns the hashes of extrinsics which were actually present in the pool.
+	fn remove(&self, hashes: &[Hash], is_valid: bool) -> Vec<Hash>;
 }
@@ -18,7 +18,8 @@
 
 use runtime_primitives::traits::{Block as BlockT, Header as HeaderT};
 use codec::{Encode, Decode, Input, Output};
-pub use self::generic::{BlockAnnounce, RemoteCallRequest, ConsensusVote, SignedConsensusVote, FromBlock};
+pub use self::generic::{BlockAnnounce, RemoteCallRequest, RemoteHeaderRequest, RemoteHeaderResponse,
+	ConsensusVote, SignedConsensusVote, FromBlock};
 
 /// A unique ID of a request.
 pub type RequestId = u64;
@@ -446,6 +447,10 @@ pub mod generic {
 		RemoteCallRequest(RemoteCallRequest<Hash>),
 		/// Remote method call response.
 		RemoteCallResponse(RemoteCallResponse),
+		/// Remote header request.
+		RemoteHeaderRequest(RemoteHeaderRequest<Number>),
+		/// Remote header response.
+		RemoteHeaderResponse(RemoteHeaderResponse<Header>),
 		/// Chain-specific message
 		ChainSpecific(Vec<u8>),
 	}
@@ -487,6 +492,14 @@ pub mod generic {
 					dest.push_byte(7);
 					dest.push(m);
 				}
+				Message::RemoteHeaderRequest(ref m) => {
+					dest.push_byte(8);
+					dest.push(m);
+				}
+				Message::RemoteHeaderResponse(ref m) => {
+					dest.push_byte(9);
+					dest.push(m);
+				}
 				Message::ChainSpecific(ref m) => {
 					dest.push_byte(255);
 					dest.push(m);
@@ -508,6 +521,8 @@ pub mod generic {
 				5 => Some(Message::BftMessage(Decode::decode(input)?)),
 				6 => Some(Message::RemoteCallRequest(Decode::decode(input)?)),
 				7 => Some(Message::RemoteCallResponse(Decode::decode(input)?)),
+				8 => Some(Message::RemoteHeaderRequest(Decode::decode(input)?)),
+				9 => Some(Message::RemoteHeaderResponse(Decode::decode(input)?)),
 				255 => Some(Message::ChainSpecific(Decode::decode(input)?)),
 				_ => None,
 			}
@@ -678,4 +693,58 @@ pub mod generic {
 			})
 		}
 	}
+
+	#[derive(Debug, PartialEq, Eq, Clone)]
+	/// Remote canonical header request.
+	pub struct RemoteHeaderRequest<N> {
+		/// Unique request id.
+		pub id: RequestId,
+		/// Number of the block to request the header of.
+		pub block: N,
+	}
+
+	impl<Number: Encode> Encode for RemoteHeaderRequest<Number> {
+		fn encode_to<T: Output>(&self, dest: &mut T) {
+			dest.push(&self.id);
+			dest.push(&self.block);
+		}
+	}
+
+	impl<Number: Decode> Decode for RemoteHeaderRequest<Number> {
+		fn decode<I: Input>(input: &mut I) -> Option<Self> {
+			Some(RemoteHeaderRequest {
+				id: Decode::decode(input)?,
+				block: Decode::decode(input)?,
+			})
+		}
+	}
+
+	#[derive(Debug, PartialEq, Eq, Clone)]
+	/// Remote canonical header response.
+	pub struct RemoteHeaderResponse<Header> {
+		/// Id of a request this response was made for.
+		pub id: RequestId,
+		/// Header. None if the header can not be proved (e.g. it is unknown).
+		pub header: Option<Header>,
+		/// Proof of the header against the root of the CHT covering it.
+		pub proof: Vec<Vec<u8>>,
+	}
+
+	impl<Header: Encode> Encode for RemoteHeaderResponse<Header> {
+		fn encode_to<T: Output>(&self, dest: &mut T) {
+			dest.push(&self.id);
+			dest.push(&self.header);
+			dest.push(&self.proof);
+		}
+	}
+
+	impl<Header: Decode> Decode for RemoteHeaderResponse<Header> {
+		fn decode<I: Input>(input: &mut I) -> Option<Self> {
+			Some(RemoteHeaderResponse {
+				id: Decode::decode(input)?,
+				header: Decode::decode(input)?,
+				proof: Decode::decode(input)?,
+			})
+		}
+	}
 }
@@ -25,7 +25,7 @@ use linked_hash_map::LinkedHashMap;
 use linked_hash_map::Entry;
 use parking_lot::Mutex;
 use client;
-use client::light::fetcher::{Fetcher, FetchChecker, RemoteCallRequest};
+use client::light::fetcher::{Fetcher, FetchChecker, RemoteCallRequest, RemoteHeaderRequest};
 use io::SyncIo;
 use message;
 use network_libp2p::PeerId;
@@ -48,6 +48,9 @@ pub trait OnDemandService<Block: BlockT>: Send + Sync {
 
 	/// When call response is received from remote node.
 	fn on_remote_call_response(&self, io: &mut SyncIo, peer: PeerId, response: message::RemoteCallResponse);
+
+	/// When header response is received from remote node.
+	fn on_remote_header_response(&self, io: &mut SyncIo, peer: PeerId, response: message::RemoteHeaderResponse<Block::Header>);
 }
 
 /// On-demand requests service. Dispatches requests to appropriate peers.
@@ -56,9 +59,9 @@ pub struct OnDemand<B: BlockT, E: service::ExecuteInContext<B>> {
 	checker: Arc<FetchChecker<B>>,
 }
 
-/// On-demand remote call response.
-pub struct RemoteCallResponse {
-	receiver: Receiver<Result<client::CallResult, client::error::Error>>,
+/// On-demand remote response.
+pub struct RemoteResponse<T> {
+	receiver: Receiver<Result<T, client::error::Error>>,
 }
 
 #[derive(Default)]
@@ -78,15 +81,17 @@ struct Request<Block: BlockT> {
 
 enum RequestData<Block: BlockT> {
 	RemoteCall(RemoteCallRequest<Block::Hash>, Sender<Result<client::CallResult, client::error::Error>>),
+	RemoteHeader(RemoteHeaderRequest<Block::Header>, Sender<Result<Block::Header, client::error::Error>>),
 }
 
 enum Accept<Block: BlockT> {
 	Ok,
 	CheckFailed(client::error::Error, RequestData<Block>),
+	Unexpected(RequestData<Block>),
 }
 
-impl Future for RemoteCallResponse {
-	type Item = client::CallResult;
+impl<T> Future for RemoteResponse<T> {
+	type Item = T;
 	type Error = client::error::Error;
 
 	fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
@@ -149,6 +154,13 @@ impl<B: BlockT, E> OnDemand<B, E> where
 			Accept::CheckFailed(error, retry_request_data) => {
 				trace!(target: "sync", "Failed to check remote {} response from peer {}: {}", rtype, peer, error);
 
+				io.disconnect_peer(peer);
+				core.remove_peer(peer);
+				Some(retry_request_data)
+			},
+			Accept::Unexpected(retry_request_data) => {
+				trace!(target: "sync", "Unexpected response to remote {} from peer {}", rtype, peer);
+
 				io.disconnect_peer(peer);
 				core.remove_peer(peer);
 				Some(retry_request_data)
@@ -203,6 +215,21 @@ impl<B, E> OnDemandService<B> for OnDemand<B, E> where
 				},
 				Err(error) => Accept::CheckFailed(error, RequestData::RemoteCall(request, sender)),
 			},
+			data => Accept::Unexpected(data),
+		})
+	}
+
+	fn on_remote_header_response(&self, io: &mut SyncIo, peer: PeerId, response: message::RemoteHeaderResponse<B::Header>) {
+		self.accept_response("header", io, peer, response.id, |request| match request.data {
+			RequestData::RemoteHeader(request, sender) => match self.checker.check_header_proof(&request, response.header, response.proof) {
+				Ok(response) => {
+					// we do not bother if receiver has been dropped already
+					let _ = sender.send(Ok(response));
+					Accept::Ok
+				},
+				Err(error) => Accept::CheckFailed(error, RequestData::RemoteHeader(request, sender)),
+			},
+			data => Accept::Unexpected(data),
 		})
 	}
 }
@@ -212,12 +239,19 @@ impl<B, E> Fetcher<B> for OnDemand<B, E> where
 	E: service::ExecuteInContext<B>,
 	B::Header: HeaderT,
 {
-	type RemoteCallResult = RemoteCallResponse;
+	type RemoteCallResult = RemoteResponse<client::CallResult>;
+	type RemoteHeaderResult = RemoteResponse<B::Header>;
 
 	fn remote_call(&self, request: RemoteCallRequest<B::Hash>) -> Self::RemoteCallResult {
 		let (sender, receiver) = channel();
 		self.schedule_request(RequestData::RemoteCall(request, sender),
-			RemoteCallResponse { receiver })
+			RemoteResponse { receiver })
+	}
+
+	fn remote_header(&self, request: RemoteHeaderRequest<B::Header>) -> Self::RemoteHeaderResult {
+		let (sender, receiver) = channel();
+		self.schedule_request(RequestData::RemoteHeader(request, sender),
+			RemoteResponse { receiver })
 	}
 }
 
@@ -311,6 +345,10 @@ impl<Block: BlockT> Request<Block> {
 				method: data.method.clone(),
 				data: data.call_data.clone(),
 			}),
+			RequestData::RemoteHeader(ref data, _) => message::generic::Message::RemoteHeaderRequest(message::RemoteHeaderRequest {
+				id: self.id,
+				block: data.block,
+			}),
 		}
 	}
 }
@@ -323,13 +361,13 @@ pub mod tests {
 	use futures::Future;
 	use parking_lot::RwLock;
 	use client;
-	use client::light::fetcher::{Fetcher, FetchChecker, RemoteCallRequest};
+	use client::light::fetcher::{Fetcher, FetchChecker, RemoteCallRequest, RemoteHeaderRequest, RemoteReadRequest};
 	use message;
 	use network_libp2p::PeerId;
 	use service::{Roles, ExecuteInContext};
 	use test::TestIo;
 	use super::{REQUEST_TIMEOUT, OnDemand, OnDemandService};
-	use test_client::runtime::{Block, Hash};
+	use test_client::runtime::{Block, Hash, Header};
 
 	pub struct DummyExecutor;
 	struct DummyFetchChecker { ok: bool }
@@ -348,6 +386,20 @@ pub mod tests {
 				false => Err(client::error::ErrorKind::Backend("Test error".into()).into()),
 			}
 		}
+
+		fn check_read_proof(&self, _request: &RemoteReadRequest<Hash>, _remote_proof: Vec<Vec<u8>>) -> client::error::Result<Option<Vec<u8>>> {
+			match self.ok {
+				true => Ok(Some(vec![42])),
+				false => Err(client::error::ErrorKind::Backend("Test error".into()).into()),
+			}
+		}
+
+		fn check_header_proof(&self, _request: &RemoteHeaderRequest<Header>, header: Option<Header>, _remote_proof: Vec<Vec<u8>>) -> client::error::Result<Header> {
+			match self.ok {
+				true => Ok(header.unwrap()),
+				false => Err(client::error::ErrorKind::Backend("Test error".into()).into()),
+			}
+		}
 	}
 
 	fn dummy(ok: bool) -> (Arc<DummyExecutor>, Arc<OnDemand<Block, DummyExecutor>>) {
@@ -369,6 +421,16 @@ pub mod tests {
 		});
 	}
 
+	fn dummy_header(number: u64) -> Header {
+		Header {
+			parent_hash: Default::default(),
+			number,
+			state_root: Default::default(),
+			extrinsics_root: Default::default(),
+			digest: Default::default(),
+		}
+	}
+
 	#[test]
 	fn knows_about_peers_roles() {
 		let (_, on_demand) = dummy(true);
@@ -462,4 +524,38 @@ pub mod tests {
 		receive_call_response(&*on_demand, &mut network, 0, 0);
 		thread.join().unwrap();
 	}
+
+	#[test]
+	fn receives_remote_header_response() {
+		let (_x, on_demand) = dummy(true);
+		let queue = RwLock::new(VecDeque::new());
+		let mut network = TestIo::new(&queue, None);
+		on_demand.on_connect(0, Roles::FULL);
+
+		let response = on_demand.remote_header(RemoteHeaderRequest { cht_root: Default::default(), block: 1 });
+		let thread = ::std::thread::spawn(move || {
+			let result = response.wait().unwrap();
+			assert_eq!(result.number, 1);
+		});
+
+		on_demand.on_remote_header_response(&mut network, 0, message::RemoteHeaderResponse {
+			id: 0,
+			header: Some(dummy_header(1)),
+			proof: vec![],
+		});
+		thread.join().unwrap();
+	}
+
+	#[test]
+	fn disconnects_from_peer_on_response_of_wrong_type() {
+		let (_x, on_demand) = dummy(true);
+		let queue = RwLock::new(VecDeque::new());
+		let mut network = TestIo::new(&queue, None);
+		on_demand.on_connect(0, Roles::FULL);
+
+		on_demand.remote_header(RemoteHeaderRequest { cht_root: Default::default(), block: 1 });
+		receive_call_response(&*on_demand, &mut network, 0, 0);
+		assert!(network.to_disconnect.contains(&0));
+		assert_eq!(on_demand.core.lock().pending_requests.len(), 1);
+	}
 }
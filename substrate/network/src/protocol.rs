@@ -19,7 +19,7 @@ use std::{mem, cmp};
 use std::sync::Arc;
 use std::time;
 use parking_lot::RwLock;
-use runtime_primitives::traits::{Block as BlockT, Header as HeaderT, Hash, HashFor, As};
+use runtime_primitives::traits::{Block as BlockT, Header as HeaderT, Hash, HashFor, NumberFor, As};
 use runtime_primitives::generic::BlockId;
 use network_libp2p::PeerId;
 use codec::{Encode, Decode};
@@ -279,6 +279,8 @@ impl<B: BlockT, S: Specialization<B>> Protocol<B, S> {
 			GenericMessage::Transactions(m) => self.on_extrinsics(io, peer_id, m),
 			GenericMessage::RemoteCallRequest(request) => self.on_remote_call_request(io, peer_id, request),
 			GenericMessage::RemoteCallResponse(response) => self.on_remote_call_response(io, peer_id, response),
+			GenericMessage::RemoteHeaderRequest(request) => self.on_remote_header_request(io, peer_id, request),
+			GenericMessage::RemoteHeaderResponse(response) => self.on_remote_header_response(io, peer_id, response),
 			other => self.specialization.write().on_message(&mut ProtocolContext::new(&self.context_data, io), peer_id, other),
 		}
 	}
@@ -319,6 +321,18 @@ impl<B: BlockT, S: Specialization<B>> Protocol<B, S> {
 	fn on_block_request(&self, io: &mut SyncIo, peer: PeerId, request: message::BlockRequest<B>) {
 		trace!(target: "sync", "BlockRequest {} from {}: from {:?} to {:?} max {:?}", request.id, peer, request.from, request.to, request.max);
 		let mut blocks = Vec::new();
+
+		// blocks are not served by light clients: they only have headers, and ancient headers
+		// would be fetched from the network, blocking this thread
+		if self.config.roles & Roles::LIGHT == Roles::LIGHT {
+			trace!(target: "sync", "Ignoring BlockRequest {} from {}: light node", request.id, peer);
+			self.send_message(io, peer, GenericMessage::BlockResponse(message::generic::BlockResponse {
+				id: request.id,
+				blocks,
+			}));
+			return;
+		}
+
 		let mut id = match request.from {
 			message::FromBlock::Hash(h) => BlockId::Hash(h),
 			message::FromBlock::Number(n) => BlockId::Number(n),
@@ -604,6 +618,27 @@ impl<B: BlockT, S: Specialization<B>> Protocol<B, S> {
 		self.on_demand.as_ref().map(|s| s.on_remote_call_response(io, peer_id, response));
 	}
 
+	fn on_remote_header_request(&self, io: &mut SyncIo, peer_id: PeerId, request: message::RemoteHeaderRequest<NumberFor<B>>) {
+		trace!(target: "sync", "Remote header proof request {} from {} ({})", request.id, peer_id, request.block);
+		let (header, proof) = match self.context_data.chain.header_proof(request.block) {
+			Ok((header, proof)) => (Some(header), proof),
+			Err(error) => {
+				trace!(target: "sync", "Remote header proof request {} from {} ({}) failed with: {}",
+					request.id, peer_id, request.block, error);
+				(None, Default::default())
+			},
+		};
+
+		self.send_message(io, peer_id, GenericMessage::RemoteHeaderResponse(message::RemoteHeaderResponse {
+			id: request.id, header, proof,
+		}));
+	}
+
+	fn on_remote_header_response(&self, io: &mut SyncIo, peer_id: PeerId, response: message::RemoteHeaderResponse<B::Header>) {
+		trace!(target: "sync", "Remote header proof response {} from {}", response.id, peer_id);
+		self.on_demand.as_ref().map(|s| s.on_remote_header_response(io, peer_id, response));
+	}
+
 	/// Execute a closure with access to a network context and specialization.
 	pub fn with_spec<F, U>(&self, io: &mut SyncIo, f: F) -> U
 		where F: FnOnce(&mut S, &mut Context<B>) -> U
@@ -121,6 +121,11 @@ impl<B: BlockT> ChainSync<B> {
 	/// Handle new connected peer.
 	pub(crate) fn new_peer(&mut self, protocol: &mut Context<B>, peer_id: PeerId) {
 		if let Some(info) = protocol.peer_info(peer_id) {
+			if info.roles & Roles::LIGHT == Roles::LIGHT {
+				debug!(target:"sync", "New light peer {}: blocks are not downloaded from light nodes.", peer_id);
+				return;
+			}
+
 			match (block_status(&*protocol.client(), &*self.import_queue, info.best_hash), info.best_number) {
 				(Err(e), _) => {
 					debug!(target:"sync", "Error reading blockchain: {:?}", e);
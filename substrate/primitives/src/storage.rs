@@ -21,11 +21,44 @@ use bytes;
 use rstd::vec::Vec;
 
 /// Contract storage key.
-#[derive(PartialEq, Eq)]
+#[derive(PartialEq, Eq, Clone)]
 #[cfg_attr(feature = "std", derive(Serialize, Deserialize, Debug, Hash, PartialOrd, Ord))]
 pub struct StorageKey(#[cfg_attr(feature = "std", serde(with="bytes"))] pub Vec<u8>);
 
 /// Contract storage entry data.
-#[derive(PartialEq, Eq)]
+#[derive(PartialEq, Eq, Clone)]
 #[cfg_attr(feature = "std", derive(Serialize, Deserialize, Debug, Hash, PartialOrd, Ord))]
 pub struct StorageData(#[cfg_attr(feature = "std", serde(with="bytes"))] pub Vec<u8>);
+
+/// Storage change set
+#[derive(PartialEq, Eq, Clone)]
+#[cfg_attr(feature = "std", derive(Serialize, Deserialize, Debug))]
+#[cfg_attr(feature = "std", serde(rename_all = "camelCase"))]
+pub struct StorageChangeSet<Hash> {
+	/// Block hash
+	pub block: Hash,
+	/// A list of changes
+	pub changes: Vec<(StorageKey, Option<StorageData>)>,
+}
+
+/// Prefix of the top-level storage keys under which the roots of child tries are stored.
+///
+/// Top-level entries with this prefix may only be changed through the child storage API.
+pub const CHILD_STORAGE_KEY_PREFIX: &'static [u8] = b":child_storage:";
+
+/// Whether the given top-level storage key is the key of a child trie.
+pub fn is_child_storage_key(key: &[u8]) -> bool {
+	key.starts_with(CHILD_STORAGE_KEY_PREFIX)
+}
+
+/// Storage key holding the index of the extrinsic that is currently being executed, encoded as
+/// a little-endian `u32`.
+pub const EXTRINSIC_INDEX: &'static [u8] = b":extrinsic_index";
+
+/// Storage key enabling changes tries: if it has a value in the state of a block's parent, the
+/// changes trie of the block is built and its root committed to in the block digest.
+pub const CHANGES_TRIE_ENABLED: &'static [u8] = b":changes_trie";
+
+/// Storage key of the max number of instructions a single call of the Wasm runtime may execute,
+/// encoded as a little-endian `u64`. Executors fall back to their default if it is not set.
+pub const MAX_INSTRUCTIONS: &'static [u8] = b":max_instructions";
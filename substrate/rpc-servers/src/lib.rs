@@ -30,7 +30,7 @@ extern crate substrate_runtime_primitives;
 extern crate log;
 
 use std::io;
-use substrate_runtime_primitives::traits::Block as BlockT;
+use substrate_runtime_primitives::traits::{Block as BlockT, NumberFor};
 
 type Metadata = apis::metadata::Metadata;
 type RpcHandler = pubsub::PubSubHandler<Metadata>;
@@ -43,10 +43,10 @@ pub fn rpc_handler<Block: BlockT, S, C, A, Y>(
 	system: Y,
 ) -> RpcHandler where
 	Block: 'static,
-	S: apis::state::StateApi<Block::Hash>,
-	C: apis::chain::ChainApi<Block::Hash, Block::Header, Metadata=Metadata>,
+	S: apis::state::StateApi<Block::Hash, Metadata=Metadata>,
+	C: apis::chain::ChainApi<Block::Hash, Block::Header, NumberFor<Block>, Block::Extrinsic, Metadata=Metadata>,
 	A: apis::author::AuthorApi<Block::Hash, Block::Extrinsic, Metadata=Metadata>,
-	Y: apis::system::SystemApi,
+	Y: apis::system::SystemApi<Block::Hash, NumberFor<Block>>,
 {
 	let mut io = pubsub::PubSubHandler::default();
 	io.extend_with(state.to_delegate());
@@ -51,6 +51,15 @@ build_rpc_trait! {
 		#[rpc(name = "author_submitExtrinsic")]
 		fn submit_extrinsic(&self, Bytes) -> Result<Hash>;
 
+		/// Returns all extrinsics in the pool, split into those ready for inclusion in the
+		/// next block and those waiting in the future queue.
+		#[rpc(name = "author_pendingExtrinsics")]
+		fn pending_extrinsics(&self) -> Result<PendingExtrinsics<Hash>>;
+
+		/// Remove given extrinsics from the pool. Returns hashes of the extrinsics that were removed.
+		#[rpc(name = "author_removeExtrinsic")]
+		fn remove_extrinsic(&self, Vec<Hash>) -> Result<Vec<Hash>>;
+
 		#[pubsub(name = "author_extrinsicUpdate")] {
 			/// Submit an extrinsic to watch.
 			#[rpc(name = "author_submitAndWatchExtrinsic")]
@@ -64,6 +73,28 @@ build_rpc_trait! {
 	}
 }
 
+/// Extrinsic residing in the pool.
+#[derive(Debug, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PendingExtrinsic<Hash> {
+	/// Hash of the extrinsic.
+	pub hash: Hash,
+	/// Encoded sender of the extrinsic, if known.
+	pub sender: Option<Bytes>,
+	/// Index (nonce) of the extrinsic.
+	pub index: u64,
+}
+
+/// Extrinsics in the pool.
+#[derive(Debug, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PendingExtrinsics<Hash> {
+	/// Extrinsics ready to be included in the next block.
+	pub ready: Vec<PendingExtrinsic<Hash>>,
+	/// Extrinsics waiting for their dependencies (e.g. lower indices) to be satisfied.
+	pub future: Vec<PendingExtrinsic<Hash>>,
+}
+
 /// Authoring API
 pub struct Author<B, E, Block: traits::Block, P> {
 	/// Substrate client
@@ -112,6 +143,36 @@ impl<B, E, Block, P, Ex, Hash> AuthorApi<Hash, Ex> for Author<B, E, Block, P> wh
 			)
 	}
 
+	fn pending_extrinsics(&self) -> Result<PendingExtrinsics<Hash>> {
+		let best_block_hash = self.client.info()?.chain.best_hash;
+		let pending = self.pool
+			.pending(generic::BlockId::hash(best_block_hash))
+			.map_err(|e| e.into_pool_error()
+				.map(Into::into)
+				.unwrap_or_else(|e| error::ErrorKind::Verification(Box::new(e)).into())
+			)?;
+
+		let mut extrinsics = PendingExtrinsics { ready: Vec::new(), future: Vec::new() };
+		for xt in pending {
+			let is_ready = xt.is_ready;
+			let xt = PendingExtrinsic {
+				hash: xt.hash,
+				sender: xt.sender.map(Into::into),
+				index: xt.index,
+			};
+			if is_ready {
+				extrinsics.ready.push(xt);
+			} else {
+				extrinsics.future.push(xt);
+			}
+		}
+		Ok(extrinsics)
+	}
+
+	fn remove_extrinsic(&self, hashes: Vec<Hash>) -> Result<Vec<Hash>> {
+		Ok(self.pool.remove(&hashes, false))
+	}
+
 	fn watch_extrinsic(&self, _metadata: Self::Metadata, subscriber: pubsub::Subscriber<Status<Hash>>, xt: Bytes) {
 
 		let submit = || -> Result<_> {
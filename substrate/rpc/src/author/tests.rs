@@ -79,6 +79,22 @@ impl<BlockHash> api::ExtrinsicPool<Extrinsic, BlockHash, u64> for DummyTxPool {
 	fn import_notification_stream(&self) -> api::EventStream {
 		unreachable!()
 	}
+
+	fn pending(&self, _block: BlockHash) -> Result<Vec<api::PendingExtrinsic<Hash>>, Self::Error> {
+		Ok(self.submitted.lock().iter().map(|xt| api::PendingExtrinsic {
+			hash: *xt,
+			sender: Some(vec![1]),
+			index: *xt,
+			is_ready: *xt < 10,
+		}).collect())
+	}
+
+	fn remove(&self, hashes: &[Hash], _is_valid: bool) -> Vec<Hash> {
+		let mut submitted = self.submitted.lock();
+		let removed = submitted.iter().cloned().filter(|xt| hashes.contains(xt)).collect();
+		submitted.retain(|xt| !hashes.contains(xt));
+		removed
+	}
 }
 
 #[test]
@@ -143,3 +159,38 @@ fn should_watch_extrinsic() {
 		Some(r#"{"jsonrpc":"2.0","method":"test","params":{"result":{"usurped":5},"subscription":0}}"#.into())
 	);
 }
+
+#[test]
+fn should_return_pending_extrinsics() {
+	let runtime = runtime::Runtime::new().unwrap();
+	let pool = Arc::new(DummyTxPool::default());
+	pool.submitted.lock().extend(vec![5, 15]);
+	let p = Author {
+		client: Arc::new(test_client::new()),
+		pool: pool.clone(),
+		subscriptions: Subscriptions::new(runtime.executor()),
+	};
+
+	assert_eq!(
+		AuthorApi::pending_extrinsics(&p).unwrap(),
+		PendingExtrinsics {
+			ready: vec![PendingExtrinsic { hash: 5, sender: Some(vec![1].into()), index: 5 }],
+			future: vec![PendingExtrinsic { hash: 15, sender: Some(vec![1].into()), index: 15 }],
+		}
+	);
+}
+
+#[test]
+fn should_remove_extrinsics() {
+	let runtime = runtime::Runtime::new().unwrap();
+	let pool = Arc::new(DummyTxPool::default());
+	pool.submitted.lock().extend(vec![5, 15]);
+	let p = Author {
+		client: Arc::new(test_client::new()),
+		pool: pool.clone(),
+		subscriptions: Subscriptions::new(runtime.executor()),
+	};
+
+	assert_eq!(AuthorApi::remove_extrinsic(&p, vec![15, 20]).unwrap(), vec![15]);
+	assert_eq!(*pool.submitted.lock(), vec![5]);
+}
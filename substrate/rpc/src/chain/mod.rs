@@ -18,11 +18,12 @@
 
 use std::sync::Arc;
 
-use runtime_primitives::traits::Block as BlockT;
-use runtime_primitives::generic::BlockId;
+use runtime_primitives::bft::Justification;
+use runtime_primitives::traits::{Block as BlockT, Header as HeaderT};
+use runtime_primitives::generic::{BlockId, SignedBlock};
 use client::{self, Client, BlockchainEvents};
 
-use jsonrpc_macros::pubsub;
+use jsonrpc_macros::{pubsub, Trailing};
 use jsonrpc_pubsub::SubscriptionId;
 use rpc::Result as RpcResult;
 use rpc::futures::{Future, Sink, Stream};
@@ -38,7 +39,7 @@ use self::error::{Result, ResultExt};
 
 build_rpc_trait! {
 	/// Polkadot blockchain API
-	pub trait ChainApi<Hash, Header> {
+	pub trait ChainApi<Hash, Header, Number, Extrinsic> {
 		type Metadata;
 
 		/// Get header of a relay chain block.
@@ -49,6 +50,28 @@ build_rpc_trait! {
 		#[rpc(name = "chain_getHead")]
 		fn head(&self) -> Result<Hash>;
 
+		/// Get hash of the last finalized block.
+		#[rpc(name = "chain_getFinalizedHead")]
+		fn finalized_head(&self) -> Result<Hash>;
+
+		/// Get full block with its justification.
+		#[rpc(name = "chain_getBlock")]
+		fn block(&self, Hash) -> Result<Option<SignedBlock<Header, Extrinsic, Hash>>>;
+
+		/// Get hash of the n-th block in the canon chain.
+		///
+		/// By default returns latest block hash.
+		#[rpc(name = "chain_getBlockHash")]
+		fn block_hash(&self, Trailing<Number>) -> Result<Option<Hash>>;
+
+		/// Get justification of a block.
+		#[rpc(name = "chain_getJustification")]
+		fn justification(&self, Hash) -> Result<Option<Justification<Hash>>>;
+
+		/// Get import status of a block.
+		#[rpc(name = "chain_getBlockStatus")]
+		fn block_status(&self, Hash) -> Result<BlockStatus>;
+
 		#[pubsub(name = "chain_newHead")] {
 			/// New head subscription
 			#[rpc(name = "subscribe_newHead")]
@@ -58,6 +81,41 @@ build_rpc_trait! {
 			#[rpc(name = "unsubscribe_newHead")]
 			fn unsubscribe_new_head(&self, SubscriptionId) -> RpcResult<bool>;
 		}
+
+		#[pubsub(name = "chain_finalizedHead")] {
+			/// Finalized head subscription
+			#[rpc(name = "chain_subscribeFinalizedHeads")]
+			fn subscribe_finalized_heads(&self, Self::Metadata, pubsub::Subscriber<Header>);
+
+			/// Unsubscribe from finalized head subscription.
+			#[rpc(name = "chain_unsubscribeFinalizedHeads")]
+			fn unsubscribe_finalized_heads(&self, SubscriptionId) -> RpcResult<bool>;
+		}
+	}
+}
+
+/// Block status as reported over RPC.
+#[derive(Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum BlockStatus {
+	/// Added to the import queue.
+	Queued,
+	/// Already in the blockchain.
+	InChain,
+	/// Block or parent is known to be bad.
+	KnownBad,
+	/// Not in the queue or the blockchain.
+	Unknown,
+}
+
+impl From<client::BlockStatus> for BlockStatus {
+	fn from(status: client::BlockStatus) -> Self {
+		match status {
+			client::BlockStatus::Queued => BlockStatus::Queued,
+			client::BlockStatus::InChain => BlockStatus::InChain,
+			client::BlockStatus::KnownBad => BlockStatus::KnownBad,
+			client::BlockStatus::Unknown => BlockStatus::Unknown,
+		}
 	}
 }
 
@@ -79,7 +137,7 @@ impl<B, E, Block: BlockT> Chain<B, E, Block> {
 	}
 }
 
-impl<B, E, Block> ChainApi<Block::Hash, Block::Header> for Chain<B, E, Block> where
+impl<B, E, Block> ChainApi<Block::Hash, Block::Header, <Block::Header as HeaderT>::Number, Block::Extrinsic> for Chain<B, E, Block> where
 	Block: BlockT + 'static,
 	B: client::backend::Backend<Block> + Send + Sync + 'static,
 	E: client::CallExecutor<Block> + Send + Sync + 'static,
@@ -94,6 +152,32 @@ impl<B, E, Block> ChainApi<Block::Hash, Block::Header> for Chain<B, E, Block> wh
 		Ok(self.client.info().chain_err(|| "Blockchain error")?.chain.best_hash)
 	}
 
+	fn finalized_head(&self) -> Result<Block::Hash> {
+		Ok(self.client.info().chain_err(|| "Blockchain error")?.chain.finalized_hash)
+	}
+
+	fn block(&self, hash: Block::Hash) -> Result<Option<SignedBlock<Block::Header, Block::Extrinsic, Block::Hash>>> {
+		self.client.block(&BlockId::Hash(hash)).chain_err(|| "Blockchain error")
+	}
+
+	fn block_hash(&self, number: Trailing<<Block::Header as HeaderT>::Number>) -> Result<Option<Block::Hash>> {
+		match number.into() {
+			None => self.head().map(Some),
+			// through the header, which light clients fetch from the network once pruned
+			Some(number) => self.client.header(&BlockId::Number(number))
+				.map(|header| header.map(|header| header.hash()))
+				.chain_err(|| "Blockchain error"),
+		}
+	}
+
+	fn justification(&self, hash: Block::Hash) -> Result<Option<Justification<Block::Hash>>> {
+		self.client.justification(&BlockId::Hash(hash)).chain_err(|| "Blockchain error")
+	}
+
+	fn block_status(&self, hash: Block::Hash) -> Result<BlockStatus> {
+		self.client.block_status(&BlockId::Hash(hash)).map(Into::into).chain_err(|| "Blockchain error")
+	}
+
 	fn subscribe_new_head(&self, _metadata: Self::Metadata, subscriber: pubsub::Subscriber<Block::Header>) {
 		self.subscriptions.add(subscriber, |sink| {
 			let stream = self.client.import_notification_stream()
@@ -111,4 +195,21 @@ impl<B, E, Block> ChainApi<Block::Hash, Block::Header> for Chain<B, E, Block> wh
 	fn unsubscribe_new_head(&self, id: SubscriptionId) -> RpcResult<bool> {
 		Ok(self.subscriptions.cancel(id))
 	}
+
+	fn subscribe_finalized_heads(&self, _metadata: Self::Metadata, subscriber: pubsub::Subscriber<Block::Header>) {
+		self.subscriptions.add(subscriber, |sink| {
+			let stream = self.client.finality_notification_stream()
+				.map(|notification| Ok(notification.header))
+				.map_err(|e| warn!("Finality notification stream error: {:?}", e));
+			sink
+				.sink_map_err(|e| warn!("Error sending notifications: {:?}", e))
+				.send_all(stream)
+				// we ignore the resulting Stream (if the first stream is over we are unsubscribed)
+				.map(|_| ())
+		});
+	}
+
+	fn unsubscribe_finalized_heads(&self, id: SubscriptionId) -> RpcResult<bool> {
+		Ok(self.subscriptions.cancel(id))
+	}
 }
@@ -73,3 +73,120 @@ fn should_notify_about_latest_block() {
 	// no more notifications on this channel
 	assert_eq!(core.block_on(next.into_future()).unwrap().0, None);
 }
+
+#[test]
+fn should_return_finalized_head() {
+	let core = ::tokio::runtime::Runtime::new().unwrap();
+	let remote = core.executor();
+
+	let client = Chain {
+		client: Arc::new(test_client::new()),
+		subscriptions: Subscriptions::new(remote),
+	};
+
+	let genesis_hash = client.client.info().unwrap().chain.genesis_hash;
+	assert_eq!(client.finalized_head().unwrap(), genesis_hash);
+
+	let builder = client.client.new_block().unwrap();
+	client.client.justify_and_import(BlockOrigin::Own, builder.bake().unwrap()).unwrap();
+	assert_eq!(client.finalized_head().unwrap(), genesis_hash);
+
+	let hash = client.client.block_hash(1).unwrap().unwrap();
+	client.client.finalize_block(BlockId::Hash(hash)).unwrap();
+	assert_eq!(client.finalized_head().unwrap(), hash);
+}
+
+#[test]
+fn should_notify_about_finalized_block() {
+	let mut core = ::tokio::runtime::Runtime::new().unwrap();
+	let remote = core.executor();
+	let (subscriber, id, transport) = pubsub::Subscriber::new_test("test");
+
+	{
+		let api = Chain {
+			client: Arc::new(test_client::new()),
+			subscriptions: Subscriptions::new(remote),
+		};
+
+		api.subscribe_finalized_heads(Default::default(), subscriber);
+
+		// assert id assigned
+		assert_eq!(core.block_on(id), Ok(Ok(SubscriptionId::Number(0))));
+
+		let builder = api.client.new_block().unwrap();
+		api.client.justify_and_import(BlockOrigin::Own, builder.bake().unwrap()).unwrap();
+		api.client.finalize_block(BlockId::Number(1)).unwrap();
+	}
+
+	// assert notification send to transport
+	let (notification, next) = core.block_on(transport.into_future()).unwrap();
+	assert!(notification.is_some());
+	// no more notifications on this channel
+	assert_eq!(core.block_on(next.into_future()).unwrap().0, None);
+}
+
+#[test]
+fn should_return_a_block() {
+	let core = ::tokio::runtime::Runtime::new().unwrap();
+	let remote = core.executor();
+
+	let api = Chain {
+		client: Arc::new(test_client::new()),
+		subscriptions: Subscriptions::new(remote),
+	};
+
+	let block = api.client.new_block().unwrap().bake().unwrap();
+	let block_hash = block.hash();
+	api.client.justify_and_import(BlockOrigin::Own, block).unwrap();
+
+	assert_matches!(
+		api.block(block_hash),
+		Ok(Some(ref x)) if x.block.header.number == 1 && x.block.extrinsics.is_empty()
+	);
+	assert_matches!(
+		api.justification(block_hash),
+		Ok(Some(_))
+	);
+	assert_matches!(
+		api.block(5.into()),
+		Ok(None)
+	);
+}
+
+#[test]
+fn should_return_block_hash() {
+	let core = ::tokio::runtime::Runtime::new().unwrap();
+	let remote = core.executor();
+
+	let api = Chain {
+		client: Arc::new(test_client::new()),
+		subscriptions: Subscriptions::new(remote),
+	};
+
+	assert_matches!(
+		api.block_hash(None.into()),
+		Ok(Some(ref x)) if x == &api.client.genesis_hash()
+	);
+	assert_matches!(
+		api.block_hash(Some(0u64).into()),
+		Ok(Some(ref x)) if x == &api.client.genesis_hash()
+	);
+	assert_matches!(
+		api.block_hash(Some(1u64).into()),
+		Ok(None)
+	);
+}
+
+#[test]
+fn should_return_block_status() {
+	let core = ::tokio::runtime::Runtime::new().unwrap();
+	let remote = core.executor();
+
+	let api = Chain {
+		client: Arc::new(test_client::new()),
+		subscriptions: Subscriptions::new(remote),
+	};
+
+	assert_eq!(api.block_status(api.client.genesis_hash()).unwrap(), BlockStatus::InChain);
+	assert_eq!(api.block_status(5.into()).unwrap(), BlockStatus::Unknown);
+}
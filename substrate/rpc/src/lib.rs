@@ -21,9 +21,12 @@
 extern crate jsonrpc_core as rpc;
 extern crate jsonrpc_pubsub;
 extern crate parking_lot;
+extern crate serde;
 extern crate substrate_codec as codec;
 extern crate substrate_client as client;
+extern crate substrate_executor as executor;
 extern crate substrate_extrinsic_pool as extrinsic_pool;
+extern crate substrate_network as network;
 extern crate substrate_primitives as primitives;
 extern crate substrate_runtime_primitives as runtime_primitives;
 extern crate substrate_state_machine as state_machine;
@@ -35,6 +38,8 @@ extern crate error_chain;
 extern crate jsonrpc_macros;
 #[macro_use]
 extern crate log;
+#[macro_use]
+extern crate serde_derive;
 
 #[cfg(test)]
 #[macro_use]
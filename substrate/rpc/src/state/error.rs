@@ -30,6 +30,18 @@ error_chain! {
 			description("not implemented yet"),
 			display("Method Not Implemented"),
 		}
+
+		/// Requested page size exceeds the maximum.
+		InvalidCount(value: u32, max: u32) {
+			description("invalid count"),
+			display("Invalid count requested: {} (max: {})", value, max),
+		}
+
+		/// Block tracing is not enabled on this node.
+		TracingDisabled {
+			description("tracing disabled"),
+			display("Block tracing is disabled; start the node with --trace-runtime to enable it"),
+		}
 	}
 }
 
@@ -37,6 +49,7 @@ impl From<Error> for rpc::Error {
 	fn from(e: Error) -> Self {
 		match e {
 			Error(ErrorKind::Unimplemented, _) => errors::unimplemented(),
+			e @ Error(ErrorKind::InvalidCount(..), _) => rpc::Error::invalid_params(e.to_string()),
 			e => errors::internal(e),
 		}
 	}
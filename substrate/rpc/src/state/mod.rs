@@ -21,19 +21,35 @@ mod error;
 #[cfg(test)]
 mod tests;
 
+use std::collections::HashMap;
 use std::sync::Arc;
-use client::{self, Client, CallExecutor};
 
+use client::{self, Client, CallExecutor, BlockchainEvents};
+use executor::trace::ExtrinsicTrace;
+use jsonrpc_macros::{pubsub, Trailing};
+use jsonrpc_pubsub::SubscriptionId;
+use primitives::Bytes;
+use primitives::hexdisplay::HexDisplay;
+use primitives::storage::{StorageKey, StorageData, StorageChangeSet};
+use rpc::Result as RpcResult;
+use rpc::futures::{stream, Future, Sink, Stream};
 use runtime_primitives::generic::BlockId;
 use runtime_primitives::traits::Block as BlockT;
-use primitives::storage::{StorageKey, StorageData};
-use primitives::hexdisplay::HexDisplay;
+use state_machine::Backend as StateBackend;
+use tokio::runtime::TaskExecutor;
+
+use subscriptions::Subscriptions;
+
+use self::error::{Result, ErrorKind};
 
-use self::error::Result;
+/// Maximal number of storage entries returned by a single keys or pairs query.
+const MAX_STORAGE_PAGE_SIZE: u32 = 1000;
 
 build_rpc_trait! {
 	/// Polkadot state API
 	pub trait StateApi<Hash> {
+		type Metadata;
+
 		/// Returns a storage entry at a specific block's state.
 		#[rpc(name = "state_getStorageAt")]
 		fn storage_at(&self, StorageKey, Hash) -> Result<StorageData>;
@@ -65,22 +81,175 @@ build_rpc_trait! {
 		/// Call a contract at the best block.
 		#[rpc(name = "state_call")]
 		fn call(&self, String, Vec<u8>) -> Result<Vec<u8>>;
+
+		/// Returns up to `count` storage keys with given prefix, starting after `start_key`.
+		///
+		/// Queries the best block if no block hash is given.
+		#[rpc(name = "state_getKeys")]
+		fn storage_keys(&self, StorageKey, u32, Option<StorageKey>, Trailing<Hash>) -> Result<Vec<StorageKey>>;
+
+		/// Returns up to `count` storage entries with given key prefix, starting after `start_key`.
+		///
+		/// Queries the best block if no block hash is given.
+		#[rpc(name = "state_getStoragePairs")]
+		fn storage_pairs(&self, StorageKey, u32, Option<StorageKey>, Trailing<Hash>) -> Result<Vec<(StorageKey, StorageData)>>;
+
+		/// Returns proof of storage entries at a specific block's state.
+		///
+		/// Uses the best block if no block hash is given.
+		#[rpc(name = "state_getReadProof")]
+		fn read_proof(&self, Vec<StorageKey>, Trailing<Hash>) -> Result<ReadProof<Hash>>;
+
+		/// Call a contract at a block's state and return the result together with execution proof.
+		///
+		/// Uses the best block if no block hash is given.
+		#[rpc(name = "state_getExecutionProof")]
+		fn execution_proof(&self, String, Vec<u8>, Trailing<Hash>) -> Result<ExecutionProof<Hash>>;
+
+		/// Execute a block again on top of the state of its parent and return the host function
+		/// calls made by the runtime, grouped by extrinsic. Only available on nodes started with
+		/// `--trace-runtime`.
+		#[rpc(name = "state_traceBlock")]
+		fn trace_block(&self, Hash) -> Result<Vec<ExtrinsicTrace>>;
+
+		/// Returns the codec-encoded metadata of the runtime at a block's state.
+		///
+		/// Uses the best block if no block hash is given.
+		#[rpc(name = "state_getMetadata")]
+		fn metadata(&self, Trailing<Hash>) -> Result<Bytes>;
+
+		/// Query historical storage entries (by key) starting from a block given as the second
+		/// parameter. Returns only the blocks in which any of the keys has changed, along with the
+		/// changed keys and their new values.
+		///
+		/// Ends at the best block if no end block hash is given.
+		#[rpc(name = "state_queryStorage")]
+		fn query_storage(&self, Vec<StorageKey>, Hash, Trailing<Hash>) -> Result<Vec<StorageChangeSet<Hash>>>;
+
+		#[pubsub(name = "state_storage")] {
+			/// New storage subscription
+			#[rpc(name = "state_subscribeStorage")]
+			fn subscribe_storage(&self, Self::Metadata, pubsub::Subscriber<StorageChangeSet<Hash>>, Vec<StorageKey>);
+
+			/// Unsubscribe from storage subscription
+			#[rpc(name = "state_unsubscribeStorage")]
+			fn unsubscribe_storage(&self, SubscriptionId) -> RpcResult<bool>;
+		}
+	}
+}
+
+/// Proof of storage entries at given block.
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ReadProof<Hash> {
+	/// Block hash used to generate the proof.
+	pub at: Hash,
+	/// Trie nodes proving the entries are included in the block's storage trie.
+	pub proof: Vec<Bytes>,
+}
+
+/// Result of a runtime call together with its execution proof.
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ExecutionProof<Hash> {
+	/// Block hash used to generate the proof.
+	pub at: Hash,
+	/// Data returned by the call.
+	pub result: Bytes,
+	/// Trie nodes touched during execution of the call.
+	pub proof: Vec<Bytes>,
+}
+
+/// State API with subscriptions support.
+pub struct State<B, E, Block: BlockT> {
+	/// Substrate client.
+	client: Arc<Client<B, E, Block>>,
+	/// Current subscriptions.
+	subscriptions: Subscriptions,
+	/// Whether blocks may be traced.
+	tracing: bool,
+}
+
+impl<B, E, Block: BlockT> State<B, E, Block> {
+	/// Create new State API RPC handler.
+	pub fn new(client: Arc<Client<B, E, Block>>, executor: TaskExecutor) -> Self {
+		State {
+			client,
+			subscriptions: Subscriptions::new(executor),
+			tracing: false,
+		}
+	}
+
+	/// Enable or disable `state_traceBlock`, which re-executes whole blocks. Disabled by default.
+	pub fn with_tracing(mut self, tracing: bool) -> Self {
+		self.tracing = tracing;
+		self
+	}
+}
+
+impl<B, E, Block> State<B, E, Block> where
+	Block: BlockT,
+	B: client::backend::Backend<Block>,
+	E: CallExecutor<Block>,
+{
+	fn unwrap_or_best(&self, hash: Trailing<Block::Hash>) -> Result<Block::Hash> {
+		Ok(match hash.into() {
+			None => self.client.info()?.chain.best_hash,
+			Some(hash) => hash,
+		})
 	}
 }
 
-impl<B, E, Block> StateApi<Block::Hash> for Arc<Client<B, E, Block>> where
+fn check_page_size(count: u32) -> Result<usize> {
+	if count > MAX_STORAGE_PAGE_SIZE {
+		return Err(ErrorKind::InvalidCount(count, MAX_STORAGE_PAGE_SIZE).into());
+	}
+	Ok(count as usize)
+}
+
+/// Read values of given `keys` at `block` and return those that differ from the `last` seen values.
+///
+/// `last` is updated with the new values. Keys that have never been seen before are always reported.
+fn storage_changes<B, E, Block>(
+	client: &Client<B, E, Block>,
+	block: Block::Hash,
+	keys: &[StorageKey],
+	last: &mut HashMap<StorageKey, Option<StorageData>>,
+) -> Result<StorageChangeSet<Block::Hash>> where
+	Block: BlockT,
+	B: client::backend::Backend<Block>,
+	E: CallExecutor<Block>,
+{
+	let state = client.state_at(&BlockId::Hash(block))?;
+	let mut changes = Vec::new();
+	for key in keys {
+		let value = state.storage(&key.0)
+			.map_err(|e| client::error::Error::from_state(Box::new(e)))?
+			.map(StorageData);
+		if last.get(key) != Some(&value) {
+			last.insert(key.clone(), value.clone());
+			changes.push((key.clone(), value));
+		}
+	}
+
+	Ok(StorageChangeSet { block, changes })
+}
+
+impl<B, E, Block> StateApi<Block::Hash> for State<B, E, Block> where
 	Block: BlockT + 'static,
 	B: client::backend::Backend<Block> + Send + Sync + 'static,
 	E: CallExecutor<Block> + Send + Sync + 'static,
 {
+	type Metadata = ::metadata::Metadata;
+
 	fn storage_at(&self, key: StorageKey, block: Block::Hash) -> Result<StorageData> {
 		trace!(target: "rpc", "Querying storage at {:?} for key {}", block, HexDisplay::from(&key.0));
-		Ok(self.as_ref().storage(&BlockId::Hash(block), &key)?)
+		Ok(self.client.storage(&BlockId::Hash(block), &key)?)
 	}
 
 	fn call_at(&self, method: String, data: Vec<u8>, block: Block::Hash) -> Result<Vec<u8>> {
 		trace!(target: "rpc", "Calling runtime at {:?} for method {} ({})", block, method, HexDisplay::from(&data));
-		Ok(self.as_ref().executor().call(&BlockId::Hash(block), &method, &data)?.return_data)
+		Ok(self.client.executor().call(&BlockId::Hash(block), &method, &data)?.return_data)
 	}
 
 	fn storage_hash_at(&self, key: StorageKey, block: Block::Hash) -> Result<Block::Hash> {
@@ -93,18 +262,134 @@ impl<B, E, Block> StateApi<Block::Hash> for Arc<Client<B, E, Block>> where
 	}
 
 	fn storage_hash(&self, key: StorageKey) -> Result<Block::Hash> {
-		self.storage_hash_at(key, self.as_ref().info()?.chain.best_hash)
+		self.storage_hash_at(key, self.client.info()?.chain.best_hash)
 	}
 
 	fn storage_size(&self, key: StorageKey) -> Result<u64> {
-		self.storage_size_at(key, self.as_ref().info()?.chain.best_hash)
+		self.storage_size_at(key, self.client.info()?.chain.best_hash)
 	}
 
 	fn storage(&self, key: StorageKey) -> Result<StorageData> {
-		self.storage_at(key, self.as_ref().info()?.chain.best_hash)
+		self.storage_at(key, self.client.info()?.chain.best_hash)
 	}
 
 	fn call(&self, method: String, data: Vec<u8>) -> Result<Vec<u8>> {
-		self.call_at(method, data, self.as_ref().info()?.chain.best_hash)
+		self.call_at(method, data, self.client.info()?.chain.best_hash)
+	}
+
+	fn storage_keys(&self, prefix: StorageKey, count: u32, start_key: Option<StorageKey>, block: Trailing<Block::Hash>) -> Result<Vec<StorageKey>> {
+		let count = check_page_size(count)?;
+		let block = self.unwrap_or_best(block)?;
+		trace!(target: "rpc", "Querying storage keys at {:?} with prefix {}", block, HexDisplay::from(&prefix.0));
+		Ok(self.client.storage_keys(&BlockId::Hash(block), &prefix, start_key.as_ref(), count)?)
+	}
+
+	fn storage_pairs(&self, prefix: StorageKey, count: u32, start_key: Option<StorageKey>, block: Trailing<Block::Hash>) -> Result<Vec<(StorageKey, StorageData)>> {
+		let count = check_page_size(count)?;
+		let block = self.unwrap_or_best(block)?;
+		trace!(target: "rpc", "Querying storage pairs at {:?} with prefix {}", block, HexDisplay::from(&prefix.0));
+		Ok(self.client.storage_pairs(&BlockId::Hash(block), &prefix, start_key.as_ref(), count)?)
+	}
+
+	fn read_proof(&self, keys: Vec<StorageKey>, block: Trailing<Block::Hash>) -> Result<ReadProof<Block::Hash>> {
+		let block = self.unwrap_or_best(block)?;
+		trace!(target: "rpc", "Generating read proof at {:?} for {} keys", block, keys.len());
+		let keys = keys.into_iter().map(|key| key.0).collect::<Vec<_>>();
+		let proof = self.client.read_proof(&BlockId::Hash(block), &keys)?;
+		Ok(ReadProof {
+			at: block,
+			proof: proof.into_iter().map(Bytes).collect(),
+		})
+	}
+
+	fn metadata(&self, block: Trailing<Block::Hash>) -> Result<Bytes> {
+		let block = self.unwrap_or_best(block)?;
+		trace!(target: "rpc", "Querying runtime metadata at {:?}", block);
+		Ok(Bytes(self.client.executor().call(&BlockId::Hash(block), "metadata", &[])?.return_data))
+	}
+
+	fn query_storage(&self, keys: Vec<StorageKey>, from: Block::Hash, to: Trailing<Block::Hash>) -> Result<Vec<StorageChangeSet<Block::Hash>>> {
+		let to = self.unwrap_or_best(to)?;
+		trace!(target: "rpc", "Querying storage changes of {} keys from {:?} to {:?}", keys.len(), from, to);
+		let mut result = Vec::new();
+		for (block, changed_keys) in self.client.key_changes(from, to, &keys)? {
+			let state = self.client.state_at(&BlockId::Hash(block))?;
+			let mut changes = Vec::new();
+			for key in changed_keys {
+				let value = state.storage(&key.0)
+					.map_err(|e| client::error::Error::from_state(Box::new(e)))?
+					.map(StorageData);
+				changes.push((key, value));
+			}
+			result.push(StorageChangeSet { block, changes });
+		}
+		Ok(result)
+	}
+
+	fn execution_proof(&self, method: String, data: Vec<u8>, block: Trailing<Block::Hash>) -> Result<ExecutionProof<Block::Hash>> {
+		let block = self.unwrap_or_best(block)?;
+		trace!(target: "rpc", "Generating execution proof at {:?} for method {} ({})", block, method, HexDisplay::from(&data));
+		let (result, proof) = self.client.execution_proof(&BlockId::Hash(block), &method, &data)?;
+		Ok(ExecutionProof {
+			at: block,
+			result: Bytes(result),
+			proof: proof.into_iter().map(Bytes).collect(),
+		})
+	}
+
+	fn trace_block(&self, block: Block::Hash) -> Result<Vec<ExtrinsicTrace>> {
+		if !self.tracing {
+			return Err(ErrorKind::TracingDisabled.into());
+		}
+		trace!(target: "rpc", "Tracing execution of block {:?}", block);
+		Ok(self.client.trace_block(&BlockId::Hash(block))?)
+	}
+
+	fn subscribe_storage(
+		&self,
+		_metadata: Self::Metadata,
+		subscriber: pubsub::Subscriber<StorageChangeSet<Block::Hash>>,
+		keys: Vec<StorageKey>,
+	) {
+		let mut last = HashMap::new();
+		let initial = match self.client.info()
+			.map_err(error::Error::from)
+			.and_then(|info| storage_changes(&self.client, info.chain.best_hash, &keys, &mut last))
+		{
+			Ok(initial) => initial,
+			Err(err) => {
+				// reject the subscriber (ignore errors - we don't care if subscriber is no longer there).
+				let _ = subscriber.reject(err.into());
+				return;
+			},
+		};
+
+		// the stream must not keep the client alive, since the client holds the notification sink.
+		let client = Arc::downgrade(&self.client);
+		self.subscriptions.add(subscriber, |sink| {
+			let stream = self.client.import_notification_stream()
+				.filter(|notification| notification.is_new_best)
+				.filter_map(move |notification| {
+					let client = client.upgrade()?;
+					match storage_changes(&client, notification.hash, &keys, &mut last) {
+						Ok(ref changes) if changes.changes.is_empty() => None,
+						Ok(changes) => Some(Ok(changes)),
+						Err(e) => {
+							warn!("Error reading storage changes at {:?}: {:?}", notification.hash, e);
+							None
+						},
+					}
+				})
+				.map_err(|e| warn!("Block notification stream error: {:?}", e));
+			sink
+				.sink_map_err(|e| warn!("Error sending notifications: {:?}", e))
+				.send_all(stream::iter_ok::<_, ()>(vec![Ok(initial)]).chain(stream))
+				// we ignore the resulting Stream (if the first stream is over we are unsubscribed)
+				.map(|_| ())
+		});
+	}
+
+	fn unsubscribe_storage(&self, id: SubscriptionId) -> RpcResult<bool> {
+		Ok(self.subscriptions.cancel(id))
 	}
 }
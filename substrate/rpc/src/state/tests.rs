@@ -16,26 +16,156 @@
 
 use super::*;
 use self::error::{Error, ErrorKind};
+use jsonrpc_macros::pubsub;
+use client::BlockOrigin;
 use test_client::{self, TestClient};
 
 #[test]
 fn should_return_storage() {
+	let core = ::tokio::runtime::Runtime::new().unwrap();
 	let client = Arc::new(test_client::new());
 	let genesis_hash = client.genesis_hash();
+	let client = State::new(client, core.executor());
 
 	assert_matches!(
-		StateApi::storage_at(&client, StorageKey(vec![10]), genesis_hash),
+		client.storage_at(StorageKey(vec![10]), genesis_hash),
 		Err(Error(ErrorKind::Client(client::error::ErrorKind::NoValueForKey(ref k)), _)) if *k == vec![10]
 	)
 }
 
 #[test]
 fn should_call_contract() {
+	let core = ::tokio::runtime::Runtime::new().unwrap();
 	let client = Arc::new(test_client::new());
 	let genesis_hash = client.genesis_hash();
+	let client = State::new(client, core.executor());
 
 	assert_matches!(
-		StateApi::call_at(&client, "balanceOf".into(), vec![1,2,3], genesis_hash),
+		client.call_at("balanceOf".into(), vec![1,2,3], genesis_hash),
 		Err(Error(ErrorKind::Client(client::error::ErrorKind::Execution(_)), _))
 	)
 }
+
+#[test]
+fn should_return_storage_keys_and_pairs() {
+	let core = ::tokio::runtime::Runtime::new().unwrap();
+	let client = Arc::new(test_client::new());
+	let genesis_hash = client.genesis_hash();
+	let client = State::new(client, core.executor());
+
+	let code_key = StorageKey(b":code".to_vec());
+	assert_eq!(
+		client.storage_keys(StorageKey(b":co".to_vec()), 10, None, Some(genesis_hash).into()).unwrap(),
+		vec![code_key.clone()]
+	);
+	assert_matches!(
+		client.storage_pairs(StorageKey(b":co".to_vec()), 10, None, None.into()),
+		Ok(ref pairs) if pairs.len() == 1 && pairs[0].0 == code_key && !(pairs[0].1).0.is_empty()
+	);
+	assert_eq!(
+		client.storage_keys(StorageKey(b":co".to_vec()), 10, Some(code_key), None.into()).unwrap(),
+		vec![]
+	);
+	assert_matches!(
+		client.storage_keys(StorageKey(vec![]), MAX_STORAGE_PAGE_SIZE + 1, None, None.into()),
+		Err(Error(ErrorKind::InvalidCount(_, _), _))
+	);
+}
+
+#[test]
+fn should_return_read_proof() {
+	let core = ::tokio::runtime::Runtime::new().unwrap();
+	let client = Arc::new(test_client::new());
+	let genesis_hash = client.genesis_hash();
+	let client = State::new(client, core.executor());
+
+	assert_matches!(
+		client.read_proof(vec![StorageKey(b":code".to_vec())], Some(genesis_hash).into()),
+		Ok(ref proof) if proof.at == genesis_hash && !proof.proof.is_empty()
+	);
+}
+
+#[test]
+fn should_return_execution_proof() {
+	let core = ::tokio::runtime::Runtime::new().unwrap();
+	let client = Arc::new(test_client::new());
+	let genesis_hash = client.genesis_hash();
+	let client = State::new(client, core.executor());
+
+	assert_matches!(
+		client.execution_proof("authorities".into(), vec![], None.into()),
+		Ok(ref proof) if proof.at == genesis_hash && !proof.result.is_empty() && !proof.proof.is_empty()
+	);
+}
+
+#[test]
+fn should_trace_block() {
+	let core = ::tokio::runtime::Runtime::new().unwrap();
+	let client = Arc::new(test_client::new());
+	let builder = client.new_block().unwrap();
+	client.justify_and_import(BlockOrigin::Own, builder.bake().unwrap()).unwrap();
+	let best_hash = client.info().unwrap().chain.best_hash;
+	assert_matches!(
+		State::new(client.clone(), core.executor()).trace_block(best_hash),
+		Err(Error(ErrorKind::TracingDisabled, _))
+	);
+	let client = State::new(client, core.executor()).with_tracing(true);
+
+	assert_matches!(
+		client.trace_block(best_hash),
+		Ok(ref traces) if traces.iter().any(|trace| !trace.calls.is_empty())
+	);
+	assert_matches!(
+		client.trace_block(5.into()),
+		Err(Error(ErrorKind::Client(client::error::ErrorKind::UnknownBlock(_)), _))
+	);
+}
+
+#[test]
+fn should_notify_about_storage_changes() {
+	let mut core = ::tokio::runtime::Runtime::new().unwrap();
+	let remote = core.executor();
+	let (subscriber, id, transport) = pubsub::Subscriber::new_test("test");
+
+	{
+		let api = State::new(Arc::new(test_client::new()), remote);
+
+		api.subscribe_storage(Default::default(), subscriber, vec![StorageKey(b":code".to_vec())]);
+
+		// assert id assigned
+		assert_eq!(core.block_on(id), Ok(Ok(SubscriptionId::Number(0))));
+
+		let builder = api.client.new_block().unwrap();
+		api.client.justify_and_import(BlockOrigin::Own, builder.bake().unwrap()).unwrap();
+	}
+
+	// assert initial values sent to transport
+	let (notification, next) = core.block_on(transport.into_future()).unwrap();
+	assert!(notification.is_some());
+	// `:code` has not changed, so no more notifications on this channel
+	assert_eq!(core.block_on(next.into_future()).unwrap().0, None);
+}
+
+#[test]
+fn should_query_storage() {
+	let core = ::tokio::runtime::Runtime::new().unwrap();
+	let client = Arc::new(test_client::new());
+	let genesis_hash = client.genesis_hash();
+	for _ in 0..2 {
+		let builder = client.new_block().unwrap();
+		client.justify_and_import(BlockOrigin::Own, builder.bake().unwrap()).unwrap();
+	}
+	let block1_hash = client.block_hash(1).unwrap().unwrap();
+	let api = State::new(client, core.executor());
+
+	let keys = vec![StorageKey(b":code".to_vec()), StorageKey(vec![10])];
+	// the test runtime does not enable changes tries
+	assert_matches!(
+		api.query_storage(keys.clone(), genesis_hash, None.into()),
+		Err(Error(ErrorKind::Client(client::error::ErrorKind::ChangesTrieUnavailable(_)), _))
+	);
+	assert_matches!(
+		api.query_storage(keys, block1_hash, Some(genesis_hash).into()),
+		Err(Error(ErrorKind::Client(client::error::ErrorKind::InvalidBlockRange(_)), _))
+	);
+}
@@ -21,11 +21,16 @@ pub mod error;
 #[cfg(test)]
 mod tests;
 
+use std::sync::Arc;
+
+use network;
+use runtime_primitives::traits::{Block as BlockT, NumberFor};
+
 use self::error::Result;
 
 build_rpc_trait! {
 	/// Substrate system RPC API
-	pub trait SystemApi {
+	pub trait SystemApi<Hash, Number> {
 		/// Get the node's implementation name. Plain old string.
 		#[rpc(name = "system_name")]
 		fn system_name(&self) -> Result<String>;
@@ -37,5 +42,161 @@ build_rpc_trait! {
 		/// Get the chain's type. Given as a string identifier.
 		#[rpc(name = "system_chain")]
 		fn system_chain(&self) -> Result<String>;
+
+		/// Return health status of the node.
+		#[rpc(name = "system_health")]
+		fn system_health(&self) -> Result<Health>;
+
+		/// Returns currently connected peers.
+		#[rpc(name = "system_peers")]
+		fn system_peers(&self) -> Result<Vec<PeerInfo<Hash, Number>>>;
+
+		/// Returns the state of the network: this node's id and sync status.
+		#[rpc(name = "system_networkState")]
+		fn system_network_state(&self) -> Result<NetworkState<Number>>;
+	}
+}
+
+/// Static information about the running node.
+#[derive(Debug, Clone)]
+pub struct SystemInfo {
+	/// Implementation name.
+	pub impl_name: String,
+	/// Implementation version.
+	pub impl_version: String,
+	/// Chain name.
+	pub chain_name: String,
+}
+
+/// Health status of the node.
+#[derive(Debug, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Health {
+	/// Number of connected peers.
+	pub peers: usize,
+	/// Is the node syncing.
+	pub is_syncing: bool,
+	/// Should this node have any peers. False for nodes running without boot nodes.
+	pub should_have_peers: bool,
+}
+
+/// Information about a connected peer.
+#[derive(Debug, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PeerInfo<Hash, Number> {
+	/// Peer id.
+	pub peer_id: String,
+	/// Roles of the peer.
+	pub roles: String,
+	/// Protocol version.
+	pub protocol_version: u32,
+	/// Peer best block hash.
+	pub best_hash: Hash,
+	/// Peer best block number.
+	pub best_number: Number,
+}
+
+/// Sync state of the node.
+#[derive(Debug, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum SyncState {
+	/// Initial sync is complete, keep-up sync is active.
+	Idle,
+	/// Actively catching up with the chain.
+	Downloading,
+}
+
+impl From<network::SyncState> for SyncState {
+	fn from(state: network::SyncState) -> Self {
+		match state {
+			network::SyncState::Idle => SyncState::Idle,
+			network::SyncState::Downloading => SyncState::Downloading,
+		}
+	}
+}
+
+/// State of the node's network.
+#[derive(Debug, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct NetworkState<Number> {
+	/// This node's id, if known.
+	pub node_id: Option<String>,
+	/// Sync state.
+	pub sync_state: SyncState,
+	/// Best block number seen among the peers.
+	pub best_seen_block: Option<Number>,
+	/// Number of connected peers.
+	pub num_peers: usize,
+	/// Number of peers we are downloading blocks from.
+	pub num_active_peers: usize,
+}
+
+/// System API implementation.
+pub struct System<B: BlockT> {
+	/// Static node information.
+	info: SystemInfo,
+	/// Network sync status provider.
+	sync: Arc<network::SyncProvider<B>>,
+	/// Whether the node is expected to be connected to any peers.
+	should_have_peers: bool,
+}
+
+impl<B: BlockT> System<B> {
+	/// Create new System API RPC handler.
+	pub fn new(info: SystemInfo, sync: Arc<network::SyncProvider<B>>, should_have_peers: bool) -> Self {
+		System {
+			info,
+			sync,
+			should_have_peers,
+		}
+	}
+}
+
+impl<B: BlockT> SystemApi<B::Hash, NumberFor<B>> for System<B> {
+	fn system_name(&self) -> Result<String> {
+		Ok(self.info.impl_name.clone())
+	}
+
+	fn system_version(&self) -> Result<String> {
+		Ok(self.info.impl_version.clone())
+	}
+
+	fn system_chain(&self) -> Result<String> {
+		Ok(self.info.chain_name.clone())
+	}
+
+	fn system_health(&self) -> Result<Health> {
+		let status = self.sync.status();
+		Ok(Health {
+			peers: status.num_peers,
+			is_syncing: status.sync.state == network::SyncState::Downloading,
+			should_have_peers: self.should_have_peers,
+		})
+	}
+
+	fn system_peers(&self) -> Result<Vec<PeerInfo<B::Hash, NumberFor<B>>>> {
+		Ok(self.sync.peers().into_iter()
+			.filter_map(|peer| {
+				let id = peer.id;
+				peer.dot_info.map(|info| PeerInfo {
+					peer_id: id.unwrap_or_default(),
+					roles: format!("{:?}", info.roles),
+					protocol_version: info.protocol_version,
+					best_hash: info.best_hash,
+					best_number: info.best_number,
+				})
+			})
+			.collect())
+	}
+
+	fn system_network_state(&self) -> Result<NetworkState<NumberFor<B>>> {
+		let status = self.sync.status();
+		Ok(NetworkState {
+			node_id: self.sync.node_id(),
+			sync_state: status.sync.state.into(),
+			best_seen_block: status.sync.best_seen_block,
+			num_peers: status.num_peers,
+			num_active_peers: status.num_active_peers,
+		})
 	}
 }
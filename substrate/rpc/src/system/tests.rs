@@ -15,24 +15,66 @@
 // along with Substrate.  If not, see <http://www.gnu.org/licenses/>.
 
 use super::*;
-use super::error::*;
 
-impl SystemApi for () {
-	fn system_name(&self) -> Result<String> {
-		Ok("testclient".into())
+use network::{self, ProtocolStatus, SyncStatus, SyncState as NetworkSyncState, NetworkPeerInfo, Roles};
+use test_client::runtime::Block;
+use primitives::H256;
+
+#[derive(Default)]
+struct Status {
+	peers: usize,
+	is_syncing: bool,
+}
+
+impl network::SyncProvider<Block> for Status {
+	fn status(&self) -> ProtocolStatus<Block> {
+		ProtocolStatus {
+			sync: SyncStatus {
+				state: if self.is_syncing { NetworkSyncState::Downloading } else { NetworkSyncState::Idle },
+				best_seen_block: if self.is_syncing { Some(5) } else { None },
+			},
+			num_peers: self.peers,
+			num_active_peers: 0,
+		}
 	}
-	fn system_version(&self) -> Result<String> {
-		Ok("0.2.0".into())
+
+	fn peers(&self) -> Vec<NetworkPeerInfo<Block>> {
+		(0..self.peers).map(|i| NetworkPeerInfo {
+			id: Some(format!("peer{}", i)),
+			client_version: "test".into(),
+			capabilities: vec![],
+			remote_address: "".into(),
+			local_address: "".into(),
+			dot_info: Some(network::PeerInfo {
+				roles: Roles::FULL,
+				protocol_version: 1,
+				best_hash: H256::from(5),
+				best_number: 1,
+			}),
+		}).collect()
 	}
-	fn system_chain(&self) -> Result<String> {
-		Ok("testchain".into())
+
+	fn node_id(&self) -> Option<String> {
+		Some("test".into())
 	}
 }
 
+fn api(sync: Status, should_have_peers: bool) -> System<Block> {
+	System::new(
+		SystemInfo {
+			impl_name: "testclient".into(),
+			impl_version: "0.2.0".into(),
+			chain_name: "testchain".into(),
+		},
+		Arc::new(sync),
+		should_have_peers,
+	)
+}
+
 #[test]
 fn system_name_works() {
 	assert_eq!(
-		SystemApi::system_name(&()).unwrap(),
+		api(Status::default(), false).system_name().unwrap(),
 		"testclient".to_owned()
 	);
 }
@@ -40,7 +82,7 @@ fn system_name_works() {
 #[test]
 fn system_version_works() {
 	assert_eq!(
-		SystemApi::system_version(&()).unwrap(),
+		api(Status::default(), false).system_version().unwrap(),
 		"0.2.0".to_owned()
 	);
 }
@@ -48,7 +90,56 @@ fn system_version_works() {
 #[test]
 fn system_chain_works() {
 	assert_eq!(
-		SystemApi::system_chain(&()).unwrap(),
+		api(Status::default(), false).system_chain().unwrap(),
 		"testchain".to_owned()
 	);
 }
+
+#[test]
+fn system_health() {
+	assert_eq!(
+		api(Status::default(), true).system_health().unwrap(),
+		Health {
+			peers: 0,
+			is_syncing: false,
+			should_have_peers: true,
+		}
+	);
+
+	assert_eq!(
+		api(Status { peers: 5, is_syncing: true }, false).system_health().unwrap(),
+		Health {
+			peers: 5,
+			is_syncing: true,
+			should_have_peers: false,
+		}
+	);
+}
+
+#[test]
+fn system_peers() {
+	assert_eq!(
+		api(Status { peers: 1, is_syncing: false }, true).system_peers().unwrap(),
+		vec![PeerInfo {
+			peer_id: "peer0".into(),
+			roles: "FULL".into(),
+			protocol_version: 1,
+			best_hash: H256::from(5),
+			best_number: 1,
+		}]
+	);
+}
+
+#[test]
+fn system_network_state() {
+	assert_eq!(
+		api(Status { peers: 2, is_syncing: true }, true).system_network_state().unwrap(),
+		NetworkState {
+			node_id: Some("test".into()),
+			sync_state: SyncState::Downloading,
+			best_seen_block: Some(5),
+			num_peers: 2,
+			num_active_peers: 0,
+		}
+	);
+}
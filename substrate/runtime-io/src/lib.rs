@@ -26,6 +26,71 @@
 #![cfg_attr(feature = "std", doc = "Substrate runtime standard library as compiled when linked with Rust's standard library.")]
 #![cfg_attr(not(feature = "std"), doc = "Substrate's runtime standard library as compiled without Rust's standard library.")]
 
+/// Level of a runtime log message, from the most to the least severe.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum LogLevel {
+	/// Serious errors.
+	Error = 1,
+	/// Hazardous situations.
+	Warn = 2,
+	/// Useful information.
+	Info = 3,
+	/// Lower priority information.
+	Debug = 4,
+	/// Very low priority, often extremely verbose, information.
+	Trace = 5,
+}
+
+/// Log a message formatted like `format!` with given level. The target defaults to the path of
+/// the current module.
+///
+/// Native runtimes log through the `log` crate, and the executor does the same for messages of
+/// Wasm runtimes, so they are filtered like those of the node.
+#[macro_export]
+macro_rules! runtime_log {
+	(target: $target:expr, $level:expr, $($arg:tt)+) => (
+		$crate::log_fmt($level, $target, format_args!($($arg)+))
+	);
+	($level:expr, $($arg:tt)+) => (
+		runtime_log!(target: module_path!(), $level, $($arg)+)
+	);
+}
+
+/// Log a message at the error level.
+#[macro_export]
+macro_rules! runtime_error {
+	(target: $target:expr, $($arg:tt)+) => (runtime_log!(target: $target, $crate::LogLevel::Error, $($arg)+));
+	($($arg:tt)+) => (runtime_log!($crate::LogLevel::Error, $($arg)+));
+}
+
+/// Log a message at the warn level.
+#[macro_export]
+macro_rules! runtime_warn {
+	(target: $target:expr, $($arg:tt)+) => (runtime_log!(target: $target, $crate::LogLevel::Warn, $($arg)+));
+	($($arg:tt)+) => (runtime_log!($crate::LogLevel::Warn, $($arg)+));
+}
+
+/// Log a message at the info level.
+#[macro_export]
+macro_rules! runtime_info {
+	(target: $target:expr, $($arg:tt)+) => (runtime_log!(target: $target, $crate::LogLevel::Info, $($arg)+));
+	($($arg:tt)+) => (runtime_log!($crate::LogLevel::Info, $($arg)+));
+}
+
+/// Log a message at the debug level.
+#[macro_export]
+macro_rules! runtime_debug {
+	(target: $target:expr, $($arg:tt)+) => (runtime_log!(target: $target, $crate::LogLevel::Debug, $($arg)+));
+	($($arg:tt)+) => (runtime_log!($crate::LogLevel::Debug, $($arg)+));
+}
+
+/// Log a message at the trace level.
+#[macro_export]
+macro_rules! runtime_trace {
+	(target: $target:expr, $($arg:tt)+) => (runtime_log!(target: $target, $crate::LogLevel::Trace, $($arg)+));
+	($($arg:tt)+) => (runtime_log!($crate::LogLevel::Trace, $($arg)+));
+}
+
 #[cfg(feature = "std")]
 include!("../with_std.rs");
 
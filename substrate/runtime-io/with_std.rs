@@ -23,14 +23,17 @@ extern crate substrate_primitives as primitives;
 extern crate substrate_state_machine;
 extern crate triehash;
 extern crate ed25519;
+#[macro_use]
+extern crate log;
 
 #[doc(hidden)]
 pub extern crate substrate_codec as codec;
 // re-export hashing functions.
 pub use primitives::{blake2_256, twox_128, twox_256};
 
-pub use substrate_state_machine::{Externalities, TestExternalities};
+pub use substrate_state_machine::{Externalities, OffchainExt, TestExternalities};
 use primitives::hexdisplay::HexDisplay;
+use std::fmt;
 
 // TODO: use the real error, not NoError.
 
@@ -82,6 +85,72 @@ pub fn clear_prefix(prefix: &[u8]) {
 	);
 }
 
+/// Get `key` from the child trie stored under `storage_key`.
+pub fn child_storage(storage_key: &[u8], key: &[u8]) -> Option<Vec<u8>> {
+	ext::with(|ext| ext.child_storage(storage_key, key))
+		.expect("child_storage cannot be called outside of an Externalities-provided environment.")
+}
+
+/// Set `key` of the child trie stored under `storage_key` to Some value.
+pub fn set_child_storage(storage_key: &[u8], key: &[u8], value: &[u8]) {
+	ext::with(|ext|
+		ext.set_child_storage(storage_key.to_vec(), key.to_vec(), value.to_vec())
+	);
+}
+
+/// Clear `key` of the child trie stored under `storage_key`.
+pub fn clear_child_storage(storage_key: &[u8], key: &[u8]) {
+	ext::with(|ext|
+		ext.clear_child_storage(storage_key, key)
+	);
+}
+
+/// Clear the whole child trie stored under `storage_key`.
+pub fn kill_child_storage(storage_key: &[u8]) {
+	ext::with(|ext|
+		ext.kill_child_storage(storage_key)
+	);
+}
+
+/// The root of the child trie stored under `storage_key`, or None if `storage_key` is not a
+/// child storage key.
+pub fn child_storage_root(storage_key: &[u8]) -> Option<[u8; 32]> {
+	ext::with(|ext|
+		ext.child_storage_root(storage_key)
+	).unwrap_or(None)
+}
+
+/// The root of the changes trie of the current block, or None if changes tries are not enabled.
+pub fn storage_changes_root() -> Option<[u8; 32]> {
+	ext::with(|ext|
+		ext.storage_changes_root()
+	).unwrap_or(None)
+}
+
+/// Start a new storage transaction, nested in the currently open one (if any).
+pub fn start_transaction() {
+	ext::with(|ext|
+		ext.start_transaction()
+	);
+}
+
+/// Commit the innermost storage transaction. Panics if there is no open transaction.
+pub fn commit_transaction() {
+	let committed = ext::with(|ext|
+		ext.commit_transaction()
+	).unwrap_or(false);
+	assert!(committed, "commit_transaction called without an open storage transaction");
+}
+
+/// Discard the changes made within the innermost storage transaction. Panics if there is no
+/// open transaction.
+pub fn rollback_transaction() {
+	let rolled_back = ext::with(|ext|
+		ext.rollback_transaction()
+	).unwrap_or(false);
+	assert!(rolled_back, "rollback_transaction called without an open storage transaction");
+}
+
 /// The current relay chain identifier.
 pub fn chain_id() -> u64 {
 	ext::with(|ext|
@@ -89,6 +158,35 @@ pub fn chain_id() -> u64 {
 	).unwrap_or(0)
 }
 
+/// Run `f` with the node-local capabilities of the offchain worker. Panics if the runtime is not
+/// executed as an offchain worker.
+fn with_offchain<R, F: FnOnce(&mut OffchainExt) -> R>(f: F) -> R {
+	ext::with(|ext| ext.offchain().map(f))
+		.and_then(|result| result)
+		.expect("offchain capabilities are only available to offchain workers")
+}
+
+/// Submit an encoded unsigned extrinsic to the local extrinsic pool, once the offchain worker
+/// succeeds. Only available to offchain workers.
+pub fn submit_extrinsic(extrinsic: &[u8]) {
+	with_offchain(|offchain| offchain.submit_extrinsic(extrinsic.to_vec()))
+}
+
+/// Get `key` from the node-local storage. Only available to offchain workers.
+pub fn local_storage(key: &[u8]) -> Option<Vec<u8>> {
+	with_offchain(|offchain| offchain.local_storage(key))
+}
+
+/// Set the node-local storage of some particular key. Only available to offchain workers.
+pub fn set_local_storage(key: &[u8], value: &[u8]) {
+	with_offchain(|offchain| offchain.set_local_storage(key, Some(value)))
+}
+
+/// Clear the node-local storage of some particular key. Only available to offchain workers.
+pub fn clear_local_storage(key: &[u8]) {
+	with_offchain(|offchain| offchain.set_local_storage(key, None))
+}
+
 /// "Commit" all existing operations and get the resultant storage root.
 pub fn storage_root() -> [u8; 32] {
 	ext::with(|ext|
@@ -157,6 +255,27 @@ pub fn print<T: Printable + Sized>(value: T) {
 	value.print();
 }
 
+fn log_level(level: LogLevel) -> log::LogLevel {
+	match level {
+		LogLevel::Error => log::LogLevel::Error,
+		LogLevel::Warn => log::LogLevel::Warn,
+		LogLevel::Info => log::LogLevel::Info,
+		LogLevel::Debug => log::LogLevel::Debug,
+		LogLevel::Trace => log::LogLevel::Trace,
+	}
+}
+
+/// Log `message` with given level and target.
+pub fn log(level: LogLevel, target: &str, message: &str) {
+	log!(target: target, log_level(level), "{}", message);
+}
+
+/// Log a message formatted from `args`. Used by the logging macros.
+#[doc(hidden)]
+pub fn log_fmt(level: LogLevel, target: &str, args: fmt::Arguments) {
+	log!(target: target, log_level(level), "{}", args);
+}
+
 #[macro_export]
 macro_rules! impl_stubs {
 	( $( $new_name:ident $($nodecode:ident)* => $invoke: expr ),*) => {
@@ -244,4 +363,20 @@ mod std_tests {
 			assert!(storage(b":abc").is_none());
 		});
 	}
+
+	#[test]
+	#[should_panic(expected = "only available to offchain workers")]
+	fn local_storage_is_only_available_to_offchain_workers() {
+		with_externalities(&mut TestExternalities::new(), || local_storage(b"foo"));
+	}
+
+	#[test]
+	fn log_levels_map_to_log_crate() {
+		assert_eq!(log_level(LogLevel::Error), log::LogLevel::Error);
+		assert_eq!(log_level(LogLevel::Trace), log::LogLevel::Trace);
+		assert!(log_level(LogLevel::Warn) < log_level(LogLevel::Debug));
+
+		runtime_debug!(target: "runtime-io", "{} is {}", "answer", 42);
+		runtime_log!(LogLevel::Info, "no explicit target");
+	}
 }
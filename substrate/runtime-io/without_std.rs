@@ -25,6 +25,7 @@ pub extern crate substrate_codec as codec;
 
 use core::intrinsics;
 use rstd::vec::Vec;
+use rstd::fmt;
 pub use rstd::{mem, slice};
 
 #[panic_implementation]
@@ -54,15 +55,29 @@ extern "C" {
 	fn ext_print_utf8(utf8_data: *const u8, utf8_len: u32);
 	fn ext_print_hex(data: *const u8, len: u32);
 	fn ext_print_num(value: u64);
+	fn ext_log(level: u32, target_data: *const u8, target_len: u32, message_data: *const u8, message_len: u32);
 	fn ext_set_storage(key_data: *const u8, key_len: u32, value_data: *const u8, value_len: u32);
 	fn ext_clear_storage(key_data: *const u8, key_len: u32);
 	fn ext_exists_storage(key_data: *const u8, key_len: u32) -> u32;
 	fn ext_clear_prefix(prefix_data: *const u8, prefix_len: u32);
 	fn ext_get_allocated_storage(key_data: *const u8, key_len: u32, written_out: *mut u32) -> *mut u8;
 	fn ext_get_storage_into(key_data: *const u8, key_len: u32, value_data: *mut u8, value_len: u32, value_offset: u32) -> u32;
+	fn ext_get_allocated_child_storage(storage_key_data: *const u8, storage_key_len: u32, key_data: *const u8, key_len: u32, written_out: *mut u32) -> *mut u8;
+	fn ext_set_child_storage(storage_key_data: *const u8, storage_key_len: u32, key_data: *const u8, key_len: u32, value_data: *const u8, value_len: u32);
+	fn ext_clear_child_storage(storage_key_data: *const u8, storage_key_len: u32, key_data: *const u8, key_len: u32);
+	fn ext_kill_child_storage(storage_key_data: *const u8, storage_key_len: u32);
+	fn ext_child_storage_root(storage_key_data: *const u8, storage_key_len: u32, result: *mut u8) -> u32;
+	fn ext_storage_changes_root(result: *mut u8) -> u32;
+	fn ext_start_transaction();
+	fn ext_commit_transaction() -> u32;
+	fn ext_rollback_transaction() -> u32;
 	fn ext_storage_root(result: *mut u8);
 	fn ext_enumerated_trie_root(values_data: *const u8, lens_data: *const u32, lens_len: u32, result: *mut u8);
 	fn ext_chain_id() -> u64;
+	fn ext_submit_extrinsic(data: *const u8, len: u32);
+	fn ext_get_allocated_local_storage(key_data: *const u8, key_len: u32, written_out: *mut u32) -> *mut u8;
+	fn ext_set_local_storage(key_data: *const u8, key_len: u32, value_data: *const u8, value_len: u32);
+	fn ext_clear_local_storage(key_data: *const u8, key_len: u32);
 	fn ext_blake2_256(data: *const u8, len: u32, out: *mut u8);
 	fn ext_twox_128(data: *const u8, len: u32, out: *mut u8);
 	fn ext_twox_256(data: *const u8, len: u32, out: *mut u8);
@@ -135,6 +150,102 @@ pub fn read_storage(key: &[u8], value_out: &mut [u8], value_offset: usize) -> Op
 	}
 }
 
+/// Get `key` from the child trie stored under `storage_key`.
+pub fn child_storage(storage_key: &[u8], key: &[u8]) -> Option<Vec<u8>> {
+	let mut length: u32 = 0;
+	unsafe {
+		let ptr = ext_get_allocated_child_storage(
+			storage_key.as_ptr(), storage_key.len() as u32,
+			key.as_ptr(), key.len() as u32,
+			&mut length
+		);
+		if length == u32::max_value() {
+			None
+		} else {
+			Some(Vec::from_raw_parts(ptr, length as usize, length as usize))
+		}
+	}
+}
+
+/// Set `key` of the child trie stored under `storage_key` to Some value.
+pub fn set_child_storage(storage_key: &[u8], key: &[u8], value: &[u8]) {
+	unsafe {
+		ext_set_child_storage(
+			storage_key.as_ptr(), storage_key.len() as u32,
+			key.as_ptr(), key.len() as u32,
+			value.as_ptr(), value.len() as u32
+		);
+	}
+}
+
+/// Clear `key` of the child trie stored under `storage_key`.
+pub fn clear_child_storage(storage_key: &[u8], key: &[u8]) {
+	unsafe {
+		ext_clear_child_storage(
+			storage_key.as_ptr(), storage_key.len() as u32,
+			key.as_ptr(), key.len() as u32
+		);
+	}
+}
+
+/// Clear the whole child trie stored under `storage_key`.
+pub fn kill_child_storage(storage_key: &[u8]) {
+	unsafe {
+		ext_kill_child_storage(
+			storage_key.as_ptr(), storage_key.len() as u32
+		);
+	}
+}
+
+/// The root of the child trie stored under `storage_key`, or None if `storage_key` is not a
+/// child storage key.
+pub fn child_storage_root(storage_key: &[u8]) -> Option<[u8; 32]> {
+	let mut result: [u8; 32] = Default::default();
+	let is_child = unsafe {
+		ext_child_storage_root(storage_key.as_ptr(), storage_key.len() as u32, result.as_mut_ptr())
+	};
+	if is_child != 0 {
+		Some(result)
+	} else {
+		None
+	}
+}
+
+/// The root of the changes trie of the current block, or None if changes tries are not enabled.
+pub fn storage_changes_root() -> Option<[u8; 32]> {
+	let mut result: [u8; 32] = Default::default();
+	let is_enabled = unsafe {
+		ext_storage_changes_root(result.as_mut_ptr())
+	};
+	if is_enabled != 0 {
+		Some(result)
+	} else {
+		None
+	}
+}
+
+/// Start a new storage transaction, nested in the currently open one (if any).
+pub fn start_transaction() {
+	unsafe {
+		ext_start_transaction();
+	}
+}
+
+/// Commit the innermost storage transaction. Panics if there is no open transaction.
+pub fn commit_transaction() {
+	if unsafe { ext_commit_transaction() } == 0 {
+		panic!("commit_transaction called without an open storage transaction");
+	}
+}
+
+/// Discard the changes made within the innermost storage transaction. Panics if there is no
+/// open transaction.
+pub fn rollback_transaction() {
+	if unsafe { ext_rollback_transaction() } == 0 {
+		panic!("rollback_transaction called without an open storage transaction");
+	}
+}
+
 /// The current storage's root.
 pub fn storage_root() -> [u8; 32] {
 	let mut result: [u8; 32] = Default::default();
@@ -187,6 +298,41 @@ pub fn chain_id() -> u64 {
 	}
 }
 
+/// Submit an encoded unsigned extrinsic to the local extrinsic pool, once the offchain worker
+/// succeeds. Only available to offchain workers.
+pub fn submit_extrinsic(extrinsic: &[u8]) {
+	unsafe {
+		ext_submit_extrinsic(extrinsic.as_ptr(), extrinsic.len() as u32);
+	}
+}
+
+/// Get `key` from the node-local storage. Only available to offchain workers.
+pub fn local_storage(key: &[u8]) -> Option<Vec<u8>> {
+	let mut length: u32 = 0;
+	unsafe {
+		let ptr = ext_get_allocated_local_storage(key.as_ptr(), key.len() as u32, &mut length);
+		if length == u32::max_value() {
+			None
+		} else {
+			Some(Vec::from_raw_parts(ptr, length as usize, length as usize))
+		}
+	}
+}
+
+/// Set the node-local storage of some particular key. Only available to offchain workers.
+pub fn set_local_storage(key: &[u8], value: &[u8]) {
+	unsafe {
+		ext_set_local_storage(key.as_ptr(), key.len() as u32, value.as_ptr(), value.len() as u32);
+	}
+}
+
+/// Clear the node-local storage of some particular key. Only available to offchain workers.
+pub fn clear_local_storage(key: &[u8]) {
+	unsafe {
+		ext_clear_local_storage(key.as_ptr(), key.len() as u32);
+	}
+}
+
 /// Conduct a 256-bit Blake2 hash.
 pub fn blake2_256(data: &[u8]) -> [u8; 32] {
 	let mut result: [u8; 32] = Default::default();
@@ -253,6 +399,32 @@ pub fn print<T: Printable + Sized>(value: T) {
 	value.print();
 }
 
+/// Log `message` with given level and target.
+pub fn log(level: LogLevel, target: &str, message: &str) {
+	unsafe {
+		ext_log(level as u32, target.as_ptr(), target.len() as u32, message.as_ptr(), message.len() as u32);
+	}
+}
+
+struct MessageWriter(Vec<u8>);
+
+impl fmt::Write for MessageWriter {
+	fn write_str(&mut self, s: &str) -> fmt::Result {
+		self.0.extend_from_slice(s.as_bytes());
+		Ok(())
+	}
+}
+
+/// Log a message formatted from `args`. Used by the logging macros.
+#[doc(hidden)]
+pub fn log_fmt(level: LogLevel, target: &str, args: fmt::Arguments) {
+	let mut message = MessageWriter(Vec::new());
+	let _ = fmt::write(&mut message, args);
+	unsafe {
+		ext_log(level as u32, target.as_ptr(), target.len() as u32, message.0.as_ptr(), message.0.len() as u32);
+	}
+}
+
 #[macro_export]
 macro_rules! impl_stubs {
 	( $( $new_name:ident $($nodecode:ident)* => $invoke:expr ),* ) => {
@@ -103,6 +103,7 @@ macro_rules! decl_module {
 
 		decl_dispatch! {
 			impl for $mod_type<$trait_instance: $trait_name>;
+			[]
 			$($rest)*
 		}
 	};
@@ -126,6 +127,7 @@ macro_rules! decl_module {
 		struct $mod_type<$trait_instance: $trait_name>(::core::marker::PhantomData<$trait_instance>);
 		decl_dispatch! {
 			impl for $mod_type<$trait_instance: $trait_name>;
+			[]
 			$($rest)*
 		}
 	}
@@ -137,6 +139,7 @@ macro_rules! decl_dispatch {
 	// WITHOUT AUX
 	(
 		impl for $mod_type:ident<$trait_instance:ident: $trait_name:ident>;
+		[ $( $call_types:ident )* ]
 		$(#[$attr:meta])*
 		pub enum $call_type:ident {
 			$(
@@ -160,12 +163,14 @@ macro_rules! decl_dispatch {
 		}
 		decl_dispatch! {
 			impl for $mod_type<$trait_instance: $trait_name>;
+			[ $( $call_types )* $call_type ]
 			$($rest)*
 		}
 	};
 	// WITH AUX
 	(
 		impl for $mod_type:ident<$trait_instance:ident: $trait_name:ident>;
+		[ $( $call_types:ident )* ]
 		$(#[$attr:meta])*
 		pub enum $call_type:ident where aux: $aux_type:ty {
 			$(
@@ -189,12 +194,14 @@ macro_rules! decl_dispatch {
 		}
 		decl_dispatch! {
 			impl for $mod_type<$trait_instance: $trait_name>;
+			[ $( $call_types )* $call_type ]
 			$($rest)*
 		}
 	};
 	// BASE CASE
 	(
 		impl for $mod_type:ident<$trait_instance:ident: $trait_name:ident>;
+		[ $( $call_types:ident )* ]
 	) => {
 		impl<$trait_instance: $trait_name> $mod_type<$trait_instance> {
 			pub fn aux_dispatch<D: $crate::dispatch::AuxDispatchable<Trait = $trait_instance>>(d: D, aux: &D::Aux) -> $crate::dispatch::Result {
@@ -203,6 +210,15 @@ macro_rules! decl_dispatch {
 			pub fn dispatch<D: $crate::dispatch::Dispatchable<Trait = $trait_instance>>(d: D) -> $crate::dispatch::Result {
 				d.dispatch()
 			}
+			/// The metadata of every dispatchable enum of this module.
+			#[allow(unused_mut)]
+			pub fn call_metadata() -> $crate::dispatch::Vec<$crate::metadata::CallMetadata> {
+				let mut calls = $crate::dispatch::Vec::new();
+				$(
+					calls.push(<$call_types<$trait_instance>>::metadata());
+				)*
+				calls
+			}
 		}
 	}
 }
@@ -377,6 +393,32 @@ macro_rules! __decl_dispatch_module_common {
 			for $call_type<$trait_instance>
 		{}
 
+		impl<$trait_instance: $trait_name> $call_type<$trait_instance> {
+			/// The metadata of this dispatchable enum.
+			#[allow(unused_mut)]
+			pub fn metadata() -> $crate::metadata::CallMetadata {
+				let mut functions = $crate::dispatch::Vec::new();
+				$(
+					let mut arguments = $crate::dispatch::Vec::new();
+					$(
+						arguments.push($crate::metadata::FunctionArgumentMetadata {
+							name: stringify!($param_name).as_bytes().to_vec(),
+							ty: stringify!($param).as_bytes().to_vec(),
+						});
+					)*
+					functions.push($crate::metadata::FunctionMetadata {
+						id: $id,
+						name: stringify!($fn_name).as_bytes().to_vec(),
+						arguments,
+					});
+				)*
+				$crate::metadata::CallMetadata {
+					name: stringify!($call_type).as_bytes().to_vec(),
+					functions,
+				}
+			}
+		}
+
 		#[cfg(feature = "std")]
 		impl<$trait_instance: $trait_name> $crate::dispatch::fmt::Debug
 			for $call_type<$trait_instance>
@@ -31,6 +31,7 @@ pub use self::storage::generator::Storage as GenericStorage;
 
 pub mod dispatch;
 pub mod storage;
+pub mod metadata;
 mod hashable;
 
 pub use self::storage::{StorageVec, StorageList, StorageValue, StorageMap};
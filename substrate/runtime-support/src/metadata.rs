@@ -0,0 +1,349 @@
+// Copyright 2018 Parity Technologies (UK) Ltd.
+// This file is part of Substrate.
+
+// Substrate is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// Substrate is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with Substrate.  If not, see <http://www.gnu.org/licenses/>.
+
+//! Runtime metadata: a codec-encoded description of the modules of a runtime, their
+//! dispatchable calls and their storage items.
+//!
+//! Names and types are kept as the UTF-8 bytes of their source representation, e.g.
+//! `T::AccountId`; they are informational only and not resolved any further.
+
+use rstd::prelude::Vec;
+use codec::{Decode, Encode, Input, Output};
+
+/// Metadata of a whole runtime.
+#[derive(Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "std", derive(Debug))]
+pub struct RuntimeMetadata {
+	/// The modules of the runtime.
+	pub modules: Vec<ModuleMetadata>,
+}
+
+/// Metadata of a single module.
+#[derive(Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "std", derive(Debug))]
+pub struct ModuleMetadata {
+	/// The name under which the module is exposed by the runtime.
+	pub name: Vec<u8>,
+	/// The dispatchable enums of the module (`Call` and `PrivCall`).
+	pub calls: Vec<CallMetadata>,
+	/// The storage items of the module.
+	pub storage: Vec<StorageItemMetadata>,
+}
+
+/// Metadata of a dispatchable enum.
+#[derive(Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "std", derive(Debug))]
+pub struct CallMetadata {
+	/// The name of the enum.
+	pub name: Vec<u8>,
+	/// Its variants.
+	pub functions: Vec<FunctionMetadata>,
+}
+
+/// Metadata of a single dispatchable function.
+#[derive(Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "std", derive(Debug))]
+pub struct FunctionMetadata {
+	/// The index of the function, as used in the encoding of the call.
+	pub id: u8,
+	/// The name of the function.
+	pub name: Vec<u8>,
+	/// The arguments of the function, in order. The auxilliary argument is not included.
+	pub arguments: Vec<FunctionArgumentMetadata>,
+}
+
+/// Metadata of a dispatchable function's argument.
+#[derive(Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "std", derive(Debug))]
+pub struct FunctionArgumentMetadata {
+	/// The name of the argument.
+	pub name: Vec<u8>,
+	/// The type of the argument.
+	pub ty: Vec<u8>,
+}
+
+/// What is returned for a storage item that is not present.
+#[derive(Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "std", derive(Debug))]
+pub enum StorageModifier {
+	/// `None` is returned.
+	Optional,
+	/// The default value of the type is returned.
+	Default,
+	/// The runtime panics.
+	Required,
+}
+
+/// The shape of a storage item.
+#[derive(Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "std", derive(Debug))]
+pub enum StorageType {
+	/// A single value of the given type, stored under the item's key.
+	Plain(Vec<u8>),
+	/// A map; values are stored under the item's prefix followed by the encoded key.
+	Map {
+		/// The type of the key.
+		key: Vec<u8>,
+		/// The type of the value.
+		value: Vec<u8>,
+	},
+}
+
+/// Metadata of a single storage item.
+#[derive(Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "std", derive(Debug))]
+pub struct StorageItemMetadata {
+	/// The name of the item.
+	pub name: Vec<u8>,
+	/// The key of the item (or the key prefix for maps), before hashing.
+	pub prefix: Vec<u8>,
+	/// What is returned when the item is not present.
+	pub modifier: StorageModifier,
+	/// The shape and type(s) of the item.
+	pub ty: StorageType,
+}
+
+impl Encode for RuntimeMetadata {
+	fn encode_to<T: Output>(&self, dest: &mut T) {
+		dest.push(&self.modules);
+	}
+}
+
+impl Decode for RuntimeMetadata {
+	fn decode<I: Input>(value: &mut I) -> Option<Self> {
+		Some(RuntimeMetadata {
+			modules: Decode::decode(value)?,
+		})
+	}
+}
+
+impl Encode for ModuleMetadata {
+	fn encode_to<T: Output>(&self, dest: &mut T) {
+		dest.push(&self.name);
+		dest.push(&self.calls);
+		dest.push(&self.storage);
+	}
+}
+
+impl Decode for ModuleMetadata {
+	fn decode<I: Input>(value: &mut I) -> Option<Self> {
+		Some(ModuleMetadata {
+			name: Decode::decode(value)?,
+			calls: Decode::decode(value)?,
+			storage: Decode::decode(value)?,
+		})
+	}
+}
+
+impl Encode for CallMetadata {
+	fn encode_to<T: Output>(&self, dest: &mut T) {
+		dest.push(&self.name);
+		dest.push(&self.functions);
+	}
+}
+
+impl Decode for CallMetadata {
+	fn decode<I: Input>(value: &mut I) -> Option<Self> {
+		Some(CallMetadata {
+			name: Decode::decode(value)?,
+			functions: Decode::decode(value)?,
+		})
+	}
+}
+
+impl Encode for FunctionMetadata {
+	fn encode_to<T: Output>(&self, dest: &mut T) {
+		dest.push_byte(self.id);
+		dest.push(&self.name);
+		dest.push(&self.arguments);
+	}
+}
+
+impl Decode for FunctionMetadata {
+	fn decode<I: Input>(value: &mut I) -> Option<Self> {
+		Some(FunctionMetadata {
+			id: value.read_byte()?,
+			name: Decode::decode(value)?,
+			arguments: Decode::decode(value)?,
+		})
+	}
+}
+
+impl Encode for FunctionArgumentMetadata {
+	fn encode_to<T: Output>(&self, dest: &mut T) {
+		dest.push(&self.name);
+		dest.push(&self.ty);
+	}
+}
+
+impl Decode for FunctionArgumentMetadata {
+	fn decode<I: Input>(value: &mut I) -> Option<Self> {
+		Some(FunctionArgumentMetadata {
+			name: Decode::decode(value)?,
+			ty: Decode::decode(value)?,
+		})
+	}
+}
+
+impl Encode for StorageModifier {
+	fn encode_to<T: Output>(&self, dest: &mut T) {
+		dest.push_byte(*self as u8);
+	}
+}
+
+impl Decode for StorageModifier {
+	fn decode<I: Input>(value: &mut I) -> Option<Self> {
+		match value.read_byte()? {
+			x if x == StorageModifier::Optional as u8 => Some(StorageModifier::Optional),
+			x if x == StorageModifier::Default as u8 => Some(StorageModifier::Default),
+			x if x == StorageModifier::Required as u8 => Some(StorageModifier::Required),
+			_ => None,
+		}
+	}
+}
+
+impl Encode for StorageType {
+	fn encode_to<T: Output>(&self, dest: &mut T) {
+		match *self {
+			StorageType::Plain(ref ty) => {
+				dest.push_byte(0);
+				dest.push(ty);
+			}
+			StorageType::Map { ref key, ref value } => {
+				dest.push_byte(1);
+				dest.push(key);
+				dest.push(value);
+			}
+		}
+	}
+}
+
+impl Decode for StorageType {
+	fn decode<I: Input>(value: &mut I) -> Option<Self> {
+		match value.read_byte()? {
+			0 => Some(StorageType::Plain(Decode::decode(value)?)),
+			1 => Some(StorageType::Map {
+				key: Decode::decode(value)?,
+				value: Decode::decode(value)?,
+			}),
+			_ => None,
+		}
+	}
+}
+
+impl Encode for StorageItemMetadata {
+	fn encode_to<T: Output>(&self, dest: &mut T) {
+		dest.push(&self.name);
+		dest.push(&self.prefix);
+		dest.push(&self.modifier);
+		dest.push(&self.ty);
+	}
+}
+
+impl Decode for StorageItemMetadata {
+	fn decode<I: Input>(value: &mut I) -> Option<Self> {
+		Some(StorageItemMetadata {
+			name: Decode::decode(value)?,
+			prefix: Decode::decode(value)?,
+			modifier: Decode::decode(value)?,
+			ty: Decode::decode(value)?,
+		})
+	}
+}
+
+/// Implement a function returning the metadata of a runtime, given its modules.
+///
+/// Every module must have been declared with `decl_module!`; those that also have a
+/// `decl_storage!` are marked `with storage`.
+///
+/// ```ignore
+/// impl_outer_metadata! {
+/// 	pub fn metadata {
+/// 		Consensus,
+/// 		System with storage,
+/// 	}
+/// }
+/// ```
+#[macro_export]
+macro_rules! impl_outer_metadata {
+	(
+		pub fn $fn_name:ident {
+			$( $module:ident $( with $storage:ident )*, )*
+		}
+	) => {
+		/// The metadata of this runtime.
+		pub fn $fn_name() -> $crate::metadata::RuntimeMetadata {
+			let mut modules = $crate::dispatch::Vec::new();
+			$(
+				modules.push($crate::metadata::ModuleMetadata {
+					name: stringify!($module).as_bytes().to_vec(),
+					calls: $module::call_metadata(),
+					storage: __module_storage_metadata!($module $( $storage )*),
+				});
+			)*
+			$crate::metadata::RuntimeMetadata { modules }
+		}
+	}
+}
+
+#[macro_export]
+#[doc(hidden)]
+macro_rules! __module_storage_metadata {
+	($module:ident) => { $crate::dispatch::Vec::new() };
+	($module:ident storage) => { $module::storage_metadata() };
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn metadata_encoding_roundtrips() {
+		let metadata = RuntimeMetadata {
+			modules: vec![ModuleMetadata {
+				name: b"Test".to_vec(),
+				calls: vec![CallMetadata {
+					name: b"Call".to_vec(),
+					functions: vec![FunctionMetadata {
+						id: 1,
+						name: b"transfer".to_vec(),
+						arguments: vec![FunctionArgumentMetadata {
+							name: b"value".to_vec(),
+							ty: b"u64".to_vec(),
+						}],
+					}],
+				}],
+				storage: vec![
+					StorageItemMetadata {
+						name: b"Value".to_vec(),
+						prefix: b"tst:val".to_vec(),
+						modifier: StorageModifier::Required,
+						ty: StorageType::Plain(b"u32".to_vec()),
+					},
+					StorageItemMetadata {
+						name: b"Map".to_vec(),
+						prefix: b"tst:map:".to_vec(),
+						modifier: StorageModifier::Optional,
+						ty: StorageType::Map { key: b"u32".to_vec(), value: b"u64".to_vec() },
+					},
+				],
+			}],
+		};
+
+		let encoded = metadata.encode();
+		assert_eq!(RuntimeMetadata::decode(&mut &encoded[..]), Some(metadata));
+	}
+}
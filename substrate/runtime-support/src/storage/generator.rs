@@ -541,6 +541,14 @@ macro_rules! decl_storage {
 		}
 		impl<$traitinstance: $traittype> $modulename<$traitinstance> {
 			__impl_store_fns!($traitinstance $($t)*);
+
+			/// The metadata of every storage item of this module.
+			#[allow(unused_mut)]
+			pub fn storage_metadata() -> $crate::dispatch::Vec<$crate::metadata::StorageItemMetadata> {
+				let mut items = $crate::dispatch::Vec::new();
+				__impl_store_metadata!(items; $($t)*);
+				items
+			}
 		}
 	};
 	(
@@ -556,6 +564,14 @@ macro_rules! decl_storage {
 		}
 		impl<$traitinstance: $traittype> $modulename<$traitinstance> {
 			__impl_store_fns!($traitinstance $($t)*);
+
+			/// The metadata of every storage item of this module.
+			#[allow(unused_mut)]
+			pub fn storage_metadata() -> $crate::dispatch::Vec<$crate::metadata::StorageItemMetadata> {
+				let mut items = $crate::dispatch::Vec::new();
+				__impl_store_metadata!(items; $($t)*);
+				items
+			}
 		}
 	}
 }
@@ -763,6 +779,139 @@ macro_rules! __impl_store_fns {
 	($traitinstance:ident) => ()
 }
 
+#[macro_export]
+#[doc(hidden)]
+macro_rules! __impl_store_item_metadata {
+	($items:ident; $name:ident $key:expr; $modifier:ident; map [$kty:ty => $ty:ty]) => {
+		$items.push($crate::metadata::StorageItemMetadata {
+			name: stringify!($name).as_bytes().to_vec(),
+			prefix: $key.to_vec(),
+			modifier: $crate::metadata::StorageModifier::$modifier,
+			ty: $crate::metadata::StorageType::Map {
+				key: stringify!($kty).as_bytes().to_vec(),
+				value: stringify!($ty).as_bytes().to_vec(),
+			},
+		});
+	};
+	($items:ident; $name:ident $key:expr; $modifier:ident; $ty:ty) => {
+		$items.push($crate::metadata::StorageItemMetadata {
+			name: stringify!($name).as_bytes().to_vec(),
+			prefix: $key.to_vec(),
+			modifier: $crate::metadata::StorageModifier::$modifier,
+			ty: $crate::metadata::StorageType::Plain(stringify!($ty).as_bytes().to_vec()),
+		});
+	}
+}
+
+#[macro_export]
+#[doc(hidden)]
+macro_rules! __impl_store_metadata {
+	// simple values
+	($items:ident; $name:ident : $key:expr => $ty:ty; $($t:tt)*) => {
+		__impl_store_item_metadata!($items; $name $key; Optional; $ty);
+		__impl_store_metadata!($items; $($t)*);
+	};
+	($items:ident; pub $name:ident : $key:expr => $ty:ty; $($t:tt)*) => {
+		__impl_store_item_metadata!($items; $name $key; Optional; $ty);
+		__impl_store_metadata!($items; $($t)*);
+	};
+	($items:ident; $name:ident : $key:expr => default $ty:ty; $($t:tt)*) => {
+		__impl_store_item_metadata!($items; $name $key; Default; $ty);
+		__impl_store_metadata!($items; $($t)*);
+	};
+	($items:ident; pub $name:ident : $key:expr => default $ty:ty; $($t:tt)*) => {
+		__impl_store_item_metadata!($items; $name $key; Default; $ty);
+		__impl_store_metadata!($items; $($t)*);
+	};
+	($items:ident; $name:ident : $key:expr => required $ty:ty; $($t:tt)*) => {
+		__impl_store_item_metadata!($items; $name $key; Required; $ty);
+		__impl_store_metadata!($items; $($t)*);
+	};
+	($items:ident; pub $name:ident : $key:expr => required $ty:ty; $($t:tt)*) => {
+		__impl_store_item_metadata!($items; $name $key; Required; $ty);
+		__impl_store_metadata!($items; $($t)*);
+	};
+
+	($items:ident; $name:ident get($getfn:ident) : $key:expr => $ty:ty; $($t:tt)*) => {
+		__impl_store_item_metadata!($items; $name $key; Optional; $ty);
+		__impl_store_metadata!($items; $($t)*);
+	};
+	($items:ident; pub $name:ident get($getfn:ident) : $key:expr => $ty:ty; $($t:tt)*) => {
+		__impl_store_item_metadata!($items; $name $key; Optional; $ty);
+		__impl_store_metadata!($items; $($t)*);
+	};
+	($items:ident; $name:ident get($getfn:ident) : $key:expr => default $ty:ty; $($t:tt)*) => {
+		__impl_store_item_metadata!($items; $name $key; Default; $ty);
+		__impl_store_metadata!($items; $($t)*);
+	};
+	($items:ident; pub $name:ident get($getfn:ident) : $key:expr => default $ty:ty; $($t:tt)*) => {
+		__impl_store_item_metadata!($items; $name $key; Default; $ty);
+		__impl_store_metadata!($items; $($t)*);
+	};
+	($items:ident; $name:ident get($getfn:ident) : $key:expr => required $ty:ty; $($t:tt)*) => {
+		__impl_store_item_metadata!($items; $name $key; Required; $ty);
+		__impl_store_metadata!($items; $($t)*);
+	};
+	($items:ident; pub $name:ident get($getfn:ident) : $key:expr => required $ty:ty; $($t:tt)*) => {
+		__impl_store_item_metadata!($items; $name $key; Required; $ty);
+		__impl_store_metadata!($items; $($t)*);
+	};
+
+	// maps
+	($items:ident; $name:ident : $prefix:expr => map [$kty:ty => $ty:ty]; $($t:tt)*) => {
+		__impl_store_item_metadata!($items; $name $prefix; Optional; map [$kty => $ty]);
+		__impl_store_metadata!($items; $($t)*);
+	};
+	($items:ident; pub $name:ident : $prefix:expr => map [$kty:ty => $ty:ty]; $($t:tt)*) => {
+		__impl_store_item_metadata!($items; $name $prefix; Optional; map [$kty => $ty]);
+		__impl_store_metadata!($items; $($t)*);
+	};
+	($items:ident; $name:ident : $prefix:expr => default map [$kty:ty => $ty:ty]; $($t:tt)*) => {
+		__impl_store_item_metadata!($items; $name $prefix; Default; map [$kty => $ty]);
+		__impl_store_metadata!($items; $($t)*);
+	};
+	($items:ident; pub $name:ident : $prefix:expr => default map [$kty:ty => $ty:ty]; $($t:tt)*) => {
+		__impl_store_item_metadata!($items; $name $prefix; Default; map [$kty => $ty]);
+		__impl_store_metadata!($items; $($t)*);
+	};
+	($items:ident; $name:ident : $prefix:expr => required map [$kty:ty => $ty:ty]; $($t:tt)*) => {
+		__impl_store_item_metadata!($items; $name $prefix; Required; map [$kty => $ty]);
+		__impl_store_metadata!($items; $($t)*);
+	};
+	($items:ident; pub $name:ident : $prefix:expr => required map [$kty:ty => $ty:ty]; $($t:tt)*) => {
+		__impl_store_item_metadata!($items; $name $prefix; Required; map [$kty => $ty]);
+		__impl_store_metadata!($items; $($t)*);
+	};
+
+	($items:ident; $name:ident get($getfn:ident) : $prefix:expr => map [$kty:ty => $ty:ty]; $($t:tt)*) => {
+		__impl_store_item_metadata!($items; $name $prefix; Optional; map [$kty => $ty]);
+		__impl_store_metadata!($items; $($t)*);
+	};
+	($items:ident; pub $name:ident get($getfn:ident) : $prefix:expr => map [$kty:ty => $ty:ty]; $($t:tt)*) => {
+		__impl_store_item_metadata!($items; $name $prefix; Optional; map [$kty => $ty]);
+		__impl_store_metadata!($items; $($t)*);
+	};
+	($items:ident; $name:ident get($getfn:ident) : $prefix:expr => default map [$kty:ty => $ty:ty]; $($t:tt)*) => {
+		__impl_store_item_metadata!($items; $name $prefix; Default; map [$kty => $ty]);
+		__impl_store_metadata!($items; $($t)*);
+	};
+	($items:ident; pub $name:ident get($getfn:ident) : $prefix:expr => default map [$kty:ty => $ty:ty]; $($t:tt)*) => {
+		__impl_store_item_metadata!($items; $name $prefix; Default; map [$kty => $ty]);
+		__impl_store_metadata!($items; $($t)*);
+	};
+	($items:ident; $name:ident get($getfn:ident) : $prefix:expr => required map [$kty:ty => $ty:ty]; $($t:tt)*) => {
+		__impl_store_item_metadata!($items; $name $prefix; Required; map [$kty => $ty]);
+		__impl_store_metadata!($items; $($t)*);
+	};
+	($items:ident; pub $name:ident get($getfn:ident) : $prefix:expr => required map [$kty:ty => $ty:ty]; $($t:tt)*) => {
+		__impl_store_item_metadata!($items; $name $prefix; Required; map [$kty => $ty]);
+		__impl_store_metadata!($items; $($t)*);
+	};
+
+	// exit
+	($items:ident;) => ()
+}
+
 #[macro_export]
 #[doc(hidden)]
 macro_rules! __impl_store_item {
@@ -1057,4 +1206,39 @@ mod tests {
 		assert!(Map::get(&5, &storage).is_none());
 		assert!(Map::get(&999, &storage).is_none());
 	}
+
+	mod declared {
+		pub trait Trait {}
+		pub struct Module<T: Trait>(::std::marker::PhantomData<T>);
+
+		decl_storage! {
+			trait Store for Module<T: Trait>;
+
+			pub Value get(value): b"tst:val" => required u32;
+			Map: b"tst:map:" => default map [ u32 => u64 ];
+		}
+	}
+
+	#[test]
+	fn decl_storage_generates_metadata() {
+		use metadata::{StorageItemMetadata, StorageModifier, StorageType};
+
+		struct Test;
+		impl declared::Trait for Test {}
+
+		assert_eq!(declared::Module::<Test>::storage_metadata(), vec![
+			StorageItemMetadata {
+				name: b"Value".to_vec(),
+				prefix: b"tst:val".to_vec(),
+				modifier: StorageModifier::Required,
+				ty: StorageType::Plain(b"u32".to_vec()),
+			},
+			StorageItemMetadata {
+				name: b"Map".to_vec(),
+				prefix: b"tst:map:".to_vec(),
+				modifier: StorageModifier::Default,
+				ty: StorageType::Map { key: b"u32".to_vec(), value: b"u64".to_vec() },
+			},
+		]);
+	}
 }
@@ -56,6 +56,8 @@ impl<S: codec::Codec + Default> StorageVec for AuthorityStorageVec<S> {
 
 pub const CODE: &'static [u8] = b":code";
 
+pub use substrate_primitives::storage::MAX_INSTRUCTIONS;
+
 pub type KeyValue = (Vec<u8>, Vec<u8>);
 
 pub trait Trait: system::Trait {
@@ -131,6 +133,8 @@ pub struct GenesisConfig<T: Trait> {
 	pub authorities: Vec<T::SessionKey>,
 	#[serde(with = "substrate_primitives::bytes")]
 	pub code: Vec<u8>,
+	#[serde(default)]
+	pub max_instructions: Option<u64>,
 }
 
 #[cfg(any(feature = "std", test))]
@@ -139,6 +143,7 @@ impl<T: Trait> Default for GenesisConfig<T> {
 		GenesisConfig {
 			authorities: vec![],
 			code: vec![],
+			max_instructions: None,
 		}
 	}
 }
@@ -146,14 +151,17 @@ impl<T: Trait> Default for GenesisConfig<T> {
 #[cfg(any(feature = "std", test))]
 impl<T: Trait> primitives::BuildStorage for GenesisConfig<T>
 {
-	fn build_storage(self) -> ::std::result::Result<runtime_io::TestExternalities, String> {
+	fn build_storage(self) -> ::std::result::Result<primitives::StorageMap, String> {
 		use codec::{Encode, KeyedVec};
 		let auth_count = self.authorities.len() as u32;
-		let mut r: runtime_io::TestExternalities = self.authorities.into_iter().enumerate().map(|(i, v)|
+		let mut r: primitives::StorageMap = self.authorities.into_iter().enumerate().map(|(i, v)|
 			((i as u32).to_keyed_vec(AUTHORITY_AT), v.encode())
 		).collect();
 		r.insert(AUTHORITY_COUNT.to_vec(), auth_count.encode());
 		r.insert(CODE.to_vec(), self.code);
+		if let Some(max_instructions) = self.max_instructions {
+			r.insert(MAX_INSTRUCTIONS.to_vec(), max_instructions.encode());
+		}
 		Ok(r)
 	}
 }
@@ -52,9 +52,8 @@ extern crate substrate_runtime_staking as staking;
 use rstd::prelude::*;
 use rstd::marker::PhantomData;
 use rstd::result;
-use runtime_support::StorageValue;
 use primitives::traits::{self, Header, Zero, One, Checkable, Applyable, CheckEqual, Executable,
-	MakePayment, Hash, AuxLookup};
+	MakePayment, AuxLookup};
 use codec::{Codec, Encode};
 use system::extrinsics_root;
 use primitives::{ApplyOutcome, ApplyError};
@@ -123,6 +122,7 @@ impl<
 		// execute transactions
 		let (header, extrinsics) = block.deconstruct();
 		extrinsics.into_iter().for_each(Self::apply_extrinsic_no_note);
+		<system::Module<System>>::note_finished_extrinsics();
 
 		// post-transactional book-keeping.
 		Finalisation::execute();
@@ -134,6 +134,7 @@ impl<
 	/// Finalise the block - it is up the caller to ensure that all header fields are valid
 	/// except state-root.
 	pub fn finalise_block() -> System::Header {
+		<system::Module<System>>::note_finished_extrinsics();
 		Finalisation::execute();
 
 		// setup extrinsics
@@ -194,22 +195,22 @@ impl<
 		// decode parameters and dispatch
 		let r = xt.apply();
 
-		<system::ExtrinsicIndex<System>>::put(<system::ExtrinsicIndex<System>>::get() + 1u32);
+		<system::Module<System>>::note_applied_extrinsic();
 
 		r.map(|_| internal::ApplyOutcome::Success).or_else(|e| Ok(internal::ApplyOutcome::Fail(e)))
 	}
 
 	fn final_checks(header: &System::Header) {
-		// check digest
-		assert!(header.digest() == &<system::Module<System>>::digest());
-
 		// remove temporaries.
-		<system::Module<System>>::finalise();
+		let new_header = <system::Module<System>>::finalise();
+
+		// check digest, including the changes trie root.
+		assert!(header.digest() == new_header.digest());
 
 		// check storage root.
-		let storage_root = System::Hashing::storage_root();
-		header.state_root().check_equal(&storage_root);
-		assert!(header.state_root() == &storage_root, "Storage root must match that calculated.");
+		let storage_root = new_header.state_root();
+		header.state_root().check_equal(storage_root);
+		assert!(header.state_root() == storage_root, "Storage root must match that calculated.");
 	}
 }
 
@@ -270,7 +271,7 @@ mod tests {
 
 	#[test]
 	fn staking_balance_transfer_dispatch_works() {
-		let mut t = system::GenesisConfig::<Test>::default().build_storage().unwrap();
+		let mut t: runtime_io::TestExternalities = system::GenesisConfig::<Test>::default().build_storage().unwrap().into();
 		t.extend(staking::GenesisConfig::<Test> {
 			sessions_per_era: 0,
 			current_era: 0,
@@ -298,7 +299,7 @@ mod tests {
 	}
 
 	fn new_test_ext() -> runtime_io::TestExternalities {
-		let mut t = system::GenesisConfig::<Test>::default().build_storage().unwrap();
+		let mut t: runtime_io::TestExternalities = system::GenesisConfig::<Test>::default().build_storage().unwrap().into();
 		t.extend(consensus::GenesisConfig::<Test>::default().build_storage().unwrap());
 		t.extend(session::GenesisConfig::<Test>::default().build_storage().unwrap());
 		t.extend(staking::GenesisConfig::<Test>::default().build_storage().unwrap());
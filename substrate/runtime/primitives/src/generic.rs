@@ -22,6 +22,9 @@ use std::fmt;
 #[cfg(feature = "std")]
 use serde::{Deserialize, Deserializer};
 
+#[cfg(feature = "std")]
+use substrate_primitives::bytes;
+
 use rstd::prelude::*;
 use codec::{Decode, Encode, Codec, Input, Output};
 use runtime_support::AuxDispatchable;
@@ -234,6 +237,92 @@ where
 	}
 }
 
+/// A log of a block digest. Logs are encoded as opaque byte vectors, the same way in the codec and
+/// in JSON, with the commitment to the root of the changes trie marked by a reserved prefix.
+#[derive(PartialEq, Eq, Clone)]
+#[cfg_attr(feature = "std", derive(Debug))]
+pub enum DigestItem {
+	/// Commitment to the root of the changes trie of the block.
+	ChangesTrieRoot([u8; 32]),
+	/// Any other log, which is opaque to the client.
+	Other(Vec<u8>),
+}
+
+/// Prefix of the logs committing to the root of a changes trie.
+pub const CHANGES_TRIE_ROOT_LOG_PREFIX: &'static [u8] = b":changes_trie_root:";
+
+impl DigestItem {
+	fn from_raw(log: Vec<u8>) -> Self {
+		let prefix_len = CHANGES_TRIE_ROOT_LOG_PREFIX.len();
+		if log.len() == prefix_len + 32 && log.starts_with(CHANGES_TRIE_ROOT_LOG_PREFIX) {
+			let mut root = [0u8; 32];
+			root.copy_from_slice(&log[prefix_len..]);
+			DigestItem::ChangesTrieRoot(root)
+		} else {
+			DigestItem::Other(log)
+		}
+	}
+
+	fn to_raw(&self) -> Vec<u8> {
+		match *self {
+			DigestItem::ChangesTrieRoot(ref root) => {
+				let mut log = CHANGES_TRIE_ROOT_LOG_PREFIX.to_vec();
+				log.extend_from_slice(&root[..]);
+				log
+			},
+			DigestItem::Other(ref log) => log.clone(),
+		}
+	}
+}
+
+impl Default for DigestItem {
+	fn default() -> Self {
+		DigestItem::Other(Vec::new())
+	}
+}
+
+impl Decode for DigestItem {
+	fn decode<I: Input>(input: &mut I) -> Option<Self> {
+		Vec::<u8>::decode(input).map(DigestItem::from_raw)
+	}
+}
+
+impl Encode for DigestItem {
+	fn encode_to<T: Output>(&self, dest: &mut T) {
+		match *self {
+			DigestItem::Other(ref log) => log.encode_to(dest),
+			DigestItem::ChangesTrieRoot(_) => self.to_raw().encode_to(dest),
+		}
+	}
+}
+
+#[cfg(feature = "std")]
+impl ::serde::Serialize for DigestItem {
+	fn serialize<S: ::serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+		bytes::serialize(&self.to_raw(), serializer)
+	}
+}
+
+#[cfg(feature = "std")]
+impl<'a> Deserialize<'a> for DigestItem {
+	fn deserialize<D: Deserializer<'a>>(de: D) -> Result<Self, D::Error> {
+		bytes::deserialize(de).map(DigestItem::from_raw)
+	}
+}
+
+impl traits::DigestItem for DigestItem {
+	fn from_changes_trie_root(root: [u8; 32]) -> Option<Self> {
+		Some(DigestItem::ChangesTrieRoot(root))
+	}
+
+	fn as_changes_trie_root(&self) -> Option<[u8; 32]> {
+		match *self {
+			DigestItem::ChangesTrieRoot(root) => Some(root),
+			DigestItem::Other(_) => None,
+		}
+	}
+}
+
 #[derive(Default, PartialEq, Eq, Clone)]
 #[cfg_attr(feature = "std", derive(Debug, Serialize, Deserialize))]
 pub struct Digest<Item> {
@@ -253,9 +342,12 @@ impl<Item: Encode> Encode for Digest<Item> {
 }
 
 impl<Item> traits::Digest for Digest<Item> where
-	Item: Member + Default + Codec
+	Item: traits::DigestItem + Default + Codec
 {
 	type Item = Item;
+	fn logs(&self) -> &[Self::Item] {
+		&self.logs
+	}
 	fn push(&mut self, item: Self::Item) {
 		self.logs.push(item);
 	}
@@ -354,7 +446,7 @@ impl<Number, Hash, DigestItem> Encode for Header<Number, Hash, DigestItem> where
 impl<Number, Hash, DigestItem> traits::Header for Header<Number, Hash, DigestItem> where
 	Number: Member + ::rstd::hash::Hash + Copy + Codec + MaybeDisplay + SimpleArithmetic + Codec,
 	Hash: HashT,
-	DigestItem: Member + Default + Codec,
+	DigestItem: traits::DigestItem + Default + Codec,
 	Hash::Output: Default + ::rstd::hash::Hash + Copy + Member + MaybeDisplay + SimpleBitOps + Codec,
  {
 	type Number = Number;
@@ -393,7 +485,7 @@ impl<Number, Hash, DigestItem> traits::Header for Header<Number, Hash, DigestIte
 impl<Number, Hash, DigestItem> Header<Number, Hash, DigestItem> where
 	Number: Member + ::rstd::hash::Hash + Copy + Codec + MaybeDisplay + SimpleArithmetic + Codec,
 	Hash: HashT,
-	DigestItem: Member + Default + Codec,
+	DigestItem: traits::DigestItem + Default + Codec,
 	Hash::Output: Default + ::rstd::hash::Hash + Copy + Member + MaybeDisplay + SimpleBitOps + Codec,
  {
 	/// Convenience helper for computing the hash of the header without having
@@ -523,13 +615,26 @@ impl<Header: Encode, Extrinsic: Encode, Hash: Encode> Encode
 mod tests {
 	use codec::{Decode, Encode};
 	use substrate_primitives::{H256, H512};
-	use super::{Digest, Header, UncheckedExtrinsic, Extrinsic};
+	use super::{Digest, DigestItem, Header, UncheckedExtrinsic, Extrinsic};
 
 	type Block = super::Block<
-		Header<u64, ::traits::BlakeTwo256, Vec<u8>>,
+		Header<u64, ::traits::BlakeTwo256, DigestItem>,
 		UncheckedExtrinsic<H256, u64, u64, ::Ed25519Signature>,
 	>;
 
+	#[test]
+	fn digest_items_are_encoded_as_opaque_logs() {
+		let other = DigestItem::Other(vec![1]);
+		assert_eq!(other.encode(), vec![1u8].encode());
+		assert_eq!(::serde_json::to_string(&other).unwrap(), r#""0x01""#);
+
+		let changes_trie_root = DigestItem::ChangesTrieRoot([4u8; 32]);
+		let mut log = super::CHANGES_TRIE_ROOT_LOG_PREFIX.to_vec();
+		log.extend_from_slice(&[4u8; 32]);
+		assert_eq!(changes_trie_root.encode(), log.encode());
+		assert_eq!(DigestItem::decode(&mut &log.encode()[..]), Some(changes_trie_root));
+	}
+
 	#[test]
 	fn block_roundtrip_serialization() {
 		let block: Block = Block {
@@ -538,7 +643,7 @@ mod tests {
 				number: 100_000,
 				state_root: [1u8; 32].into(),
 				extrinsics_root: [2u8; 32].into(),
-				digest: Digest { logs: vec![vec![1, 2, 3], vec![4, 5, 6]] },
+				digest: Digest { logs: vec![DigestItem::Other(vec![1, 2, 3]), DigestItem::ChangesTrieRoot([4u8; 32])] },
 			},
 			extrinsics: vec![
 				UncheckedExtrinsic::new(
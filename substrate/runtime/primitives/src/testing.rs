@@ -41,8 +41,16 @@ impl Encode for Digest {
 	}
 }
 
+impl traits::DigestItem for u64 {
+	fn from_changes_trie_root(_root: [u8; 32]) -> Option<Self> { None }
+	fn as_changes_trie_root(&self) -> Option<[u8; 32]> { None }
+}
+
 impl traits::Digest for Digest {
 	type Item = u64;
+	fn logs(&self) -> &[Self::Item] {
+		&self.logs
+	}
 	fn push(&mut self, item: Self::Item) {
 		self.logs.push(item);
 	}
@@ -309,8 +309,23 @@ impl<T: Send + Sync + Sized + MaybeSerializeDebug + Eq + PartialEq + Clone + 'st
 /// Something that acts like a `Digest` - it can have `Log`s `push`ed onto it and these `Log`s are
 /// each `Codec`.
 pub trait Digest {
-	type Item: Member;
+	type Item: DigestItem;
+	fn logs(&self) -> &[Self::Item];
 	fn push(&mut self, item: Self::Item);
+
+	/// The root of the changes trie committed to by one of the logs, if any.
+	fn changes_trie_root(&self) -> Option<[u8; 32]> {
+		self.logs().iter().filter_map(DigestItem::as_changes_trie_root).next()
+	}
+}
+
+/// A single `Log` of a `Digest`. Some logs have a meaning to the client; item types that can't
+/// express them simply return `None`.
+pub trait DigestItem: Member {
+	/// Create the log committing to the changes trie with given root.
+	fn from_changes_trie_root(root: [u8; 32]) -> Option<Self>;
+	/// The root of the changes trie this log commits to, if any.
+	fn as_changes_trie_root(&self) -> Option<[u8; 32]>;
 }
 
 /// Something which fulfills the abstract idea of a Substrate header. It has types for a `Number`,
@@ -322,7 +337,7 @@ pub trait Header: Clone + Send + Sync + Codec + Eq + MaybeSerializeDebug + 'stat
 	type Number: Member + ::rstd::hash::Hash + Copy + MaybeDisplay + SimpleArithmetic + Codec;
 	type Hash: Member + ::rstd::hash::Hash + Copy + MaybeDisplay + Default + SimpleBitOps + Codec + AsRef<[u8]>;
 	type Hashing: Hash<Output = Self::Hash>;
-	type Digest: Member + Default;
+	type Digest: Member + Default + Digest;
 
 	fn new(
 		number: Self::Number,
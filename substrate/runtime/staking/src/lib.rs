@@ -37,6 +37,7 @@ extern crate substrate_runtime_std as rstd;
 extern crate substrate_codec as codec;
 extern crate substrate_primitives;
 extern crate substrate_runtime_contract as contract;
+#[macro_use]
 extern crate substrate_runtime_io as runtime_io;
 extern crate substrate_runtime_primitives as primitives;
 extern crate substrate_runtime_consensus as consensus;
@@ -239,6 +240,8 @@ decl_storage! {
 /// The storage items associated with an account/key.
 ///
 /// TODO: keys should also be able to take AsRef<KeyType> to ensure Vec<u8>s can be passed as &[u8]
+/// TODO: keep the storage of each contract in a child trie, so that it can be killed without a
+/// prefix walk. This changes the storage layout of existing contracts and needs a migration.
 pub(crate) struct StorageOf<T>(::rstd::marker::PhantomData<T>);
 impl<T: Trait> double_map::StorageDoubleMap for StorageOf<T> {
 	type Key1 = T::AccountId;
@@ -577,6 +580,8 @@ impl<T: Trait> Module<T> {
 		} else {
 			// slash
 			let early_era_slash = Self::early_era_slash();
+			runtime_info!(target: "staking", "Session ended early, slashing {} validators",
+				<session::Module<T>>::validators().len());
 			for v in <session::Module<T>>::validators().iter() {
 				if let Some(rem) = Self::slash(v, early_era_slash) {
 					let noms = Self::current_nominators_for(v);
@@ -643,6 +648,7 @@ impl<T: Trait> Module<T> {
 		for v in vals.iter() {
 			<CurrentNominatorsFor<T>>::insert(v, Self::nominators_for(v));
 		}
+		runtime_debug!(target: "staking", "New era with {} validators", vals.len());
 		<session::Module<T>>::set_validators(vals);
 	}
 
@@ -60,7 +60,7 @@ impl Trait for Test {
 }
 
 pub fn new_test_ext(ext_deposit: u64, session_length: u64, sessions_per_era: u64, current_era: u64, monied: bool, reward: u64) -> runtime_io::TestExternalities {
-	let mut t = system::GenesisConfig::<Test>::default().build_storage().unwrap();
+	let mut t: runtime_io::TestExternalities = system::GenesisConfig::<Test>::default().build_storage().unwrap().into();
 	let balance_factor = if ext_deposit > 0 {
 		256
 	} else {
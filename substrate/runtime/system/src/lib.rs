@@ -34,6 +34,7 @@ extern crate serde_derive;
 
 extern crate substrate_runtime_io as runtime_io;
 extern crate substrate_codec as codec;
+extern crate substrate_primitives;
 extern crate substrate_runtime_primitives as primitives;
 extern crate safe_mix;
 
@@ -41,6 +42,8 @@ use rstd::prelude::*;
 use primitives::traits::{self, CheckEqual, SimpleArithmetic, SimpleBitOps, Zero, One, Bounded,
 	Hash, Member, MaybeDisplay};
 use runtime_support::{StorageValue, StorageMap, Parameter};
+use runtime_support::storage::unhashed;
+use substrate_primitives::storage::EXTRINSIC_INDEX;
 use safe_mix::TripletMix;
 
 #[cfg(any(feature = "std", test))]
@@ -80,13 +83,19 @@ decl_module! {
 	pub struct Module<T: Trait>;
 }
 
+/// Storage key of the extrinsic index before it moved to the well-known `:extrinsic_index`. It is
+/// still set in the genesis state and cleared by the first block, so that the state of existing
+/// chains is unchanged.
+const LEGACY_EXTRINSIC_INDEX: &[u8] = b"sys:xti";
+
 decl_storage! {
 	trait Store for Module<T: Trait>;
 
 	pub AccountNonce get(account_nonce): b"sys:non" => default map [ T::AccountId => T::Index ];
 	pub BlockHash get(block_hash): b"sys:old" => required map [ T::BlockNumber => T::Hash ];
 
-	pub ExtrinsicIndex get(extrinsic_index): b"sys:xti" => required u32;
+	// The number of extrinsics applied in the current block. Set once all of them are applied.
+	ExtrinsicCount: b"sys:xtc" => default u32;
 	pub ExtrinsicData get(extrinsic_data): b"sys:xtd" => required map [ u32 => Vec<u8> ];
 	RandomSeed get(random_seed): b"sys:rnd" => required T::Hash;
 	// The current block number being processed. Set by `execute_block`.
@@ -105,19 +114,27 @@ impl<T: Trait> Module<T> {
 		<BlockHash<T>>::insert(*number - One::one(), parent_hash);
 		<ExtrinsicsRoot<T>>::put(txs_root);
 		<RandomSeed<T>>::put(Self::calculate_random());
-		<ExtrinsicIndex<T>>::put(0);
+		unhashed::put(EXTRINSIC_INDEX, &0u32);
 	}
 
-	/// Remove temporary "environment" entries in storage.
+	/// Remove temporary "environment" entries in storage. If changes tries are enabled, the root
+	/// of the block's changes trie is committed to in the digest.
 	pub fn finalise() -> T::Header {
 		<RandomSeed<T>>::kill();
-		<ExtrinsicIndex<T>>::kill();
+		<ExtrinsicCount<T>>::kill();
+		unhashed::kill(EXTRINSIC_INDEX);
+		runtime_support::storage::kill(LEGACY_EXTRINSIC_INDEX);
 
 		let number = <Number<T>>::take();
 		let parent_hash = <ParentHash<T>>::take();
-		let digest = <Digest<T>>::take();
+		let mut digest = <Digest<T>>::take();
 		let extrinsics_root = <ExtrinsicsRoot<T>>::take();
 		let storage_root = T::Hashing::storage_root();
+		if let Some(changes_root) = runtime_io::storage_changes_root() {
+			if let Some(item) = traits::DigestItem::from_changes_trie_root(changes_root) {
+				traits::Digest::push(&mut digest, item);
+			}
+		}
 		<T::Header as traits::Header>::new(number, extrinsics_root, storage_root, parent_hash, digest)
 	}
 
@@ -172,6 +189,23 @@ impl<T: Trait> Module<T> {
 		<RandomSeed<T>>::put(seed);
 	}
 
+	/// Gets the index of the extrinsic that is currently being applied.
+	pub fn extrinsic_index() -> u32 {
+		unhashed::get_or_default(EXTRINSIC_INDEX)
+	}
+
+	/// Note that the current extrinsic has been applied; subsequent changes belong to the next one.
+	pub fn note_applied_extrinsic() {
+		unhashed::put(EXTRINSIC_INDEX, &(Self::extrinsic_index() + 1u32));
+	}
+
+	/// Note that all extrinsics of the block have been applied; subsequent changes don't belong
+	/// to any extrinsic.
+	pub fn note_finished_extrinsics() {
+		let extrinsic_count: u32 = unhashed::take_or_default(EXTRINSIC_INDEX);
+		<ExtrinsicCount<T>>::put(extrinsic_count);
+	}
+
 	/// Increment a particular account's nonce by 1.
 	pub fn inc_account_nonce(who: &T::AccountId) {
 		<AccountNonce<T>>::insert(who, Self::account_nonce(who) + T::Index::one());
@@ -183,9 +217,10 @@ impl<T: Trait> Module<T> {
 		<ExtrinsicData<T>>::insert(Self::extrinsic_index(), encoded_xt);
 	}
 
-	/// Remove all extrinsics data and save the extrinsics trie root.
+	/// Remove all extrinsics data and save the extrinsics trie root. Must be called after
+	/// `note_finished_extrinsics`.
 	pub fn derive_extrinsics() {
-		let extrinsics = (0..Self::extrinsic_index()).map(<ExtrinsicData<T>>::take).collect();
+		let extrinsics = (0..<ExtrinsicCount<T>>::get()).map(<ExtrinsicData<T>>::take).collect();
 		let xts_root = extrinsics_data_root::<T::Hashing>(extrinsics);
 		<ExtrinsicsRoot<T>>::put(xts_root);
 	}
@@ -195,27 +230,42 @@ impl<T: Trait> Module<T> {
 #[derive(Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 #[serde(deny_unknown_fields)]
-pub struct GenesisConfig<T: Trait>(PhantomData<T>);
+pub struct GenesisConfig<T: Trait> {
+	/// Whether to build the changes trie of every block, so that the blocks in which storage keys
+	/// changed can be queried.
+	#[serde(default)]
+	pub changes_trie: bool,
+	#[serde(skip)]
+	pub _phantom: PhantomData<T>,
+}
 
 #[cfg(any(feature = "std", test))]
 impl<T: Trait> Default for GenesisConfig<T> {
 	fn default() -> Self {
-		GenesisConfig(PhantomData)
+		GenesisConfig {
+			changes_trie: false,
+			_phantom: PhantomData,
+		}
 	}
 }
 
 #[cfg(any(feature = "std", test))]
 impl<T: Trait> primitives::BuildStorage for GenesisConfig<T>
 {
-	fn build_storage(self) -> Result<runtime_io::TestExternalities, String> {
+	fn build_storage(self) -> Result<primitives::StorageMap, String> {
 		use codec::Encode;
+		use substrate_primitives::storage::CHANGES_TRIE_ENABLED;
 
-		Ok(map![
+		let mut storage: primitives::StorageMap = map![
 			Self::hash(&<BlockHash<T>>::key_for(T::BlockNumber::zero())).to_vec() => [69u8; 32].encode(),
 			Self::hash(<Number<T>>::key()).to_vec() => 1u64.encode(),
 			Self::hash(<ParentHash<T>>::key()).to_vec() => [69u8; 32].encode(),
 			Self::hash(<RandomSeed<T>>::key()).to_vec() => [0u8; 32].encode(),
-			Self::hash(<ExtrinsicIndex<T>>::key()).to_vec() => [0u8; 4].encode()
-		])
+			Self::hash(LEGACY_EXTRINSIC_INDEX).to_vec() => [0u8; 4].encode()
+		];
+		if self.changes_trie {
+			storage.insert(CHANGES_TRIE_ENABLED.to_vec(), vec![1]);
+		}
+		Ok(storage)
 	}
 }
@@ -171,7 +171,7 @@ mod tests {
 
 	#[test]
 	fn timestamp_works() {
-		let mut t = system::GenesisConfig::<Test>::default().build_storage().unwrap();
+		let mut t: runtime_io::TestExternalities = system::GenesisConfig::<Test>::default().build_storage().unwrap().into();
 		t.extend(GenesisConfig::<Test> { period: 0 }.build_storage().unwrap());
 
 		with_externalities(&mut t, || {
@@ -184,7 +184,7 @@ mod tests {
 	#[test]
 	#[should_panic(expected = "Timestamp must be updated only once in the block")]
 	fn double_timestamp_should_fail() {
-		let mut t = system::GenesisConfig::<Test>::default().build_storage().unwrap();
+		let mut t: runtime_io::TestExternalities = system::GenesisConfig::<Test>::default().build_storage().unwrap().into();
 		t.extend(GenesisConfig::<Test> { period: 5 }.build_storage().unwrap());
 
 		with_externalities(&mut t, || {
@@ -197,7 +197,7 @@ mod tests {
 	#[test]
 	#[should_panic(expected = "Timestamp but increment by at least <BlockPeriod> between sequential blocks")]
 	fn block_period_is_enforced() {
-		let mut t = system::GenesisConfig::<Test>::default().build_storage().unwrap();
+		let mut t: runtime_io::TestExternalities = system::GenesisConfig::<Test>::default().build_storage().unwrap().into();
 		t.extend(GenesisConfig::<Test> { period: 5 }.build_storage().unwrap());
 
 		with_externalities(&mut t, || {
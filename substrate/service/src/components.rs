@@ -194,8 +194,17 @@ impl<Factory: ServiceFactory> Components for FullComponents<Factory> {
 			cache_size: None,
 			path: config.database_path.as_str().into(),
 			pruning: config.pruning.clone(),
+			state_cache_size: config.state_cache_size,
+			backend: config.database_backend,
 		};
-		Ok((Arc::new(client_db::new_client(db_settings, executor, &config.chain_spec, config.execution_strategy)?), None))
+		let call_executor = executor.clone().with_max_instructions(config.max_call_instructions);
+		Ok((Arc::new(client_db::new_client(
+			db_settings,
+			executor,
+			call_executor,
+			&config.chain_spec,
+			config.execution_strategy,
+		)?), None))
 	}
 
 	fn build_extrinsic_pool(config: ExtrinsicPoolOptions, client: Arc<ComponentClient<Self>>)
@@ -230,6 +239,8 @@ impl<Factory: ServiceFactory> Components for LightComponents<Factory> {
 			cache_size: None,
 			path: config.database_path.as_str().into(),
 			pruning: config.pruning.clone(),
+			state_cache_size: config.state_cache_size,
+			backend: config.database_backend,
 		};
 		let db_storage = client_db::light::LightStorage::new(db_settings)?;
 		let light_blockchain = client::light::new_light_blockchain(db_storage);
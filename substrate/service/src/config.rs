@@ -21,7 +21,7 @@ use chain_spec::ChainSpec;
 pub use client::ExecutionStrategy;
 pub use network::Roles;
 pub use network::NetworkConfiguration;
-pub use client_db::PruningMode;
+pub use client_db::{DatabaseBackend, PruningConstraints, PruningMode};
 use runtime_primitives::BuildStorage;
 use serde::{Serialize, de::DeserializeOwned};
 
@@ -37,8 +37,12 @@ pub struct Configuration<C, G: Serialize + DeserializeOwned + BuildStorage> {
 	pub keystore_path: String,
 	/// Path to the database.
 	pub database_path: String,
+	/// Key-value store backing the database.
+	pub database_backend: DatabaseBackend,
 	/// Pruning settings.
 	pub pruning: PruningMode,
+	/// Size of the state cache in bytes.
+	pub state_cache_size: usize,
 	/// Additional key seeds.
 	pub keys: Vec<String>,
 	/// Chain configuration.
@@ -55,6 +59,11 @@ pub struct Configuration<C, G: Serialize + DeserializeOwned + BuildStorage> {
 	pub min_heap_pages: usize,
 	/// Maximum number of heap pages to allocate for Wasm execution.
 	pub max_heap_pages: usize,
+	/// Maximum number of instructions executed by a single Wasm call outside of block
+	/// execution, e.g. made over RPC.
+	pub max_call_instructions: u64,
+	/// Log the host function calls made by the runtime.
+	pub trace_runtime: bool,
 }
 
 impl<C: Default, G: Serialize + DeserializeOwned + BuildStorage> Configuration<C, G> {
@@ -68,13 +77,17 @@ impl<C: Default, G: Serialize + DeserializeOwned + BuildStorage> Configuration<C
 			network: Default::default(),
 			keystore_path: Default::default(),
 			database_path: Default::default(),
+			database_backend: Default::default(),
 			keys: Default::default(),
 			custom: Default::default(),
 			telemetry: Default::default(),
 			pruning: PruningMode::ArchiveAll,
+			state_cache_size: 16 * 1024 * 1024,
 			execution_strategy: ExecutionStrategy::Both,
 			min_heap_pages: 8,
 			max_heap_pages: 1024,
+			max_call_instructions: 1_000_000_000,
+			trace_runtime: false,
 		};
 		configuration.network.boot_nodes = configuration.chain_spec.boot_nodes().to_vec();
 		configuration
@@ -23,6 +23,7 @@ extern crate futures;
 extern crate exit_future;
 extern crate serde;
 extern crate serde_json;
+extern crate substrate_codec as codec;
 extern crate substrate_keystore as keystore;
 extern crate substrate_primitives as primitives;
 extern crate substrate_runtime_primitives as runtime_primitives;
@@ -48,19 +49,22 @@ mod components;
 mod error;
 mod config;
 mod chain_spec;
+mod offchain;
 
 use std::sync::Arc;
 use futures::prelude::*;
 use keystore::Store as Keystore;
 use client::BlockchainEvents;
-use network::ManageNetwork;
+use client::backend::Backend as ClientBackend;
+use network::{ManageNetwork, SyncProvider, TransactionPool};
 use runtime_primitives::traits::{Header, As};
+use runtime_primitives::generic::BlockId;
 use exit_future::Signal;
 use tokio::runtime::TaskExecutor;
 use substrate_executor::NativeExecutor;
 
 pub use self::error::{ErrorKind, Error};
-pub use config::{Configuration, Roles, PruningMode};
+pub use config::{Configuration, Roles, DatabaseBackend, PruningConstraints, PruningMode};
 pub use chain_spec::ChainSpec;
 pub use extrinsic_pool::txpool::{Options as ExtrinsicPoolOptions};
 pub use extrinsic_pool::api::{ExtrinsicPool as ExtrinsicPoolApi};
@@ -77,6 +81,7 @@ pub struct Service<Components: components::Components> {
 	network: Arc<components::NetworkService<Components::Factory>>,
 	extrinsic_pool: Arc<Components::ExtrinsicPool>,
 	keystore: Keystore,
+	should_have_peers: bool,
 	signal: Option<Signal>,
 }
 
@@ -84,7 +89,8 @@ pub struct Service<Components: components::Components> {
 pub fn new_client<Factory: components::ServiceFactory>(config: FactoryFullConfiguration<Factory>)
 	-> Result<Arc<ComponentClient<components::FullComponents<Factory>>>, error::Error>
 {
-	let executor = NativeExecutor::with_heap_pages(config.min_heap_pages, config.max_heap_pages);
+	let executor = NativeExecutor::with_heap_pages(config.min_heap_pages, config.max_heap_pages)
+		.with_tracing(config.trace_runtime);
 	let (client, _) = components::FullComponents::<Factory>::build_client(
 		&config,
 		executor,
@@ -106,7 +112,8 @@ impl<Components> Service<Components>
 		let (signal, exit) = ::exit_future::signal();
 
 		// Create client
-		let executor = NativeExecutor::with_heap_pages(config.min_heap_pages, config.max_heap_pages);
+		let executor = NativeExecutor::with_heap_pages(config.min_heap_pages, config.max_heap_pages)
+				.with_tracing(config.trace_runtime);
 
 		let mut keystore = Keystore::open(config.keystore_path.as_str().into())?;
 		for seed in &config.keys {
@@ -130,6 +137,8 @@ impl<Components> Service<Components>
 		);
 		let extrinsic_pool_adapter = extrinsic_pool.clone();
 
+		let offchain_worker = config.roles.contains(Roles::AUTHORITY);
+		let should_have_peers = !config.network.boot_nodes.is_empty() || !config.network.reserved_nodes.is_empty();
 		let network_params = network::Params {
 			config: network::ProtocolConfig {
 				roles: config.roles,
@@ -151,9 +160,20 @@ impl<Components> Service<Components>
 			// block notifications
 			let network = network.clone();
 			let txpool = extrinsic_pool.clone();
+			let events_client = client.clone();
 
 			let events = client.import_notification_stream()
 				.for_each(move |notification| {
+					// extrinsics of retracted blocks are no longer included in the chain
+					for retracted in &notification.retracted {
+						match events_client.body(&BlockId::hash(*retracted)) {
+							Ok(Some(extrinsics)) => for extrinsic in &extrinsics {
+								txpool.import(extrinsic);
+							},
+							Ok(None) => {},
+							Err(e) => warn!("Error reading retracted block {}: {:?}", retracted, e),
+						}
+					}
 					network.on_block_imported(notification.hash, &notification.header);
 					txpool.prune_imported(&notification.hash);
 					Ok(())
@@ -163,6 +183,36 @@ impl<Components> Service<Components>
 			task_executor.spawn(events);
 		}
 
+		if offchain_worker {
+			if let Some(storage) = client.backend().offchain_storage() {
+				// offchain worker, run on a dedicated thread so that it doesn't hold up the
+				// processing of notifications
+				let (sender, receiver) = ::std::sync::mpsc::channel();
+				let txpool = extrinsic_pool.clone();
+				let offchain_client = client.clone();
+				::std::thread::Builder::new().name("offchain-worker".into()).spawn(move || {
+					// of the blocks which were finalized while it was running, only the last one is processed
+					while let Ok(mut hash) = receiver.recv() {
+						while let Ok(next) = receiver.try_recv() {
+							hash = next;
+						}
+						let mut context = offchain::OffchainContext::<ComponentBlock<Components>, _, _>::new(&*txpool, &storage);
+						if let Err(e) = offchain_client.offchain_worker(&BlockId::hash(hash), &mut context) {
+							warn!("Offchain worker failed at block {}: {:?}", hash, e);
+						}
+					}
+				}).map_err(|e| format!("Error spawning the offchain worker thread: {}", e))?;
+
+				let network = network.clone();
+				let events = client.finality_notification_stream()
+					.filter(move |_| network.status().sync.state != network::SyncState::Downloading)
+					.for_each(move |notification| sender.send(notification.hash).map_err(|_| ()))
+					.select(exit.clone())
+					.then(|_| Ok(()));
+				task_executor.spawn(events);
+			}
+		}
+
 		{
 			// extrinsic notifications
 			let network = network.clone();
@@ -184,6 +234,7 @@ impl<Components> Service<Components>
 			extrinsic_pool: extrinsic_pool,
 			signal: Some(signal),
 			keystore: keystore,
+			should_have_peers: should_have_peers,
 		})
 	}
 
@@ -197,6 +248,12 @@ impl<Components> Service<Components>
 		self.network.clone()
 	}
 
+	/// Whether the node is expected to be connected to other nodes, i.e. it has been given
+	/// any boot or reserved nodes to connect to.
+	pub fn should_have_peers(&self) -> bool {
+		self.should_have_peers
+	}
+
 	/// Get shared extrinsic pool instance.
 	pub fn extrinsic_pool(&self) -> Arc<PoolApi<Components>> {
 		self.extrinsic_pool.api()
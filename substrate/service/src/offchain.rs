@@ -0,0 +1,71 @@
+// Copyright 2018 Parity Technologies (UK) Ltd.
+// This file is part of Substrate.
+
+// Substrate is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// Substrate is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with Substrate.  If not, see <http://www.gnu.org/licenses/>.
+
+//! Node-local capabilities of the offchain worker, which the service runs after each block
+//! is finalized.
+
+use std::marker::PhantomData;
+use client::OffchainExt;
+use client::backend::OffchainStorage;
+use codec::Decode;
+use network::TransactionPool;
+use runtime_primitives::traits::Block as BlockT;
+
+/// Gives the offchain worker access to the local extrinsic pool and to the node-local storage.
+pub struct OffchainContext<'a, Block, P: 'a, S: 'a> {
+	pool: &'a P,
+	storage: &'a S,
+	_block: PhantomData<Block>,
+}
+
+impl<'a, Block, P, S> OffchainContext<'a, Block, P, S> {
+	/// Create a new context submitting extrinsics to `pool` and storing data in `storage`.
+	pub fn new(pool: &'a P, storage: &'a S) -> Self {
+		OffchainContext {
+			pool,
+			storage,
+			_block: PhantomData,
+		}
+	}
+}
+
+impl<'a, Block, P, S> OffchainExt for OffchainContext<'a, Block, P, S> where
+	Block: BlockT,
+	P: TransactionPool<Block>,
+	S: OffchainStorage,
+{
+	fn submit_extrinsic(&mut self, extrinsic: Vec<u8>) {
+		match <Block::Extrinsic as Decode>::decode(&mut &extrinsic[..]) {
+			Some(extrinsic) => if self.pool.import(&extrinsic).is_none() {
+				debug!(target: "offchain", "Extrinsic submitted by the offchain worker has not been accepted");
+			},
+			None => debug!(target: "offchain", "Invalid extrinsic submitted by the offchain worker"),
+		}
+	}
+
+	fn local_storage(&self, key: &[u8]) -> Option<Vec<u8>> {
+		self.storage.get(key).unwrap_or_else(|e| {
+			warn!(target: "offchain", "Error reading the offchain storage: {:?}", e);
+			None
+		})
+	}
+
+	fn set_local_storage(&mut self, key: &[u8], value: Option<&[u8]>) {
+		if let Err(e) = self.storage.set(key, value) {
+			warn!(target: "offchain", "Error writing the offchain storage: {:?}", e);
+		}
+	}
+}
@@ -78,6 +78,9 @@ pub enum Error<E: fmt::Debug> {
 	Db(E),
 	/// `Codec` decoding error.
 	Decoding,
+	/// Trying to finalize a block which is not the next one, e.g. an unknown or already
+	/// finalized block.
+	InvalidBlock,
 }
 
 impl<E: fmt::Debug> fmt::Debug for Error<E> {
@@ -85,6 +88,7 @@ impl<E: fmt::Debug> fmt::Debug for Error<E> {
 		match self {
 			Error::Db(e) => e.fmt(f),
 			Error::Decoding => write!(f, "Error decoding slicable value"),
+			Error::InvalidBlock => write!(f, "Trying to finalize an invalid block"),
 		}
 	}
 }
@@ -111,12 +115,24 @@ pub struct CommitSet<H: Hash> {
 /// Pruning contraints. If none are specified pruning is
 #[derive(Default, Debug, Clone)]
 pub struct Constraints {
-	/// Maximum blocks. Defaults to 0 when unspecified, effectively keeping only unfinalized states.
+	/// Maximum blocks. Defaults to 0 when neither constraint is specified, effectively keeping
+	/// only unfinalized states. Unbounded when only `max_mem` is specified.
 	pub max_blocks: Option<u32>,
-	/// Maximum memory in the pruning overlay.
+	/// Maximum memory in the pruning and unfinalized overlays, in bytes. Finalized blocks are
+	/// pruned early while the limit is exceeded.
 	pub max_mem: Option<usize>,
 }
 
+impl Constraints {
+	fn max_blocks(&self) -> Option<u64> {
+		match (self.max_blocks, self.max_mem) {
+			(Some(max_blocks), _) => Some(max_blocks as u64),
+			(None, Some(_)) => None,
+			(None, None) => Some(0),
+		}
+	}
+}
+
 /// Pruning mode.
 #[derive(Debug, Clone)]
 pub enum PruningMode {
@@ -156,10 +172,6 @@ impl<BlockHash: Hash, Key: Hash> StateDbSync<BlockHash, Key> {
 		trace!("StateDb settings: {:?}", mode);
 		let unfinalized: UnfinalizedOverlay<BlockHash, Key> = UnfinalizedOverlay::new(db)?;
 		let pruning: Option<RefWindow<BlockHash, Key>> = match mode {
-			PruningMode::Constrained(Constraints {
-				max_mem: Some(_),
-				..
-			}) => unimplemented!(),
 			PruningMode::Constrained(_) => Some(RefWindow::new(db)?),
 			PruningMode::ArchiveAll | PruningMode::ArchiveCanonical => None,
 		};
@@ -193,35 +205,39 @@ impl<BlockHash: Hash, Key: Hash> StateDbSync<BlockHash, Key> {
 		}
 	}
 
-	pub fn finalize_block(&mut self, hash: &BlockHash) -> CommitSet<Key> {
+	pub fn finalize_block<E: fmt::Debug>(&mut self, hash: &BlockHash) -> Result<CommitSet<Key>, Error<E>> {
 		let mut commit = match self.mode {
 			PruningMode::ArchiveAll => {
 				CommitSet::default()
 			},
 			PruningMode::ArchiveCanonical => {
-				let mut commit = self.unfinalized.finalize(hash);
+				let mut commit = self.unfinalized.finalize(hash)?;
 				commit.data.deleted.clear();
 				commit
 			},
 			PruningMode::Constrained(_) => {
-				self.unfinalized.finalize(hash)
+				self.unfinalized.finalize(hash)?
 			},
 		};
 		if let Some(ref mut pruning) = self.pruning {
 			pruning.note_finalized(hash, &mut commit);
 		}
 		self.prune(&mut commit);
-		commit
+		Ok(commit)
 	}
 
 	fn prune(&mut self, commit: &mut CommitSet<Key>) {
+		// unfinalized states can't be discarded, but they count towards the memory limit.
+		let unfinalized_mem = self.unfinalized.mem_used();
 		if let (&mut Some(ref mut pruning), &PruningMode::Constrained(ref constraints)) = (&mut self.pruning, &self.mode) {
 			loop {
-				if pruning.window_size() <= constraints.max_blocks.unwrap_or(0) as u64 {
+				if pruning.window_size() == 0 {
 					break;
 				}
 
-				if constraints.max_mem.map_or(false, |m| pruning.mem_used() > m) {
+				let max_blocks_exceeded = constraints.max_blocks().map_or(false, |m| pruning.window_size() > m);
+				let max_mem_exceeded = constraints.max_mem.map_or(false, |m| pruning.mem_used() + unfinalized_mem > m);
+				if !max_blocks_exceeded && !max_mem_exceeded {
 					break;
 				}
 
@@ -230,11 +246,32 @@ impl<BlockHash: Hash, Key: Hash> StateDbSync<BlockHash, Key> {
 					break;
 				}
 
+				if !max_blocks_exceeded {
+					trace!(target: "state-db", "Pruning early, memory limit exceeded ({} unfinalized, {} pruning)", unfinalized_mem, pruning.mem_used());
+				}
 				pruning.prune_one(commit);
 			}
 		}
 	}
 
+	pub fn max_mem_exceeded(&self) -> bool {
+		match self.mode {
+			PruningMode::Constrained(ref constraints) => constraints.max_mem.map_or(false, |max_mem| {
+				let pruning_mem = self.pruning.as_ref().map_or(0, |pruning| pruning.mem_used());
+				self.unfinalized.mem_used() + pruning_mem > max_mem
+			}),
+			PruningMode::ArchiveAll | PruningMode::ArchiveCanonical => false,
+		}
+	}
+
+	pub fn revert_one(&mut self) -> Option<CommitSet<Key>> {
+		match self.mode {
+			// nothing is ever discarded
+			PruningMode::ArchiveAll => Some(CommitSet::default()),
+			PruningMode::ArchiveCanonical | PruningMode::Constrained(_) => self.unfinalized.revert_one(),
+		}
+	}
+
 	pub fn pin(&mut self, hash: &BlockHash) {
 		self.pinned.insert(hash.clone());
 	}
@@ -270,11 +307,30 @@ impl<BlockHash: Hash, Key: Hash> StateDb<BlockHash, Key> {
 		self.db.write().insert_block(hash, number, parent_hash, changeset)
 	}
 
-	/// Finalize a previously inserted block.
-	pub fn finalize_block(&self, hash: &BlockHash) -> CommitSet<Key> {
+	/// Finalize a previously inserted block. Fails if it is not the child of the last finalized
+	/// block.
+	pub fn finalize_block<E: fmt::Debug>(&self, hash: &BlockHash) -> Result<CommitSet<Key>, Error<E>> {
 		self.db.write().finalize_block(hash)
 	}
 
+	/// Hash and number of the last finalized block. `None` if no block has been finalized, or if
+	/// finalized blocks are not tracked in the current pruning mode.
+	pub fn last_finalized(&self) -> Option<(BlockHash, u64)> {
+		self.db.read().unfinalized.last_finalized()
+	}
+
+	/// Whether the memory used by the pruning and unfinalized overlays exceeds the `max_mem`
+	/// constraint. Pruning happens on finalization, so finalizing more blocks reduces it.
+	pub fn max_mem_exceeded(&self) -> bool {
+		self.db.read().max_mem_exceeded()
+	}
+
+	/// Revert all unfinalized blocks with the highest number. Returns `None` if there are no
+	/// unfinalized blocks left to revert.
+	pub fn revert_one(&self) -> Option<CommitSet<Key>> {
+		self.db.write().revert_one()
+	}
+
 	/// Prevents pruning of specified block and its descendants.
 	pub fn pin(&self, hash: &BlockHash) {
 		self.db.write().pin(hash)
@@ -305,10 +361,10 @@ mod tests {
 		db.commit(&state_db.insert_block(&H256::from(21), 2, &H256::from(1), make_changeset(&[21], &[921, 1])));
 		db.commit(&state_db.insert_block(&H256::from(22), 2, &H256::from(1), make_changeset(&[22], &[922])));
 		db.commit(&state_db.insert_block(&H256::from(3), 3, &H256::from(21), make_changeset(&[3], &[93])));
-		db.commit(&state_db.finalize_block(&H256::from(1)));
+		db.commit(&state_db.finalize_block::<()>(&H256::from(1)).unwrap());
 		db.commit(&state_db.insert_block(&H256::from(4), 4, &H256::from(3), make_changeset(&[4], &[94])));
-		db.commit(&state_db.finalize_block(&H256::from(21)));
-		db.commit(&state_db.finalize_block(&H256::from(3)));
+		db.commit(&state_db.finalize_block::<()>(&H256::from(21)).unwrap());
+		db.commit(&state_db.finalize_block::<()>(&H256::from(3)).unwrap());
 
 		(db, state_db)
 	}
@@ -351,4 +407,33 @@ mod tests {
 		}));
 		assert!(db.data_eq(&make_db(&[1, 21, 3, 921, 922, 93, 94])));
 	}
+
+	#[test]
+	fn prune_mem_exceeded() {
+		let (db, _) = make_test_db(PruningMode::Constrained(Constraints {
+			max_blocks: Some(2),
+			max_mem: Some(0),
+		}));
+		assert!(db.data_eq(&make_db(&[21, 3, 922, 94])));
+	}
+
+	#[test]
+	fn prune_mem_only_keeps_blocks_within_limit() {
+		let (db, _) = make_test_db(PruningMode::Constrained(Constraints {
+			max_blocks: None,
+			max_mem: Some(usize::max_value()),
+		}));
+		assert!(db.data_eq(&make_db(&[1, 21, 3, 91, 921, 922, 93, 94])));
+	}
+
+	#[test]
+	fn finalizing_reduces_exceeded_mem() {
+		let (mut db, state_db) = make_test_db(PruningMode::Constrained(Constraints {
+			max_blocks: Some(2),
+			max_mem: Some(0),
+		}));
+		assert!(state_db.max_mem_exceeded());
+		db.commit(&state_db.finalize_block::<()>(&H256::from(4)).unwrap());
+		assert!(!state_db.max_mem_exceeded());
+	}
 }
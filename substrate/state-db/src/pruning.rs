@@ -23,6 +23,7 @@
 //! The changes are journaled in the DB.
 
 use std::collections::{HashMap, HashSet, VecDeque};
+use std::mem;
 use codec::{Decode, Encode, self};
 use {CommitSet, Error, MetaDb, to_meta_key, Hash};
 
@@ -34,6 +35,8 @@ pub struct RefWindow<BlockHash: Hash, Key: Hash> {
 	death_rows: VecDeque<DeathRow<BlockHash, Key>>,
 	death_index: HashMap<Key, u64>,
 	pending_number: u64,
+	/// Estimate of the memory used by the death rows, not counting their keys, in bytes.
+	rows_mem_used: usize,
 }
 
 #[derive(Debug, PartialEq, Eq)]
@@ -84,6 +87,7 @@ impl<BlockHash: Hash, Key: Hash> RefWindow<BlockHash, Key> {
 			death_rows: Default::default(),
 			death_index: Default::default(),
 			pending_number: pending_number,
+			rows_mem_used: 0,
 		};
 		// read the journal
 		trace!(target: "state-db", "Reading pruning journal. Last pruned #{}", pending_number - 1);
@@ -115,6 +119,7 @@ impl<BlockHash: Hash, Key: Hash> RefWindow<BlockHash, Key> {
 		for k in deleted.iter() {
 			self.death_index.insert(k.clone(), imported_block);
 		}
+		self.rows_mem_used += Self::row_mem_used(&journal_key);
 		self.death_rows.push_back(
 			DeathRow {
 				hash: hash.clone(),
@@ -132,8 +137,16 @@ impl<BlockHash: Hash, Key: Hash> RefWindow<BlockHash, Key> {
 		self.death_rows.front().map(|r| r.hash.clone())
 	}
 
+	/// Estimate of the memory used by the window, in bytes.
 	pub fn mem_used(&self) -> usize {
-		0
+		let key_size = mem::size_of::<Key>();
+		// each key pending deletion is kept in its death row and in the death index
+		let keys_mem = self.death_index.len() * (2 * key_size + mem::size_of::<u64>());
+		keys_mem + self.rows_mem_used
+	}
+
+	fn row_mem_used(journal_key: &[u8]) -> usize {
+		mem::size_of::<DeathRow<BlockHash, Key>>() + journal_key.len()
 	}
 
 	/// Prune next block. Expects at least one block in the window. Adds changes to `commit`.
@@ -143,6 +156,7 @@ impl<BlockHash: Hash, Key: Hash> RefWindow<BlockHash, Key> {
 		for k in pruned.deleted.iter() {
 			self.death_index.remove(&k);
 		}
+		self.rows_mem_used -= Self::row_mem_used(&pruned.journal_key);
 		commit.data.deleted.extend(pruned.deleted.into_iter());
 		commit.meta.inserted.push((to_meta_key(LAST_PRUNED, &()), self.pending_number.encode()));
 		commit.meta.deleted.push(pruned.journal_key);
@@ -222,6 +236,25 @@ mod tests {
 		assert_eq!(pruning.pending_number, 2);
 	}
 
+	#[test]
+	fn mem_used_tracks_window() {
+		let db = make_db(&[1, 2, 3]);
+		let mut pruning: RefWindow<H256, H256> = RefWindow::new(&db).unwrap();
+		assert_eq!(pruning.mem_used(), 0);
+		let mut commit = make_commit(&[4], &[1, 2]);
+		pruning.note_finalized(&H256::random(), &mut commit);
+		let one_row = pruning.mem_used();
+		assert!(one_row > 0);
+		let mut commit = make_commit(&[5], &[3]);
+		pruning.note_finalized(&H256::random(), &mut commit);
+		assert!(pruning.mem_used() > one_row);
+
+		let mut commit = CommitSet::default();
+		pruning.prune_one(&mut commit);
+		pruning.prune_one(&mut commit);
+		assert_eq!(pruning.mem_used(), 0);
+	}
+
 	#[test]
 	fn prune_two() {
 		let mut db = make_db(&[1, 2, 3]);
@@ -18,6 +18,8 @@
 //! Maintains trees of block overlays and allows discarding trees/roots
 
 use std::collections::{HashMap, VecDeque};
+use std::fmt;
+use std::mem;
 use super::{Error, DBValue, ChangeSet, CommitSet, MetaDb, Hash, to_meta_key};
 use codec::{self, Decode, Encode};
 
@@ -29,6 +31,8 @@ pub struct UnfinalizedOverlay<BlockHash: Hash, Key: Hash> {
 	last_finalized: Option<(BlockHash, u64)>,
 	levels: VecDeque<Vec<BlockOverlay<BlockHash, Key>>>,
 	parents: HashMap<BlockHash, BlockHash>,
+	/// Estimate of the memory used by the overlays in `levels`, in bytes.
+	mem_used: usize,
 }
 
 struct JournalRecord<BlockHash: Hash, Key: Hash> {
@@ -70,6 +74,15 @@ struct BlockOverlay<BlockHash: Hash, Key: Hash> {
 	deleted: Vec<Key>,
 }
 
+impl<BlockHash: Hash, Key: Hash> BlockOverlay<BlockHash, Key> {
+	/// Estimate of the memory used by the overlay, in bytes.
+	fn mem_used(&self) -> usize {
+		let key_size = mem::size_of::<Key>();
+		let values_mem: usize = self.values.values().map(|value| key_size + value.len()).sum();
+		values_mem + self.deleted.len() * key_size + self.journal_key.len()
+	}
+}
+
 impl<BlockHash: Hash, Key: Hash> UnfinalizedOverlay<BlockHash, Key> {
 	/// Creates a new instance. Does not expect any metadata to be present in the DB.
 	pub fn new<D: MetaDb>(db: &D) -> Result<UnfinalizedOverlay<BlockHash, Key>, Error<D::Error>> {
@@ -81,6 +94,7 @@ impl<BlockHash: Hash, Key: Hash> UnfinalizedOverlay<BlockHash, Key> {
 		};
 		let mut levels = VecDeque::new();
 		let mut parents = HashMap::new();
+		let mut mem_used = 0;
 		if let Some((ref hash, mut block)) = last_finalized {
 			// read the journal
 			trace!(target: "state-db", "Reading unfinalized journal. Last finalized #{} ({:?})", block, hash);
@@ -101,6 +115,7 @@ impl<BlockHash: Hash, Key: Hash> UnfinalizedOverlay<BlockHash, Key> {
 								deleted: record.deleted,
 							};
 							trace!(target: "state-db", "Unfinalized journal entry {}.{} ({} inserted, {} deleted)", block, index, overlay.values.len(), overlay.deleted.len());
+							mem_used += overlay.mem_used();
 							level.push(overlay);
 							parents.insert(record.hash, record.parent_hash);
 							index += 1;
@@ -121,6 +136,7 @@ impl<BlockHash: Hash, Key: Hash> UnfinalizedOverlay<BlockHash, Key> {
 			last_finalized: last_finalized,
 			levels,
 			parents,
+			mem_used,
 		})
 	}
 
@@ -159,6 +175,7 @@ impl<BlockHash: Hash, Key: Hash> UnfinalizedOverlay<BlockHash, Key> {
 			values: changeset.inserted.iter().cloned().collect(),
 			deleted: changeset.deleted.clone(),
 		};
+		self.mem_used += overlay.mem_used();
 		level.push(overlay);
 		self.parents.insert(hash.clone(), parent_hash.clone());
 		let journal_record = JournalRecord {
@@ -177,6 +194,7 @@ impl<BlockHash: Hash, Key: Hash> UnfinalizedOverlay<BlockHash, Key> {
 		levels: &mut [Vec<BlockOverlay<BlockHash, Key>>],
 		parents: &mut HashMap<BlockHash, BlockHash>,
 		discarded_journals: &mut Vec<Vec<u8>>,
+		mem_used: &mut usize,
 		number: u64,
 		hash: &BlockHash,
 	) {
@@ -186,7 +204,8 @@ impl<BlockHash: Hash, Key: Hash> UnfinalizedOverlay<BlockHash, Key> {
 				if parent == *hash {
 					parents.remove(&overlay.hash);
 					discarded_journals.push(overlay.journal_key.clone());
-					Self::discard(sublevels, parents, discarded_journals, number + 1, &overlay.hash);
+					*mem_used -= overlay.mem_used();
+					Self::discard(sublevels, parents, discarded_journals, mem_used, number + 1, &overlay.hash);
 					false
 				} else {
 					true
@@ -201,16 +220,21 @@ impl<BlockHash: Hash, Key: Hash> UnfinalizedOverlay<BlockHash, Key> {
 
 	/// Select a top-level root and finalized it. Discards all sibling subtrees and the root.
 	/// Returns a set of changes that need to be added to the DB.
-	pub fn finalize(&mut self, hash: &BlockHash) -> CommitSet<Key> {
+	/// Fails if the block is not in the first level, e.g. because it is unknown or already
+	/// finalized.
+	pub fn finalize<E: fmt::Debug>(&mut self, hash: &BlockHash) -> Result<CommitSet<Key>, Error<E>> {
 		trace!(target: "state-db", "Finalizing {:?}", hash);
-		let level = self.levels.pop_front().expect("no blocks to finalize");
-		let index = level.iter().position(|overlay| overlay.hash == *hash)
-			.expect("attempting to finalize unknown block");
+		let index = match self.levels.front().and_then(|level| level.iter().position(|overlay| overlay.hash == *hash)) {
+			Some(index) => index,
+			None => return Err(Error::InvalidBlock),
+		};
+		let level = self.levels.pop_front().expect("first level contains the block, checked above; qed");
 
 		let mut commit = CommitSet::default();
 		let mut discarded_journals = Vec::new();
 		for (i, overlay) in level.into_iter().enumerate() {
 			self.parents.remove(&overlay.hash);
+			self.mem_used -= overlay.mem_used();
 			if i == index {
 				// that's the one we need to finalize
 				commit.data.inserted = overlay.values.into_iter().collect();
@@ -220,7 +244,7 @@ impl<BlockHash: Hash, Key: Hash> UnfinalizedOverlay<BlockHash, Key> {
 				// required for recursive processing. A more efficient implementaion
 				// that does not require converting to vector is possible
 				let mut vec: Vec<_> = self.levels.drain(..).collect();
-				Self::discard(&mut vec, &mut self.parents, &mut discarded_journals, 0, &overlay.hash);
+				Self::discard(&mut vec, &mut self.parents, &mut discarded_journals, &mut self.mem_used, 0, &overlay.hash);
 				self.levels.extend(vec.into_iter());
 			}
 			// cleanup journal entry
@@ -231,7 +255,32 @@ impl<BlockHash: Hash, Key: Hash> UnfinalizedOverlay<BlockHash, Key> {
 		commit.meta.inserted.push((to_meta_key(LAST_FINALIZED, &()), last_finalized.encode()));
 		self.last_finalized = Some(last_finalized);
 		trace!(target: "state-db", "Discarded {} records", commit.meta.deleted.len());
-		commit
+		Ok(commit)
+	}
+
+	/// Hash and number of the last finalized block, if any.
+	pub fn last_finalized(&self) -> Option<(BlockHash, u64)> {
+		self.last_finalized.clone()
+	}
+
+	/// Discard the most recent level, i.e. all blocks with the highest number. Returns a set of
+	/// changes that need to be added to the DB, or `None` if there are no unfinalized blocks.
+	pub fn revert_one(&mut self) -> Option<CommitSet<Key>> {
+		self.levels.pop_back().map(|level| {
+			let mut commit = CommitSet::default();
+			for overlay in level.into_iter() {
+				trace!(target: "state-db", "Reverting {:?}", overlay.hash);
+				self.mem_used -= overlay.mem_used();
+				commit.meta.deleted.push(overlay.journal_key);
+				self.parents.remove(&overlay.hash);
+			}
+			commit
+		})
+	}
+
+	/// Estimate of the memory used by the overlay, in bytes.
+	pub fn mem_used(&self) -> usize {
+		self.mem_used
 	}
 
 	/// Get a value from the node overlay. This searches in every existing changeset.
@@ -268,11 +317,10 @@ mod tests {
 	}
 
 	#[test]
-	#[should_panic]
-	fn finalize_empty_panics() {
+	fn finalize_empty_fails() {
 		let db = make_db(&[]);
 		let mut overlay = UnfinalizedOverlay::<H256, H256>::new(&db).unwrap();
-		overlay.finalize(&H256::default());
+		assert!(overlay.finalize::<()>(&H256::default()).is_err());
 	}
 
 	#[test]
@@ -309,14 +357,26 @@ mod tests {
 	}
 
 	#[test]
-	#[should_panic]
-	fn finalize_unknown_panics() {
+	fn finalize_unknown_fails() {
+		let h1 = H256::random();
+		let h2 = H256::random();
+		let db = make_db(&[]);
+		let mut overlay = UnfinalizedOverlay::<H256, H256>::new(&db).unwrap();
+		overlay.insert(&h1, 1, &H256::default(), ChangeSet::default());
+		assert!(overlay.finalize::<()>(&h2).is_err());
+	}
+
+	#[test]
+	fn finalize_finalized_fails() {
 		let h1 = H256::random();
 		let h2 = H256::random();
 		let db = make_db(&[]);
 		let mut overlay = UnfinalizedOverlay::<H256, H256>::new(&db).unwrap();
 		overlay.insert(&h1, 1, &H256::default(), ChangeSet::default());
-		overlay.finalize(&h2);
+		overlay.insert(&h2, 2, &h1, ChangeSet::default());
+		overlay.finalize::<()>(&h1).unwrap();
+		assert!(overlay.finalize::<()>(&h1).is_err());
+		assert_eq!(overlay.last_finalized(), Some((h1, 1)));
 	}
 
 	#[test]
@@ -331,7 +391,7 @@ mod tests {
 		assert_eq!(insertion.meta.inserted.len(), 2);
 		assert_eq!(insertion.meta.deleted.len(), 0);
 		db.commit(&insertion);
-		let finalization = overlay.finalize(&h1);
+		let finalization = overlay.finalize::<()>(&h1).unwrap();
 		assert_eq!(finalization.data.inserted.len(), changeset.inserted.len());
 		assert_eq!(finalization.data.deleted.len(), changeset.deleted.len());
 		assert_eq!(finalization.meta.inserted.len(), 1);
@@ -340,6 +400,42 @@ mod tests {
 		assert!(db.data_eq(&make_db(&[1, 3, 4])));
 	}
 
+	#[test]
+	fn mem_used_tracks_overlay() {
+		let h1 = H256::random();
+		let h2 = H256::random();
+		let db = make_db(&[1, 2]);
+		let mut overlay = UnfinalizedOverlay::<H256, H256>::new(&db).unwrap();
+		assert_eq!(overlay.mem_used(), 0);
+		overlay.insert(&h1, 1, &H256::default(), make_changeset(&[3, 4], &[2]));
+		let one_block = overlay.mem_used();
+		assert!(one_block > 0);
+		overlay.insert(&h2, 2, &h1, make_changeset(&[5], &[]));
+		assert!(overlay.mem_used() > one_block);
+		overlay.finalize::<()>(&h1).unwrap();
+		overlay.finalize::<()>(&h2).unwrap();
+		assert_eq!(overlay.mem_used(), 0);
+	}
+
+	#[test]
+	fn mem_used_tracks_discarded_and_reverted_blocks() {
+		let h1 = H256::random();
+		let h2 = H256::random();
+		let h3 = H256::random();
+		let mut db = make_db(&[1, 2]);
+		let mut overlay = UnfinalizedOverlay::<H256, H256>::new(&db).unwrap();
+		db.commit(&overlay.insert(&h1, 1, &H256::default(), make_changeset(&[3], &[])));
+		db.commit(&overlay.insert(&h2, 1, &H256::default(), make_changeset(&[4, 5], &[2])));
+		db.commit(&overlay.insert(&h3, 2, &h2, make_changeset(&[6], &[])));
+		let restored = UnfinalizedOverlay::<H256, H256>::new(&db).unwrap();
+		assert_eq!(restored.mem_used(), overlay.mem_used());
+
+		// reverting h3 and discarding h2 leaves no overlays
+		overlay.revert_one().unwrap();
+		overlay.finalize::<()>(&h1).unwrap();
+		assert_eq!(overlay.mem_used(), 0);
+	}
+
 	#[test]
 	fn restore_from_journal() {
 		let h1 = H256::random();
@@ -356,6 +452,39 @@ mod tests {
 		assert_eq!(overlay.last_finalized, overlay2.last_finalized);
 	}
 
+	#[test]
+	fn revert_discards_the_last_level() {
+		let h1 = H256::random();
+		let h2_1 = H256::random();
+		let h2_2 = H256::random();
+		let mut db = make_db(&[1, 2]);
+		let mut overlay = UnfinalizedOverlay::<H256, H256>::new(&db).unwrap();
+		db.commit(&overlay.insert(&h1, 1, &H256::default(), make_changeset(&[3], &[])));
+		db.commit(&overlay.insert(&h2_1, 2, &h1, make_changeset(&[4], &[])));
+		db.commit(&overlay.insert(&h2_2, 2, &h1, make_changeset(&[5], &[])));
+		assert_eq!(db.meta.len(), 4);
+
+		db.commit(&overlay.revert_one().unwrap());
+		assert!(contains(&overlay, 3));
+		assert!(!contains(&overlay, 4));
+		assert!(!contains(&overlay, 5));
+		assert_eq!(overlay.parents.len(), 1);
+		assert_eq!(db.meta.len(), 2);
+
+		let overlay2 = UnfinalizedOverlay::<H256, H256>::new(&db).unwrap();
+		assert_eq!(overlay.levels, overlay2.levels);
+		assert_eq!(overlay.parents, overlay2.parents);
+
+		// blocks can be inserted again on top of the remaining level
+		db.commit(&overlay.insert(&h2_1, 2, &h1, make_changeset(&[4], &[])));
+		db.commit(&overlay.revert_one().unwrap());
+		db.commit(&overlay.revert_one().unwrap());
+		assert!(overlay.revert_one().is_none());
+		assert!(!contains(&overlay, 3));
+		db.commit(&overlay.insert(&h1, 1, &H256::default(), make_changeset(&[3], &[])));
+		assert!(contains(&overlay, 3));
+	}
+
 	#[test]
 	fn insert_finalize_two() {
 		let h1 = H256::random();
@@ -371,12 +500,12 @@ mod tests {
 		assert!(contains(&overlay, 5));
 		assert_eq!(overlay.levels.len(), 2);
 		assert_eq!(overlay.parents.len(), 2);
-		db.commit(&overlay.finalize(&h1));
+		db.commit(&overlay.finalize::<()>(&h1).unwrap());
 		assert_eq!(overlay.levels.len(), 1);
 		assert_eq!(overlay.parents.len(), 1);
 		assert!(!contains(&overlay, 5));
 		assert!(contains(&overlay, 7));
-		db.commit(&overlay.finalize(&h2));
+		db.commit(&overlay.finalize::<()>(&h2).unwrap());
 		assert_eq!(overlay.levels.len(), 0);
 		assert_eq!(overlay.parents.len(), 0);
 		assert!(db.data_eq(&make_db(&[1, 4, 6, 7, 8])));
@@ -445,7 +574,7 @@ mod tests {
 		assert_eq!(overlay.last_finalized, overlay2.last_finalized);
 
 		// finalize 1. 2 and all its children should be discarded
-		db.commit(&overlay.finalize(&h_1));
+		db.commit(&overlay.finalize::<()>(&h_1).unwrap());
 		assert_eq!(overlay.levels.len(), 2);
 		assert_eq!(overlay.parents.len(), 6);
 		assert!(!contains(&overlay, 1));
@@ -456,7 +585,7 @@ mod tests {
 		assert!(contains(&overlay, 111));
 
 		// finalize 1_2. 1_1 and all its children should be discarded
-		db.commit(&overlay.finalize(&h_1_2));
+		db.commit(&overlay.finalize::<()>(&h_1_2).unwrap());
 		assert_eq!(overlay.levels.len(), 1);
 		assert_eq!(overlay.parents.len(), 3);
 		assert!(!contains(&overlay, 11));
@@ -466,7 +595,7 @@ mod tests {
 		assert!(contains(&overlay, 123));
 
 		// finalize 1_2_2
-		db.commit(&overlay.finalize(&h_1_2_2));
+		db.commit(&overlay.finalize::<()>(&h_1_2_2).unwrap());
 		assert_eq!(overlay.levels.len(), 0);
 		assert_eq!(overlay.parents.len(), 0);
 		assert!(db.data_eq(&make_db(&[1, 12, 122])));
@@ -19,6 +19,7 @@
 use std::{error, fmt};
 use std::collections::HashMap;
 use std::sync::Arc;
+use memorydb::MemoryDB;
 use trie_backend::{TryIntoTrieBackend, TrieBackend};
 
 /// A state backend is used to read state data and can have changes committed
@@ -30,7 +31,7 @@ pub trait Backend: TryIntoTrieBackend {
 	type Error: super::Error;
 
 	/// Changes to be applied if committing
-	type Transaction;
+	type Transaction: Consolidate + Default;
 
 	/// Get keyed storage associated with specific address, or None if there is nothing associated.
 	fn storage(&self, key: &[u8]) -> Result<Option<Vec<u8>>, Self::Error>;
@@ -44,15 +45,68 @@ pub trait Backend: TryIntoTrieBackend {
 	/// call `f` for each of those keys.
 	fn for_keys_with_prefix<F: FnMut(&[u8])>(&self, prefix: &[u8], f: F);
 
+	/// Retrieve up to `count` keys starting with the given prefix, in lexicographic order and
+	/// starting after `start_key` if one is given.
+	fn keys_with_prefix(&self, prefix: &[u8], start_key: Option<&[u8]>, count: usize) -> Vec<Vec<u8>>;
+
 	/// Calculate the storage root, with given delta over what is already stored in
 	/// the backend, and produce a "transaction" that can be used to commit.
 	fn storage_root<I>(&self, delta: I) -> ([u8; 32], Self::Transaction)
 		where I: IntoIterator<Item=(Vec<u8>, Option<Vec<u8>>)>;
 
+	/// Get keyed storage of the child trie stored under `storage_key`, or None if there is
+	/// nothing associated.
+	fn child_storage(&self, storage_key: &[u8], key: &[u8]) -> Result<Option<Vec<u8>>, Self::Error>;
+
+	/// Retrieve all entries keys of the child trie stored under `storage_key` and call `f` for
+	/// each of those keys.
+	fn for_keys_in_child_storage<F: FnMut(&[u8])>(&self, storage_key: &[u8], f: F) -> Result<(), Self::Error>;
+
+	/// Calculate the root of the child trie stored under `storage_key`, with given delta over
+	/// what is already stored in the backend, and produce a "transaction" that can be used to
+	/// commit. Also returns whether the resulting child trie is empty.
+	///
+	/// The new root still has to be written to the main trie under `storage_key`.
+	fn child_storage_root<I>(&self, storage_key: &[u8], delta: I) -> ([u8; 32], bool, Self::Transaction)
+		where I: IntoIterator<Item=(Vec<u8>, Option<Vec<u8>>)>;
+
+	/// Calculate the root of the child trie stored under `storage_key` once it has been killed,
+	/// with given delta over an empty trie, and produce a "transaction" that can be used to
+	/// commit. Also returns whether the resulting child trie is empty.
+	fn killed_child_storage_root<I>(&self, storage_key: &[u8], delta: I) -> ([u8; 32], bool, Self::Transaction)
+		where I: IntoIterator<Item=(Vec<u8>, Option<Vec<u8>>)>;
+
+	/// Build a new standalone trie of the given pairs, e.g. a changes trie, and produce a
+	/// "transaction" that can be used to commit its nodes along with the state.
+	fn build_trie<I>(&self, pairs: I) -> ([u8; 32], Self::Transaction)
+		where I: IntoIterator<Item=(Vec<u8>, Vec<u8>)>;
+
 	/// Get all key/value pairs into a Vec.
 	fn pairs(&self) -> Vec<(Vec<u8>, Vec<u8>)>;
 }
 
+/// Transactions that can be merged together.
+pub trait Consolidate {
+	/// Merge `other` into `self`. Changes of `other` take precedence.
+	fn consolidate(&mut self, other: Self);
+}
+
+impl Consolidate for () {
+	fn consolidate(&mut self, _other: Self) {}
+}
+
+impl<T> Consolidate for Vec<T> {
+	fn consolidate(&mut self, other: Self) {
+		self.extend(other);
+	}
+}
+
+impl Consolidate for MemoryDB {
+	fn consolidate(&mut self, other: Self) {
+		MemoryDB::consolidate(self, other);
+	}
+}
+
 /// Error impossible.
 // TODO: use `!` type when stabilized.
 #[derive(Debug)]
@@ -73,28 +127,47 @@ impl error::Error for Void {
 #[derive(Clone, PartialEq, Eq)]
 pub struct InMemory {
 	inner: Arc<HashMap<Vec<u8>, Vec<u8>>>,
+	children: Arc<HashMap<Vec<u8>, HashMap<Vec<u8>, Vec<u8>>>>,
 }
 
 impl Default for InMemory {
 	fn default() -> Self {
 		InMemory {
 			inner: Arc::new(Default::default()),
+			children: Arc::new(Default::default()),
 		}
 	}
 }
 
+fn apply_change(map: &mut HashMap<Vec<u8>, Vec<u8>>, key: Vec<u8>, val: Option<Vec<u8>>) {
+	match val {
+		Some(v) => { map.insert(key, v); },
+		None => { map.remove(&key); },
+	}
+}
+
 impl InMemory {
 	/// Copy the state, with applied updates
 	pub fn update(&self, changes: <Self as Backend>::Transaction) -> Self {
 		let mut inner: HashMap<_, _> = (&*self.inner).clone();
-		for (key, val) in changes {
-			match val {
-				Some(v) => { inner.insert(key, v); },
-				None => { inner.remove(&key); },
+		let mut children: HashMap<_, _> = (&*self.children).clone();
+		for (storage_key, key, val) in changes {
+			match storage_key {
+				Some(storage_key) => {
+					let child = children.entry(storage_key.clone()).or_insert_with(HashMap::new);
+					apply_change(child, key, val);
+					if child.is_empty() {
+						children.remove(&storage_key);
+					}
+				},
+				None => apply_change(&mut inner, key, val),
 			}
 		}
 
-		inner.into()
+		InMemory {
+			inner: Arc::new(inner),
+			children: Arc::new(children),
+		}
 	}
 }
 
@@ -102,15 +175,34 @@ impl From<HashMap<Vec<u8>, Vec<u8>>> for InMemory {
 	fn from(inner: HashMap<Vec<u8>, Vec<u8>>) -> Self {
 		InMemory {
 			inner: Arc::new(inner),
+			children: Arc::new(Default::default()),
 		}
 	}
 }
 
+/// Compute the trie root of the given pairs with the delta applied.
+fn root_with_delta<'a, I, D>(existing: I, delta: D) -> ([u8; 32], bool)
+	where
+		I: IntoIterator<Item=(&'a Vec<u8>, &'a Vec<u8>)>,
+		D: IntoIterator<Item=(Vec<u8>, Option<Vec<u8>>)>,
+{
+	let pairs: Vec<_> = existing.into_iter().map(|(k, v)| (k.clone(), Some(v.clone())))
+		.chain(delta)
+		.collect::<HashMap<_, _>>()
+		.into_iter()
+		.filter_map(|(k, maybe_val)| maybe_val.map(|val| (k, val)))
+		.collect();
+	let is_empty = pairs.is_empty();
+
+	(::triehash::trie_root(pairs).0, is_empty)
+}
+
 impl super::Error for Void {}
 
 impl Backend for InMemory {
 	type Error = Void;
-	type Transaction = Vec<(Vec<u8>, Option<Vec<u8>>)>;
+	/// Changes to the top-level storage (`None` storage key) and to the child tries.
+	type Transaction = Vec<(Option<Vec<u8>>, Vec<u8>, Option<Vec<u8>>)>;
 
 	fn storage(&self, key: &[u8]) -> Result<Option<Vec<u8>>, Self::Error> {
 		Ok(self.inner.get(key).map(Clone::clone))
@@ -124,21 +216,72 @@ impl Backend for InMemory {
 		self.inner.keys().filter(|key| key.starts_with(prefix)).map(|k| &**k).for_each(f);
 	}
 
+	fn keys_with_prefix(&self, prefix: &[u8], start_key: Option<&[u8]>, count: usize) -> Vec<Vec<u8>> {
+		let mut keys: Vec<Vec<u8>> = self.inner.keys()
+			.filter(|key| key.starts_with(prefix) && start_key.map_or(true, |start| &key[..] > start))
+			.cloned()
+			.collect();
+		keys.sort();
+		keys.truncate(count);
+		keys
+	}
+
 	fn storage_root<I>(&self, delta: I) -> ([u8; 32], Self::Transaction)
 		where I: IntoIterator<Item=(Vec<u8>, Option<Vec<u8>>)>
 	{
-		let existing_pairs = self.inner.iter().map(|(k, v)| (k.clone(), Some(v.clone())));
-
-		let transaction: Vec<_> = delta.into_iter().collect();
-		let root = ::triehash::trie_root(existing_pairs.chain(transaction.iter().cloned())
-			.collect::<HashMap<_, _>>()
-			.into_iter()
-			.filter_map(|(k, maybe_val)| maybe_val.map(|val| (k, val)))
-		).0;
+		let delta: Vec<_> = delta.into_iter().collect();
+		let (root, _) = root_with_delta(self.inner.iter(), delta.iter().cloned());
+		let transaction = delta.into_iter().map(|(k, v)| (None, k, v)).collect();
 
 		(root, transaction)
 	}
 
+	fn child_storage(&self, storage_key: &[u8], key: &[u8]) -> Result<Option<Vec<u8>>, Self::Error> {
+		Ok(self.children.get(storage_key).and_then(|child| child.get(key)).map(Clone::clone))
+	}
+
+	fn for_keys_in_child_storage<F: FnMut(&[u8])>(&self, storage_key: &[u8], f: F) -> Result<(), Self::Error> {
+		if let Some(child) = self.children.get(storage_key) {
+			child.keys().map(|k| &**k).for_each(f);
+		}
+		Ok(())
+	}
+
+	fn child_storage_root<I>(&self, storage_key: &[u8], delta: I) -> ([u8; 32], bool, Self::Transaction)
+		where I: IntoIterator<Item=(Vec<u8>, Option<Vec<u8>>)>
+	{
+		let delta: Vec<_> = delta.into_iter().collect();
+		let empty = HashMap::new();
+		let existing = self.children.get(storage_key).unwrap_or(&empty);
+		let (root, is_empty) = root_with_delta(existing.iter(), delta.iter().cloned());
+		let transaction = delta.into_iter().map(|(k, v)| (Some(storage_key.to_vec()), k, v)).collect();
+
+		(root, is_empty, transaction)
+	}
+
+	fn killed_child_storage_root<I>(&self, storage_key: &[u8], delta: I) -> ([u8; 32], bool, Self::Transaction)
+		where I: IntoIterator<Item=(Vec<u8>, Option<Vec<u8>>)>
+	{
+		let delta: Vec<_> = delta.into_iter().collect();
+		let empty = HashMap::new();
+		let (root, is_empty) = root_with_delta(empty.iter(), delta.iter().cloned());
+		// the in-memory children are maps of their entries, which have to be removed one by one
+		let transaction = self.children.get(storage_key).into_iter()
+			.flat_map(|child| child.keys().map(|k| (k.clone(), None)))
+			.chain(delta)
+			.map(|(k, v)| (Some(storage_key.to_vec()), k, v))
+			.collect();
+
+		(root, is_empty, transaction)
+	}
+
+	fn build_trie<I>(&self, pairs: I) -> ([u8; 32], Self::Transaction)
+		where I: IntoIterator<Item=(Vec<u8>, Vec<u8>)>
+	{
+		// standalone tries are not kept in memory
+		(::triehash::trie_root(pairs).0, Vec::new())
+	}
+
 	fn pairs(&self) -> Vec<(Vec<u8>, Vec<u8>)> {
 		self.inner.iter().map(|(k, v)| (k.clone(), v.clone())).collect()
 	}
@@ -147,11 +290,21 @@ impl Backend for InMemory {
 impl TryIntoTrieBackend for InMemory {
 	fn try_into_trie_backend(self) -> Option<TrieBackend> {
 		use ethereum_types::H256 as TrieH256;
-		use memorydb::MemoryDB;
 		use patricia_trie::{TrieDBMut, TrieMut};
 
 		let mut root = TrieH256::default();
 		let mut mdb = MemoryDB::default();
+		// child tries share the node storage; their roots are already part of the top-level map
+		for child in self.children.values() {
+			let mut child_root = TrieH256::default();
+			let mut trie = TrieDBMut::new(&mut mdb, &mut child_root);
+			for (key, value) in child.iter() {
+				if let Err(e) = trie.insert(&key, &value) {
+					warn!(target: "trie", "Failed to write to trie: {}", e);
+					return None;
+				}
+			}
+		}
 		{
 			let mut trie = TrieDBMut::new(&mut mdb, &mut root);
 			for (key, value) in self.inner.iter() {
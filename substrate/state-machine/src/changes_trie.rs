@@ -0,0 +1,197 @@
+// Copyright 2018 Parity Technologies (UK) Ltd.
+// This file is part of Substrate.
+
+// Substrate is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// Substrate is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with Substrate.  If not, see <http://www.gnu.org/licenses/>.
+
+//! Changes tries.
+//!
+//! The changes trie of a block maps every top-level storage key changed in the block to the
+//! indices of the extrinsics that changed it, encoded as `Vec<u32>`. The list is empty for
+//! changes made outside of extrinsics and for the roots of changed child tries. Keys that end the
+//! block with the value they had in the parent state are not considered changed. The trie nodes
+//! are committed along with the state trie nodes, so the changes trie can be read from the node
+//! storage of any state of the chain.
+
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use byteorder::{ByteOrder, LittleEndian};
+use backend::Backend;
+use trie_backend::TrieBackend;
+use {OverlayedChanges, CHANGES_TRIE_ENABLED, EXTRINSIC_INDEX};
+
+/// Build the changes trie of the given changes, on top of the given parent state. Returns its
+/// root and the transaction storing its nodes, or `None` if changes tries are not enabled in the
+/// parent state.
+pub fn build_changes_trie<B: Backend>(
+	backend: &B,
+	changes: &OverlayedChanges,
+) -> Result<Option<([u8; 32], B::Transaction)>, B::Error> {
+	if !backend.exists_storage(CHANGES_TRIE_ENABLED)? {
+		return Ok(None);
+	}
+
+	let mut extrinsics: BTreeMap<Vec<u8>, BTreeSet<u32>> = BTreeMap::new();
+	for (key, key_extrinsics) in changes.top_changed_keys() {
+		if &key[..] == EXTRINSIC_INDEX {
+			continue;
+		}
+
+		let entry = extrinsics.entry(key.clone()).or_insert_with(BTreeSet::new);
+		if let Some(key_extrinsics) = key_extrinsics {
+			entry.extend(key_extrinsics.iter().cloned());
+		}
+	}
+	for storage_key in changes.changed_children() {
+		extrinsics.entry(storage_key.clone()).or_insert_with(BTreeSet::new);
+	}
+
+	let mut changed = BTreeMap::new();
+	for (key, key_extrinsics) in extrinsics {
+		if is_changed(backend, changes, &key)? {
+			changed.insert(key, key_extrinsics);
+		}
+	}
+
+	let pairs = changed.into_iter().map(|(key, extrinsics)| (key, encode_extrinsics(&extrinsics)));
+	Ok(Some(backend.build_trie(pairs)))
+}
+
+/// Whether the final value of `key` differs from its value in the parent state. For the storage
+/// key of a child trie, whether any of the final values in the child trie does.
+fn is_changed<B: Backend>(backend: &B, changes: &OverlayedChanges, key: &[u8]) -> Result<bool, B::Error> {
+	if let Some(value) = changes.storage(key) {
+		if value != backend.storage(key)?.as_ref().map(AsRef::as_ref) {
+			return Ok(true);
+		}
+	}
+
+	let child_values: HashMap<&Vec<u8>, &Option<Vec<u8>>> = changes.child_changes(key).collect();
+	for (child_key, value) in child_values {
+		if *value != backend.child_storage(key, child_key)? {
+			return Ok(true);
+		}
+	}
+	Ok(false)
+}
+
+/// Read the indices of the extrinsics that changed `key` from the changes trie with given root,
+/// using the node storage of `state`. `None` if the key has not been changed.
+pub fn key_changes(state: &TrieBackend, root: [u8; 32], key: &[u8]) -> Result<Option<Vec<u32>>, String> {
+	match state.with_root(root.into()).storage(key)? {
+		Some(encoded) => decode_extrinsics(&encoded)
+			.map(Some)
+			.ok_or_else(|| format!("Invalid changes trie entry for key {:?}", key)),
+		None => Ok(None),
+	}
+}
+
+/// Encode extrinsic indices the same way the codec encodes `Vec<u32>`.
+fn encode_extrinsics(extrinsics: &BTreeSet<u32>) -> Vec<u8> {
+	let mut encoded = vec![0u8; 4 + 4 * extrinsics.len()];
+	LittleEndian::write_u32(&mut encoded[..4], extrinsics.len() as u32);
+	for (i, extrinsic) in extrinsics.iter().enumerate() {
+		LittleEndian::write_u32(&mut encoded[4 + 4 * i..], *extrinsic);
+	}
+	encoded
+}
+
+fn decode_extrinsics(encoded: &[u8]) -> Option<Vec<u32>> {
+	if encoded.len() < 4 || encoded.len() != 4 + 4 * LittleEndian::read_u32(encoded) as usize {
+		return None;
+	}
+	Some(encoded[4..].chunks(4).map(LittleEndian::read_u32).collect())
+}
+
+#[cfg(test)]
+mod tests {
+	use std::collections::HashMap;
+	use backend::InMemory;
+	use trie_backend::TryIntoTrieBackend;
+	use {Ext, Externalities};
+	use super::*;
+
+	fn backend(enabled: bool) -> TrieBackend {
+		let mut storage = HashMap::new();
+		if enabled {
+			storage.insert(CHANGES_TRIE_ENABLED.to_vec(), vec![1]);
+		}
+		InMemory::from(storage).try_into_trie_backend().unwrap()
+	}
+
+	fn set_extrinsic_index(ext: &mut Ext<TrieBackend>, index: u32) {
+		let mut encoded = [0u8; 4];
+		LittleEndian::write_u32(&mut encoded, index);
+		ext.set_storage(EXTRINSIC_INDEX.to_vec(), encoded.to_vec());
+	}
+
+	#[test]
+	fn extrinsics_encoding_roundtrips() {
+		let extrinsics: BTreeSet<u32> = vec![0, 5, 1 << 20].into_iter().collect();
+		let encoded = encode_extrinsics(&extrinsics);
+		assert_eq!(decode_extrinsics(&encoded), Some(vec![0, 5, 1 << 20]));
+		assert_eq!(decode_extrinsics(&encoded[1..]), None);
+	}
+
+	#[test]
+	fn not_built_when_disabled() {
+		let backend = backend(false);
+		let mut overlay = OverlayedChanges::default();
+		Ext::new(&mut overlay, &backend).set_storage(b"key".to_vec(), b"value".to_vec());
+		assert!(build_changes_trie(&backend, &overlay).unwrap().is_none());
+	}
+
+	#[test]
+	fn tracks_extrinsics_of_changed_keys() {
+		let backend = backend(true);
+		let mut overlay = OverlayedChanges::default();
+		{
+			let mut ext = Ext::new(&mut overlay, &backend);
+			ext.set_storage(b"initial".to_vec(), vec![1]);
+			set_extrinsic_index(&mut ext, 0);
+			ext.set_storage(b"key1".to_vec(), vec![1]);
+			set_extrinsic_index(&mut ext, 1);
+			ext.set_storage(b"key1".to_vec(), vec![2]);
+			ext.set_storage(b"key2".to_vec(), vec![2]);
+			ext.clear_storage(EXTRINSIC_INDEX);
+		}
+
+		let (root, transaction) = build_changes_trie(&backend, &overlay).unwrap().unwrap();
+		let changes = TrieBackend::with_memorydb(transaction, Default::default());
+		assert_eq!(key_changes(&changes, root, b"key1").unwrap(), Some(vec![0, 1]));
+		assert_eq!(key_changes(&changes, root, b"key2").unwrap(), Some(vec![1]));
+		assert_eq!(key_changes(&changes, root, b"initial").unwrap(), Some(vec![]));
+		assert_eq!(key_changes(&changes, root, b"key3").unwrap(), None);
+		assert_eq!(key_changes(&changes, root, EXTRINSIC_INDEX).unwrap(), None);
+	}
+
+	#[test]
+	fn skips_keys_restored_to_parent_value() {
+		let backend = backend(true);
+		let mut overlay = OverlayedChanges::default();
+		{
+			let mut ext = Ext::new(&mut overlay, &backend);
+			set_extrinsic_index(&mut ext, 0);
+			ext.set_storage(b"key1".to_vec(), vec![1]);
+			ext.clear_storage(b"key1");
+			ext.set_storage(CHANGES_TRIE_ENABLED.to_vec(), vec![1]);
+			ext.set_storage(b"key2".to_vec(), vec![2]);
+			ext.clear_storage(EXTRINSIC_INDEX);
+		}
+
+		let (root, transaction) = build_changes_trie(&backend, &overlay).unwrap().unwrap();
+		let changes = TrieBackend::with_memorydb(transaction, Default::default());
+		assert_eq!(key_changes(&changes, root, b"key1").unwrap(), None);
+		assert_eq!(key_changes(&changes, root, CHANGES_TRIE_ENABLED).unwrap(), None);
+		assert_eq!(key_changes(&changes, root, b"key2").unwrap(), Some(vec![0]));
+	}
+}
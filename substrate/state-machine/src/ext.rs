@@ -17,8 +17,9 @@
 //! Conrete externalities implementation.
 
 use std::{error, fmt};
-use backend::Backend;
-use {Externalities, OverlayedChanges};
+use backend::{Backend, Consolidate};
+use changes_trie::build_changes_trie;
+use {Externalities, OffchainExt, OverlayedChanges, is_child_storage_key};
 
 /// Errors that can occur when interacting with the externalities.
 #[derive(Debug, Copy, Clone)]
@@ -57,6 +58,10 @@ pub struct Ext<'a, B: 'a + Backend> {
 	backend: &'a B,
 	// The transaction necessary to commit to the backend.
 	transaction: Option<(B::Transaction, [u8; 32])>,
+	// The transaction storing the changes trie, if it has been built.
+	changes_trie_transaction: Option<B::Transaction>,
+	// The node-local capabilities, if executing as an offchain worker.
+	offchain: Option<&'a mut OffchainExt>,
 }
 
 impl<'a, B: 'a + Backend> Ext<'a, B> {
@@ -66,13 +71,26 @@ impl<'a, B: 'a + Backend> Ext<'a, B> {
 			overlay,
 			backend,
 			transaction: None,
+			changes_trie_transaction: None,
+			offchain: None,
 		}
 	}
 
-	/// Get the transaction necessary to update the backend.
+	/// Give the runtime access to given node-local capabilities of an offchain worker.
+	pub fn with_offchain(mut self, offchain: &'a mut OffchainExt) -> Self {
+		self.offchain = Some(offchain);
+		self
+	}
+
+	/// Get the transaction necessary to update the backend, including the changes trie nodes
+	/// if the changes trie has been built.
 	pub fn transaction(mut self) -> B::Transaction {
 		let _ = self.storage_root();
-		self.transaction.expect("transaction always set after calling storage root; qed").0
+		let mut transaction = self.transaction.expect("transaction always set after calling storage root; qed").0;
+		if let Some(changes_trie_transaction) = self.changes_trie_transaction {
+			transaction.consolidate(changes_trie_transaction);
+		}
+		transaction
 	}
 
 	/// Invalidates the currently cached storage root and the db transaction.
@@ -90,8 +108,7 @@ impl<'a, B: 'a + Backend> Ext<'a, B> {
 
 		self.backend.pairs().iter()
 			.map(|&(ref k, ref v)| (k.to_vec(), Some(v.to_vec())))
-			.chain(self.overlay.committed.clone().into_iter())
-			.chain(self.overlay.prospective.clone().into_iter())
+			.chain(self.overlay.top_changes().map(|(k, v)| (k.clone(), v.clone())))
 			.collect::<HashMap<_, _>>()
 			.into_iter()
 			.filter_map(|(k, maybe_val)| maybe_val.map(|val| (k, val)))
@@ -115,6 +132,11 @@ impl<'a, B: 'a> Externalities for Ext<'a, B>
 	}
 
 	fn place_storage(&mut self, key: Vec<u8>, value: Option<Vec<u8>>) {
+		if is_child_storage_key(&key) {
+			warn!("Refusing to directly set child storage key");
+			return;
+		}
+
 		self.mark_dirty();
 		self.overlay.set_storage(key, value);
 	}
@@ -122,10 +144,55 @@ impl<'a, B: 'a> Externalities for Ext<'a, B>
 	fn clear_prefix(&mut self, prefix: &[u8]) {
 		self.mark_dirty();
 		self.backend.for_keys_with_prefix(prefix, |key| {
-			self.overlay.set_storage(key.to_vec(), None);
+			if !is_child_storage_key(key) {
+				self.overlay.set_storage(key.to_vec(), None);
+			}
 		});
 	}
 
+	fn child_storage(&self, storage_key: &[u8], key: &[u8]) -> Option<Vec<u8>> {
+		if !is_child_storage_key(storage_key) {
+			return None;
+		}
+
+		self.overlay.child_storage(storage_key, key).map(|x| x.map(|x| x.to_vec())).unwrap_or_else(||
+			self.backend.child_storage(storage_key, key).expect("Externalities not allowed to fail within runtime"))
+	}
+
+	fn place_child_storage(&mut self, storage_key: Vec<u8>, key: Vec<u8>, value: Option<Vec<u8>>) {
+		if !is_child_storage_key(&storage_key) {
+			warn!("Refusing to set child storage under a non-child storage key");
+			return;
+		}
+
+		self.mark_dirty();
+		self.overlay.set_child_storage(storage_key, key, value);
+	}
+
+	fn kill_child_storage(&mut self, storage_key: &[u8]) {
+		if !is_child_storage_key(storage_key) {
+			warn!("Refusing to kill child storage under a non-child storage key");
+			return;
+		}
+
+		self.mark_dirty();
+		self.overlay.kill_child_storage(storage_key);
+		self.overlay.set_storage(storage_key.to_vec(), None);
+	}
+
+	fn start_transaction(&mut self) {
+		self.overlay.start_transaction();
+	}
+
+	fn commit_transaction(&mut self) -> bool {
+		self.overlay.commit_transaction()
+	}
+
+	fn rollback_transaction(&mut self) -> bool {
+		self.mark_dirty();
+		self.overlay.rollback_transaction()
+	}
+
 	fn chain_id(&self) -> u64 {
 		42
 	}
@@ -135,13 +202,63 @@ impl<'a, B: 'a> Externalities for Ext<'a, B>
 			return root.clone();
 		}
 
-		// compute and memoize
-		let delta = self.overlay.committed.iter()
-			.chain(self.overlay.prospective.iter())
-			.map(|(k, v)| (k.clone(), v.clone()));
+		// compute and memoize; child tries go first as their roots are part of the main trie
+		let mut transaction = B::Transaction::default();
+		let mut child_roots = Vec::new();
+		for storage_key in self.overlay.changed_children() {
+			let delta = self.overlay.child_changes(storage_key)
+				.map(|(k, v)| (k.clone(), v.clone()));
+			let (root, is_empty, child_transaction) = if self.overlay.is_child_killed(storage_key) {
+				self.backend.killed_child_storage_root(storage_key, delta)
+			} else {
+				self.backend.child_storage_root(storage_key, delta)
+			};
+			transaction.consolidate(child_transaction);
+			child_roots.push((storage_key.clone(), if is_empty { None } else { Some(root.to_vec()) }));
+		}
+
+		let delta = self.overlay.top_changes()
+			.map(|(k, v)| (k.clone(), v.clone()))
+			.chain(child_roots);
 
-		let (root, transaction) = self.backend.storage_root(delta);
+		let (root, top_transaction) = self.backend.storage_root(delta);
+		transaction.consolidate(top_transaction);
 		self.transaction = Some((transaction, root));
 		root
 	}
+
+	fn child_storage_root(&mut self, storage_key: &[u8]) -> Option<[u8; 32]> {
+		if !is_child_storage_key(storage_key) {
+			return None;
+		}
+
+		let delta = self.overlay.child_changes(storage_key)
+			.map(|(k, v)| (k.clone(), v.clone()));
+		if self.overlay.is_child_killed(storage_key) {
+			Some(self.backend.killed_child_storage_root(storage_key, delta).0)
+		} else {
+			Some(self.backend.child_storage_root(storage_key, delta).0)
+		}
+	}
+
+	fn storage_changes_root(&mut self) -> Option<[u8; 32]> {
+		let changes_trie = build_changes_trie(self.backend, &*self.overlay)
+			.expect("Externalities not allowed to fail within runtime");
+		changes_trie.map(|(root, transaction)| {
+			self.changes_trie_transaction = Some(transaction);
+			root
+		})
+	}
+
+	fn extrinsic_index(&self) -> Option<u32> {
+		// the index is only ever set by the runtime, so the backend need not be read
+		self.overlay.extrinsic_index()
+	}
+
+	fn offchain(&mut self) -> Option<&mut OffchainExt> {
+		match self.offchain {
+			Some(ref mut offchain) => Some(&mut **offchain),
+			None => None,
+		}
+	}
 }
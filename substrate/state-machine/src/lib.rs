@@ -33,11 +33,16 @@ extern crate patricia_trie;
 extern crate byteorder;
 extern crate parking_lot;
 
-use std::collections::HashMap;
+extern crate substrate_primitives as primitives;
+
+use std::collections::{BTreeSet, HashMap, HashSet};
 use std::collections::hash_map::Drain;
 use std::fmt;
+use std::iter;
+use byteorder::{ByteOrder, LittleEndian};
 
 pub mod backend;
+pub mod changes_trie;
 mod ext;
 mod testing;
 mod proving_backend;
@@ -45,17 +50,65 @@ mod trie_backend;
 
 pub use testing::TestExternalities;
 pub use ext::Ext;
-pub use backend::Backend;
+pub use backend::{Backend, Consolidate};
+pub use primitives::storage::{CHANGES_TRIE_ENABLED, CHILD_STORAGE_KEY_PREFIX, EXTRINSIC_INDEX,
+	is_child_storage_key};
 pub use trie_backend::{TryIntoTrieBackend, TrieBackend, TrieH256, Storage, DBValue};
 
+/// Changes to the top-level storage and to the child tries.
+#[derive(Debug, Default, Clone)]
+struct OverlayedChangeSet {
+	/// Top-level storage changes.
+	top: HashMap<Vec<u8>, Option<Vec<u8>>>,
+	/// Child storage changes, by the storage key of the child trie.
+	children: HashMap<Vec<u8>, HashMap<Vec<u8>, Option<Vec<u8>>>>,
+	/// Storage keys of the child tries killed before the child storage changes of this set.
+	killed_children: HashSet<Vec<u8>>,
+	/// Indices of the extrinsics that changed top-level storage keys.
+	extrinsics: HashMap<Vec<u8>, BTreeSet<u32>>,
+}
+
+impl OverlayedChangeSet {
+	fn is_empty(&self) -> bool {
+		self.top.is_empty() && self.children.is_empty() && self.killed_children.is_empty()
+	}
+
+	fn clear(&mut self) {
+		self.top.clear();
+		self.children.clear();
+		self.killed_children.clear();
+		self.extrinsics.clear();
+	}
+
+	/// Apply later changes on top of these ones.
+	fn extend(&mut self, other: OverlayedChangeSet) {
+		self.top.extend(other.top);
+		for storage_key in other.killed_children {
+			self.children.remove(&storage_key);
+			self.killed_children.insert(storage_key);
+		}
+		for (storage_key, changes) in other.children {
+			self.children.entry(storage_key).or_insert_with(HashMap::new).extend(changes);
+		}
+		for (key, extrinsics) in other.extrinsics {
+			self.extrinsics.entry(key).or_insert_with(BTreeSet::new).extend(extrinsics);
+		}
+	}
+}
+
 /// The overlayed changes to state to be queried on top of the backend.
 ///
 /// A transaction shares all prospective changes within an inner overlay
 /// that can be cleared.
+///
+/// Storage transactions can be nested on top of the prospective changes: changes made
+/// within a transaction are either committed into the enclosing transaction (or into the
+/// prospective changes for the outermost one) or rolled back.
 #[derive(Debug, Default, Clone)]
 pub struct OverlayedChanges {
-	prospective: HashMap<Vec<u8>, Option<Vec<u8>>>,
-	committed: HashMap<Vec<u8>, Option<Vec<u8>>>,
+	prospective: OverlayedChangeSet,
+	committed: OverlayedChangeSet,
+	transactions: Vec<OverlayedChangeSet>,
 }
 
 impl OverlayedChanges {
@@ -63,33 +116,185 @@ impl OverlayedChanges {
 	/// to the backend); Some(None) if the key has been deleted. Some(Some(...)) for a key whose
 	/// value has been set.
 	pub fn storage(&self, key: &[u8]) -> Option<Option<&[u8]>> {
-		self.prospective.get(key)
-			.or_else(|| self.committed.get(key))
+		self.change_sets().rev().filter_map(|changes| changes.top.get(key)).next()
 			.map(|x| x.as_ref().map(AsRef::as_ref))
 	}
 
+	/// Returns a double-Option: None if the key is unknown (i.e. and the query should be refered
+	/// to the backend); Some(None) if the key has been deleted. Some(Some(...)) for a key whose
+	/// value has been set.
+	pub fn child_storage(&self, storage_key: &[u8], key: &[u8]) -> Option<Option<&[u8]>> {
+		for changes in self.change_sets().rev() {
+			if let Some(value) = changes.children.get(storage_key).and_then(|changes| changes.get(key)) {
+				return Some(value.as_ref().map(AsRef::as_ref));
+			}
+			if changes.killed_children.contains(storage_key) {
+				return Some(None);
+			}
+		}
+		None
+	}
+
 	fn set_storage(&mut self, key: Vec<u8>, val: Option<Vec<u8>>) {
-		self.prospective.insert(key, val);
+		if let Some(extrinsic) = self.extrinsic_index() {
+			self.current().extrinsics.entry(key.clone()).or_insert_with(BTreeSet::new).insert(extrinsic);
+		}
+		self.current().top.insert(key, val);
+	}
+
+	/// Index of the extrinsic that is currently being executed, if any.
+	pub(crate) fn extrinsic_index(&self) -> Option<u32> {
+		match self.storage(EXTRINSIC_INDEX) {
+			Some(Some(index)) if index.len() == 4 => Some(LittleEndian::read_u32(index)),
+			_ => None,
+		}
+	}
+
+	fn set_child_storage(&mut self, storage_key: Vec<u8>, key: Vec<u8>, val: Option<Vec<u8>>) {
+		self.current().children.entry(storage_key).or_insert_with(HashMap::new).insert(key, val);
+	}
+
+	/// Kill the given child trie: its entries in the backend and all the changes made to it so
+	/// far are discarded.
+	fn kill_child_storage(&mut self, storage_key: &[u8]) {
+		let current = self.current();
+		current.children.remove(storage_key);
+		current.killed_children.insert(storage_key.to_vec());
+	}
+
+	/// Whether the given child trie has been killed, its entries in the backend being discarded.
+	fn is_child_killed(&self, storage_key: &[u8]) -> bool {
+		self.change_sets().any(|changes| changes.killed_children.contains(storage_key))
 	}
 
-	/// Discard prospective changes to state.
+	/// Start a new storage transaction, nested in the currently open one (if any).
+	pub fn start_transaction(&mut self) {
+		self.transactions.push(Default::default());
+	}
+
+	/// Commit the innermost storage transaction into the enclosing one, or into the prospective
+	/// changes. Returns `false` if there is no open transaction.
+	pub fn commit_transaction(&mut self) -> bool {
+		match self.transactions.pop() {
+			Some(changes) => {
+				self.current().extend(changes);
+				true
+			},
+			None => false,
+		}
+	}
+
+	/// Discard the changes of the innermost storage transaction. Returns `false` if there is
+	/// no open transaction.
+	pub fn rollback_transaction(&mut self) -> bool {
+		self.transactions.pop().is_some()
+	}
+
+	/// The change set new changes go to: the innermost transaction, or the prospective changes.
+	fn current(&mut self) -> &mut OverlayedChangeSet {
+		match self.transactions.last_mut() {
+			Some(changes) => changes,
+			None => &mut self.prospective,
+		}
+	}
+
+	/// All change sets, oldest first.
+	fn change_sets(&self) -> impl DoubleEndedIterator<Item=&OverlayedChangeSet> {
+		iter::once(&self.committed)
+			.chain(iter::once(&self.prospective))
+			.chain(self.transactions.iter())
+	}
+
+	/// Iterate over all uncommitted top-level changes, with later changes to a key following
+	/// earlier ones.
+	fn top_changes(&self) -> impl Iterator<Item=(&Vec<u8>, &Option<Vec<u8>>)> {
+		self.change_sets().flat_map(|changes| changes.top.iter())
+	}
+
+	/// Iterate over the top-level keys with uncommitted changes, along with the indices of the
+	/// extrinsics that changed them. A key may be returned more than once.
+	fn top_changed_keys(&self) -> impl Iterator<Item=(&Vec<u8>, Option<&BTreeSet<u32>>)> {
+		self.change_sets().flat_map(|changes| changes.top.keys()
+			.map(move |key| (key, changes.extrinsics.get(key))))
+	}
+
+	/// Storage keys of all the child tries with uncommitted changes, including the killed ones.
+	fn changed_children(&self) -> HashSet<&Vec<u8>> {
+		self.change_sets()
+			.flat_map(|changes| changes.children.keys().chain(changes.killed_children.iter()))
+			.collect()
+	}
+
+	/// Iterate over all uncommitted changes to the given child trie since it was last killed,
+	/// with later changes to a key following earlier ones.
+	fn child_changes<'a>(&'a self, storage_key: &'a [u8]) -> impl Iterator<Item=(&'a Vec<u8>, &'a Option<Vec<u8>>)> + 'a {
+		let killed_at = self.change_sets()
+			.enumerate()
+			.filter(|&(_, changes)| changes.killed_children.contains(storage_key))
+			.map(|(index, _)| index)
+			.last()
+			.unwrap_or(0);
+		self.change_sets()
+			.skip(killed_at)
+			.filter_map(move |changes| changes.children.get(storage_key))
+			.flat_map(|changes| changes.iter())
+	}
+
+	/// Discard prospective changes to state, including any open storage transactions.
 	pub fn discard_prospective(&mut self) {
+		self.transactions.clear();
 		self.prospective.clear();
 	}
 
-	/// Commit prospective changes to state.
+	/// Commit prospective changes to state. All storage transactions must have been closed:
+	/// any left open are committed along with the prospective changes, as they are a part of
+	/// the storage root computed over the overlay.
 	pub fn commit_prospective(&mut self) {
+		debug_assert!(self.transactions.is_empty(), "storage transactions must be closed before committing");
+		for changes in ::std::mem::replace(&mut self.transactions, Vec::new()) {
+			self.prospective.extend(changes);
+		}
 		if self.committed.is_empty() {
 			::std::mem::swap(&mut self.prospective, &mut self.committed);
 		} else {
-			self.committed.extend(self.prospective.drain());
+			let prospective = ::std::mem::replace(&mut self.prospective, Default::default());
+			self.committed.extend(prospective);
+		}
+	}
+
+	/// Take a copy of the uncommitted changes, including the open storage transactions.
+	fn prospective_snapshot(&self) -> ProspectiveSnapshot {
+		ProspectiveSnapshot {
+			prospective: self.prospective.clone(),
+			transactions: self.transactions.clone(),
 		}
 	}
 
-	/// Drain prospective changes to an iterator.
-	pub fn drain(&mut self) -> Drain<Vec<u8>, Option<Vec<u8>>> {
-		self.committed.drain()
+	/// Replace the uncommitted changes with the given snapshot.
+	fn restore_prospective(&mut self, snapshot: ProspectiveSnapshot) {
+		self.prospective = snapshot.prospective;
+		self.transactions = snapshot.transactions;
 	}
+
+	/// Drain committed changes to iterators: the top-level changes, the changes to each child
+	/// trie by its storage key, and the storage keys of the child tries to kill before applying
+	/// their changes.
+	pub fn drain(&mut self) -> (
+		Drain<Vec<u8>, Option<Vec<u8>>>,
+		Drain<Vec<u8>, HashMap<Vec<u8>, Option<Vec<u8>>>>,
+		::std::collections::hash_set::Drain<Vec<u8>>,
+	) {
+		let committed = &mut self.committed;
+		committed.extrinsics.clear();
+		(committed.top.drain(), committed.children.drain(), committed.killed_children.drain())
+	}
+}
+
+/// Copy of the uncommitted changes of an overlay, restored when a call has to be executed again.
+#[derive(Clone)]
+struct ProspectiveSnapshot {
+	prospective: OverlayedChangeSet,
+	transactions: Vec<OverlayedChangeSet>,
 }
 
 /// State Machine Error bound.
@@ -147,11 +352,81 @@ pub trait Externalities {
 	/// Set or clear a storage entry (`key`) of current contract being called (effective immediately).
 	fn place_storage(&mut self, key: Vec<u8>, value: Option<Vec<u8>>);
 
+	/// Read an entry (`key`) of the child trie stored under `storage_key`.
+	///
+	/// Child storage keys must start with `CHILD_STORAGE_KEY_PREFIX`; reads with any other
+	/// storage key return `None` and writes with one are ignored.
+	fn child_storage(&self, storage_key: &[u8], key: &[u8]) -> Option<Vec<u8>>;
+
+	/// Set an entry (`key`) of the child trie stored under `storage_key` (effective immediately).
+	fn set_child_storage(&mut self, storage_key: Vec<u8>, key: Vec<u8>, value: Vec<u8>) {
+		self.place_child_storage(storage_key, key, Some(value));
+	}
+
+	/// Clear an entry (`key`) of the child trie stored under `storage_key` (effective immediately).
+	fn clear_child_storage(&mut self, storage_key: &[u8], key: &[u8]) {
+		self.place_child_storage(storage_key.to_vec(), key.to_vec(), None);
+	}
+
+	/// Set or clear an entry (`key`) of the child trie stored under `storage_key` (effective
+	/// immediately).
+	fn place_child_storage(&mut self, storage_key: Vec<u8>, key: Vec<u8>, value: Option<Vec<u8>>);
+
+	/// Clear the whole child trie stored under `storage_key` (effective immediately).
+	fn kill_child_storage(&mut self, storage_key: &[u8]);
+
+	/// Start a new storage transaction, nested in the currently open one (if any). Changes made
+	/// from now on are only effective once the transaction is committed.
+	fn start_transaction(&mut self);
+
+	/// Commit the innermost storage transaction. Returns `false` if there is no open transaction.
+	fn commit_transaction(&mut self) -> bool;
+
+	/// Discard the changes made within the innermost storage transaction. Returns `false` if
+	/// there is no open transaction.
+	fn rollback_transaction(&mut self) -> bool;
+
 	/// Get the identity of the chain.
 	fn chain_id(&self) -> u64;
 
 	/// Get the trie root of the current storage map.
 	fn storage_root(&mut self) -> [u8; 32];
+
+	/// Get the trie root of the current state of the child trie stored under `storage_key`.
+	/// `None` if `storage_key` is not a child storage key.
+	fn child_storage_root(&mut self, storage_key: &[u8]) -> Option<[u8; 32]>;
+
+	/// Get the root of the changes trie of the current changes. `None` if changes tries are not
+	/// enabled.
+	fn storage_changes_root(&mut self) -> Option<[u8; 32]>;
+
+	/// Index of the extrinsic that is currently being executed, if any. Unlike reading
+	/// `EXTRINSIC_INDEX` through `storage`, this never adds to the proof of a proving backend.
+	fn extrinsic_index(&self) -> Option<u32> {
+		match self.storage(EXTRINSIC_INDEX) {
+			Some(ref index) if index.len() == 4 => Some(LittleEndian::read_u32(index)),
+			_ => None,
+		}
+	}
+
+	/// Get the node-local capabilities of the offchain worker. `None` unless the runtime is
+	/// being executed as an offchain worker.
+	fn offchain(&mut self) -> Option<&mut OffchainExt> {
+		None
+	}
+}
+
+/// Node-local capabilities available to the runtime when executed as an offchain worker.
+pub trait OffchainExt {
+	/// Submit an encoded unsigned extrinsic to the local extrinsic pool. Extrinsics which are not
+	/// accepted by the pool are dropped.
+	fn submit_extrinsic(&mut self, extrinsic: Vec<u8>);
+
+	/// Read an entry of the node-local key/value store.
+	fn local_storage(&self, key: &[u8]) -> Option<Vec<u8>>;
+
+	/// Set or clear an entry of the node-local key/value store.
+	fn set_local_storage(&mut self, key: &[u8], value: Option<&[u8]>);
 }
 
 /// Code execution engine.
@@ -273,7 +548,7 @@ pub fn execute_using_consensus_failure_handler<
 		.to_vec();
 
 	let result = {
-		let mut orig_prospective = overlay.prospective.clone();
+		let orig_prospective = overlay.prospective_snapshot();
 
 		let (result, was_native, delta) = loop {
 			let ((result, was_native), delta) = {
@@ -292,7 +567,7 @@ pub fn execute_using_consensus_failure_handler<
 			};
 
 			if result.as_ref().err().map_or(false, |e| e.needs_retry()) {
-				overlay.prospective = orig_prospective.clone();
+				overlay.restore_prospective(orig_prospective.clone());
 			} else {
 				break (result, was_native, delta)
 			}
@@ -302,7 +577,7 @@ pub fn execute_using_consensus_failure_handler<
 		let (result, delta) = if let (true, ExecutionManager::Both(on_consensus_failure)) =
 			(was_native, manager)
 		{
-			overlay.prospective = orig_prospective.clone();
+			overlay.restore_prospective(orig_prospective.clone());
 
 			let (wasm_result, wasm_delta) = loop {
 				let ((result, _), delta) = {
@@ -320,7 +595,7 @@ pub fn execute_using_consensus_failure_handler<
 				};
 
 				if result.as_ref().err().map_or(false, |e| e.needs_retry()) {
-					overlay.prospective = orig_prospective.clone();
+					overlay.restore_prospective(orig_prospective.clone());
 				} else {
 					break (result, delta)
 				}
@@ -352,6 +627,102 @@ pub fn execute_using_consensus_failure_handler<
 	}
 }
 
+/// Buffers the effects of an offchain worker on its node-local capabilities, so that they are
+/// applied only once the call has succeeded and are not repeated when the call is retried.
+struct BufferedOffchain<'a> {
+	offchain: &'a mut OffchainExt,
+	extrinsics: Vec<Vec<u8>>,
+	local_storage: HashMap<Vec<u8>, Option<Vec<u8>>>,
+}
+
+impl<'a> BufferedOffchain<'a> {
+	fn new(offchain: &'a mut OffchainExt) -> Self {
+		BufferedOffchain {
+			offchain,
+			extrinsics: Vec::new(),
+			local_storage: HashMap::new(),
+		}
+	}
+
+	/// Discard the buffered effects of a failed call.
+	fn discard(&mut self) {
+		self.extrinsics.clear();
+		self.local_storage.clear();
+	}
+
+	/// Apply the buffered effects of a successful call.
+	fn apply(self) {
+		for (key, value) in self.local_storage {
+			self.offchain.set_local_storage(&key, value.as_ref().map(|v| &v[..]));
+		}
+		for extrinsic in self.extrinsics {
+			self.offchain.submit_extrinsic(extrinsic);
+		}
+	}
+}
+
+impl<'a> OffchainExt for BufferedOffchain<'a> {
+	fn submit_extrinsic(&mut self, extrinsic: Vec<u8>) {
+		self.extrinsics.push(extrinsic);
+	}
+
+	fn local_storage(&self, key: &[u8]) -> Option<Vec<u8>> {
+		match self.local_storage.get(key) {
+			Some(value) => value.clone(),
+			None => self.offchain.local_storage(key),
+		}
+	}
+
+	fn set_local_storage(&mut self, key: &[u8], value: Option<&[u8]>) {
+		self.local_storage.insert(key.to_vec(), value.map(|v| v.to_vec()));
+	}
+}
+
+/// Execute a call as an offchain worker, which can use the given node-local capabilities.
+///
+/// Unlike `execute`, no transaction is produced: changes made by the call to the storage are
+/// discarded. Effects on `offchain` are buffered and applied only if the call succeeds, with
+/// extrinsics submitted after the node-local storage is updated.
+pub fn execute_offchain<B: backend::Backend, Exec: CodeExecutor>(
+	backend: &B,
+	exec: &Exec,
+	offchain: &mut OffchainExt,
+	method: &str,
+	call_data: &[u8],
+	strategy: ExecutionStrategy,
+) -> Result<Vec<u8>, Box<Error>> {
+	let mut overlay = OverlayedChanges::default();
+	let code = ext::Ext::new(&mut overlay, backend).storage(b":code")
+		.ok_or(Box::new(ExecutionError::CodeEntryDoesNotExist) as Box<Error>)?
+		.to_vec();
+
+	let mut buffered = BufferedOffchain::new(offchain);
+	loop {
+		let (result, _) = {
+			let mut externalities = ext::Ext::new(&mut overlay, backend).with_offchain(&mut buffered);
+			exec.call(
+				&mut externalities,
+				&code,
+				method,
+				call_data,
+				strategy != ExecutionStrategy::AlwaysWasm,
+			)
+		};
+
+		match result {
+			Err(ref e) if e.needs_retry() => {
+				overlay.discard_prospective();
+				buffered.discard();
+			},
+			Ok(result) => {
+				buffered.apply();
+				return Ok(result);
+			},
+			Err(e) => return Err(Box::new(e) as Box<Error>),
+		}
+	}
+}
+
 /// Prove execution using the given state backend, overlayed changes, and call executor.
 /// Produces a state-backend-specific "transaction" which can be used to apply the changes
 /// to the backing store, such as the disk.
@@ -389,6 +760,33 @@ pub fn execution_proof_check<Exec: CodeExecutor>(
 	execute(&backend, overlay, exec, method, call_data, ExecutionStrategy::NativeWhenPossible)
 }
 
+/// Generate storage read proof for given keys.
+///
+/// Returns the values read together with the set of all trie nodes touched while reading them.
+pub fn prove_read<B: TryIntoTrieBackend>(
+	backend: B,
+	keys: &[Vec<u8>],
+) -> Result<(Vec<Option<Vec<u8>>>, Vec<Vec<u8>>), Box<Error>> {
+	let trie_backend = backend.try_into_trie_backend()
+		.ok_or_else(|| Box::new(ExecutionError::UnableToGenerateProof) as Box<Error>)?;
+	let proving_backend = proving_backend::ProvingBackend::new(trie_backend);
+	let values = keys.iter()
+		.map(|key| proving_backend.storage(key).map_err(|e| Box::new(e) as Box<Error>))
+		.collect::<Result<Vec<_>, _>>()?;
+	let proof = proving_backend.extract_proof();
+	Ok((values, proof))
+}
+
+/// Check storage read proof, generated by `prove_read` call.
+pub fn read_proof_check(
+	root: [u8; 32],
+	proof: Vec<Vec<u8>>,
+	key: &[u8],
+) -> Result<Option<Vec<u8>>, Box<Error>> {
+	let backend = proving_backend::create_proof_check_backend(root.into(), proof)?;
+	backend.storage(key).map_err(|e| Box::new(e) as Box<Error>)
+}
+
 #[cfg(test)]
 mod tests {
 	use super::*;
@@ -451,6 +849,189 @@ mod tests {
 		assert!(overlayed.storage(&key).unwrap().is_none());
 	}
 
+	#[test]
+	fn overlayed_transactions_work() {
+		let mut overlayed = OverlayedChanges::default();
+
+		let key = vec![42, 69, 169, 142];
+		let other = vec![1];
+
+		overlayed.set_storage(key.clone(), Some(vec![1]));
+		assert!(!overlayed.commit_transaction());
+		assert!(!overlayed.rollback_transaction());
+
+		overlayed.start_transaction();
+		overlayed.set_storage(key.clone(), Some(vec![2]));
+		assert_eq!(overlayed.storage(&key).unwrap(), Some(&[2][..]));
+
+		overlayed.start_transaction();
+		overlayed.set_storage(key.clone(), None);
+		overlayed.set_storage(other.clone(), Some(vec![3]));
+		assert!(overlayed.storage(&key).unwrap().is_none());
+		assert!(overlayed.rollback_transaction());
+		assert_eq!(overlayed.storage(&key).unwrap(), Some(&[2][..]));
+		assert!(overlayed.storage(&other).is_none());
+
+		overlayed.start_transaction();
+		overlayed.set_storage(other.clone(), Some(vec![4]));
+		assert!(overlayed.commit_transaction());
+		assert_eq!(overlayed.storage(&other).unwrap(), Some(&[4][..]));
+
+		assert!(overlayed.rollback_transaction());
+		assert_eq!(overlayed.storage(&key).unwrap(), Some(&[1][..]));
+		assert!(overlayed.storage(&other).is_none());
+
+		overlayed.set_storage(other.clone(), Some(vec![5]));
+		overlayed.commit_prospective();
+
+		// open transactions are discarded along with the prospective changes
+		overlayed.start_transaction();
+		overlayed.set_storage(other.clone(), None);
+		overlayed.discard_prospective();
+		assert!(!overlayed.commit_transaction());
+		assert_eq!(overlayed.storage(&other).unwrap(), Some(&[5][..]));
+	}
+
+	#[test]
+	#[cfg_attr(debug_assertions, should_panic)]
+	fn open_transactions_are_committed_with_prospective_changes() {
+		let mut overlayed = OverlayedChanges::default();
+		overlayed.start_transaction();
+		overlayed.set_storage(b"dog".to_vec(), Some(b"puppy".to_vec()));
+		overlayed.commit_prospective();
+
+		assert!(!overlayed.rollback_transaction());
+		assert_eq!(overlayed.storage(b"dog").unwrap(), Some(&b"puppy"[..]));
+	}
+
+	#[test]
+	fn storage_root_includes_open_transactions() {
+		let backend = InMemory::from(HashMap::new());
+		let mut overlay = OverlayedChanges::default();
+		let mut ext = Ext::new(&mut overlay, &backend);
+
+		let empty_root = ext.storage_root();
+		ext.start_transaction();
+		ext.set_storage(b"doe".to_vec(), b"reindeer".to_vec());
+		let root = ext.storage_root();
+		assert!(root != empty_root);
+
+		assert!(ext.rollback_transaction());
+		assert_eq!(ext.storage_root(), empty_root);
+	}
+
+	#[test]
+	fn child_storage_works() {
+		let storage_key = b":child_storage:test".to_vec();
+		let backend = InMemory::from(HashMap::new());
+		let mut overlay = OverlayedChanges::default();
+		let (root, transaction) = {
+			let mut ext = Ext::new(&mut overlay, &backend);
+			let empty_root = ext.storage_root();
+
+			ext.set_child_storage(storage_key.clone(), b"doe".to_vec(), b"reindeer".to_vec());
+			ext.set_child_storage(storage_key.clone(), b"dog".to_vec(), b"puppy".to_vec());
+			assert_eq!(ext.child_storage(&storage_key, b"dog"), Some(b"puppy".to_vec()));
+			assert_eq!(ext.child_storage(b"not-a-child", b"dog"), None);
+
+			let root = ext.storage_root();
+			assert!(root != empty_root);
+			(root, ext.transaction())
+		};
+
+		let backend = backend.update(transaction);
+		let mut overlay = OverlayedChanges::default();
+		let mut ext = Ext::new(&mut overlay, &backend);
+		assert_eq!(ext.storage_root(), root);
+		assert_eq!(ext.child_storage(&storage_key, b"doe"), Some(b"reindeer".to_vec()));
+
+		// child roots can't be changed directly
+		ext.set_storage(storage_key.clone(), b"root".to_vec());
+		assert_eq!(ext.storage_root(), root);
+
+		ext.kill_child_storage(&storage_key);
+		assert_eq!(ext.child_storage(&storage_key, b"doe"), None);
+		assert_eq!(ext.storage_root(), InMemory::default().storage_root(::std::iter::empty()).0);
+	}
+
+	#[test]
+	fn drain_includes_child_changes() {
+		let storage_key = b":child_storage:test".to_vec();
+		let backend = InMemory::from(HashMap::new());
+		let mut overlay = OverlayedChanges::default();
+		{
+			let mut ext = Ext::new(&mut overlay, &backend);
+			ext.set_storage(b"dog".to_vec(), b"puppy".to_vec());
+			ext.set_child_storage(storage_key.clone(), b"doe".to_vec(), b"reindeer".to_vec());
+		}
+		overlay.commit_prospective();
+
+		let (top, children): (Vec<_>, Vec<_>) = {
+			let (top, children, _) = overlay.drain();
+			(top.collect(), children.collect())
+		};
+		assert_eq!(top, vec![(b"dog".to_vec(), Some(b"puppy".to_vec()))]);
+		assert_eq!(children.len(), 1);
+		assert_eq!(children[0].0, storage_key);
+		assert_eq!(children[0].1.get(&b"doe"[..]), Some(&Some(b"reindeer".to_vec())));
+		assert_eq!(overlay.child_storage(&storage_key, b"doe"), None);
+	}
+
+	#[test]
+	fn killed_child_storage_is_rebuilt_from_empty_trie() {
+		let storage_key = b":child_storage:test".to_vec();
+		let child_root = |entries: Vec<(&[u8], &[u8])>| {
+			let backend = InMemory::from(HashMap::new());
+			let mut overlay = OverlayedChanges::default();
+			let mut ext = Ext::new(&mut overlay, &backend);
+			for (key, value) in entries {
+				ext.set_child_storage(storage_key.clone(), key.to_vec(), value.to_vec());
+			}
+			ext.storage_root()
+		};
+
+		let backend = {
+			let backend = InMemory::from(HashMap::new());
+			let mut overlay = OverlayedChanges::default();
+			let transaction = {
+				let mut ext = Ext::new(&mut overlay, &backend);
+				ext.set_child_storage(storage_key.clone(), b"doe".to_vec(), b"reindeer".to_vec());
+				ext.set_child_storage(storage_key.clone(), b"dog".to_vec(), b"puppy".to_vec());
+				ext.transaction()
+			};
+			backend.update(transaction)
+		};
+
+		let mut overlay = OverlayedChanges::default();
+		let transaction = {
+			let mut ext = Ext::new(&mut overlay, &backend);
+			ext.set_child_storage(storage_key.clone(), b"cat".to_vec(), b"kitten".to_vec());
+			ext.start_transaction();
+			ext.kill_child_storage(&storage_key);
+			ext.set_child_storage(storage_key.clone(), b"dog".to_vec(), b"hound".to_vec());
+			assert_eq!(ext.child_storage(&storage_key, b"doe"), None);
+			assert_eq!(ext.child_storage(&storage_key, b"cat"), None);
+			assert_eq!(ext.child_storage(&storage_key, b"dog"), Some(b"hound".to_vec()));
+			assert_eq!(ext.storage_root(), child_root(vec![(&b"dog"[..], &b"hound"[..])]));
+
+			// the kill is undone along with the transaction
+			assert!(ext.rollback_transaction());
+			assert_eq!(ext.child_storage(&storage_key, b"doe"), Some(b"reindeer".to_vec()));
+			assert_eq!(ext.storage_root(), child_root(vec![
+				(&b"doe"[..], &b"reindeer"[..]),
+				(&b"dog"[..], &b"puppy"[..]),
+				(&b"cat"[..], &b"kitten"[..]),
+			]));
+
+			ext.kill_child_storage(&storage_key);
+			assert_eq!(ext.child_storage_root(&storage_key), Some(InMemory::default().storage_root(::std::iter::empty()).0));
+			ext.transaction()
+		};
+		let backend = backend.update(transaction);
+		assert_eq!(backend.child_storage(&storage_key, b"doe").unwrap(), None);
+		assert_eq!(backend.storage(&storage_key).unwrap(), None);
+	}
+
 	macro_rules! map {
 		($( $name:expr => $value:expr ),*) => (
 			vec![ $( ( $name, $value ) ),* ].into_iter().collect()
@@ -467,15 +1048,24 @@ mod tests {
 		];
 		let backend = InMemory::from(initial);
 		let mut overlay = OverlayedChanges {
-			committed: map![
-				b"dog".to_vec() => Some(b"puppy".to_vec()),
-				b"dogglesworth".to_vec() => Some(b"catYYY".to_vec()),
-				b"doug".to_vec() => Some(vec![])
-			],
-			prospective: map![
-				b"dogglesworth".to_vec() => Some(b"cat".to_vec()),
-				b"doug".to_vec() => None
-			],
+			committed: OverlayedChangeSet {
+				top: map![
+					b"dog".to_vec() => Some(b"puppy".to_vec()),
+					b"dogglesworth".to_vec() => Some(b"catYYY".to_vec()),
+					b"doug".to_vec() => Some(vec![])
+				],
+				children: HashMap::new(),
+				extrinsics: HashMap::new(),
+			},
+			prospective: OverlayedChangeSet {
+				top: map![
+					b"dogglesworth".to_vec() => Some(b"cat".to_vec()),
+					b"doug".to_vec() => None
+				],
+				children: HashMap::new(),
+				extrinsics: HashMap::new(),
+			},
+			transactions: vec![],
 		};
 		let mut ext = Ext::new(&mut overlay, &backend);
 		const ROOT: [u8; 32] = hex!("8aad789dff2f538bca5d8ea56e8abe10f4c7ba3a5dea95fea4cd6e7c3a1168d3");
@@ -498,6 +1088,56 @@ mod tests {
 		).unwrap().0, vec![66]);
 	}
 
+	#[test]
+	fn retried_execution_does_not_see_open_transactions_of_failed_attempt() {
+		#[derive(Debug)]
+		struct Retry;
+
+		impl fmt::Display for Retry {
+			fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result { write!(f, "Retry") }
+		}
+
+		impl Error for Retry {
+			fn needs_retry(&self) -> bool { true }
+		}
+
+		struct RetryingCodeExecutor {
+			attempts: ::std::sync::atomic::AtomicUsize,
+		}
+
+		impl CodeExecutor for RetryingCodeExecutor {
+			type Error = Retry;
+
+			fn call<E: Externalities>(
+				&self,
+				ext: &mut E,
+				_code: &[u8],
+				_method: &str,
+				_data: &[u8],
+				use_native: bool
+			) -> (Result<Vec<u8>, Self::Error>, bool) {
+				if self.attempts.fetch_add(1, ::std::sync::atomic::Ordering::SeqCst) == 0 {
+					ext.start_transaction();
+					ext.set_storage(b"value1".to_vec(), vec![100]);
+					return (Err(Retry), use_native);
+				}
+				(Ok(ext.storage(b"value1").unwrap()), use_native)
+			}
+		}
+
+		let mut overlay = OverlayedChanges::default();
+		let result = execute(
+			&trie_backend::tests::test_trie(),
+			&mut overlay,
+			&RetryingCodeExecutor { attempts: Default::default() },
+			"test",
+			&[],
+			ExecutionStrategy::NativeWhenPossible
+		).unwrap().0;
+		assert_eq!(result, vec![42]);
+		assert!(!overlay.rollback_transaction());
+	}
+
 	#[test]
 	fn dual_execution_strategy_detects_consensus_failure() {
 		let mut consensus_failed = false;
@@ -542,4 +1182,155 @@ mod tests {
 		assert_eq!(remote_result, vec![66]);
 		assert_eq!(remote_result, local_result);
 	}
+
+	#[derive(Default)]
+	struct TestOffchain {
+		extrinsics: Vec<Vec<u8>>,
+		local_storage: HashMap<Vec<u8>, Vec<u8>>,
+	}
+
+	impl OffchainExt for TestOffchain {
+		fn submit_extrinsic(&mut self, extrinsic: Vec<u8>) {
+			self.extrinsics.push(extrinsic);
+		}
+
+		fn local_storage(&self, key: &[u8]) -> Option<Vec<u8>> {
+			self.local_storage.get(key).cloned()
+		}
+
+		fn set_local_storage(&mut self, key: &[u8], value: Option<&[u8]>) {
+			match value {
+				Some(value) => self.local_storage.insert(key.to_vec(), value.to_vec()),
+				None => self.local_storage.remove(key),
+			};
+		}
+	}
+
+	struct OffchainCodeExecutor;
+
+	impl CodeExecutor for OffchainCodeExecutor {
+		type Error = u8;
+
+		fn call<E: Externalities>(
+			&self,
+			ext: &mut E,
+			_code: &[u8],
+			_method: &str,
+			data: &[u8],
+			use_native: bool
+		) -> (Result<Vec<u8>, Self::Error>, bool) {
+			ext.set_storage(b"value1".to_vec(), data.to_vec());
+			let value = ext.storage(b"value2").unwrap_or_default();
+			let offchain = match ext.offchain() {
+				Some(offchain) => offchain,
+				None => return (Err(0), use_native),
+			};
+			offchain.set_local_storage(b"value2", Some(&value[..]));
+			offchain.submit_extrinsic(data.to_vec());
+			(Ok(vec![1]), use_native)
+		}
+	}
+
+	#[test]
+	fn offchain_execution_works() {
+		let backend = trie_backend::tests::test_trie();
+		let mut offchain = TestOffchain::default();
+		let result = execute_offchain(
+			&backend,
+			&OffchainCodeExecutor,
+			&mut offchain,
+			"offchain_worker",
+			&[42],
+			ExecutionStrategy::NativeWhenPossible,
+		).unwrap();
+
+		assert_eq!(result, vec![1]);
+		assert_eq!(offchain.extrinsics, vec![vec![42]]);
+		assert_eq!(offchain.local_storage(b"value2"), Some(vec![24]));
+		assert_eq!(backend.storage(b"value1").unwrap(), Some(vec![42]));
+	}
+
+	#[test]
+	fn offchain_effects_are_applied_once_call_succeeds() {
+		#[derive(Debug)]
+		struct Retry;
+
+		impl fmt::Display for Retry {
+			fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result { write!(f, "Retry") }
+		}
+
+		impl Error for Retry {
+			fn needs_retry(&self) -> bool { true }
+		}
+
+		struct RetryingOffchainExecutor {
+			attempts: ::std::sync::atomic::AtomicUsize,
+		}
+
+		impl CodeExecutor for RetryingOffchainExecutor {
+			type Error = Retry;
+
+			fn call<E: Externalities>(
+				&self,
+				ext: &mut E,
+				_code: &[u8],
+				_method: &str,
+				data: &[u8],
+				use_native: bool
+			) -> (Result<Vec<u8>, Self::Error>, bool) {
+				let offchain = ext.offchain().unwrap();
+				let runs = offchain.local_storage(b"runs").map_or(0, |runs| runs[0]);
+				offchain.set_local_storage(b"runs", Some(&[runs + 1]));
+				offchain.submit_extrinsic(data.to_vec());
+				if self.attempts.fetch_add(1, ::std::sync::atomic::Ordering::SeqCst) == 0 {
+					return (Err(Retry), use_native);
+				}
+				(Ok(vec![runs]), use_native)
+			}
+		}
+
+		let mut offchain = TestOffchain::default();
+		let result = execute_offchain(
+			&trie_backend::tests::test_trie(),
+			&RetryingOffchainExecutor { attempts: Default::default() },
+			&mut offchain,
+			"offchain_worker",
+			&[42],
+			ExecutionStrategy::NativeWhenPossible,
+		).unwrap();
+
+		assert_eq!(result, vec![0]);
+		assert_eq!(offchain.extrinsics, vec![vec![42]]);
+		assert_eq!(offchain.local_storage(b"runs"), Some(vec![1]));
+	}
+
+	#[test]
+	fn offchain_capabilities_are_only_available_to_offchain_workers() {
+		assert!(execute(
+			&trie_backend::tests::test_trie(),
+			&mut Default::default(),
+			&OffchainCodeExecutor,
+			"offchain_worker",
+			&[42],
+			ExecutionStrategy::NativeWhenPossible,
+		).is_err());
+	}
+
+	#[test]
+	fn prove_read_and_proof_check_works() {
+		// fetch read proof from 'remote' full node
+		let remote_backend = trie_backend::tests::test_trie();
+		let remote_root = remote_backend.storage_root(::std::iter::empty()).0;
+		let (values, remote_proof) = prove_read(remote_backend, &[b"value2".to_vec()]).unwrap();
+		assert_eq!(values, vec![Some(vec![24])]);
+
+		// check proof locally
+		let local_result1 = read_proof_check(remote_root, remote_proof.clone(), b"value2").unwrap();
+		let local_result2 = read_proof_check(remote_root, remote_proof.clone(), &[0xff]).unwrap();
+
+		// check that results are correct
+		assert_eq!(local_result1, Some(vec![24]));
+		assert_eq!(local_result2, None);
+		assert!(read_proof_check([0; 32], remote_proof, b"value2").is_err());
+	}
 }
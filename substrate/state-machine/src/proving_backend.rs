@@ -73,6 +73,36 @@ impl Backend for ProvingBackend {
 		self.backend.for_keys_with_prefix(prefix, f)
 	}
 
+	fn keys_with_prefix(&self, prefix: &[u8], start_key: Option<&[u8]>, count: usize) -> Vec<Vec<u8>> {
+		self.backend.keys_with_prefix(prefix, start_key, count)
+	}
+
+	fn child_storage(&self, storage_key: &[u8], key: &[u8]) -> Result<Option<Vec<u8>>, Self::Error> {
+		// read the child root through `storage` so that it makes it into the proof
+		let root = match self.storage(storage_key)? {
+			Some(ref root) if root.len() == 32 => TrieH256::from_slice(root),
+			Some(_) => return Err(format!("Invalid child trie root stored under {:?}", storage_key)),
+			None => return Ok(None),
+		};
+
+		let mut read_overlay = MemoryDB::default();
+		let eph = Ephemeral::new(
+			self.backend.backend_storage(),
+			&mut read_overlay,
+		);
+
+		let map_e = |e: Box<TrieError>| format!("Trie lookup error: {}", e);
+
+		let mut proof_recorder = self.proof_recorder.try_borrow_mut()
+			.expect("only fails when already borrowed; child_storage() is non-reentrant; qed");
+		TrieDB::new(&eph, &root).map_err(map_e)?
+			.get_with(key, &mut *proof_recorder).map(|x| x.map(|val| val.to_vec())).map_err(map_e)
+	}
+
+	fn for_keys_in_child_storage<F: FnMut(&[u8])>(&self, storage_key: &[u8], f: F) -> Result<(), Self::Error> {
+		self.backend.for_keys_in_child_storage(storage_key, f)
+	}
+
 	fn pairs(&self) -> Vec<(Vec<u8>, Vec<u8>)> {
 		self.backend.pairs()
 	}
@@ -82,6 +112,24 @@ impl Backend for ProvingBackend {
 	{
 		self.backend.storage_root(delta)
 	}
+
+	fn child_storage_root<I>(&self, storage_key: &[u8], delta: I) -> ([u8; 32], bool, MemoryDB)
+		where I: IntoIterator<Item=(Vec<u8>, Option<Vec<u8>>)>
+	{
+		self.backend.child_storage_root(storage_key, delta)
+	}
+
+	fn killed_child_storage_root<I>(&self, storage_key: &[u8], delta: I) -> ([u8; 32], bool, MemoryDB)
+		where I: IntoIterator<Item=(Vec<u8>, Option<Vec<u8>>)>
+	{
+		self.backend.killed_child_storage_root(storage_key, delta)
+	}
+
+	fn build_trie<I>(&self, pairs: I) -> ([u8; 32], MemoryDB)
+		where I: IntoIterator<Item=(Vec<u8>, Vec<u8>)>
+	{
+		self.backend.build_trie(pairs)
+	}
 }
 
 impl TryIntoTrieBackend for ProvingBackend {
@@ -16,36 +16,185 @@
 
 //! Test implementation for Externalities.
 
-use std::collections::HashMap;
-use super::Externalities;
+use std::collections::{HashMap, HashSet};
+use std::iter::FromIterator;
+use std::ops::{Deref, DerefMut};
+use super::{Externalities, is_child_storage_key};
 use triehash::trie_root;
 
+/// Child storage entries, by the storage key of the child trie and the key within it.
+type ChildStorage = HashMap<(Vec<u8>, Vec<u8>), Vec<u8>>;
+
 /// Simple HashMap based Externalities impl.
-pub type TestExternalities = HashMap<Vec<u8>, Vec<u8>>;
+///
+/// Derefs to the map of the top-level storage.
+#[derive(Debug, Default, Clone)]
+pub struct TestExternalities {
+	inner: HashMap<Vec<u8>, Vec<u8>>,
+	children: ChildStorage,
+	/// Snapshots of the storage taken when the open storage transactions were started,
+	/// innermost last.
+	transactions: Vec<(HashMap<Vec<u8>, Vec<u8>>, ChildStorage)>,
+}
+
+impl TestExternalities {
+	/// Create new empty externalities.
+	pub fn new() -> Self {
+		Default::default()
+	}
+
+	/// Root of the child trie with given storage key.
+	fn child_root(&self, storage_key: &[u8]) -> [u8; 32] {
+		let pairs: Vec<(Vec<u8>, Vec<u8>)> = self.children.iter()
+			.filter(|&(&(ref child_storage_key, _), _)| &child_storage_key[..] == storage_key)
+			.map(|(&(_, ref key), value)| (key.clone(), value.clone()))
+			.collect();
+		trie_root(pairs).0
+	}
+}
+
+impl Deref for TestExternalities {
+	type Target = HashMap<Vec<u8>, Vec<u8>>;
+
+	fn deref(&self) -> &Self::Target {
+		&self.inner
+	}
+}
+
+impl DerefMut for TestExternalities {
+	fn deref_mut(&mut self) -> &mut Self::Target {
+		&mut self.inner
+	}
+}
+
+impl From<HashMap<Vec<u8>, Vec<u8>>> for TestExternalities {
+	fn from(inner: HashMap<Vec<u8>, Vec<u8>>) -> Self {
+		TestExternalities {
+			inner,
+			children: HashMap::new(),
+			transactions: Vec::new(),
+		}
+	}
+}
+
+impl From<TestExternalities> for HashMap<Vec<u8>, Vec<u8>> {
+	fn from(ext: TestExternalities) -> Self {
+		ext.inner
+	}
+}
+
+impl FromIterator<(Vec<u8>, Vec<u8>)> for TestExternalities {
+	fn from_iter<I: IntoIterator<Item=(Vec<u8>, Vec<u8>)>>(iter: I) -> Self {
+		HashMap::from_iter(iter).into()
+	}
+}
+
+impl Extend<(Vec<u8>, Vec<u8>)> for TestExternalities {
+	fn extend<I: IntoIterator<Item=(Vec<u8>, Vec<u8>)>>(&mut self, iter: I) {
+		self.inner.extend(iter)
+	}
+}
+
+impl PartialEq for TestExternalities {
+	fn eq(&self, other: &TestExternalities) -> bool {
+		self.inner == other.inner && self.children == other.children
+	}
+}
+
+impl PartialEq<HashMap<Vec<u8>, Vec<u8>>> for TestExternalities {
+	fn eq(&self, other: &HashMap<Vec<u8>, Vec<u8>>) -> bool {
+		self.inner == *other
+	}
+}
+
+impl PartialEq<TestExternalities> for HashMap<Vec<u8>, Vec<u8>> {
+	fn eq(&self, other: &TestExternalities) -> bool {
+		*self == other.inner
+	}
+}
 
 impl Externalities for TestExternalities {
 	fn storage(&self, key: &[u8]) -> Option<Vec<u8>> {
-		self.get(key).map(|x| x.to_vec())
+		self.inner.get(key).map(|x| x.to_vec())
 	}
 
 	fn place_storage(&mut self, key: Vec<u8>, maybe_value: Option<Vec<u8>>) {
 		match maybe_value {
-			Some(value) => { self.insert(key, value); }
-			None => { self.remove(&key); }
+			Some(value) => { self.inner.insert(key, value); }
+			None => { self.inner.remove(&key); }
 		}
 	}
 
 	fn clear_prefix(&mut self, prefix: &[u8]) {
-		self.retain(|key, _|
+		self.inner.retain(|key, _|
 			!key.starts_with(prefix)
 		)
 	}
 
+	fn child_storage(&self, storage_key: &[u8], key: &[u8]) -> Option<Vec<u8>> {
+		self.children.get(&(storage_key.to_vec(), key.to_vec())).cloned()
+	}
+
+	fn place_child_storage(&mut self, storage_key: Vec<u8>, key: Vec<u8>, value: Option<Vec<u8>>) {
+		if !is_child_storage_key(&storage_key) {
+			warn!("Refusing to set child storage under a non-child storage key");
+			return;
+		}
+
+		match value {
+			Some(value) => { self.children.insert((storage_key, key), value); }
+			None => { self.children.remove(&(storage_key, key)); }
+		}
+	}
+
+	fn kill_child_storage(&mut self, storage_key: &[u8]) {
+		self.children.retain(|&(ref child_storage_key, _), _|
+			&child_storage_key[..] != storage_key
+		)
+	}
+
+	fn start_transaction(&mut self) {
+		let snapshot = (self.inner.clone(), self.children.clone());
+		self.transactions.push(snapshot);
+	}
+
+	fn commit_transaction(&mut self) -> bool {
+		self.transactions.pop().is_some()
+	}
+
+	fn rollback_transaction(&mut self) -> bool {
+		match self.transactions.pop() {
+			Some((inner, children)) => {
+				self.inner = inner;
+				self.children = children;
+				true
+			},
+			None => false,
+		}
+	}
+
 	fn chain_id(&self) -> u64 { 42 }
 
 	fn storage_root(&mut self) -> [u8; 32] {
-		trie_root(self.clone()).0
+		// the roots of the non-empty child tries are a part of the top-level trie
+		let mut top = self.inner.clone();
+		let storage_keys: HashSet<&Vec<u8>> = self.children.keys()
+			.map(|&(ref storage_key, _)| storage_key)
+			.collect();
+		for storage_key in storage_keys {
+			top.insert(storage_key.clone(), self.child_root(storage_key).to_vec());
+		}
+		trie_root(top).0
 	}
+
+	fn child_storage_root(&mut self, storage_key: &[u8]) -> Option<[u8; 32]> {
+		if !is_child_storage_key(storage_key) {
+			return None;
+		}
+		Some(self.child_root(storage_key))
+	}
+
+	fn storage_changes_root(&mut self) -> Option<[u8; 32]> { None }
 }
 
 #[cfg(test)]
@@ -61,4 +210,47 @@ mod tests {
 		const ROOT: [u8; 32] = hex!("8aad789dff2f538bca5d8ea56e8abe10f4c7ba3a5dea95fea4cd6e7c3a1168d3");
 		assert_eq!(ext.storage_root(), ROOT);
 	}
+
+	#[test]
+	fn transactions_should_work() {
+		let mut ext = TestExternalities::new();
+		ext.set_storage(b"doe".to_vec(), b"reindeer".to_vec());
+
+		ext.start_transaction();
+		ext.set_storage(b"dog".to_vec(), b"puppy".to_vec());
+		ext.start_transaction();
+		ext.clear_storage(b"doe");
+		assert!(ext.rollback_transaction());
+		assert_eq!(ext.storage(b"doe"), Some(b"reindeer".to_vec()));
+		assert!(ext.commit_transaction());
+		assert_eq!(ext.storage(b"dog"), Some(b"puppy".to_vec()));
+
+		ext.start_transaction();
+		ext.set_storage(b"dog".to_vec(), b"cat".to_vec());
+		assert!(ext.rollback_transaction());
+		assert_eq!(ext.storage(b"dog"), Some(b"puppy".to_vec()));
+		assert!(!ext.commit_transaction());
+		assert!(!ext.rollback_transaction());
+	}
+
+	#[test]
+	fn child_storage_works() {
+		let storage_key = b":child_storage:test".to_vec();
+		let mut ext = TestExternalities::new();
+		let empty_root = ext.storage_root();
+
+		ext.set_child_storage(storage_key.clone(), b"doe".to_vec(), b"reindeer".to_vec());
+		ext.set_child_storage(b"not-a-child".to_vec(), b"doe".to_vec(), b"reindeer".to_vec());
+		assert_eq!(ext.child_storage(&storage_key, b"doe"), Some(b"reindeer".to_vec()));
+		assert_eq!(ext.child_storage(b"not-a-child", b"doe"), None);
+		assert_eq!(ext.child_storage_root(&storage_key), Some(trie_root(vec![(b"doe".to_vec(), b"reindeer".to_vec())]).0));
+		assert!(ext.storage_root() != empty_root);
+
+		ext.start_transaction();
+		ext.kill_child_storage(&storage_key);
+		assert_eq!(ext.child_storage(&storage_key, b"doe"), None);
+		assert_eq!(ext.storage_root(), empty_root);
+		assert!(ext.rollback_transaction());
+		assert_eq!(ext.child_storage(&storage_key, b"doe"), Some(b"reindeer".to_vec()));
+	}
 }
@@ -80,6 +80,92 @@ impl TrieBackend {
 	pub fn root(&self) -> &TrieH256 {
 		&self.root
 	}
+
+	/// Create a backend reading the trie with given root, e.g. a changes trie, from the same
+	/// node storage.
+	pub fn with_root(&self, root: TrieH256) -> Self {
+		TrieBackend {
+			storage: self.storage.clone(),
+			root,
+		}
+	}
+
+	/// Get the root of the child trie stored under given storage key.
+	pub fn child_root(&self, storage_key: &[u8]) -> Result<Option<TrieH256>, String> {
+		match self.storage(storage_key)? {
+			Some(ref root) if root.len() == 32 => Ok(Some(TrieH256::from_slice(root))),
+			Some(_) => Err(format!("Invalid child trie root stored under {:?}", storage_key)),
+			None => Ok(None),
+		}
+	}
+
+	/// Call `f` for the keys of the trie with given root that start with `prefix`, in
+	/// lexicographic order and starting from `seek`, until it returns `false`.
+	fn iter_keys<F: FnMut(&[u8]) -> bool>(&self, root: &TrieH256, prefix: &[u8], seek: &[u8], mut f: F) -> Result<(), String> {
+		let mut read_overlay = MemoryDB::default();
+		let eph = Ephemeral {
+			storage: &self.storage,
+			overlay: &mut read_overlay,
+		};
+
+		let mut iter = move || -> Result<(), Box<TrieError>> {
+			let trie = TrieDB::new(&eph, root)?;
+			let mut iter = trie.iter()?;
+
+			iter.seek(seek)?;
+
+			for x in iter {
+				let (key, _) = x?;
+
+				if !key.starts_with(prefix) || !f(&key) {
+					break;
+				}
+			}
+
+			Ok(())
+		};
+
+		iter().map_err(|e| format!("Trie iteration error: {}", e))
+	}
+
+	fn update_trie<I>(&self, root: Option<TrieH256>, delta: I) -> (TrieH256, MemoryDB)
+		where I: IntoIterator<Item=(Vec<u8>, Option<Vec<u8>>)>
+	{
+		let mut write_overlay = MemoryDB::default();
+		let mut root = root;
+		let mut new_root = TrieH256::default();
+		{
+			let mut eph = Ephemeral {
+				storage: &self.storage,
+				overlay: &mut write_overlay,
+			};
+
+			let mut trie = match root {
+				Some(ref mut root) => TrieDBMut::from_existing(&mut eph, root).expect("prior state root to exist"), // TODO: handle gracefully
+				None => TrieDBMut::new(&mut eph, &mut new_root),
+			};
+			for (key, change) in delta {
+				let result = match change {
+					Some(val) => trie.insert(&key, &val),
+					None => trie.remove(&key), // TODO: archive mode
+				};
+
+				if let Err(e) = result {
+					warn!(target: "trie", "Failed to write to trie: {}", e);
+				}
+			}
+		}
+
+		(root.unwrap_or(new_root), write_overlay)
+	}
+}
+
+/// Root of an empty trie.
+fn empty_trie_root() -> TrieH256 {
+	let mut root = TrieH256::default();
+	let mut mdb = MemoryDB::default();
+	TrieDBMut::new(&mut mdb, &mut root);
+	root
 }
 
 impl super::Error for String {}
@@ -102,34 +188,31 @@ impl Backend for TrieBackend {
 	}
 
 	fn for_keys_with_prefix<F: FnMut(&[u8])>(&self, prefix: &[u8], mut f: F) {
-		let mut read_overlay = MemoryDB::default();
-		let eph = Ephemeral {
-			storage: &self.storage,
-			overlay: &mut read_overlay,
-		};
-
-		let mut iter = move || -> Result<(), Box<TrieError>> {
-			let trie = TrieDB::new(&eph, &self.root)?;
-			let mut iter = trie.iter()?;
-
-			iter.seek(prefix)?;
-
-			for x in iter {
-				let (key, _) = x?;
-
-				if !key.starts_with(prefix) {
-					break;
-				}
+		if let Err(e) = self.iter_keys(&self.root, prefix, prefix, |key| { f(key); true }) {
+			debug!(target: "trie", "Error while iterating by prefix: {}", e);
+		}
+	}
 
-				f(&key);
-			}
+	fn keys_with_prefix(&self, prefix: &[u8], start_key: Option<&[u8]>, count: usize) -> Vec<Vec<u8>> {
+		let mut keys = Vec::new();
+		if count == 0 {
+			return keys;
+		}
 
-			Ok(())
+		let seek = match start_key {
+			Some(start) if start > prefix => start,
+			_ => prefix,
 		};
-
-		if let Err(e) = iter() {
+		let result = self.iter_keys(&self.root, prefix, seek, |key| {
+			if start_key.map_or(true, |start| key > start) {
+				keys.push(key.to_vec());
+			}
+			keys.len() < count
+		});
+		if let Err(e) = result {
 			debug!(target: "trie", "Error while iterating by prefix: {}", e);
 		}
+		keys
 	}
 
 	fn pairs(&self) -> Vec<(Vec<u8>, Vec<u8>)> {
@@ -162,27 +245,57 @@ impl Backend for TrieBackend {
 	fn storage_root<I>(&self, delta: I) -> ([u8; 32], MemoryDB)
 		where I: IntoIterator<Item=(Vec<u8>, Option<Vec<u8>>)>
 	{
-		let mut write_overlay = MemoryDB::default();
-		let mut root = self.root;
-		{
-			let mut eph = Ephemeral {
-				storage: &self.storage,
-				overlay: &mut write_overlay,
-			};
+		let (root, write_overlay) = self.update_trie(Some(self.root), delta);
+		(root.0.into(), write_overlay)
+	}
 
-			let mut trie = TrieDBMut::from_existing(&mut eph, &mut root).expect("prior state root to exist"); // TODO: handle gracefully
-			for (key, change) in delta {
-				let result = match change {
-					Some(val) => trie.insert(&key, &val),
-					None => trie.remove(&key), // TODO: archive mode
-				};
+	fn child_storage(&self, storage_key: &[u8], key: &[u8]) -> Result<Option<Vec<u8>>, Self::Error> {
+		let root = match self.child_root(storage_key)? {
+			Some(root) => root,
+			None => return Ok(None),
+		};
 
-				if let Err(e) = result {
-					warn!(target: "trie", "Failed to write to trie: {}", e);
-				}
-			}
+		let mut read_overlay = MemoryDB::default();
+		let eph = Ephemeral {
+			storage: &self.storage,
+			overlay: &mut read_overlay,
+		};
+
+		let map_e = |e: Box<TrieError>| format!("Trie lookup error: {}", e);
+
+		TrieDB::new(&eph, &root).map_err(map_e)?
+			.get(key).map(|x| x.map(|val| val.to_vec())).map_err(map_e)
+	}
+
+	fn for_keys_in_child_storage<F: FnMut(&[u8])>(&self, storage_key: &[u8], mut f: F) -> Result<(), Self::Error> {
+		match self.child_root(storage_key)? {
+			Some(root) => self.iter_keys(&root, &[], &[], |key| { f(key); true }),
+			None => Ok(()),
 		}
+	}
+
+	fn child_storage_root<I>(&self, storage_key: &[u8], delta: I) -> ([u8; 32], bool, MemoryDB)
+		where I: IntoIterator<Item=(Vec<u8>, Option<Vec<u8>>)>
+	{
+		// an empty root would silently drop the child trie from the state
+		let existing_root = self.child_root(storage_key).expect("child trie root must be readable from the state");
+
+		let (root, write_overlay) = self.update_trie(existing_root, delta);
+		(root.0.into(), root == empty_trie_root(), write_overlay)
+	}
+
+	fn killed_child_storage_root<I>(&self, _storage_key: &[u8], delta: I) -> ([u8; 32], bool, MemoryDB)
+		where I: IntoIterator<Item=(Vec<u8>, Option<Vec<u8>>)>
+	{
+		// the nodes of the killed trie are left in the database, as other tries may share them
+		let (root, write_overlay) = self.update_trie(None, delta);
+		(root.0.into(), root == empty_trie_root(), write_overlay)
+	}
 
+	fn build_trie<I>(&self, pairs: I) -> ([u8; 32], MemoryDB)
+		where I: IntoIterator<Item=(Vec<u8>, Vec<u8>)>
+	{
+		let (root, write_overlay) = self.update_trie(None, pairs.into_iter().map(|(k, v)| (k, Some(v))));
 		(root.0.into(), write_overlay)
 	}
 }
@@ -311,6 +424,17 @@ pub mod tests {
 		assert!(TrieBackend::with_memorydb(MemoryDB::new(), Default::default()).pairs().is_empty());
 	}
 
+	#[test]
+	fn keys_with_prefix_are_paged() {
+		let trie = test_trie();
+		assert_eq!(trie.keys_with_prefix(b"value", None, 10), vec![b"value1".to_vec(), b"value2".to_vec()]);
+		assert_eq!(trie.keys_with_prefix(b"value", None, 1), vec![b"value1".to_vec()]);
+		assert_eq!(trie.keys_with_prefix(b"value", Some(b"value1"), 10), vec![b"value2".to_vec()]);
+		assert_eq!(trie.keys_with_prefix(b"value", Some(b"key"), 10), vec![b"value1".to_vec(), b"value2".to_vec()]);
+		assert!(trie.keys_with_prefix(b"value", Some(b"value2"), 10).is_empty());
+		assert!(trie.keys_with_prefix(b"", None, 0).is_empty());
+	}
+
 	#[test]
 	fn storage_root_is_non_default() {
 		assert!(test_trie().storage_root(::std::iter::empty()).0 != [0; 32]);
@@ -328,6 +452,32 @@ pub mod tests {
 		assert!(new_root != test_trie().storage_root(::std::iter::empty()).0);
 	}
 
+	#[test]
+	fn child_storage_works() {
+		let trie = test_trie();
+		let storage_key = b":child_storage:test".to_vec();
+		let (child_root, is_empty, mut tx) = trie.child_storage_root(&storage_key,
+			vec![(b"key".to_vec(), Some(b"child-value".to_vec()))]);
+		assert!(!is_empty);
+
+		let (root, top_tx) = trie.storage_root(vec![(storage_key.clone(), Some(child_root.to_vec()))]);
+		tx.consolidate(top_tx);
+		let (mut mdb, _) = test_db();
+		mdb.consolidate(tx);
+		let trie = TrieBackend::with_memorydb(mdb, root.into());
+
+		assert_eq!(trie.storage(b"key").unwrap(), Some(b"value".to_vec()));
+		assert_eq!(trie.child_storage(&storage_key, b"key").unwrap(), Some(b"child-value".to_vec()));
+		assert_eq!(trie.child_storage(b":child_storage:other", b"key").unwrap(), None);
+
+		let mut keys = Vec::new();
+		trie.for_keys_in_child_storage(&storage_key, |key| keys.push(key.to_vec())).unwrap();
+		assert_eq!(keys, vec![b"key".to_vec()]);
+
+		let (_, is_empty, _) = trie.child_storage_root(&storage_key, vec![(b"key".to_vec(), None)]);
+		assert!(is_empty);
+	}
+
 	#[test]
 	fn prefix_walking_works() {
 		let trie = test_trie();
@@ -18,18 +18,16 @@
 
 use client::{self, Client};
 use keyring::Keyring;
+use primitives::storage::CHANGES_TRIE_ENABLED;
 use runtime_primitives::StorageMap;
 use runtime::genesismap::{GenesisConfig, additional_storage_with_genesis};
 use executor::NativeExecutor;
 use runtime;
 use bft;
-use {Backend, Executor};
+use LocalExecutor;
 
 /// Extension trait for a test client.
 pub trait TestClient {
-	/// Crates new client instance for tests.
-	fn new_for_tests() -> Self;
-
 	/// Justify and import block to the chain.
 	fn justify_and_import(&self, origin: client::BlockOrigin, block: runtime::Block) -> client::error::Result<()>;
 
@@ -37,11 +35,9 @@ pub trait TestClient {
 	fn genesis_hash(&self) -> runtime::Hash;
 }
 
-impl TestClient for Client<Backend, Executor, runtime::Block> {
-	fn new_for_tests() -> Self {
-		client::new_in_mem(NativeExecutor::with_heap_pages(8, 8), genesis_storage()).unwrap()
-	}
-
+impl<B> TestClient for Client<B, client::LocalCallExecutor<B, NativeExecutor<LocalExecutor>>, runtime::Block>
+	where B: client::backend::LocalBackend<runtime::Block>
+{
 	fn justify_and_import(&self, origin: client::BlockOrigin, block: runtime::Block) -> client::error::Result<()> {
 		let justification = fake_justify(&block.header);
 		let justified = self.check_justification(block.header, justification)?;
@@ -95,8 +91,12 @@ fn genesis_config() -> GenesisConfig {
 	], 1000)
 }
 
-fn genesis_storage() -> StorageMap {
+/// Genesis storage of the test chain, enabling changes tries if `support_changes_trie` is set.
+pub fn genesis_storage(support_changes_trie: bool) -> StorageMap {
 		let mut storage = genesis_config().genesis_map();
+		if support_changes_trie {
+			storage.insert(CHANGES_TRIE_ENABLED.to_vec(), vec![1]);
+		}
 		let block: runtime::Block = client::genesis::construct_genesis_block(&storage);
 		storage.extend(additional_storage_with_genesis(&block));
 		storage
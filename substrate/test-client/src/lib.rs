@@ -32,6 +32,8 @@ pub extern crate substrate_client as client;
 
 mod client_ext;
 
+use std::sync::Arc;
+
 pub use client_ext::TestClient;
 
 mod local_executor {
@@ -52,5 +54,22 @@ pub type Executor = client::LocalCallExecutor<Backend, executor::NativeExecutor<
 
 /// Creates new client instance used for tests.
 pub fn new() -> client::Client<Backend, Executor, runtime::Block> {
-	TestClient::new_for_tests()
+	new_with_backend(Arc::new(Backend::new()), false)
+}
+
+/// Creates new client instance used for tests over the given backend, with changes tries enabled
+/// at genesis if `support_changes_trie` is set.
+pub fn new_with_backend<B>(
+	backend: Arc<B>,
+	support_changes_trie: bool,
+) -> client::Client<B, client::LocalCallExecutor<B, executor::NativeExecutor<LocalExecutor>>, runtime::Block>
+	where B: client::backend::LocalBackend<runtime::Block>
+{
+	let executor = client::LocalCallExecutor::new(backend.clone(), executor::NativeExecutor::with_heap_pages(8, 8));
+	client::Client::new(
+		backend,
+		executor,
+		client_ext::genesis_storage(support_changes_trie),
+		client::ExecutionStrategy::NativeWhenPossible,
+	).unwrap()
 }
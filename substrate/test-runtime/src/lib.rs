@@ -138,11 +138,11 @@ pub type BlockNumber = u64;
 /// Index of a transaction.
 pub type Index = u64;
 /// The digest of a block.
-pub type Digest = runtime_primitives::generic::Digest<Vec<u8>>;
+pub type Digest = runtime_primitives::generic::Digest<runtime_primitives::generic::DigestItem>;
 /// A test block.
 pub type Block = runtime_primitives::generic::Block<Header, Extrinsic>;
 /// A test block's header.
-pub type Header = runtime_primitives::generic::Header<BlockNumber, BlakeTwo256, Vec<u8>>;
+pub type Header = runtime_primitives::generic::Header<BlockNumber, BlakeTwo256, runtime_primitives::generic::DigestItem>;
 
 /// Run whatever tests we have.
 pub fn run_tests(mut input: &[u8]) -> Vec<u8> {
@@ -164,6 +164,7 @@ pub mod api {
 		initialise_block => |header| system::initialise_block(header),
 		execute_block => |block| system::execute_block(block),
 		apply_extrinsic => |utx| system::execute_transaction(utx),
-		finalise_block => |()| system::finalise_block()
+		finalise_block => |()| system::finalise_block(),
+		offchain_worker => |number| system::offchain_worker(number)
 	);
 }
@@ -18,16 +18,19 @@
 //! and depositing logs.
 
 use rstd::prelude::*;
-use runtime_io::{storage_root, enumerated_trie_root};
+use runtime_io::{storage_root, storage_changes_root, enumerated_trie_root, set_local_storage};
 use runtime_support::storage::{self, StorageValue, StorageMap};
-use runtime_primitives::traits::{Hash as HashT, BlakeTwo256};
+use runtime_primitives::generic::DigestItem;
+use runtime_primitives::traits::{Hash as HashT, BlakeTwo256, Digest as DigestT};
 use codec::{KeyedVec, Encode};
-use super::{AccountId, BlockNumber, Extrinsic, H256 as Hash, Block, Header};
+use super::{AccountId, BlockNumber, Extrinsic, H256 as Hash, Block, Header, Digest};
 
 const NONCE_OF: &[u8] = b"nonce:";
 const BALANCE_OF: &[u8] = b"balance:";
 const AUTHORITY_AT: &'static[u8] = b":auth:";
 const AUTHORITY_COUNT: &'static[u8] = b":auth:len";
+/// Node-local storage key of the number of the last block the offchain worker has run on.
+pub const LAST_OFFCHAIN_BLOCK: &[u8] = b"offchain:last";
 
 storage_items! {
 	ExtrinsicIndex: b"sys:xti" => required u32;
@@ -78,6 +81,9 @@ pub fn execute_block(block: Block) {
 	let storage_root = storage_root().into();
 	info_expect_equal_hash(&header.state_root, &storage_root);
 	assert!(header.state_root == storage_root, "Storage root must match that calculated.");
+
+	// check the changes trie root, if changes tries are enabled.
+	assert!(header.digest.changes_trie_root() == storage_changes_root(), "Changes trie root must match that calculated.");
 }
 
 /// Execute a transaction outside of the block execution function.
@@ -99,16 +105,25 @@ pub fn finalise_block() -> Header {
 	let number = <Number>::take();
 	let parent_hash = <ParentHash>::take();
 	let storage_root = BlakeTwo256::storage_root();
+	let mut digest = Digest::default();
+	if let Some(changes_root) = storage_changes_root() {
+		digest.push(DigestItem::ChangesTrieRoot(changes_root));
+	}
 
 	Header {
 		number,
 		extrinsics_root,
 		state_root: storage_root,
 		parent_hash,
-		digest: Default::default(),
+		digest,
 	}
 }
 
+/// Run the offchain worker, recording `number` in the node-local storage.
+pub fn offchain_worker(number: BlockNumber) {
+	set_local_storage(LAST_OFFCHAIN_BLOCK, &number.encode());
+}
+
 fn execute_transaction_backend(utx: &Extrinsic) {
 	use runtime_primitives::traits::BlindCheckable;
 